@@ -0,0 +1,106 @@
+// ----------------------------------------------------------------------------
+// Ctrl+C handling for --console
+//
+// Without a handler Ctrl+C kills the process before the index is saved. The
+// handler runs on a thread created by Windows, so it only forwards a stop
+// request to the worker loop, which saves and exits like a service stop.
+//
+// Outside Windows SIGINT and SIGTERM stop, so systemd and launchd stops save
+// too. Signal handlers may only set a flag, which a thread polls.
+// ----------------------------------------------------------------------------
+#[cfg(not(windows))]
+use posix::*;
+use std::sync::OnceLock;
+#[cfg(windows)]
+use win32::*;
+
+type StopCallback = Box<dyn Fn() + Send + Sync>;
+static ON_STOP: OnceLock<StopCallback> = OnceLock::new();
+
+// Calls on_stop on Ctrl+C, Ctrl+Break, or console close
+#[cfg(windows)]
+pub fn on_stop(on_stop: impl Fn() + Send + Sync + 'static) -> anyhow::Result<()> {
+    if ON_STOP.set(Box::new(on_stop)).is_err() {
+        anyhow::bail!("Console stop handler already installed");
+    }
+    if unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) } == 0 {
+        anyhow::bail!(
+            "Failed to set console handler: [{}]",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handler(_ctrl_type: u32) -> i32 {
+    if let Some(on_stop) = ON_STOP.get() {
+        on_stop();
+    }
+
+    // Handled. Process exits once worker loop returns.
+    TRUE
+}
+
+// Calls on_stop on SIGINT or SIGTERM
+#[cfg(not(windows))]
+pub fn on_stop(on_stop: impl Fn() + Send + Sync + 'static) -> anyhow::Result<()> {
+    use std::sync::atomic::Ordering;
+
+    if ON_STOP.set(Box::new(on_stop)).is_err() {
+        anyhow::bail!("Console stop handler already installed");
+    }
+    for signum in [SIGINT, SIGTERM] {
+        if unsafe { signal(signum, handler as extern "C" fn(i32) as usize) } == SIG_ERR {
+            anyhow::bail!(
+                "Failed to set signal handler: [{}]",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    std::thread::spawn(|| loop {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        if STOP_SIGNALLED.load(Ordering::Relaxed) {
+            if let Some(on_stop) = ON_STOP.get() {
+                on_stop();
+            }
+            break;
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(windows))]
+static STOP_SIGNALLED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[cfg(not(windows))]
+extern "C" fn handler(_signum: i32) {
+    STOP_SIGNALLED.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+// Minimal bindings. See consoleapi.h
+#[cfg(windows)]
+#[allow(non_snake_case)]
+mod win32 {
+    pub const TRUE: i32 = 1;
+
+    pub type HandlerRoutine = unsafe extern "system" fn(ctrl_type: u32) -> i32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn SetConsoleCtrlHandler(handler: Option<HandlerRoutine>, add: i32) -> i32;
+    }
+}
+
+// Minimal bindings. See signal.h. Numbers match on Linux and macOS.
+#[cfg(not(windows))]
+mod posix {
+    pub const SIGINT: i32 = 2;
+    pub const SIGTERM: i32 = 15;
+    pub const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        pub fn signal(signum: i32, handler: usize) -> usize;
+    }
+}
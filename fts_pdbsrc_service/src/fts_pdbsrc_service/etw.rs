@@ -0,0 +1,266 @@
+// ----------------------------------------------------------------------------
+// ETW events for correlating symbol resolution with debugger activity
+//
+// Events are written with TraceLogging so Windows Performance Analyzer and
+// other ETW consumers decode them without a manifest. Provider name is
+// fts_pdbsrc_service and its GUID is the standard TraceLogging hash of that
+// name, so tools accepting *fts_pdbsrc_service find it:
+//
+//   tracelog -start fts -f fts.etl -guid *fts_pdbsrc_service
+//   tracelog -stop fts
+//
+// Events:
+//   ScanStarted    roots
+//   ScanFinished   pdbs, unreadable, duration_ms
+//   PdbIndexed     uuid, path
+//   FindPdb        uuid, found
+//   ExtractServed  uuid, file, bytes
+//
+// Nothing is built unless a session has enabled the provider. Events are
+// dropped outside Windows.
+// ----------------------------------------------------------------------------
+#[cfg(windows)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+#[cfg(windows)]
+use win32::*;
+
+#[cfg(windows)]
+const PROVIDER_NAME: &str = "fts_pdbsrc_service";
+
+// 3e29e91d-57ec-54f6-a82b-619e62e29802
+#[cfg(windows)]
+const PROVIDER_GUID: GUID = GUID {
+    data1: 0x3e29_e91d,
+    data2: 0x57ec,
+    data3: 0x54f6,
+    data4: [0xa8, 0x2b, 0x61, 0x9e, 0x62, 0xe2, 0x98, 0x02],
+};
+
+// Zero until registered
+#[cfg(windows)]
+static REG_HANDLE: AtomicU64 = AtomicU64::new(0);
+
+#[cfg_attr(not(windows), allow(dead_code))]
+pub enum Value<'a> {
+    Str(&'a str),
+    U64(u64),
+    Bool(bool),
+}
+
+// Registers provider for the life of the process. Failure only disables events.
+#[cfg(windows)]
+pub fn register() {
+    let mut handle: u64 = 0;
+    let result = unsafe { EventRegister(&PROVIDER_GUID, None, std::ptr::null_mut(), &mut handle) };
+    if result != ERROR_SUCCESS {
+        log::warn!("Failed to register ETW provider: [{}]", result);
+        return;
+    }
+
+    // Provider traits name the provider for consumers that don't read per-event metadata
+    let traits = provider_metadata();
+    unsafe {
+        EventSetInformation(
+            handle,
+            EVENT_PROVIDER_SET_TRAITS,
+            traits.as_ptr() as *const std::ffi::c_void,
+            traits.len() as u32,
+        );
+    }
+    REG_HANDLE.store(handle, Ordering::Relaxed);
+}
+
+#[cfg(not(windows))]
+pub fn register() {}
+
+// Writes TraceLogging event with named fields at informational level
+#[cfg(windows)]
+pub fn write(name: &str, fields: &[(&str, Value)]) {
+    let handle = REG_HANDLE.load(Ordering::Relaxed);
+    if handle == 0 || unsafe { EventProviderEnabled(handle, LEVEL_INFORMATIONAL, 0) } == 0 {
+        return;
+    }
+
+    // Event metadata: size, tags, event name, then each field name and type
+    let mut event_metadata: Vec<u8> = vec![0, 0, 0];
+    push_str(&mut event_metadata, name);
+    for (field_name, value) in fields {
+        push_str(&mut event_metadata, field_name);
+        event_metadata.push(match value {
+            Value::Str(_) => TLG_IN_UNICODESTRING,
+            Value::U64(_) => TLG_IN_UINT64,
+            Value::Bool(_) => TLG_IN_BOOL32,
+        });
+    }
+    let len = event_metadata.len() as u16;
+    event_metadata[..2].copy_from_slice(&len.to_le_bytes());
+
+    // Field data. Strings are NUL terminated UTF-16.
+    let data: Vec<Vec<u8>> = fields
+        .iter()
+        .map(|(_, value)| match value {
+            Value::Str(s) => s
+                .encode_utf16()
+                .chain(Some(0))
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+            Value::U64(n) => n.to_le_bytes().to_vec(),
+            Value::Bool(b) => (*b as u32).to_le_bytes().to_vec(),
+        })
+        .collect();
+
+    let provider_metadata = provider_metadata();
+    let mut descriptors = vec![
+        data_descriptor(&provider_metadata, EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA),
+        data_descriptor(&event_metadata, EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA),
+    ];
+    descriptors.extend(data.iter().map(|bytes| data_descriptor(bytes, 0)));
+
+    let descriptor = EVENT_DESCRIPTOR {
+        id: 0,
+        version: 0,
+        channel: TRACELOGGING_CHANNEL,
+        level: LEVEL_INFORMATIONAL,
+        opcode: 0,
+        task: 0,
+        keyword: 0,
+    };
+    unsafe {
+        EventWriteTransfer(
+            handle,
+            &descriptor,
+            std::ptr::null(),
+            std::ptr::null(),
+            descriptors.len() as u32,
+            descriptors.as_ptr(),
+        );
+    }
+}
+
+#[cfg(not(windows))]
+pub fn write(_name: &str, _fields: &[(&str, Value)]) {}
+
+pub fn find_pdb(uuid: &Uuid, found: bool) {
+    write(
+        "FindPdb",
+        &[
+            ("uuid", Value::Str(&uuid.to_string())),
+            ("found", Value::Bool(found)),
+        ],
+    );
+}
+
+pub fn extract_served(uuid: &Uuid, file: &str, bytes: usize) {
+    write(
+        "ExtractServed",
+        &[
+            ("uuid", Value::Str(&uuid.to_string())),
+            ("file", Value::Str(file)),
+            ("bytes", Value::U64(bytes as u64)),
+        ],
+    );
+}
+
+// Size then NUL terminated provider name
+#[cfg(windows)]
+fn provider_metadata() -> Vec<u8> {
+    let mut metadata: Vec<u8> = vec![0, 0];
+    push_str(&mut metadata, PROVIDER_NAME);
+    let len = metadata.len() as u16;
+    metadata[..2].copy_from_slice(&len.to_le_bytes());
+    metadata
+}
+
+#[cfg(windows)]
+fn push_str(metadata: &mut Vec<u8>, s: &str) {
+    metadata.extend_from_slice(s.as_bytes());
+    metadata.push(0);
+}
+
+#[cfg(windows)]
+fn data_descriptor(bytes: &[u8], descriptor_type: u32) -> EVENT_DATA_DESCRIPTOR {
+    EVENT_DATA_DESCRIPTOR {
+        ptr: bytes.as_ptr() as u64,
+        size: bytes.len() as u32,
+        descriptor_type,
+    }
+}
+
+// Minimal bindings. See evntprov.h and TraceLoggingProvider.h
+#[cfg(windows)]
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+mod win32 {
+    use std::ffi::c_void;
+
+    pub const ERROR_SUCCESS: u32 = 0;
+    pub const EVENT_PROVIDER_SET_TRAITS: u32 = 2;
+    pub const EVENT_DATA_DESCRIPTOR_TYPE_EVENT_METADATA: u32 = 1;
+    pub const EVENT_DATA_DESCRIPTOR_TYPE_PROVIDER_METADATA: u32 = 2;
+    pub const TRACELOGGING_CHANNEL: u8 = 11;
+    pub const LEVEL_INFORMATIONAL: u8 = 4;
+    pub const TLG_IN_UNICODESTRING: u8 = 1;
+    pub const TLG_IN_UINT64: u8 = 10;
+    pub const TLG_IN_BOOL32: u8 = 13;
+
+    #[repr(C)]
+    pub struct GUID {
+        pub data1: u32,
+        pub data2: u16,
+        pub data3: u16,
+        pub data4: [u8; 8],
+    }
+
+    #[repr(C)]
+    pub struct EVENT_DESCRIPTOR {
+        pub id: u16,
+        pub version: u8,
+        pub channel: u8,
+        pub level: u8,
+        pub opcode: u8,
+        pub task: u16,
+        pub keyword: u64,
+    }
+
+    #[repr(C)]
+    pub struct EVENT_DATA_DESCRIPTOR {
+        pub ptr: u64,
+        pub size: u32,
+        pub descriptor_type: u32,
+    }
+
+    pub type EnableCallback = unsafe extern "system" fn(
+        source_id: *const GUID,
+        is_enabled: u32,
+        level: u8,
+        match_any_keyword: u64,
+        match_all_keyword: u64,
+        filter_data: *const c_void,
+        callback_context: *mut c_void,
+    );
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        pub fn EventRegister(
+            provider_id: *const GUID,
+            enable_callback: Option<EnableCallback>,
+            callback_context: *mut c_void,
+            reg_handle: *mut u64,
+        ) -> u32;
+        pub fn EventSetInformation(
+            reg_handle: u64,
+            information_class: u32,
+            event_information: *const c_void,
+            information_length: u32,
+        ) -> u32;
+        pub fn EventProviderEnabled(reg_handle: u64, level: u8, keyword: u64) -> u8;
+        pub fn EventWriteTransfer(
+            reg_handle: u64,
+            event_descriptor: *const EVENT_DESCRIPTOR,
+            activity_id: *const GUID,
+            related_activity_id: *const GUID,
+            user_data_count: u32,
+            user_data: *const EVENT_DATA_DESCRIPTOR,
+        ) -> u32;
+    }
+}
@@ -0,0 +1,574 @@
+// ----------------------------------------------------------------------------
+// Minimal HTTP/1.1 server exposing embedded sources
+//
+// Implements the debuginfod API so gdb/lldb can fetch sources directly:
+//   GET /buildid/<id>/source/<path>
+//   GET /buildid/<id>/debuginfo
+// <id> is either the fts_pdbsrc Uuid or the PDB's GUID+age as used by symbol servers.
+// decode_keys are only used for requests with a token from http_tokens. Other requests get
+// plaintext sources only.
+//
+// Once http_tokens is set, every route serving sources or PDBs requires a token.
+//
+// Also serves an authenticated endpoint for editor integrations and crash viewers:
+//   GET /source/<uuid>/<relpath>
+// Requests must include "Authorization: Bearer <token>" with a token from http_tokens.
+//
+// Scripts and dashboards can query the index as JSON without the TCP protocol:
+//   GET /pdb/<uuid>
+//   GET /pdb/<uuid>/files
+// ----------------------------------------------------------------------------
+use super::etw;
+use super::manifest::{self, Encryption, Manifest};
+use super::pdb_cache::{PdbBytes, PdbCache, SharedPdbCache};
+use super::srcsrv::{unescape_field, SrcSrv, ESCAPE_VARIABLE};
+use super::stats::{SharedUsageStats, UsageStats};
+use super::{index, PdbDb, PdbEntry};
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::*;
+use pdb::PDB;
+use serde::Serialize;
+use std::{
+    io::{BufRead, BufReader, Cursor, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+// Longest request line or header line, and most headers, accepted. Debuggers send far less.
+const MAX_LINE_BYTES: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+}
+
+// Whether request may read sources decrypted with decode_keys
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Access {
+    Token,
+
+    // No http_tokens configured. Plaintext only.
+    Anonymous,
+
+    // http_tokens configured but request has no valid token
+    Denied,
+}
+
+// Result of looking up an embedded file
+enum Source {
+    Found(Vec<u8>),
+    NotFound,
+
+    // Encrypted and the request may not decrypt it
+    Encrypted,
+}
+
+struct Response {
+    status: u16,
+    reason: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+// Body of GET /pdb/<uuid>
+#[derive(Serialize)]
+struct PdbInfo {
+    uuid: Uuid,
+    path: PathBuf,
+    build_id: String,
+
+    // Seconds since unix epoch
+    modified: Option<u64>,
+}
+
+// Element of GET /pdb/<uuid>/files
+#[derive(Serialize)]
+struct PdbFile {
+    original_path: String,
+    relpath: String,
+    encrypted: bool,
+}
+
+impl Response {
+    fn ok(body: Vec<u8>, content_type: &'static str) -> Response {
+        Response {
+            status: 200,
+            reason: "OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn json<T: Serialize>(value: &T) -> Response {
+        match serde_json::to_vec_pretty(value) {
+            Ok(body) => Response::ok(body, "application/json"),
+            Err(e) => {
+                log::warn!("Failed to serialize response: [{}]", e);
+                Response::error(500, "Internal Server Error")
+            }
+        }
+    }
+
+    fn error(status: u16, reason: &'static str) -> Response {
+        Response {
+            status,
+            reason,
+            content_type: "text/plain",
+            body: reason.as_bytes().to_owned(),
+        }
+    }
+}
+
+pub fn serve(
+    address: String,
+    pdbs: PdbDb,
+    decode_keys: Vec<String>,
+    tokens: Vec<String>,
+    cache: SharedPdbCache,
+    usage_stats: SharedUsageStats,
+) -> anyhow::Result<()> {
+    log::info!("Accepting HTTP connections on [{}]", address);
+    let listener = TcpListener::bind(&address)?;
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let pdbs = pdbs.clone();
+                let decode_keys = decode_keys.clone();
+                let tokens = tokens.clone();
+                let cache = cache.clone();
+                let usage_stats = usage_stats.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) =
+                        handle_connection(stream, &pdbs, &decode_keys, &tokens, &cache, &usage_stats)
+                    {
+                        log::warn!("Error handling HTTP request: [{:?}]", e);
+                    }
+                });
+            }
+            Err(e) => log::warn!("Error accepting HTTP listener: [{}]", e),
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    pdbs: &PdbDb,
+    decode_keys: &[String],
+    tokens: &[String],
+    cache: &SharedPdbCache,
+    usage_stats: &SharedUsageStats,
+) -> anyhow::Result<()> {
+    // Otherwise a client that never finishes its request holds a thread forever
+    stream.set_read_timeout(Some(super::CONNECTION_TIMEOUT))?;
+    let request = match read_request(&mut stream)? {
+        Ok(request) => request,
+        Err(response) => {
+            log::info!("HTTP response: [{}] for oversized request", response.status);
+            return write_response(&mut stream, &response, true);
+        }
+    };
+    log::info!("HTTP request: [{} {}]", request.method, request.path);
+
+    let response = match request.method.as_str() {
+        _ if super::is_paused() => Response::error(503, "Service Unavailable"),
+        "GET" | "HEAD" => route(&request, pdbs, decode_keys, tokens, cache, usage_stats),
+        _ => Response::error(405, "Method Not Allowed"),
+    };
+    log::info!("HTTP response: [{}] for [{}]", response.status, request.path);
+    write_response(&mut stream, &response, request.method != "HEAD")
+}
+
+// Connection is closed after every request
+fn write_response(stream: &mut TcpStream, response: &Response, include_body: bool) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.reason,
+        response.content_type,
+        response.body.len()
+    )?;
+    if response.status == 401 {
+        write!(stream, "WWW-Authenticate: Bearer\r\n")?;
+    }
+    write!(stream, "\r\n")?;
+    if include_body {
+        stream.write_all(&response.body)?;
+    }
+    stream.flush()?;
+
+    Ok(())
+}
+
+// Err is the response for a request too large to read
+fn read_request(stream: &mut TcpStream) -> anyhow::Result<Result<Request, Response>> {
+    let mut reader = BufReader::new(stream);
+
+    // Request line: METHOD PATH VERSION. Debuggers using srcsrv URLs may send paths with raw spaces.
+    let request_line = match read_line(&mut reader)? {
+        Some(request_line) => request_line,
+        None => return Ok(Err(Response::error(400, "Bad Request"))),
+    };
+    let request_line = request_line.trim_end();
+    let (method, rest) = request_line
+        .split_once(' ')
+        .ok_or_else(|| anyhow!("Malformed HTTP request line [{}]", request_line))?;
+    let method = method.to_owned();
+    let raw_path = match rest.rsplit_once(' ') {
+        Some((raw_path, version)) if version.starts_with("HTTP/") => raw_path,
+        _ => rest,
+    };
+
+    // Read headers. Only Authorization is used.
+    let mut authorization = None;
+    for header_count in 0.. {
+        let line = match read_line(&mut reader)? {
+            Some(_) if header_count == MAX_HEADERS => None,
+            line => line,
+        };
+        let line = match line {
+            Some(line) => line,
+            None => return Ok(Err(Response::error(431, "Request Header Fields Too Large"))),
+        };
+        if line.is_empty() || line == "\r\n" || line == "\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Authorization") {
+                authorization = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    // Drop query string and decode
+    let raw_path = raw_path.split('?').next().unwrap_or_default();
+    Ok(Ok(Request {
+        method,
+        path: percent_decode(raw_path),
+        authorization,
+    }))
+}
+
+// Reads one line including its newline. Empty at end of stream. None if longer than MAX_LINE_BYTES.
+fn read_line(reader: &mut impl BufRead) -> anyhow::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_BYTES)
+        .read_until(b'\n', &mut line)?;
+    if line.len() as u64 == MAX_LINE_BYTES && !line.ends_with(b"\n") {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8(line)?))
+}
+
+fn route(
+    request: &Request,
+    pdbs: &PdbDb,
+    decode_keys: &[String],
+    tokens: &[String],
+    cache: &SharedPdbCache,
+    usage_stats: &SharedUsageStats,
+) -> Response {
+    let path = request.path.trim_start_matches('/');
+    let segments: Vec<&str> = path.splitn(4, '/').collect();
+
+    // Single check for every route serving sources or PDBs
+    let access = authorize(request, tokens);
+    let serves_sources = matches!(
+        segments.as_slice(),
+        ["source", ..] | ["buildid", ..] | ["pdb", ..]
+    );
+    if serves_sources && access == Access::Denied {
+        return Response::error(401, "Unauthorized");
+    }
+
+    if let Some(rest) = path.strip_prefix("source/") {
+        return route_source(access, rest, pdbs, decode_keys, tokens, cache, usage_stats);
+    }
+
+    match segments.as_slice() {
+        ["buildid", id, "source", source_path] => match find_entry(pdbs, id) {
+            Some((uuid, entry)) => {
+                let decode_keys = keys_for(access, decode_keys);
+                match read_source(&entry.path, source_path, decode_keys, cache) {
+                    Ok(Source::Found(bytes)) => {
+                        UsageStats::record(usage_stats, uuid, bytes.len() as u64);
+                        etw::extract_served(&uuid, source_path, bytes.len());
+                        Response::ok(bytes, "text/plain")
+                    }
+                    Ok(Source::NotFound) => Response::error(404, "Not Found"),
+                    Ok(Source::Encrypted) => Response::error(403, "Forbidden"),
+                    Err(e) => {
+                        log::warn!(
+                            "Failed to read [{}] from [{:?}]: [{:?}]",
+                            source_path,
+                            entry.path,
+                            e
+                        );
+                        Response::error(500, "Internal Server Error")
+                    }
+                }
+            }
+            None => Response::error(404, "Not Found"),
+        },
+        ["buildid", id, "debuginfo"] => match find_entry(pdbs, id) {
+            Some((uuid, entry)) => match std::fs::read(&entry.path) {
+                Ok(bytes) => {
+                    UsageStats::record(usage_stats, uuid, bytes.len() as u64);
+                    Response::ok(bytes, "application/octet-stream")
+                }
+                Err(e) => {
+                    log::warn!("Failed to read [{:?}]: [{}]", entry.path, e);
+                    Response::error(500, "Internal Server Error")
+                }
+            },
+            None => Response::error(404, "Not Found"),
+        },
+        ["pdb", uuid] | ["pdb", uuid, "files"] => {
+            let uuid = match Uuid::parse_str(uuid) {
+                Ok(uuid) => uuid,
+                Err(_) => return Response::error(400, "Bad Request"),
+            };
+            let entry = match index::lookup(pdbs, &uuid) {
+                Some(entry) => entry,
+                None => return Response::error(404, "Not Found"),
+            };
+
+            if segments.len() == 2 {
+                return Response::json(&PdbInfo {
+                    uuid,
+                    path: entry.path,
+                    build_id: entry.build_id,
+                    modified: entry
+                        .modified
+                        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|duration| duration.as_secs()),
+                });
+            }
+            match list_files(&entry.path, cache) {
+                Ok(files) => Response::json(&files),
+                Err(e) => {
+                    log::warn!("Failed to list files in [{:?}]: [{:?}]", entry.path, e);
+                    Response::error(500, "Internal Server Error")
+                }
+            }
+        }
+        _ => Response::error(404, "Not Found"),
+    }
+}
+
+// Serves /source/<uuid>/<relpath>. Only Uuids are accepted and a token is always required.
+fn route_source(
+    access: Access,
+    rest: &str,
+    pdbs: &PdbDb,
+    decode_keys: &[String],
+    tokens: &[String],
+    cache: &SharedPdbCache,
+    usage_stats: &SharedUsageStats,
+) -> Response {
+    if tokens.is_empty() {
+        log::warn!("Rejecting [/source/{}]. No http_tokens are configured.", rest);
+        return Response::error(403, "Forbidden");
+    }
+
+    if access != Access::Token {
+        return Response::error(401, "Unauthorized");
+    }
+
+    let (uuid, relpath) = match rest.split_once('/') {
+        Some((uuid, relpath)) if !relpath.is_empty() => (uuid, relpath),
+        _ => return Response::error(400, "Bad Request"),
+    };
+    let uuid = match Uuid::parse_str(uuid) {
+        Ok(uuid) => uuid,
+        Err(_) => return Response::error(400, "Bad Request"),
+    };
+
+    let entry = index::lookup(pdbs, &uuid);
+    match entry {
+        Some(entry) => match read_source(&entry.path, relpath, decode_keys, cache) {
+            Ok(Source::Found(bytes)) => {
+                UsageStats::record(usage_stats, uuid, bytes.len() as u64);
+                etw::extract_served(&uuid, relpath, bytes.len());
+                Response::ok(bytes, "text/plain; charset=utf-8")
+            }
+            Ok(Source::NotFound) => Response::error(404, "Not Found"),
+            Ok(Source::Encrypted) => Response::error(403, "Forbidden"),
+            Err(e) => {
+                log::warn!("Failed to read [{}] from [{:?}]: [{:?}]", relpath, entry.path, e);
+                Response::error(500, "Internal Server Error")
+            }
+        },
+        None => Response::error(404, "Not Found"),
+    }
+}
+
+fn authorize(request: &Request, tokens: &[String]) -> Access {
+    if tokens.is_empty() {
+        return Access::Anonymous;
+    }
+
+    let token = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim())
+        .unwrap_or_default();
+
+    // Compared in constant time, against every token, so timing doesn't reveal a partial match
+    let is_valid = tokens.iter().fold(subtle::Choice::from(0), |is_valid, valid| {
+        is_valid | valid.as_bytes().ct_eq(token.as_bytes())
+    });
+    if bool::from(is_valid) {
+        Access::Token
+    } else {
+        Access::Denied
+    }
+}
+
+// Keys a request may decrypt with. None for requests without a token, so they only get plaintext.
+fn keys_for(access: Access, decode_keys: &[String]) -> &[String] {
+    match access {
+        Access::Token => decode_keys,
+        Access::Anonymous | Access::Denied => &[],
+    }
+}
+
+// Finds PDB by fts_pdbsrc Uuid or by GUID+age build id.
+// ELF build-ids never match since only PDBs are indexed.
+fn find_entry(pdbs: &PdbDb, id: &str) -> Option<(Uuid, PdbEntry)> {
+    let uuid = Uuid::parse_str(id)
+        .ok()
+        .filter(|uuid| pdbs.lock().unwrap().contains_key(uuid))
+        .or_else(|| {
+            pdbs.lock()
+                .unwrap()
+                .iter()
+                .find(|(_, entry)| entry.build_id.eq_ignore_ascii_case(id))
+                .map(|(uuid, _)| *uuid)
+        })?;
+    index::lookup(pdbs, &uuid).map(|entry| (uuid, entry))
+}
+
+fn read_fts_srcsrv(pdb: &mut PDB<'static, Cursor<PdbBytes>>, pdb_path: &Path) -> anyhow::Result<SrcSrv> {
+    let srcsrv_stream = pdb.named_stream(b"srcsrv")?;
+    let srcsrv = SrcSrv::parse(std::str::from_utf8(&srcsrv_stream)?);
+    if srcsrv.variable("VERCTRL") != Some("fts_pdbsrc") {
+        bail!("PDB [{:?}] srcsrv stream was not written by fts_pdbsrc", pdb_path);
+    }
+    Ok(srcsrv)
+}
+
+// Lists embedded files without reading their contents
+fn list_files(pdb_path: &Path, cache: &SharedPdbCache) -> anyhow::Result<Vec<PdbFile>> {
+    let mut pdb = PdbCache::open(cache, pdb_path)?;
+    let srcsrv = read_fts_srcsrv(&mut pdb, pdb_path)?;
+    let manifest = Manifest::read(&mut pdb)?;
+    let encryption_header = manifest.is_some_and(|manifest| manifest.encryption_header);
+
+    // Source lines are: original path * relative path * filename [* nonce]
+    Ok(srcsrv
+        .unescaped_sources()
+        .into_iter()
+        .filter(|source| source.len() >= 2)
+        .map(|source| PdbFile {
+            encrypted: encryption_header || source.get(3).is_some(),
+            original_path: source[0].clone(),
+            relpath: source[1].clone(),
+        })
+        .collect())
+}
+
+// Reads an embedded file. Path may be the original absolute path or the embedded relative path.
+// Encrypted files are only decrypted if decode_keys isn't empty.
+fn read_source(
+    pdb_path: &Path,
+    source_path: &str,
+    decode_keys: &[String],
+    cache: &SharedPdbCache,
+) -> anyhow::Result<Source> {
+    let mut pdb = PdbCache::open(cache, pdb_path)?;
+    let srcsrv = read_fts_srcsrv(&mut pdb, pdb_path)?;
+
+    // Compare paths ignoring case and separators. Debuggers following srcsrv URLs send the escaped
+    // relative path.
+    let normalize = |path: &str| path.replace('\\', "/").trim_start_matches('/').to_lowercase();
+    let mut queries = vec![normalize(source_path)];
+    if srcsrv.variable(ESCAPE_VARIABLE) == Some("1") {
+        queries.push(normalize(&unescape_field(source_path)));
+    }
+    let sources = srcsrv.unescaped_sources();
+    let source = sources.iter().find(|source| {
+        source
+            .iter()
+            .take(2)
+            .any(|path| queries.contains(&normalize(path)))
+    });
+    let source = match source {
+        Some(source) if source.len() >= 2 => source,
+        _ => return Ok(Source::NotFound),
+    };
+
+    // Read stream and decrypt if needed
+    let manifest = Manifest::read(&mut pdb)?;
+    let stream = manifest::read_file(&mut pdb, manifest.as_ref(), &source[1])?;
+    let srcsrv_nonce = source.get(3).map(String::as_str);
+    let stored = match manifest::read_encryption(&stream, manifest.as_ref(), srcsrv_nonce)? {
+        Some(_) if decode_keys.is_empty() => return Ok(Source::Encrypted),
+        Some(encryption) => decrypt(&stream, &encryption, decode_keys)?,
+        None => stream,
+    };
+    let dictionary = manifest::read_dictionary(&mut pdb, manifest.as_ref())?;
+    let plaintext = manifest::decompress(stored, manifest.as_ref(), dictionary.as_deref())?;
+    Ok(Source::Found(plaintext))
+}
+
+// copy pasted from fts_pdbsrc/src/main.rs for simplicity
+fn decrypt(stored: &[u8], encryption: &Encryption, decode_keys: &[String]) -> anyhow::Result<Vec<u8>> {
+    let nonce = Nonce::from_slice(&encryption.nonce);
+    let (header, encrypted_text) = stored.split_at(encryption.header_len);
+
+    for key_hex in decode_keys {
+        let key_bytes = hex::decode(key_hex)?;
+        let cipher = Aes256Gcm::new(Key::from_slice(&key_bytes));
+        if let Ok(plaintext) = cipher.decrypt(
+            nonce,
+            Payload {
+                msg: encrypted_text,
+                aad: header,
+            },
+        ) {
+            return Ok(plaintext);
+        }
+    }
+
+    bail!("Failed to decrypt with all keys")
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut result: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex_digits = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+            if let Ok(byte) = u8::from_str_radix(hex_digits, 16) {
+                result.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&result).to_string()
+}
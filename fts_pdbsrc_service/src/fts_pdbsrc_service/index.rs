@@ -0,0 +1,73 @@
+// ----------------------------------------------------------------------------
+// Persisted PDB index
+//
+// Scanning a large symbol share takes many minutes. The index is saved on
+// shutdown and loaded on startup so lookups succeed while the startup scan
+// runs in the background.
+//
+// Loaded entries may be stale. Rather than stat every file up front, each
+// lookup compares the file's modification time against the indexed one and
+// re-reads the PDB if it changed or drops the entry if it is gone.
+// ----------------------------------------------------------------------------
+use super::{PdbDb, PdbEntry};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// Returns previously saved index. Empty if none was saved or it could not be read.
+pub fn load() -> HashMap<Uuid, PdbEntry> {
+    let pdbs: HashMap<Uuid, PdbEntry> = index_path()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default();
+    log::info!("Loaded index of [{}] PDBs", pdbs.len());
+    pdbs
+}
+
+pub fn save(pdbs: &HashMap<Uuid, PdbEntry>) {
+    let path = match index_path() {
+        Some(path) => path,
+        None => return,
+    };
+    if let Err(e) = serde_json::to_vec(pdbs)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| {
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, json)?;
+            Ok(())
+        })
+    {
+        log::warn!("Failed to save index to [{:?}]: [{:?}]", path, e);
+    } else {
+        log::info!("Saved index of [{}] PDBs to [{:?}]", pdbs.len(), path);
+    }
+}
+
+// Returns entry for uuid after checking it still matches the file on disk
+pub fn lookup(pdbs: &PdbDb, uuid: &Uuid) -> Option<PdbEntry> {
+    let entry = pdbs.lock().unwrap().get(uuid).cloned()?;
+    let modified = std::fs::metadata(&entry.path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    if modified.is_some() && modified == entry.modified {
+        return Some(entry);
+    }
+
+    // Re-read without holding lock since opening a PDB can be slow
+    log::info!("Indexed PDB [{:?}] changed on disk. Re-reading.", entry.path);
+    let current = super::process_pdb_path(&entry.path);
+    let mut pdbs = pdbs.lock().unwrap();
+    if pdbs.get(uuid).is_some_and(|indexed| indexed.path == entry.path) {
+        pdbs.remove(uuid);
+    }
+    if let Some((current_uuid, current_entry)) = &current {
+        pdbs.insert(*current_uuid, current_entry.clone());
+    }
+    current
+        .filter(|(current_uuid, _)| current_uuid == uuid)
+        .map(|(_, entry)| entry)
+}
+
+fn index_path() -> Option<PathBuf> {
+    super::data_dir().map(|dir| dir.join("index.json"))
+}
@@ -0,0 +1,78 @@
+// ----------------------------------------------------------------------------
+// Minimal job system for work that discovers more work, e.g. walking directories
+//
+// Workers pop items from a shared queue. Each job may push further items, such
+// as subdirectories, which any idle worker can pick up. Workers exit once the
+// queue is empty and no job is still running that could refill it. Result
+// order is unspecified.
+// ----------------------------------------------------------------------------
+use std::panic::AssertUnwindSafe;
+use std::sync::{Condvar, Mutex};
+
+struct Queue<T> {
+    items: Vec<T>,
+
+    // Items queued or being processed. Zero means all work is done.
+    pending: usize,
+}
+
+// Runs job on every item, and every item jobs push, across one worker per logical core
+pub fn run_recursive_job<T, R>(items: Vec<T>, job: impl Fn(T, &mut Vec<T>, &mut Vec<R>) + Sync) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+{
+    let num_workers = num_cpus::get().max(1);
+    let queue = Mutex::new(Queue {
+        pending: items.len(),
+        items,
+    });
+    let changed = Condvar::new();
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results: Vec<R> = Default::default();
+                    let mut new_items: Vec<T> = Default::default();
+                    loop {
+                        // Wait for an item or for all work to finish
+                        let item = {
+                            let mut queue = queue.lock().unwrap();
+                            loop {
+                                if let Some(item) = queue.items.pop() {
+                                    break item;
+                                }
+                                if queue.pending == 0 {
+                                    return results;
+                                }
+                                queue = changed.wait(queue).unwrap();
+                            }
+                        };
+
+                        // Item must be marked done even if job panics or other workers wait forever
+                        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            job(item, &mut new_items, &mut results)
+                        }));
+
+                        let mut queue = queue.lock().unwrap();
+                        queue.pending += new_items.len();
+                        queue.pending -= 1;
+                        queue.items.append(&mut new_items);
+                        changed.notify_all();
+                        drop(queue);
+
+                        if let Err(e) = result {
+                            std::panic::resume_unwind(e);
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    })
+}
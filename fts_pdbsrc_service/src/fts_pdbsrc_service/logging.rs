@@ -0,0 +1,164 @@
+// ----------------------------------------------------------------------------
+// Service log output
+//
+// Logs are free-form text by default. Fleets shipping logs to Splunk or ELK
+// can set "log_format": "Json" to get one JSON object per line instead:
+//
+//   {"timestamp":"...","level":"INFO","target":"...","event":"Detected deletion of []",
+//    "message":"Detected deletion of [...]","uuid":"...","path":"..."}
+//
+// Values are always logged inside [brackets]. event is the message with those
+// values removed so every occurrence of a log statement can be grouped. uuid
+// and path are the first bracketed values that parse as a uuid and a path.
+// Format may change when config reloads. Running with --console logs to stdout.
+// ----------------------------------------------------------------------------
+use log::Log;
+use serde::{Deserialize, Serialize};
+use simplelog::{Config, LevelFilter, WriteLogger};
+use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_format(format: LogFormat) {
+    JSON.store(format == LogFormat::Json, Ordering::Relaxed);
+}
+
+type Output = Box<dyn Write + Send>;
+
+// Writes text through simplelog, or JSON lines to the same output
+struct ServiceLogger {
+    text: Box<WriteLogger<Output>>,
+    json: Mutex<Output>,
+}
+
+// Installs logger writing to file. Level is controlled with log::set_max_level.
+pub fn init(file: File) -> anyhow::Result<()> {
+    let json = Box::new(file.try_clone()?);
+    init_output(Box::new(file), json)
+}
+
+// Installs logger writing to stdout
+pub fn init_console() -> anyhow::Result<()> {
+    init_output(Box::new(std::io::stdout()), Box::new(std::io::stdout()))
+}
+
+fn init_output(text: Output, json: Output) -> anyhow::Result<()> {
+    let text = WriteLogger::new(LevelFilter::Trace, Config::default(), text);
+    let json = Mutex::new(json);
+    log::set_boxed_logger(Box::new(ServiceLogger { text, json }))?;
+    log::set_max_level(LevelFilter::Trace);
+    Ok(())
+}
+
+impl Log for ServiceLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if !JSON.load(Ordering::Relaxed) {
+            self.text.log(record);
+            return;
+        }
+
+        let message = record.args().to_string();
+        let values = bracketed_values(&message);
+        let line = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "level": record.level().to_string(),
+            "target": record.target(),
+            "event": event(&message),
+            "message": message,
+            "uuid": values.iter().find_map(|value| Uuid::parse_str(value).ok()),
+            "path": values.iter().find(|value| is_path(value)),
+        });
+
+        // Nowhere to report failure to write the log
+        let mut file = self.json.lock().unwrap();
+        let _ = serde_json::to_writer(&mut *file, &line);
+        let _ = file.write_all(b"\n");
+    }
+
+    fn flush(&self) {
+        self.text.flush();
+        let _ = self.json.lock().unwrap().flush();
+    }
+}
+
+// Message with bracketed values removed, e.g. "Loaded index of [] PDBs"
+fn event(message: &str) -> String {
+    let mut event = String::with_capacity(message.len());
+    let mut depth = 0;
+    for c in message.chars() {
+        match c {
+            '[' => {
+                if depth == 0 {
+                    event.push(c);
+                }
+                depth += 1;
+            }
+            ']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    event.push(c);
+                }
+            }
+            _ if depth == 0 => event.push(c),
+            _ => (),
+        }
+    }
+    event
+}
+
+// Outermost bracketed values. Debug formatted strings and paths are unquoted.
+fn bracketed_values(message: &str) -> Vec<String> {
+    let mut values: Vec<String> = Default::default();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in message.char_indices() {
+        match c {
+            '[' => {
+                if depth == 0 {
+                    start = idx + 1;
+                }
+                depth += 1;
+            }
+            ']' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let value = &message[start..idx];
+
+                    // Debug escaping of paths is close enough to JSON to parse as a JSON string
+                    let unquoted = if value.starts_with('"') {
+                        serde_json::from_str::<String>(value).ok()
+                    } else {
+                        None
+                    };
+                    values.push(unquoted.unwrap_or_else(|| value.to_owned()));
+                }
+            }
+            _ => (),
+        }
+    }
+    values
+}
+
+fn is_path(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    let drive = bytes.len() > 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    drive || value.starts_with("\\\\") || value.starts_with('/')
+}
@@ -0,0 +1,242 @@
+// ----------------------------------------------------------------------------
+// Manifest stream describing how embedded files are stored.
+// Copy pasted from fts_pdbsrc/src/manifest.rs for simplicity
+//
+// Large files are split across numbered streams since pdbstr and MSF
+// struggle with very large streams. Small files may instead be packed together
+// into shared streams to keep the stream count down. PDBs written before the
+// manifest existed have no manifest stream; every file is a single stream.
+//
+// Relative paths that pdbstr or MSF may mangle are stored under a hashed
+// stream name. The manifest maps the true path to its stream.
+//
+// Files may be zstd compressed, prior to encryption, with a dictionary trained
+// across all embedded files. The dictionary lives in its own stream.
+//
+// Files may also be compressed with zstd or lz4, prior to encryption. Each
+// compressed file starts with a small header naming the algorithm.
+//
+// Encrypted files start with a small header holding the per-file nonce.
+// Older PDBs instead kept the nonce in the srcsrv source line.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use pdb::{Source, PDB};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+pub const STREAM_NAME: &str = "fts_pdbsrc_manifest";
+pub const DICTIONARY_STREAM_NAME: &str = "fts_pdbsrc_zstd_dictionary";
+
+// Compression header is COMPRESSION_MAGIC followed by one byte: 0 none, 1 zstd, 2 lz4
+const COMPRESSION_MAGIC: &[u8; 4] = b"FTSC";
+
+// Encryption header is ENCRYPTION_MAGIC, version byte 1, nonce, then flags byte 0
+const ENCRYPTION_MAGIC: &[u8; 4] = b"FTSE";
+const ENCRYPTION_HEADER_LEN: usize = ENCRYPTION_MAGIC.len() + 1 + NONCE_SIZE + 1;
+pub const NONCE_SIZE: usize = 12;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub files: Vec<ManifestFile>,
+
+    // Files are compressed with dictionary stored in DICTIONARY_STREAM_NAME
+    #[serde(default)]
+    pub zstd_dictionary: bool,
+
+    // Every file starts with a compression header
+    #[serde(default)]
+    pub compression_header: bool,
+
+    // Every file is encrypted and starts with an encryption header. srcsrv holds no nonces.
+    #[serde(default)]
+    pub encryption_header: bool,
+
+    // Hex HMAC-SHA256 of srcsrv stream
+    #[serde(default)]
+    pub srcsrv_mac: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub relpath: String,
+
+    // Base stream name. Absent for files embedded before stream names were recorded.
+    #[serde(default)]
+    pub stream: Option<String>,
+
+    // Number of .partN streams. Zero if file is stored in a single stream.
+    #[serde(default)]
+    pub chunks: u32,
+
+    // Byte range within stream for files packed together with other files
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+impl Manifest {
+    // Returns None for PDBs embedded before manifests were written
+    pub fn read<'s, S: Source<'s> + 's>(pdb: &mut PDB<'s, S>) -> anyhow::Result<Option<Manifest>> {
+        match pdb.named_stream(STREAM_NAME.as_bytes()) {
+            Ok(stream) => Ok(Some(serde_json::from_slice(stream.as_slice())?)),
+            Err(pdb::Error::StreamNameNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn file(&self, relpath: &str) -> Option<&ManifestFile> {
+        self.files.iter().find(|file| file.relpath == relpath)
+    }
+}
+
+// True for every stream fts_pdbsrc writes other than srcsrv
+pub fn is_fts_stream(stream_name: &str) -> bool {
+    stream_name.starts_with("/fts_pdbsrc/") || stream_name.starts_with("fts_pdbsrc_")
+}
+
+pub fn chunk_stream_name(stream_name: &str, chunk: u32) -> String {
+    format!("{}.part{}", stream_name, chunk)
+}
+
+// Name used for every file prior to stream name hashing
+fn legacy_stream_name(relpath: &str) -> String {
+    format!("/fts_pdbsrc/{}", relpath)
+}
+
+// Reads stored bytes for file, reassembling chunks if needed. Bytes may be encrypted.
+pub fn read_file<'s, S: Source<'s> + 's>(
+    pdb: &mut PDB<'s, S>,
+    manifest: Option<&Manifest>,
+    relpath: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let file = manifest.and_then(|manifest| manifest.file(relpath));
+    let chunks = file.map(|file| file.chunks).unwrap_or(0);
+    let base_stream_name = file
+        .and_then(|file| file.stream.clone())
+        .unwrap_or_else(|| legacy_stream_name(relpath));
+
+    if chunks == 0 {
+        let stream = pdb
+            .named_stream(base_stream_name.as_bytes())
+            .with_context(|| format!("Missing stream [{}]", base_stream_name))?;
+
+        // Packed files are a slice of a shared stream
+        let (offset, size) = match file.map(|file| (file.offset, file.size)) {
+            Some((Some(offset), Some(size))) => (offset as usize, size as usize),
+            _ => return Ok(stream.as_slice().to_owned()),
+        };
+        let bytes = stream
+            .as_slice()
+            .get(offset..offset + size)
+            .ok_or_else(|| anyhow!("[{}] exceeds pack stream [{}]", relpath, base_stream_name))?;
+        return Ok(bytes.to_owned());
+    }
+
+    let mut bytes: Vec<u8> = Default::default();
+    for chunk in 0..chunks {
+        let stream_name = chunk_stream_name(&base_stream_name, chunk);
+        let stream = pdb
+            .named_stream(stream_name.as_bytes())
+            .with_context(|| format!("Missing stream [{}]", stream_name))?;
+        bytes.extend_from_slice(stream.as_slice());
+    }
+    Ok(bytes)
+}
+
+// Returns zstd dictionary if files were compressed with one
+pub fn read_dictionary<'s, S: Source<'s> + 's>(
+    pdb: &mut PDB<'s, S>,
+    manifest: Option<&Manifest>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match manifest {
+        Some(manifest) if manifest.zstd_dictionary => {
+            let stream = pdb
+                .named_stream(DICTIONARY_STREAM_NAME.as_bytes())
+                .with_context(|| format!("Missing stream [{}]", DICTIONARY_STREAM_NAME))?;
+            Ok(Some(stream.as_slice().to_owned()))
+        }
+        _ => Ok(None),
+    }
+}
+
+// Nonce and location of ciphertext for an encrypted file
+pub struct Encryption {
+    pub nonce: [u8; NONCE_SIZE],
+
+    // Bytes before ciphertext. Authenticated as associated data.
+    pub header_len: usize,
+}
+
+// Returns None if file is plaintext. Files embedded before encryption headers take their nonce
+// from the srcsrv source line instead.
+pub fn read_encryption(
+    bytes: &[u8],
+    manifest: Option<&Manifest>,
+    srcsrv_nonce: Option<&str>,
+) -> anyhow::Result<Option<Encryption>> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    if !manifest.is_some_and(|manifest| manifest.encryption_header) {
+        return match srcsrv_nonce {
+            Some(nonce_hex) => {
+                hex::decode_to_slice(nonce_hex, &mut nonce)
+                    .with_context(|| format!("Invalid nonce [{}]", nonce_hex))?;
+                Ok(Some(Encryption { nonce, header_len: 0 }))
+            }
+            None => Ok(None),
+        };
+    }
+
+    if bytes.len() < ENCRYPTION_HEADER_LEN || &bytes[..ENCRYPTION_MAGIC.len()] != ENCRYPTION_MAGIC {
+        bail!("Missing encryption header");
+    }
+    let header = &bytes[ENCRYPTION_MAGIC.len()..ENCRYPTION_HEADER_LEN];
+    if header[0] != 1 || header[1 + NONCE_SIZE] != 0 {
+        bail!(
+            "Unknown encryption header version [{}] or flags [{}]",
+            header[0],
+            header[1 + NONCE_SIZE]
+        );
+    }
+    nonce.copy_from_slice(&header[1..1 + NONCE_SIZE]);
+    Ok(Some(Encryption {
+        nonce,
+        header_len: ENCRYPTION_HEADER_LEN,
+    }))
+}
+
+// Undoes compression applied at embed time. Bytes must already be decrypted.
+pub fn decompress(
+    bytes: Vec<u8>,
+    manifest: Option<&Manifest>,
+    dictionary: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    // Files embedded with only a dictionary have no header
+    if !manifest.is_some_and(|manifest| manifest.compression_header) {
+        return match dictionary {
+            Some(dictionary) => decompress_zstd(&bytes, Some(dictionary)),
+            None => Ok(bytes),
+        };
+    }
+
+    let header_len = COMPRESSION_MAGIC.len() + 1;
+    if bytes.len() < header_len || &bytes[..COMPRESSION_MAGIC.len()] != COMPRESSION_MAGIC {
+        bail!("Missing compression header");
+    }
+    let compressed = &bytes[header_len..];
+    match bytes[COMPRESSION_MAGIC.len()] {
+        0 => Ok(compressed.to_owned()),
+        1 => decompress_zstd(compressed, dictionary),
+        2 => lz4_flex::decompress_size_prepended(compressed)
+            .map_err(|e| anyhow!("Failed to decompress lz4: [{}]", e)),
+        id => bail!("Unknown compression id [{}]", id),
+    }
+}
+
+fn decompress_zstd(bytes: &[u8], dictionary: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+    let mut plaintext: Vec<u8> = Default::default();
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(bytes, dictionary.unwrap_or(&[]))?;
+    decoder.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
@@ -0,0 +1,129 @@
+// ----------------------------------------------------------------------------
+// LRU cache of PDB contents for server-side extraction
+//
+// Reading a PDB from disk for every request is wasteful when a debugger asks
+// for many files from the same PDB. Recently used PDBs are kept in memory,
+// bounded by count and total bytes. Entries are invalidated by the file
+// watcher and also dropped if the file's size or modification time changes.
+// ----------------------------------------------------------------------------
+use serde::{Deserialize, Serialize};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdbCacheConfig {
+    pub max_count: usize,
+    pub max_bytes: u64,
+}
+
+impl Default for PdbCacheConfig {
+    fn default() -> PdbCacheConfig {
+        PdbCacheConfig {
+            max_count: 16,
+            max_bytes: 512 * 1024 * 1024,
+        }
+    }
+}
+
+// Cheaply cloneable PDB contents. Cursor over this is a pdb::Source.
+#[derive(Clone, Debug)]
+pub struct PdbBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for PdbBytes {
+    fn as_ref(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+struct CacheEntry {
+    path: PathBuf,
+    bytes: PdbBytes,
+    len: u64,
+    modified: SystemTime,
+}
+
+#[derive(Default)]
+pub struct PdbCache {
+    config: PdbCacheConfig,
+
+    // Least recently used first
+    entries: Vec<CacheEntry>,
+}
+
+pub type SharedPdbCache = Arc<Mutex<PdbCache>>;
+
+impl PdbCache {
+    pub fn new(config: PdbCacheConfig) -> SharedPdbCache {
+        Arc::new(Mutex::new(PdbCache {
+            config,
+            entries: Default::default(),
+        }))
+    }
+
+    // Returns PDB contents from cache or reads them from disk. Path may be a compressed .pd_.
+    pub fn open(cache: &SharedPdbCache, path: &Path) -> anyhow::Result<pdb::PDB<'static, Cursor<PdbBytes>>> {
+        let bytes = PdbCache::get_or_load(cache, path)?;
+        Ok(pdb::PDB::open(Cursor::new(bytes))?)
+    }
+
+    fn get_or_load(cache: &SharedPdbCache, path: &Path) -> anyhow::Result<PdbBytes> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata.modified()?;
+
+        // Check cache
+        {
+            let mut cache = cache.lock().unwrap();
+            if let Some(idx) = cache.entries.iter().position(|entry| entry.path == path) {
+                let entry = cache.entries.remove(idx);
+                if entry.len == metadata.len() && entry.modified == modified {
+                    let bytes = entry.bytes.clone();
+                    cache.entries.push(entry);
+                    return Ok(bytes);
+                }
+                log::info!("Cached PDB [{:?}] changed on disk", path);
+            }
+        }
+
+        // Load without holding lock so other requests aren't blocked on disk
+        let bytes = PdbBytes(Arc::new(std::fs::read(super::expand_if_compressed(path)?)?));
+        let len = bytes.0.len() as u64;
+
+        let mut cache = cache.lock().unwrap();
+        if len <= cache.config.max_bytes {
+            cache.entries.retain(|entry| entry.path != path);
+            cache.entries.push(CacheEntry {
+                path: path.to_owned(),
+                bytes: bytes.clone(),
+                len: metadata.len(),
+                modified,
+            });
+            cache.evict();
+        }
+
+        Ok(bytes)
+    }
+
+    // Drops entry for path. Called by file watcher.
+    pub fn invalidate(&mut self, path: &Path) {
+        self.entries.retain(|entry| entry.path != path);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict(&mut self) {
+        let total_bytes =
+            |entries: &[CacheEntry]| -> u64 { entries.iter().map(|entry| entry.bytes.0.len() as u64).sum() };
+
+        while self.entries.len() > self.config.max_count
+            || (self.entries.len() > 1 && total_bytes(&self.entries) > self.config.max_bytes)
+        {
+            let entry = self.entries.remove(0);
+            log::trace!("Evicting cached PDB [{:?}]", entry.path);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// ----------------------------------------------------------------------------
+// Cheap pre-filter for PDBs during scanning
+//
+// Opening every candidate with pdb::PDB just to look for the srcsrv stream is
+// expensive on large symbol shares. This reads only the MSF superblock, stream
+// directory, and PDB info stream then checks the named stream table for
+// fts_pdbsrc streams. Anything unexpected falls back to the full open.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+const MSF_MAGIC: &[u8; 32] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+const SUPERBLOCK_SIZE: usize = 56;
+const PDB_INFO_STREAM: usize = 1;
+const NIL_STREAM_SIZE: u32 = 0xFFFF_FFFF;
+
+// Upper bound on PDB info stream size. Real streams are a few hundred bytes plus stream names.
+const MAX_INFO_STREAM_SIZE: u32 = 16 * 1024 * 1024;
+
+// Returns false only if the PDB definitely has no fts_pdbsrc streams
+pub fn is_likely_fts_pdb(path: &Path) -> bool {
+    match read_stream_names(path) {
+        Ok(names) => contains(&names, b"/fts_pdbsrc/") || contains(&names, b"fts_pdbsrc_manifest"),
+        Err(e) => {
+            log::trace!(
+                "Quick scan of [{:?}] failed, falling back to full open: [{:?}]",
+                path,
+                e
+            );
+            true
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let slice = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow!("Unexpected end of data at offset [{}]", offset))?;
+    Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+fn read_blocks(file: &mut File, blocks: &[u32], block_size: u32, len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; blocks.len() * block_size as usize];
+    for (idx, block) in blocks.iter().enumerate() {
+        let start = idx * block_size as usize;
+        file.seek(SeekFrom::Start(*block as u64 * block_size as u64))?;
+        file.read_exact(&mut bytes[start..start + block_size as usize])?;
+    }
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+// Returns raw string buffer of PDB info stream's named stream map
+fn read_stream_names(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+
+    // Superblock
+    let mut superblock = [0u8; SUPERBLOCK_SIZE];
+    file.read_exact(&mut superblock)?;
+    if &superblock[..32] != MSF_MAGIC {
+        bail!("Not an MSF 7.00 file");
+    }
+    let block_size = read_u32(&superblock, 32)?;
+    let num_directory_bytes = read_u32(&superblock, 44)?;
+    let block_map_addr = read_u32(&superblock, 52)?;
+    if !block_size.is_power_of_two() || !(512..=32768).contains(&block_size) {
+        bail!("Unexpected block size [{}]", block_size);
+    }
+
+    // Block map lists the blocks holding the stream directory
+    let num_directory_blocks = num_directory_bytes.div_ceil(block_size) as usize;
+    if num_directory_blocks * 4 > block_size as usize {
+        bail!("Stream directory too large for quick scan");
+    }
+    let block_map = read_blocks(&mut file, &[block_map_addr], block_size, num_directory_blocks * 4)?;
+    let directory_blocks: Vec<u32> = (0..num_directory_blocks)
+        .map(|idx| read_u32(&block_map, idx * 4))
+        .collect::<anyhow::Result<_>>()?;
+    let directory = read_blocks(
+        &mut file,
+        &directory_blocks,
+        block_size,
+        num_directory_bytes as usize,
+    )?;
+
+    // Directory: num_streams, stream sizes, then block list for each stream
+    let num_streams = read_u32(&directory, 0)? as usize;
+    if num_streams <= PDB_INFO_STREAM {
+        bail!("Missing PDB info stream");
+    }
+    let num_blocks = |size: u32| -> usize {
+        if size == NIL_STREAM_SIZE {
+            0
+        } else {
+            size.div_ceil(block_size) as usize
+        }
+    };
+    let stream_size = |idx: usize| read_u32(&directory, 4 + idx * 4);
+
+    let mut block_list_offset = 4 + num_streams * 4;
+    for idx in 0..PDB_INFO_STREAM {
+        block_list_offset += num_blocks(stream_size(idx)?) * 4;
+    }
+    let info_size = stream_size(PDB_INFO_STREAM)?;
+    if info_size == NIL_STREAM_SIZE || info_size > MAX_INFO_STREAM_SIZE {
+        bail!("Unexpected PDB info stream size [{}]", info_size);
+    }
+    let info_blocks: Vec<u32> = (0..num_blocks(info_size))
+        .map(|idx| read_u32(&directory, block_list_offset + idx * 4))
+        .collect::<anyhow::Result<_>>()?;
+    let info = read_blocks(&mut file, &info_blocks, block_size, info_size as usize)?;
+
+    // PDB info stream: version, signature, age, guid, then named stream map string buffer
+    let names_size = read_u32(&info, 28)? as usize;
+    let names = info
+        .get(32..32 + names_size)
+        .ok_or_else(|| anyhow!("Named stream map exceeds PDB info stream"))?;
+    Ok(names.to_owned())
+}
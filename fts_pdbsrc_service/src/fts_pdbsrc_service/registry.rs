@@ -0,0 +1,246 @@
+// ----------------------------------------------------------------------------
+// Service settings deployed through Group Policy
+//
+// Some IT departments push configuration as registry values rather than files
+// next to executables. If the policy key exists its values override the JSON
+// config. Values that are absent fall back to the JSON config. Each service
+// instance reads the key named for it, SOFTWARE\Policies\fts\<service name>.
+//
+//   Paths           REG_MULTI_SZ   Directories to scan, one per line
+//   FollowSymlinks  REG_DWORD      Applies to every entry in Paths
+//   LogLevel        REG_SZ         Off, Error, Warn, Info, Debug, or Trace
+//   Port            REG_DWORD      Port for msgpack protocol
+//
+// There is no policy outside Windows. Only the JSON config is read.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+#[cfg(windows)]
+use std::ptr::null_mut;
+#[cfg(windows)]
+use win32::*;
+
+pub fn policy_key() -> String {
+    format!(r"SOFTWARE\Policies\fts\{}", super::launch_args().name)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PolicyConfig {
+    pub paths: Option<Vec<String>>,
+    pub follow_symlinks: bool,
+    pub log_level: Option<String>,
+    pub port: Option<u32>,
+}
+
+// Open handle to policy key under HKEY_LOCAL_MACHINE
+#[cfg(windows)]
+struct PolicyKey(HKEY);
+
+#[cfg(windows)]
+impl PolicyKey {
+    // Returns None if key does not exist
+    fn open() -> anyhow::Result<Option<PolicyKey>> {
+        let wide_key = to_wide(&policy_key());
+        let mut hkey: HKEY = null_mut();
+        let result = unsafe {
+            RegOpenKeyExW(
+                HKEY_LOCAL_MACHINE,
+                wide_key.as_ptr(),
+                0,
+                KEY_READ | KEY_NOTIFY,
+                &mut hkey,
+            )
+        };
+        match result {
+            ERROR_SUCCESS => Ok(Some(PolicyKey(hkey))),
+            ERROR_FILE_NOT_FOUND => Ok(None),
+            _ => bail!(
+                "Failed to open [HKLM\\{}]: [{}]",
+                policy_key(),
+                std::io::Error::from_raw_os_error(result as i32)
+            ),
+        }
+    }
+
+    // Returns value type and raw bytes, or None if value does not exist
+    fn query(&self, name: &str) -> anyhow::Result<Option<(u32, Vec<u8>)>> {
+        let wide_name = to_wide(name);
+        let mut value_type: u32 = 0;
+        let mut len: u32 = 0;
+
+        unsafe {
+            // First call gets size, second call gets data
+            let mut result = RegQueryValueExW(
+                self.0,
+                wide_name.as_ptr(),
+                null_mut(),
+                &mut value_type,
+                null_mut(),
+                &mut len,
+            );
+            let mut data: Vec<u8> = vec![0; len as usize];
+            if result == ERROR_SUCCESS {
+                result = RegQueryValueExW(
+                    self.0,
+                    wide_name.as_ptr(),
+                    null_mut(),
+                    &mut value_type,
+                    data.as_mut_ptr(),
+                    &mut len,
+                );
+                data.truncate(len as usize);
+            }
+
+            match result {
+                ERROR_SUCCESS => Ok(Some((value_type, data))),
+                ERROR_FILE_NOT_FOUND => Ok(None),
+                _ => bail!(
+                    "Failed to read registry value [{}]: [{}]",
+                    name,
+                    std::io::Error::from_raw_os_error(result as i32)
+                ),
+            }
+        }
+    }
+
+    fn query_strings(&self, name: &str) -> anyhow::Result<Option<Vec<String>>> {
+        match self.query(name)? {
+            Some((REG_SZ, data)) | Some((REG_EXPAND_SZ, data)) | Some((REG_MULTI_SZ, data)) => {
+                let wide: Vec<u16> = data
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+                    .collect();
+                Ok(Some(
+                    wide.split(|c| *c == 0)
+                        .filter(|s| !s.is_empty())
+                        .map(String::from_utf16_lossy)
+                        .collect(),
+                ))
+            }
+            Some((value_type, _)) => bail!("Registry value [{}] has unexpected type [{}]", name, value_type),
+            None => Ok(None),
+        }
+    }
+
+    fn query_dword(&self, name: &str) -> anyhow::Result<Option<u32>> {
+        match self.query(name)? {
+            Some((REG_DWORD, data)) if data.len() == 4 => {
+                Ok(Some(u32::from_le_bytes([data[0], data[1], data[2], data[3]])))
+            }
+            Some((value_type, _)) => bail!("Registry value [{}] has unexpected type [{}]", name, value_type),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for PolicyKey {
+    fn drop(&mut self) {
+        unsafe {
+            RegCloseKey(self.0);
+        }
+    }
+}
+
+// Returns None if policy key does not exist
+#[cfg(windows)]
+pub fn read() -> anyhow::Result<Option<PolicyConfig>> {
+    let key = match PolicyKey::open()? {
+        Some(key) => key,
+        None => return Ok(None),
+    };
+
+    Ok(Some(PolicyConfig {
+        paths: key.query_strings("Paths")?,
+        follow_symlinks: key.query_dword("FollowSymlinks")?.unwrap_or(0) != 0,
+        log_level: key
+            .query_strings("LogLevel")?
+            .and_then(|strings| strings.into_iter().next()),
+        port: key.query_dword("Port")?,
+    }))
+}
+
+#[cfg(not(windows))]
+pub fn read() -> anyhow::Result<Option<PolicyConfig>> {
+    Ok(None)
+}
+
+// Blocks until a value under policy key changes. Errors if key does not exist.
+#[cfg(windows)]
+pub fn wait_for_change() -> anyhow::Result<()> {
+    let key = PolicyKey::open()?.ok_or_else(|| anyhow!("[HKLM\\{}] does not exist", policy_key()))?;
+    let result = unsafe {
+        RegNotifyChangeKeyValue(
+            key.0,
+            1,
+            REG_NOTIFY_CHANGE_NAME | REG_NOTIFY_CHANGE_LAST_SET,
+            null_mut(),
+            0,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        bail!(
+            "Failed to watch [HKLM\\{}]: [{}]",
+            policy_key(),
+            std::io::Error::from_raw_os_error(result as i32)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn wait_for_change() -> anyhow::Result<()> {
+    bail!("Registry policy is only supported on Windows")
+}
+
+#[cfg(windows)]
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+// Minimal registry bindings. See winreg.h
+#[cfg(windows)]
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
+mod win32 {
+    use std::ffi::c_void;
+
+    pub type HKEY = *mut c_void;
+
+    pub const HKEY_LOCAL_MACHINE: HKEY = 0x8000_0002usize as HKEY;
+    pub const ERROR_SUCCESS: u32 = 0;
+    pub const ERROR_FILE_NOT_FOUND: u32 = 2;
+    pub const KEY_READ: u32 = 0x2_0019;
+    pub const KEY_NOTIFY: u32 = 0x0010;
+    pub const REG_SZ: u32 = 1;
+    pub const REG_EXPAND_SZ: u32 = 2;
+    pub const REG_DWORD: u32 = 4;
+    pub const REG_MULTI_SZ: u32 = 7;
+    pub const REG_NOTIFY_CHANGE_NAME: u32 = 0x0001;
+    pub const REG_NOTIFY_CHANGE_LAST_SET: u32 = 0x0004;
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        pub fn RegOpenKeyExW(
+            key: HKEY,
+            sub_key: *const u16,
+            options: u32,
+            sam_desired: u32,
+            result: *mut HKEY,
+        ) -> u32;
+        pub fn RegQueryValueExW(
+            key: HKEY,
+            value_name: *const u16,
+            reserved: *mut u32,
+            value_type: *mut u32,
+            data: *mut u8,
+            data_len: *mut u32,
+        ) -> u32;
+        pub fn RegNotifyChangeKeyValue(
+            key: HKEY,
+            watch_subtree: i32,
+            notify_filter: u32,
+            event: *mut c_void,
+            asynchronous: i32,
+        ) -> u32;
+        pub fn RegCloseKey(key: HKEY) -> u32;
+    }
+}
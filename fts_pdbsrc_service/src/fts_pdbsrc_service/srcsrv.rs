@@ -0,0 +1,117 @@
+// ----------------------------------------------------------------------------
+// Parsing for srcsrv streams. Copy pasted from fts_pdbsrc/src/srcsrv.rs for simplicity
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default)]
+pub struct SrcSrv {
+    pub ini: Vec<(String, String)>,
+    pub variables: Vec<(String, String)>,
+    pub sources: Vec<Vec<String>>,
+}
+
+impl SrcSrv {
+    pub fn parse(text: &str) -> SrcSrv {
+        enum Section {
+            None,
+            Ini,
+            Variables,
+            Sources,
+        }
+
+        let mut result = SrcSrv::default();
+        let mut section = Section::None;
+
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+
+            // Section headers look like "SRCSRV: ini -------"
+            if let Some(header) = line.strip_prefix("SRCSRV:") {
+                let header = header.trim_start().to_lowercase();
+                section = if header.starts_with("ini") {
+                    Section::Ini
+                } else if header.starts_with("variables") {
+                    Section::Variables
+                } else if header.starts_with("source files") {
+                    Section::Sources
+                } else {
+                    Section::None
+                };
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let split_variable = |line: &str| -> Option<(String, String)> {
+                let idx = line.find('=')?;
+                Some((line[..idx].trim().to_owned(), line[idx + 1..].to_owned()))
+            };
+
+            match section {
+                Section::Ini => result.ini.extend(split_variable(line)),
+                Section::Variables => result.variables.extend(split_variable(line)),
+                Section::Sources => result
+                    .sources
+                    .push(line.split('*').map(|s| s.to_owned()).collect()),
+                Section::None => (),
+            }
+        }
+
+        result
+    }
+
+    // Variable names are case insensitive
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.ini
+            .iter()
+            .chain(self.variables.iter())
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Returns source lines with fts_pdbsrc escaping removed from relative path and filename
+    pub fn unescaped_sources(&self) -> Vec<Vec<String>> {
+        let is_escaped = self.variable(ESCAPE_VARIABLE) == Some("1");
+        self.sources
+            .iter()
+            .map(|source| {
+                source
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, field)| match idx {
+                        1 | 2 if is_escaped => unescape_field(field),
+                        _ => field.clone(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// Set by fts_pdbsrc when relative path and filename fields are escaped
+pub const ESCAPE_VARIABLE: &str = "FTS_PDBSRC_ESCAPE";
+
+pub fn unescape_field(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(idx) = rest.find('@') {
+        result.push_str(&rest[..idx]);
+        let escaped = rest
+            .get(idx + 1..idx + 3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                result.push(byte as char);
+                rest = &rest[idx + 3..];
+            }
+            None => {
+                result.push('@');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,143 @@
+// ----------------------------------------------------------------------------
+// Per-PDB usage statistics
+//
+// Symbol stores grow without bound. Counting requests per PDB shows owners
+// which builds are actually being debugged so the rest can be pruned. Counts
+// are kept in memory and periodically saved so they survive restarts.
+//
+// Lookups over the msgpack and JSON protocols count as requests. Bytes served
+// counts HTTP responses and FetchFile replies. Usually extract_one reads the
+// PDB itself so those bytes aren't seen.
+// ----------------------------------------------------------------------------
+use super::PdbEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Usage {
+    requests: u64,
+    last_access: Option<u64>,
+    bytes_served: u64,
+}
+
+// Usage of one indexed or previously requested PDB
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PdbStats {
+    pub uuid: Uuid,
+
+    // None if PDB is no longer indexed
+    pub path: Option<PathBuf>,
+
+    pub requests: u64,
+
+    // Unix seconds. None if never requested.
+    pub last_access: Option<u64>,
+
+    pub bytes_served: u64,
+}
+
+#[derive(Default)]
+pub struct UsageStats {
+    usage: HashMap<Uuid, Usage>,
+
+    // Changed since last save
+    dirty: bool,
+}
+
+pub type SharedUsageStats = Arc<Mutex<UsageStats>>;
+
+impl UsageStats {
+    // Loads previously saved stats and starts a thread that saves them periodically
+    pub fn load() -> SharedUsageStats {
+        let path = stats_path();
+        let usage: HashMap<Uuid, Usage> = path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        log::info!("Loaded usage stats for [{}] PDBs", usage.len());
+
+        let stats = Arc::new(Mutex::new(UsageStats { usage, dirty: false }));
+        if let Some(path) = path {
+            let stats = stats.clone();
+            std::thread::spawn(move || loop {
+                std::thread::sleep(SAVE_INTERVAL);
+                let json = {
+                    let mut stats = stats.lock().unwrap();
+                    if !stats.dirty {
+                        continue;
+                    }
+                    stats.dirty = false;
+                    serde_json::to_vec(&stats.usage)
+                };
+                if let Err(e) = json.map_err(anyhow::Error::from).and_then(|json| {
+                    std::fs::create_dir_all(path.parent().unwrap())?;
+                    std::fs::write(&path, json)?;
+                    Ok(())
+                }) {
+                    log::warn!("Failed to save usage stats to [{:?}]: [{:?}]", path, e);
+                }
+            });
+        }
+
+        stats
+    }
+
+    pub fn record(stats: &SharedUsageStats, uuid: Uuid, bytes_served: u64) {
+        let mut stats = stats.lock().unwrap();
+        let usage = stats.usage.entry(uuid).or_default();
+        usage.requests += 1;
+        usage.last_access = Some(unix_now());
+        usage.bytes_served += bytes_served;
+        stats.dirty = true;
+    }
+
+    // Returns stats for every indexed PDB, including those never requested, plus any previously
+    // requested PDB that is no longer indexed. Most recently used first.
+    pub fn snapshot(&self, pdbs: &HashMap<Uuid, PdbEntry>) -> Vec<PdbStats> {
+        let mut result: Vec<PdbStats> = pdbs
+            .iter()
+            .map(|(uuid, entry)| (uuid, Some(entry.path.clone())))
+            .chain(
+                self.usage
+                    .keys()
+                    .filter(|uuid| !pdbs.contains_key(uuid))
+                    .map(|uuid| (uuid, None)),
+            )
+            .map(|(uuid, path)| {
+                let usage = self.usage.get(uuid).cloned().unwrap_or_default();
+                PdbStats {
+                    uuid: *uuid,
+                    path,
+                    requests: usage.requests,
+                    last_access: usage.last_access,
+                    bytes_served: usage.bytes_served,
+                }
+            })
+            .collect();
+
+        result.sort_by(|a, b| {
+            b.last_access
+                .cmp(&a.last_access)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        result
+    }
+}
+
+fn stats_path() -> Option<PathBuf> {
+    super::data_dir().map(|dir| dir.join("stats.json"))
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
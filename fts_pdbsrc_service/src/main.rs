@@ -1,18 +1,26 @@
 use anyhow::*;
 
-#[cfg(windows)]
 fn main() -> anyhow::Result<()> {
-    std::env::vars().for_each(|v| println!("cargo:warning=Envvar: {:?}", v));
-
-    // Init logging
-    init_logging()?;
+    // Init logging. Only Windows has a service control manager. Elsewhere always run in the foreground
+    // under systemd, launchd, etc.
+    let console = fts_pdbsrc_service::launch_args().console || cfg!(not(windows));
+    if console {
+        fts_pdbsrc_service::logging::init_console()?;
+    } else {
+        init_logging()?;
+    }
 
     std::panic::set_hook(Box::new(|panic_info| {
         log::error!("panic occurred: [{:?}]", panic_info);
     }));
 
-    // Run program (convert enum to anyhow::error)
-    match fts_pdbsrc_service::run() {
+    // Run program
+    let result = if console {
+        fts_pdbsrc_service::run_console()
+    } else {
+        fts_pdbsrc_service::run()
+    };
+    match result {
         Ok(_) => Ok(()),
         Err(e) => {
             log::error!("Unexpected error: [{}]", e);
@@ -23,9 +31,9 @@ fn main() -> anyhow::Result<()> {
 
 fn init_logging() -> anyhow::Result<()> {
     // Find log dir
-    let log_dir = dirs::data_local_dir()
+    let log_dir = fts_pdbsrc_service::data_dir()
         .expect("Failed to local dir")
-        .join("fts/fts_pdbsrc_service/logs");
+        .join("logs");
     std::fs::create_dir_all(&log_dir)?;
 
     // Delete old log files
@@ -46,92 +54,337 @@ fn init_logging() -> anyhow::Result<()> {
 
     // Create new log file
     let local_time = chrono::Local::now();
-    let new_log_path = log_dir.join(local_time.format("fts_pdbsrc_service-%Y-%m-%d--%Hh-%Mm-%Ss.log").to_string());
+    let new_log_path = log_dir.join(
+        local_time
+            .format("fts_pdbsrc_service-%Y-%m-%d--%Hh-%Mm-%Ss.log")
+            .to_string(),
+    );
 
-    // Initialize simple log
-    use simplelog::*;
-    CombinedLogger::init(vec![WriteLogger::new(
-        LevelFilter::Trace,
-        Config::default(),
-        std::fs::File::create(new_log_path)?,
-    )])?;
+    // Text until config picks a format
+    fts_pdbsrc_service::logging::init(std::fs::File::create(new_log_path)?)?;
 
     log::info!("Created on: {}", local_time);
 
     Ok(())
 }
 
-#[cfg(not(windows))]
-fn main() {
-    panic!("This program is only intended to run on Windows.");
-}
-
-#[cfg(windows)]
 mod fts_pdbsrc_service {
+    mod console;
+    mod etw;
+    mod http;
+    mod index;
+    mod job_system;
+    pub mod logging;
+    mod manifest;
+    mod pdb_cache;
+    mod quick_scan;
+    mod registry;
+    mod srcsrv;
+    mod stats;
+
     use anyhow::*;
     use serde::{Deserialize, Serialize};
+    use stats::{SharedUsageStats, UsageStats};
     use std::{
-        collections::HashMap,
-        ffi::OsString,
+        collections::{HashMap, HashSet},
         fs::File,
         io::{Read, Write},
         net::{TcpListener, TcpStream},
         path::{Path, PathBuf},
-        sync::{mpsc, Arc, Mutex},
-        time::Duration,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+            mpsc, Arc, Mutex,
+        },
+        time::{Duration, Instant, SystemTime},
     };
+    use structopt::StructOpt;
     use uuid::Uuid;
 
+    #[cfg(windows)]
+    use std::ffi::OsString;
+    #[cfg(windows)]
     use windows_service::{
         define_windows_service,
         service::{
             ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
         },
         service_control_handler::{self, ServiceControlHandlerResult},
-        service_dispatcher, Result,
+        service_dispatcher,
     };
 
-    const SERVICE_NAME: &str = "fts_pdbsrc_service";
+    #[cfg(windows)]
     const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
 
+    // Set by `fts_pdbsrc install_service` so several instances can run side by side, each with its own
+    // config, port, and data. Defaults match an instance installed without options.
+    #[derive(Debug, StructOpt)]
+    pub struct LaunchArgs {
+        #[structopt(long, default_value = "fts_pdbsrc_service")]
+        pub name: String,
+
+        #[structopt(long, parse(from_os_str))]
+        pub config: Option<PathBuf>,
+
+        // Overrides port from config and registry policy
+        #[structopt(long)]
+        pub port: Option<u16>,
+
+        // Run in the current console instead of under the service control manager. Logs go to stdout.
+        #[structopt(long)]
+        pub console: bool,
+    }
+
+    pub fn launch_args() -> &'static LaunchArgs {
+        static LAUNCH_ARGS: std::sync::OnceLock<LaunchArgs> = std::sync::OnceLock::new();
+        LAUNCH_ARGS.get_or_init(LaunchArgs::from_args)
+    }
+
+    // Index, usage stats, and logs. Named for the instance.
+    pub fn data_dir() -> Option<PathBuf> {
+        dirs::data_local_dir().map(|dir| dir.join("fts").join(&launch_args().name))
+    }
+
+    // Environment variable naming service config. --config takes precedence.
+    const CONFIG_VARIABLE: &str = "FTS_PDBSRC_SERVICE_CONFIG";
+    const CONFIG_FILENAME: &str = "fts_pdbsrc_service_config.json";
+
+    // First of --config, FTS_PDBSRC_SERVICE_CONFIG, per-user config in %APPDATA%\fts if it exists, and
+    // config beside the exe. Per-user is for the account the service runs as, e.g. `serve` users.
+    fn config_path() -> anyhow::Result<PathBuf> {
+        let explicit_config_path = launch_args().config.clone().or_else(|| {
+            std::env::var_os(CONFIG_VARIABLE)
+                .filter(|var| !var.is_empty())
+                .map(PathBuf::from)
+        });
+        if let Some(config_path) = explicit_config_path {
+            return Ok(config_path);
+        }
+
+        match dirs::config_dir().map(|dir| dir.join("fts").join(CONFIG_FILENAME)) {
+            Some(user_config_path) if user_config_path.exists() => Ok(user_config_path),
+            _ => Ok(std::env::current_exe()?.with_file_name(CONFIG_FILENAME)),
+        }
+    }
+
+    // Reported by Status requests
+    static START_TIME: std::sync::OnceLock<Instant> = std::sync::OnceLock::new();
+
+    // Set by the Pause service control, e.g. during symbol share maintenance. Scanning stops and requests
+    // are refused but the index is kept in memory. Continue reloads to pick up anything missed.
+    static PAUSED: AtomicBool = AtomicBool::new(false);
+
+    pub fn is_paused() -> bool {
+        PAUSED.load(Ordering::Relaxed)
+    }
+
+    // Service controls forwarded from the control handler to the service worker loop. Only Stop is sent
+    // outside Windows.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    enum ControlRequest {
+        Stop,
+        Pause,
+        Continue,
+    }
+
+    // Reported to the service control manager as the worker loop changes state
+    enum WorkerState {
+        Running,
+        Paused,
+    }
+
     #[derive(Clone, Debug, Serialize, Deserialize)]
     struct Config {
         pub paths: Vec<ConfigPath>,
         pub log_level: simplelog::LevelFilter,
+
+        // Text, or Json for one object per line
+        #[serde(default)]
+        pub log_format: logging::LogFormat,
+
+        // Address for debuginfod compatible HTTP server. e.g. "localhost:8002"
+        #[serde(default)]
+        pub http_address: Option<String>,
+
+        // Keys used to decrypt sources served over HTTP
+        #[serde(default)]
+        pub decode_keys: Vec<String>,
+
+        // Bearer tokens accepted by the authenticated /source/ HTTP endpoint
+        #[serde(default)]
+        pub http_tokens: Vec<String>,
+
+        // Bounds for PDBs kept in memory for HTTP requests
+        #[serde(default)]
+        pub pdb_cache: pdb_cache::PdbCacheConfig,
+
+        // Address for length-prefixed JSON protocol used by non-Rust tools. e.g. "localhost:23686"
+        #[serde(default)]
+        pub json_address: Option<String>,
+
+        // Port for msgpack protocol. Clients must set a matching service_port.
+        #[serde(default = "Config::default_port")]
+        pub port: u16,
+
+        // Minutes between full rescans of paths. Catches PDBs missed while file watches were broken,
+        // which is common on network shares. None disables.
+        #[serde(default)]
+        pub rescan_interval_minutes: Option<u64>,
+
+        // Which copy to index when the same PDB is found at several paths
+        #[serde(default)]
+        pub duplicate_policy: DuplicatePolicy,
+    }
+
+    impl Config {
+        fn default_port() -> u16 {
+            23685
+        }
+    }
+
+    // Every policy logs a warning for each duplicate
+    #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+    enum DuplicatePolicy {
+        // Keep most recently modified copy
+        #[default]
+        Newest,
+
+        // Keep copy under earliest entry in paths
+        FirstRoot,
+
+        // Keep whichever copy was found first. Order is unspecified since scanning is parallel.
+        Warn,
     }
 
     #[derive(Clone, Debug, Serialize, Deserialize)]
     struct ConfigPath {
         pub path: PathBuf,
         pub follow_symlinks: bool,
+
+        // Globs matched against full paths, e.g. "**/obj/**". Matching files are not indexed.
+        #[serde(default)]
+        pub exclude: Vec<String>,
     }
 
-    pub fn run() -> Result<()> {
+    impl ConfigPath {
+        fn exclude_patterns(&self) -> anyhow::Result<ExcludePatterns> {
+            let mut patterns: ExcludePatterns = Default::default();
+            for exclude in &self.exclude {
+                let pattern = exclude.replace('\\', "/");
+                patterns.files.push(
+                    glob::Pattern::new(&pattern).with_context(|| format!("Invalid exclude [{}]", exclude))?,
+                );
+                if let Some(dir_pattern) = pattern.strip_suffix("/**") {
+                    patterns.directories.push(glob::Pattern::new(dir_pattern)?);
+                }
+            }
+            Ok(patterns)
+        }
+    }
+
+    // Compiled ConfigPath::exclude
+    #[derive(Default)]
+    struct ExcludePatterns {
+        files: Vec<glob::Pattern>,
+
+        // Patterns ending in "/**" exclude everything beneath a directory so it isn't walked at all
+        directories: Vec<glob::Pattern>,
+    }
+
+    impl ExcludePatterns {
+        const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+            case_sensitive: false,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+
+        fn excludes_file(&self, path: &Path) -> bool {
+            self.files
+                .iter()
+                .any(|pattern| pattern.matches_path_with(path, ExcludePatterns::MATCH_OPTIONS))
+        }
+
+        fn excludes_directory(&self, path: &Path) -> bool {
+            self.directories
+                .iter()
+                .any(|pattern| pattern.matches_path_with(path, ExcludePatterns::MATCH_OPTIONS))
+        }
+    }
+
+    // Indexed PDB
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct PdbEntry {
+        pub path: PathBuf,
+
+        // PDB GUID+age in symbol server format
+        pub build_id: String,
+
+        // File modification time when indexed. Used to detect stale entries.
+        #[serde(default)]
+        pub modified: Option<SystemTime>,
+    }
+
+    pub type PdbDb = Arc<Mutex<HashMap<Uuid, PdbEntry>>>;
+
+    // Configured root paths and how to choose between copies of the same PDB
+    #[derive(Clone, Debug)]
+    struct Roots {
+        paths: Vec<ConfigPath>,
+        duplicate_policy: DuplicatePolicy,
+    }
+
+    impl Roots {
+        fn new(config: &Config) -> Roots {
+            Roots {
+                paths: config.paths.clone(),
+                duplicate_policy: config.duplicate_policy,
+            }
+        }
+    }
+
+    // Replaced when config reloads
+    type SharedRoots = Arc<Mutex<Roots>>;
+
+    // How often periodic rescan thread checks whether a rescan is due
+    const RESCAN_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+    #[cfg(windows)]
+    pub fn run() -> anyhow::Result<()> {
         log::info!("Starting service");
 
         // Register generated `ffi_service_main` with the system and start the service, blocking
         // this thread until the service is stopped.
-        service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+        service_dispatcher::start(&launch_args().name, ffi_service_main)?;
+        Ok(())
+    }
+
+    #[cfg(not(windows))]
+    pub fn run() -> anyhow::Result<()> {
+        run_console()
     }
 
     // Generate the windows service boilerplate.
     // The boilerplate contains the low-level service entry function (ffi_service_main) that parses
     // incoming service arguments into Vec<OsString> and passes them to user defined service
     // entry (my_service_main).
+    #[cfg(windows)]
     define_windows_service!(ffi_service_main, my_service_main);
 
     // Service entry function which is called on background thread by the system with service
     // parameters. There is no stdout or stderr at this point so make sure to configure the log
     // output to file if needed.
+    #[cfg(windows)]
     pub fn my_service_main(_arguments: Vec<OsString>) {
         if let Err(e) = run_service() {
             log::error!("Unexpected error: [{:?}]", e);
         }
     }
 
+    #[cfg(windows)]
     pub fn run_service() -> anyhow::Result<()> {
-        // Create a channel to be able to poll a stop event from the service worker loop.
-        let (shutdown_tx, shutdown_rx) = mpsc::channel();
+        START_TIME.get_or_init(Instant::now);
+
+        // Create a channel to be able to poll control events from the service worker loop.
+        let (control_tx, control_rx) = mpsc::channel();
 
         // Define system service event handler that will be receiving service events.
         let event_handler = move |control_event| -> ServiceControlHandlerResult {
@@ -142,7 +395,17 @@ mod fts_pdbsrc_service {
 
                 // Handle stop
                 ServiceControl::Stop => {
-                    shutdown_tx.send(()).unwrap();
+                    control_tx.send(ControlRequest::Stop).unwrap();
+                    ServiceControlHandlerResult::NoError
+                }
+
+                // Status is reported by the worker loop once it has paused or continued
+                ServiceControl::Pause => {
+                    control_tx.send(ControlRequest::Pause).unwrap();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Continue => {
+                    control_tx.send(ControlRequest::Continue).unwrap();
                     ServiceControlHandlerResult::NoError
                 }
 
@@ -152,7 +415,10 @@ mod fts_pdbsrc_service {
 
         // Register system service event handler.
         // The returned status handle should be used to report service status changes to the system.
-        let status_handle = service_control_handler::register(SERVICE_NAME, event_handler)?;
+        let status_handle = service_control_handler::register(&launch_args().name, event_handler)?;
+
+        // Events are only written while an ETW session has the provider enabled
+        etw::register();
 
         // Tell the system that service is initializing itself
         log::info!("Setting service to StartPending");
@@ -166,74 +432,266 @@ mod fts_pdbsrc_service {
             process_id: None,
         })?;
 
+        let set_state = |state: WorkerState| -> anyhow::Result<()> {
+            let current_state = match state {
+                WorkerState::Running => ServiceState::Running,
+                WorkerState::Paused => ServiceState::Paused,
+            };
+            status_handle.set_service_status(ServiceStatus {
+                service_type: SERVICE_TYPE,
+                current_state,
+                controls_accepted: ServiceControlAccept::STOP | ServiceControlAccept::PAUSE_CONTINUE,
+                exit_code: ServiceExitCode::Win32(0),
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            })?;
+            Ok(())
+        };
+        run_worker(control_rx, &set_state)?;
+
+        // Tell the system that service has stopped.
+        status_handle.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: ServiceState::Stopped,
+            controls_accepted: ServiceControlAccept::empty(),
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })?;
+
+        Ok(())
+    }
+
+    // Runs in the current console for development, or for users without rights to install a service.
+    // Also how the service runs outside Windows. Ctrl+C or SIGTERM stops and saves the index like a
+    // service stop.
+    pub fn run_console() -> anyhow::Result<()> {
+        START_TIME.get_or_init(Instant::now);
+        etw::register();
+
+        let (control_tx, control_rx) = mpsc::channel();
+        let control_tx = Mutex::new(control_tx);
+        console::on_stop(move || {
+            let _ = control_tx.lock().unwrap().send(ControlRequest::Stop);
+        })?;
+
+        log::info!("Running in console. Press Ctrl+C to stop.");
+        run_worker(control_rx, &|_| Ok(()))
+    }
+
+    // Scans, watches, and answers requests until a stop request
+    fn run_worker(
+        control_rx: mpsc::Receiver<ControlRequest>,
+        set_state: &dyn Fn(WorkerState) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
         // Determine config path
-        let mut config_path = std::env::current_exe()?;
-        config_path.set_file_name("fts_pdbsrc_service_config.json");
+        let config_path = config_path()?;
 
         // Read config
         let config: Config = read_config(&config_path)?;
 
         // Update log level
         log::set_max_level(config.log_level);
+        logging::set_format(config.log_format);
 
-        // Create initial set of PDBs
-        let pdbs = find_pdbs(&config.paths);
-        let pdbs: Arc<Mutex<HashMap<Uuid, PathBuf>>> = Arc::new(Mutex::new(pdbs));
+        // Serve saved index while a full scan runs in background. Only scan up front if there is none.
+        let saved_pdbs = index::load();
+        let scan_in_background = !saved_pdbs.is_empty();
+        let pdbs = if scan_in_background {
+            saved_pdbs
+        } else {
+            find_pdbs(&Roots::new(&config))
+        };
+        let pdbs: PdbDb = Arc::new(Mutex::new(pdbs));
+        if scan_in_background {
+            let pdbs = pdbs.clone();
+            let roots = Roots::new(&config);
+            std::thread::spawn(move || {
+                if let Err(e) = rescan(&roots, &pdbs, None) {
+                    log::warn!("Startup scan failed: [{:?}]", e);
+                }
+            });
+        }
+        let cache = pdb_cache::PdbCache::new(config.pdb_cache.clone());
+        let usage_stats = UsageStats::load();
+        let roots: SharedRoots = Arc::new(Mutex::new(Roots::new(&config)));
+
+        // Zero disables periodic rescan
+        let rescan_interval_minutes = Arc::new(AtomicU64::new(config.rescan_interval_minutes.unwrap_or(0)));
 
         // Watch each config filepath for changes
-        let mut path_watchers = watch_paths(&config.paths, pdbs.clone());
+        let path_watchers = watch_paths(&Roots::new(&config), pdbs.clone(), cache.clone());
+        let path_watchers = Arc::new(Mutex::new(path_watchers));
 
-        // Watch config file
         // When config changes, clear old watchs/pdbs and refresh
+        let reload = {
+            let config_path = config_path.clone();
+            let pdbs = pdbs.clone();
+            let cache = cache.clone();
+            let roots = roots.clone();
+            let rescan_interval_minutes = rescan_interval_minutes.clone();
+            let port = config.port;
+            move || -> anyhow::Result<()> {
+                if is_paused() {
+                    log::info!("Paused. Config will reload on continue.");
+                    return Ok(());
+                }
+
+                // Read and parse config
+                let new_config: Config = read_config(&config_path)?;
+
+                // Update log level
+                log::set_max_level(new_config.log_level);
+                logging::set_format(new_config.log_format);
+
+                // Clear old watchers and recreate
+                let mut path_watchers = path_watchers.lock().unwrap();
+                path_watchers.clear();
+                let new_roots = Roots::new(&new_config);
+                *path_watchers = watch_paths(&new_roots, pdbs.clone(), cache.clone());
+
+                // Drop cached PDBs which may no longer be indexed
+                cache.lock().unwrap().clear();
+
+                // Find new pdbs
+                *pdbs.lock().unwrap() = find_pdbs(&new_roots);
+                *roots.lock().unwrap() = new_roots;
+                let minutes = new_config.rescan_interval_minutes.unwrap_or(0);
+                rescan_interval_minutes.store(minutes, Ordering::Relaxed);
+
+                if new_config.port != port {
+                    log::warn!("Port changed to [{}]. Restart service to apply.", new_config.port);
+                }
+                Ok(())
+            }
+        };
+        let reload = Arc::new(reload);
+
+        // Watch config file
         let mut config_watcher = hotwatch::Hotwatch::new().expect("hotwatch failed to initialize!");
-        let pdbs2 = pdbs.clone();
+        let reload2 = reload.clone();
         config_watcher
             .watch(&config_path, move |event: hotwatch::Event| {
-                let _ = || -> anyhow::Result<()> {
-                    if let hotwatch::Event::Write(path) = event {
-                        log::info!("Config file [{:?}] changed. Re-parsing log.", path);
-
-                        // Read and parse config
-                        let new_config: Config = read_config(&path)?;
+                if let hotwatch::Event::Write(path) = event {
+                    log::info!("Config file [{:?}] changed. Re-parsing log.", path);
+                    if let Err(e) = reload2() {
+                        log::warn!("Failed to reload config: [{:?}]", e);
+                    }
+                }
+            })
+            .unwrap_or_else(|_| panic!("failed to watch [{:?}]!", &config_path));
 
-                        // Update log level
-                        log::set_max_level(new_config.log_level);
+        // Continue reloads everything since changes made while paused were ignored
+        let reload_on_continue = reload.clone();
 
-                        // Clear old watchers
-                        path_watchers.clear();
+        // Watch policy registry key
+        std::thread::spawn(move || loop {
+            if let Err(e) = registry::wait_for_change() {
+                log::info!("Not watching registry policy: [{:?}]", e);
+                break;
+            }
 
-                        // Recreate watchers
-                        path_watchers = watch_paths(&new_config.paths, pdbs2.clone());
+            log::info!(
+                "Registry policy [HKLM\\{}] changed. Reloading config.",
+                registry::policy_key()
+            );
+            if let Err(e) = reload() {
+                log::warn!("Failed to reload config: [{:?}]", e);
+            }
+        });
+
+        // Periodically rescan paths. Interval is re-read each poll so config reloads apply.
+        {
+            let pdbs = pdbs.clone();
+            let roots = roots.clone();
+            std::thread::spawn(move || {
+                let mut last_scan = Instant::now();
+                loop {
+                    std::thread::sleep(RESCAN_POLL_INTERVAL);
+                    let minutes = rescan_interval_minutes.load(Ordering::Relaxed);
+                    if minutes == 0 || last_scan.elapsed() < Duration::from_secs(minutes * 60) {
+                        continue;
+                    }
+                    if is_paused() {
+                        continue;
+                    }
 
-                        // Find new pdbs
-                        *pdbs2.lock().unwrap() = find_pdbs(&new_config.paths);
+                    log::info!("Starting periodic rescan. Interval: [{}] minutes", minutes);
+                    let roots = roots.lock().unwrap().clone();
+                    if let Err(e) = rescan(&roots, &pdbs, None) {
+                        log::warn!("Periodic rescan failed: [{:?}]", e);
                     }
+                    last_scan = Instant::now();
+                }
+            });
+        }
 
-                    Ok(())
-                }();
-            })
-            .unwrap_or_else(|_| panic!("failed to watch [{:?}]!", &config_path));
+        // Serve HTTP if requested
+        if let Some(http_address) = config.http_address.clone() {
+            let pdbs = pdbs.clone();
+            let decode_keys = config.decode_keys.clone();
+            let tokens = config.http_tokens.clone();
+            let cache = cache.clone();
+            let usage_stats = usage_stats.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = http::serve(http_address, pdbs, decode_keys, tokens, cache, usage_stats) {
+                    log::error!("HTTP server failed: [{:?}]", e);
+                }
+            });
+        }
 
-        // Listen to connections
-        std::thread::spawn(move || accept_connections(pdbs));
+        // Serve JSON protocol if requested
+        if let Some(json_address) = config.json_address.clone() {
+            let pdbs = pdbs.clone();
+            let usage_stats = usage_stats.clone();
+            let cache = cache.clone();
+            let roots = roots.clone();
+            std::thread::spawn(move || {
+                let result = accept_connections(pdbs, usage_stats, cache, roots, &json_address, Codec::Json);
+                if let Err(e) = result {
+                    log::error!("JSON listener failed: [{:?}]", e);
+                }
+            });
+        }
+
+        // Listen to connections. Default port chosen randomly.
+        let address = format!("localhost:{}", config.port);
+        {
+            let pdbs = pdbs.clone();
+            std::thread::spawn(move || {
+                accept_connections(pdbs, usage_stats, cache, roots, &address, Codec::MessagePack)
+            });
+        }
 
         // Tell the system that service is running
         log::info!("Setting service to running");
-        status_handle.set_service_status(ServiceStatus {
-            service_type: SERVICE_TYPE,
-            current_state: ServiceState::Running,
-            controls_accepted: ServiceControlAccept::STOP,
-            exit_code: ServiceExitCode::Win32(0),
-            checkpoint: 0,
-            wait_hint: Duration::default(),
-            process_id: None,
-        })?;
+        set_state(WorkerState::Running)?;
 
         loop {
-            // Poll shutdown event.
-            match shutdown_rx.recv_timeout(Duration::from_secs(1)) {
+            // Poll control events.
+            match control_rx.recv_timeout(Duration::from_secs(1)) {
                 // Break the loop either upon stop or channel disconnect
-                Ok(_) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Ok(ControlRequest::Stop) | Err(mpsc::RecvTimeoutError::Disconnected) => break,
+
+                Ok(ControlRequest::Pause) => {
+                    log::info!("Pausing. Scanning stops and requests are refused until continue.");
+                    PAUSED.store(true, Ordering::Relaxed);
+                    set_state(WorkerState::Paused)?;
+                }
+
+                Ok(ControlRequest::Continue) => {
+                    log::info!("Continuing. Reloading config and rescanning in background.");
+                    PAUSED.store(false, Ordering::Relaxed);
+                    set_state(WorkerState::Running)?;
+                    let reload = reload_on_continue.clone();
+                    std::thread::spawn(move || {
+                        if let Err(e) = reload() {
+                            log::warn!("Failed to reload after continue: [{:?}]", e);
+                        }
+                    });
+                }
 
                 // Continue work if no events were received within the timeout
                 Err(mpsc::RecvTimeoutError::Timeout) => (),
@@ -241,17 +699,7 @@ mod fts_pdbsrc_service {
         }
         // END DO STUFF
 
-        // Tell the system that service has stopped.
-        status_handle.set_service_status(ServiceStatus {
-            service_type: SERVICE_TYPE,
-            current_state: ServiceState::Stopped,
-            controls_accepted: ServiceControlAccept::empty(),
-            exit_code: ServiceExitCode::Win32(0),
-            checkpoint: 0,
-            wait_hint: Duration::default(),
-            process_id: None,
-        })?;
-
+        index::save(&pdbs.lock().unwrap());
         Ok(())
     }
 
@@ -260,44 +708,366 @@ mod fts_pdbsrc_service {
     enum Message {
         FindPdb(Uuid),
         FoundPdb((Uuid, Option<PathBuf>)),
+        FindPdbs(Vec<Uuid>),
+        FoundPdbs(Vec<(Uuid, Option<PathBuf>)>),
+
+        // Look up by file name, e.g. game.pdb. Response is uuid, path, and modified time in unix seconds.
+        FindPdbByName(String),
+        FoundPdbByName(Vec<(Uuid, PathBuf, Option<u64>)>),
+
+        // Sent in place of a response when a request could not be understood
+        ProtocolError(String),
+
+        // Usage of indexed PDBs, most recently used first. Request is index of first result.
+        // Response is total count and up to MAX_STATS_RESULTS results starting at that index.
+        Stats(u32),
+        FoundStats((u32, Vec<stats::PdbStats>)),
+
+        // First message from clients that support versioning. Response carries PROTOCOL_VERSION.
+        Hello(u32),
+        HelloAck(u32),
+
+        // Every indexed PDB, sorted by path. Request is index of first result.
+        // Response is total count and a page of results starting at that index.
+        ListPdbs(u32),
+        ListedPdbs((u32, Vec<(Uuid, PathBuf)>)),
+
+        // Raw, possibly encrypted, bytes of a named stream in an indexed PDB. Lets users without
+        // read access to the PDB extract through the service. Only srcsrv and fts_pdbsrc streams
+        // may be fetched. Response is None if PDB or stream was not found.
+        FetchFile((Uuid, String)),
+        FetchedFile(Option<Vec<u8>>),
+
+        // Re-walk every configured root, or only the given path which must be under one. For network
+        // shares where file watch events are missed. Response is number of PDBs found by the walk.
+        Rescan(Option<PathBuf>),
+        Rescanned(u32),
+
+        // Service version, uptime, and number of indexed PDBs. For `fts_pdbsrc status`.
+        Status,
+        FoundStatus(StatusInfo),
     }
 
-    fn accept_connections(relevant_pdbs: Arc<Mutex<HashMap<Uuid, PathBuf>>>) -> anyhow::Result<()> {
-        log::info!("Accepting connections");
-        let handle_connection =
-            |mut stream: &mut TcpStream, pdb_db: Arc<Mutex<HashMap<Uuid, PathBuf>>>| -> anyhow::Result<()> {
-                loop {
-                    let msg = read_message(&mut stream)?;
-                    match msg {
-                        Message::FindPdb(uuid) => {
-                            log::info!("Received request for PDB with Uuid: [{}]", uuid);
-
-                            let search_result: Option<PathBuf> = pdb_db.lock().unwrap().get(&uuid).cloned();
-                            match search_result {
-                                Some(path) => {
-                                    log::info!("Found path [{:?}] for uuid [{}]", path, uuid);
-                                    send_message(&mut stream, Message::FoundPdb((uuid, Some(path.clone()))))?
-                                }
-                                None => {
-                                    log::info!("Failed to find match for uuid [{}]", uuid);
-                                    send_message(&mut stream, Message::FoundPdb((uuid, None)))?
+    // Must match fts_pdbsrc
+    #[derive(Serialize, Deserialize, Debug)]
+    struct StatusInfo {
+        version: String,
+        uptime_seconds: u64,
+        pdbs: u32,
+    }
+
+    // Bump when Message changes. Variants are only ever appended so msgpack indices stay stable.
+    //   2: Connection switches to Codec::ChunkedMessagePack after HelloAck
+    //   3: ListPdbs
+    //   4: FetchFile
+    //   5: Rescan
+    //   6: Status
+    const PROTOCOL_VERSION: u32 = 6;
+
+    // Why a message could not be read
+    enum ReadError {
+        // Peer closed connection between messages
+        Closed,
+
+        // Frame was received but is not a valid message. Peer gets a ProtocolError response.
+        Malformed(String),
+
+        Io(std::io::Error),
+    }
+
+    // Keeps FoundPdbByName well under the msgpack packet size limit
+    const MAX_FIND_BY_NAME_RESULTS: usize = 256;
+
+    // Keeps FoundStats under the msgpack packet size limit even with long paths
+    const MAX_STATS_RESULTS: usize = 128;
+
+    // Pages for ListPdbs. Clients that completed the handshake have a much larger packet size limit.
+    const MAX_LIST_PDBS_RESULTS: usize = 128;
+    const MAX_LIST_PDBS_RESULTS_CHUNKED: usize = 4096;
+
+    // Returns indexed PDBs whose file name matches, newest first.
+    // Compressed .pd_ files match their .pdb name.
+    fn find_pdbs_by_name(pdb_db: &PdbDb, name: &str) -> Vec<(Uuid, PathBuf, Option<u64>)> {
+        let normalize = |name: &str| {
+            let name = name.to_lowercase();
+            match name.strip_suffix(".pd_") {
+                Some(stem) => format!("{}.pdb", stem),
+                None => name,
+            }
+        };
+        let query = normalize(name);
+
+        let matches: Vec<(Uuid, PathBuf)> = pdb_db
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| {
+                entry
+                    .path
+                    .file_name()
+                    .is_some_and(|file_name| normalize(&file_name.to_string_lossy()) == query)
+            })
+            .map(|(uuid, entry)| (*uuid, entry.path.clone()))
+            .collect();
+
+        // Stat files outside of lock
+        let mut found: Vec<(Uuid, PathBuf, Option<u64>)> = matches
+            .into_iter()
+            .map(|(uuid, path)| {
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|duration| duration.as_secs());
+                (uuid, path, modified)
+            })
+            .collect();
+        found.sort_by_key(|(_, _, modified)| std::cmp::Reverse(*modified));
+        found.truncate(MAX_FIND_BY_NAME_RESULTS);
+        found
+    }
+
+    // Clients send a request and wait for the response. Anything slower is stuck or not a client.
+    const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+    // Wire format of a connection. All carry the same Message enum.
+    //   MessagePack: u16 native endian size then msgpack. Used by fts_pdbsrc.exe until handshake.
+    //   ChunkedMessagePack: msgpack split into chunks. Each chunk is a u32 little endian header
+    //     then up to MAX_CHUNK_SIZE bytes. Header is chunk size, with MORE_CHUNKS_BIT set on all but
+    //     the last chunk. Used after handshake with clients speaking protocol 2 or later.
+    //   Json: u32 big endian size then UTF-8 JSON. Documented for tools in other languages.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Codec {
+        MessagePack,
+        ChunkedMessagePack,
+        Json,
+    }
+
+    // Largest message of any codec other than MessagePack
+    const MAX_MESSAGE_SIZE: u32 = 16 * 1024 * 1024;
+
+    const MAX_CHUNK_SIZE: u32 = 1024 * 1024;
+    const MORE_CHUNKS_BIT: u32 = 0x8000_0000;
+
+    fn accept_connections(
+        relevant_pdbs: PdbDb,
+        usage_stats: SharedUsageStats,
+        cache: pdb_cache::SharedPdbCache,
+        roots: SharedRoots,
+        address: &str,
+        codec: Codec,
+    ) -> anyhow::Result<()> {
+        log::info!("Accepting [{:?}] connections on [{}]", codec, address);
+        let handle_connection = move |stream: &mut TcpStream,
+                                      pdb_db: PdbDb,
+                                      usage: SharedUsageStats,
+                                      cache: pdb_cache::SharedPdbCache,
+                                      roots: SharedRoots|
+              -> anyhow::Result<()> {
+            // Changes after handshake
+            let mut codec = codec;
+            loop {
+                let msg = match read_message(stream, codec) {
+                    Ok(msg) => msg,
+                    Err(ReadError::Closed) => return Ok(()),
+                    Err(ReadError::Malformed(reason)) => {
+                        // Stream position is unknown after a bad frame so the connection can't be reused
+                        let _ = send_message(stream, codec, Message::ProtocolError(reason.clone()));
+                        bail!("Malformed frame: [{}]", reason);
+                    }
+                    Err(ReadError::Io(e)) => return Err(e.into()),
+                };
+                match msg {
+                    Message::Hello(version) => {
+                        if version != PROTOCOL_VERSION {
+                            log::info!(
+                                "Client speaks protocol [{}]. Service speaks [{}].",
+                                version,
+                                PROTOCOL_VERSION
+                            );
+                        }
+                        send_message(stream, codec, Message::HelloAck(PROTOCOL_VERSION))?;
+                        if version >= 2 && codec == Codec::MessagePack {
+                            codec = Codec::ChunkedMessagePack;
+                        }
+                    }
+                    Message::FindPdb(uuid) => {
+                        log::info!("Received request for PDB with Uuid: [{}]", uuid);
+
+                        let search_result: Option<PathBuf> =
+                            index::lookup(&pdb_db, &uuid).map(|entry| entry.path);
+                        etw::find_pdb(&uuid, search_result.is_some());
+                        match search_result {
+                            Some(path) => {
+                                log::info!("Found path [{:?}] for uuid [{}]", path, uuid);
+                                UsageStats::record(&usage, uuid, 0);
+                                let response = Message::FoundPdb((uuid, Some(path.clone())));
+                                send_message(stream, codec, response)?
+                            }
+                            None => {
+                                log::info!("Failed to find match for uuid [{}]", uuid);
+                                send_message(stream, codec, Message::FoundPdb((uuid, None)))?
+                            }
+                        }
+                    }
+                    Message::FindPdbs(uuids) => {
+                        log::info!("Received request for [{}] PDBs", uuids.len());
+
+                        let found: Vec<(Uuid, Option<PathBuf>)> = uuids
+                            .into_iter()
+                            .map(|uuid| (uuid, index::lookup(&pdb_db, &uuid).map(|entry| entry.path)))
+                            .collect();
+                        for (uuid, path) in &found {
+                            etw::find_pdb(uuid, path.is_some());
+                            if path.is_some() {
+                                UsageStats::record(&usage, *uuid, 0);
+                            }
+                        }
+                        log::info!(
+                            "Found [{}] of [{}] PDBs",
+                            found.iter().filter(|(_, path)| path.is_some()).count(),
+                            found.len()
+                        );
+                        send_message(stream, codec, Message::FoundPdbs(found))?
+                    }
+                    Message::FindPdbByName(name) => {
+                        log::info!("Received request for PDBs named [{}]", name);
+
+                        let found = find_pdbs_by_name(&pdb_db, &name);
+                        log::info!("Found [{}] PDBs named [{}]", found.len(), name);
+                        send_message(stream, codec, Message::FoundPdbByName(found))?
+                    }
+                    Message::Stats(start) => {
+                        log::info!("Received request for usage stats starting at [{}]", start);
+
+                        let all_stats = usage.lock().unwrap().snapshot(&pdb_db.lock().unwrap());
+                        let total = all_stats.len() as u32;
+                        let page: Vec<stats::PdbStats> = all_stats
+                            .into_iter()
+                            .skip(start as usize)
+                            .take(MAX_STATS_RESULTS)
+                            .collect();
+                        send_message(stream, codec, Message::FoundStats((total, page)))?
+                    }
+                    Message::ListPdbs(start) => {
+                        log::info!("Received request for indexed PDBs starting at [{}]", start);
+
+                        let mut all_pdbs: Vec<(Uuid, PathBuf)> = pdb_db
+                            .lock()
+                            .unwrap()
+                            .iter()
+                            .map(|(uuid, entry)| (*uuid, entry.path.clone()))
+                            .collect();
+                        all_pdbs.sort_by(|a, b| a.1.cmp(&b.1));
+                        let page_size = match codec {
+                            Codec::MessagePack => MAX_LIST_PDBS_RESULTS,
+                            Codec::ChunkedMessagePack | Codec::Json => MAX_LIST_PDBS_RESULTS_CHUNKED,
+                        };
+                        let total = all_pdbs.len() as u32;
+                        let page: Vec<(Uuid, PathBuf)> = all_pdbs
+                            .into_iter()
+                            .skip(start as usize)
+                            .take(page_size)
+                            .collect();
+                        send_message(stream, codec, Message::ListedPdbs((total, page)))?
+                    }
+                    Message::FetchFile((uuid, name)) => {
+                        log::info!("Received request for stream [{}] of PDB [{}]", name, uuid);
+
+                        // Replies can exceed the legacy u16 frame
+                        if codec == Codec::MessagePack {
+                            let reason = "FetchFile requires Hello handshake".to_owned();
+                            send_message(stream, codec, Message::ProtocolError(reason))?;
+                            continue;
+                        }
+                        if !manifest::is_fts_stream(&name) && name != "srcsrv" {
+                            let reason = format!("Stream [{}] may not be fetched", name);
+                            log::warn!("{}", reason);
+                            send_message(stream, codec, Message::ProtocolError(reason))?;
+                            continue;
+                        }
+
+                        let path = index::lookup(&pdb_db, &uuid).map(|entry| entry.path);
+                        let bytes = match path {
+                            Some(path) => match fetch_stream(&cache, &path, &name) {
+                                Ok(bytes) => bytes,
+                                Err(e) => {
+                                    log::warn!("Failed to read [{}] from [{:?}]: [{:?}]", name, path, e);
+                                    None
                                 }
+                            },
+                            None => None,
+                        };
+                        if let Some(bytes) = &bytes {
+                            UsageStats::record(&usage, uuid, bytes.len() as u64);
+                            etw::extract_served(&uuid, &name, bytes.len());
+                        }
+                        send_message(stream, codec, Message::FetchedFile(bytes))?
+                    }
+                    Message::Rescan(root) => {
+                        log::info!("Received request to rescan [{:?}]", root);
+
+                        // Scan without holding lock so config reloads aren't blocked
+                        let roots = roots.lock().unwrap().clone();
+                        match rescan(&roots, &pdb_db, root.as_deref()) {
+                            Ok(num_found) => {
+                                send_message(stream, codec, Message::Rescanned(num_found as u32))?
+                            }
+                            Err(e) => {
+                                let reason = format!("Failed to rescan: [{}]", e);
+                                log::warn!("{}", reason);
+                                send_message(stream, codec, Message::ProtocolError(reason))?
                             }
                         }
-                        _ => return Err(anyhow!("Unexpected message: [{:?}]", msg)),
+                    }
+                    Message::Status => {
+                        log::info!("Received request for status");
+
+                        let status = StatusInfo {
+                            version: env!("CARGO_PKG_VERSION").to_owned(),
+                            uptime_seconds: START_TIME.get().map_or(0, |start| start.elapsed().as_secs()),
+                            pdbs: pdb_db.lock().unwrap().len() as u32,
+                        };
+                        send_message(stream, codec, Message::FoundStatus(status))?
+                    }
+                    _ => {
+                        // Frame was well formed so connection remains usable
+                        let reason = format!("Unexpected message: [{:?}]", msg);
+                        log::warn!("{}", reason);
+                        send_message(stream, codec, Message::ProtocolError(reason))?
                     }
                 }
-            };
+            }
+        };
 
         // Listen
-        let listener = TcpListener::bind("localhost:23685")?; // port chosen randomly
+        let listener = TcpListener::bind(address)?;
         for stream in listener.incoming() {
             match stream {
+                // Clients treat a closed connection as no service and fail fast
+                Ok(stream) if is_paused() => {
+                    log::debug!("Paused. Refusing connection.");
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                }
                 Ok(mut stream) => {
                     let pdb_copy = relevant_pdbs.clone();
+                    let usage_stats = usage_stats.clone();
+                    let cache = cache.clone();
+                    let roots = roots.clone();
                     std::thread::spawn(move || {
-                        let _ = handle_connection(&mut stream, pdb_copy);
-                        stream.shutdown(std::net::Shutdown::Both).unwrap();
+                        let peer = stream
+                            .peer_addr()
+                            .map(|addr| addr.to_string())
+                            .unwrap_or_else(|_| "unknown".to_owned());
+                        if let Err(e) = stream.set_read_timeout(Some(CONNECTION_TIMEOUT)) {
+                            log::warn!("Failed to set timeout for connection from [{}]: [{}]", peer, e);
+                        }
+                        if let Err(e) = handle_connection(&mut stream, pdb_copy, usage_stats, cache, roots) {
+                            log::warn!("Closing connection from [{}]: [{:?}]", peer, e);
+                        }
+
+                        // Peer may have already closed
+                        let _ = stream.shutdown(std::net::Shutdown::Both);
                     });
                 }
                 Err(e) => log::warn!("Error accepting listener: [{}]", e),
@@ -307,66 +1077,209 @@ mod fts_pdbsrc_service {
         Ok(())
     }
 
+    // Returns None if PDB does not contain stream
+    fn fetch_stream(
+        cache: &pdb_cache::SharedPdbCache,
+        path: &Path,
+        name: &str,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut pdb = pdb_cache::PdbCache::open(cache, path)?;
+        match pdb.named_stream(name.as_bytes()) {
+            Ok(stream) => Ok(Some(stream.as_slice().to_vec())),
+            Err(pdb::Error::StreamNameNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     // copy pasted from fts_pdbsrc/src/main.rs for simplicity
-    fn send_message(stream: &mut TcpStream, message: Message) -> anyhow::Result<()> {
-        // Serialize message
-        let buf = rmp_serde::to_vec(&message).unwrap();
+    fn send_message(stream: &mut TcpStream, codec: Codec, message: Message) -> anyhow::Result<()> {
+        // Serialize message and write packet size
+        match codec {
+            Codec::MessagePack => {
+                let buf = rmp_serde::to_vec(&message).unwrap();
+                if buf.len() > u16::MAX as usize {
+                    bail!("Message of [{}] bytes exceeds packet size limit", buf.len());
+                }
+                stream.write_all(&u16::to_ne_bytes(buf.len() as u16))?;
+                stream.write_all(&buf)?;
+            }
+            Codec::ChunkedMessagePack => {
+                let buf = rmp_serde::to_vec(&message).unwrap();
+                if buf.len() > MAX_MESSAGE_SIZE as usize {
+                    bail!("Message of [{}] bytes exceeds packet size limit", buf.len());
+                }
+                let mut chunks = buf.chunks(MAX_CHUNK_SIZE as usize).peekable();
+                while let Some(chunk) = chunks.next() {
+                    let more = if chunks.peek().is_some() {
+                        MORE_CHUNKS_BIT
+                    } else {
+                        0
+                    };
+                    stream.write_all(&u32::to_le_bytes(chunk.len() as u32 | more))?;
+                    stream.write_all(chunk)?;
+                }
+            }
+            Codec::Json => {
+                let buf = serde_json::to_vec(&message)?;
+                if buf.len() > MAX_MESSAGE_SIZE as usize {
+                    bail!("Message of [{}] bytes exceeds packet size limit", buf.len());
+                }
+                stream.write_all(&u32::to_be_bytes(buf.len() as u32))?;
+                stream.write_all(&buf)?;
+            }
+        }
 
-        // Write packet size
-        let packet_size = u16::to_ne_bytes(buf.len() as u16);
-        stream.write_all(&packet_size)?;
+        Ok(())
+    }
 
-        // Write message
-        stream.write_all(&buf)?;
+    // Validates frames since anything may connect to the port
+    fn read_message(stream: &mut TcpStream, codec: Codec) -> std::result::Result<Message, ReadError> {
+        // Chunked messages are many frames
+        let mut packet_buf: Vec<u8> = Default::default();
+        loop {
+            let (frame, more) = read_frame(stream, codec, packet_buf.is_empty())?;
+            if packet_buf.len() + frame.len() > MAX_MESSAGE_SIZE as usize {
+                return Err(ReadError::Malformed(format!(
+                    "Message of at least [{}] bytes exceeds packet size limit",
+                    packet_buf.len() + frame.len()
+                )));
+            }
+            packet_buf.extend(frame);
+            if !more {
+                break;
+            }
+        }
 
-        Ok(())
+        // Deserialize
+        let packet_size = packet_buf.len();
+        let invalid = |e: &dyn std::fmt::Display| {
+            ReadError::Malformed(format!("Invalid message of [{}] bytes: [{}]", packet_size, e))
+        };
+        match codec {
+            Codec::MessagePack | Codec::ChunkedMessagePack => {
+                rmp_serde::from_read_ref(&packet_buf).map_err(|e| invalid(&e))
+            }
+            Codec::Json => serde_json::from_slice(&packet_buf).map_err(|e| invalid(&e)),
+        }
     }
 
-    // copy pasted from fts_pdbsrc/src/main.rs for simplicity
-    fn read_message(stream: &mut TcpStream) -> anyhow::Result<Message> {
+    // Returns frame contents and whether more chunks follow. Only a close before the first frame
+    // of a message is a clean close.
+    fn read_frame(
+        stream: &mut TcpStream,
+        codec: Codec,
+        first_frame: bool,
+    ) -> std::result::Result<(Vec<u8>, bool), ReadError> {
         // Read packet size
-        let mut packet_size_buf: [u8; 2] = Default::default();
-        stream.read_exact(&mut packet_size_buf)?;
-        let packet_size = u16::from_ne_bytes(packet_size_buf);
+        let mut packet_size_buf: [u8; 4] = Default::default();
+        let packet_size_buf = match codec {
+            Codec::MessagePack => &mut packet_size_buf[..2],
+            Codec::ChunkedMessagePack | Codec::Json => &mut packet_size_buf[..],
+        };
+        match stream.read_exact(packet_size_buf) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof && first_frame => {
+                return Err(ReadError::Closed)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(ReadError::Malformed(format!(
+                    "Connection closed mid message: [{}]",
+                    e
+                )))
+            }
+            Err(e) => return Err(ReadError::Io(e)),
+        }
+        let (packet_size, more, max_size) = match codec {
+            Codec::MessagePack => (
+                u16::from_ne_bytes([packet_size_buf[0], packet_size_buf[1]]) as u32,
+                false,
+                u16::MAX as u32,
+            ),
+            Codec::ChunkedMessagePack => {
+                let header = u32::from_le_bytes([
+                    packet_size_buf[0],
+                    packet_size_buf[1],
+                    packet_size_buf[2],
+                    packet_size_buf[3],
+                ]);
+                (
+                    header & !MORE_CHUNKS_BIT,
+                    header & MORE_CHUNKS_BIT != 0,
+                    MAX_CHUNK_SIZE,
+                )
+            }
+            Codec::Json => (
+                u32::from_be_bytes([
+                    packet_size_buf[0],
+                    packet_size_buf[1],
+                    packet_size_buf[2],
+                    packet_size_buf[3],
+                ]),
+                false,
+                MAX_MESSAGE_SIZE,
+            ),
+        };
+        if packet_size == 0 {
+            return Err(ReadError::Malformed("Zero length frame".to_owned()));
+        }
+        if packet_size > max_size {
+            return Err(ReadError::Malformed(format!(
+                "Frame of [{}] bytes exceeds packet size limit",
+                packet_size
+            )));
+        }
 
-        // Read packet
+        // Read packet. Peers speaking another protocol typically stall or hang up here.
         let mut packet_buf = vec![0; packet_size as usize]; // TODO: make thread_local
-        stream.read_exact(&mut packet_buf)?;
-
-        // Deserialize
-        let message: Message = rmp_serde::from_read_ref(&packet_buf)?;
+        stream.read_exact(&mut packet_buf).map_err(|e| {
+            ReadError::Malformed(format!(
+                "Incomplete frame. Expected [{}] bytes starting with [{:02x?}]: [{}]",
+                packet_size, packet_size_buf, e
+            ))
+        })?;
 
-        Ok(message)
+        Ok((packet_buf, more))
     }
 
-    fn watch_paths(
-        paths: &[ConfigPath],
-        pdbs: Arc<Mutex<HashMap<Uuid, PathBuf>>>,
-    ) -> Vec<hotwatch::Hotwatch> {
-        paths
+    fn watch_paths(roots: &Roots, pdbs: PdbDb, cache: pdb_cache::SharedPdbCache) -> Vec<hotwatch::Hotwatch> {
+        roots
+            .paths
             .iter()
             .filter_map(|entry| {
                 let mut hw = hotwatch::Hotwatch::new().expect("hotwatch failed to initialize!");
                 let pdbs2 = pdbs.clone();
+                let cache2 = cache.clone();
+                let roots2 = roots.clone();
+                let exclude = entry.exclude_patterns().unwrap_or_else(|e| {
+                    log::warn!("Ignoring excludes for [{:?}]: [{:?}]", entry.path, e);
+                    Default::default()
+                });
                 match hw.watch(&entry.path, move |event: hotwatch::Event| {
+                    // Continue rescans everything
+                    if is_paused() {
+                        return;
+                    }
+
                     // Help to detect PDB
                     let is_pdb = |path: &Path| -> bool {
-                        matches!(path.extension().and_then(|os_str| os_str.to_str()), Some("pdb"))
+                        matches!(
+                            path.extension().and_then(|os_str| os_str.to_str()),
+                            Some("pdb") | Some("pd_")
+                        )
                     };
 
                     // Remove PDBs that are removed or renamed (src)
                     match &event {
-                        hotwatch::Event::Remove(path) | hotwatch::Event::Rename(path, _) => {
-                            // Ignore non-pdbs
-                            if !is_pdb(path) {
-                                return;
-                            }
+                        // Non-PDB sources are skipped rather than returning since a temp file renamed
+                        // to .pdb must still reach the destination handling below
+                        hotwatch::Event::Remove(path) | hotwatch::Event::Rename(path, _) if is_pdb(path) => {
+                            cache2.lock().unwrap().invalidate(path);
 
                             // Remove PDB if it's in the db
                             let mut pdbs = pdbs2.lock().unwrap();
-                            let maybe_key = pdbs
-                                .iter()
-                                .find_map(|(key, val)| if val == path { Some(*key) } else { None });
+                            let maybe_key =
+                                pdbs.iter()
+                                    .find_map(|(key, val)| if val.path == *path { Some(*key) } else { None });
 
                             if let Some(key) = maybe_key {
                                 log::info!("Detected deletion of [{:?}]", pdbs.get(&key));
@@ -378,17 +1291,20 @@ mod fts_pdbsrc_service {
 
                     // Add PDBs that are created, modified, or renamed (dst)
                     match &event {
-                        hotwatch::Event::Create(path) | hotwatch::Event::Write(path) => {
+                        hotwatch::Event::Create(path)
+                        | hotwatch::Event::Write(path)
+                        | hotwatch::Event::Rename(_, path) => {
                             // Ignore events for non-PDBs
-                            if !is_pdb(path) {
+                            if !is_pdb(path) || exclude.excludes_file(path) {
                                 return;
                             }
+                            cache2.lock().unwrap().invalidate(path);
 
-                            // PDB was created or modified, process it
-                            log::info!("Detected creation or modification of [{:?}]", path);
-                            if let Some((uuid, path)) = process_pdb_path(path) {
-                                log::info!("Found valid PDB [{:?}] with Uuid [{}]", path, uuid);
-                                pdbs2.lock().unwrap().insert(uuid, path);
+                            // PDB was created, modified, or renamed into place, process it
+                            log::info!("Detected creation, modification, or rename of [{:?}]", path);
+                            if let Some((uuid, entry)) = process_pdb_path(path) {
+                                log::info!("Found valid PDB [{:?}] with Uuid [{}]", entry.path, uuid);
+                                insert_pdb(&mut pdbs2.lock().unwrap(), uuid, entry, &roots2);
                             }
                         }
                         _ => (), // Ignore other events
@@ -407,28 +1323,86 @@ mod fts_pdbsrc_service {
             .collect()
     }
 
+    // Registry policy, if present, overrides values in config file
     fn read_config(config_path: &Path) -> anyhow::Result<Config> {
+        let policy = registry::read()?;
+
         log::info!("Loading config file: [{:?}]", config_path);
-        let config_file = std::fs::File::open(&config_path)?;
+        let mut config: Config = match std::fs::File::open(config_path) {
+            Ok(config_file) => {
+                log::info!("Parsing config");
+                serde_json::from_reader(&config_file)?
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && policy.is_some() => {
+                log::info!("No config file. Using registry policy only.");
+                serde_json::from_value(serde_json::json!({ "paths": [], "log_level": "Info" }))?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(policy) = policy {
+            log::info!("Applying registry policy: [{:?}]", policy);
+            let follow_symlinks = policy.follow_symlinks;
+            if let Some(paths) = policy.paths {
+                config.paths = paths
+                    .into_iter()
+                    .map(|path| ConfigPath {
+                        path: path.into(),
+                        follow_symlinks,
+                        exclude: Default::default(),
+                    })
+                    .collect();
+            }
+            if let Some(log_level) = policy.log_level {
+                config.log_level = log_level
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid registry LogLevel [{}]", log_level))?;
+            }
+            if let Some(port) = policy.port {
+                config.port = std::convert::TryFrom::try_from(port)
+                    .map_err(|_| anyhow!("Invalid registry Port [{}]", port))?;
+            }
+        }
+        if let Some(port) = launch_args().port {
+            config.port = port;
+        }
 
-        log::info!("Parsing config");
-        let config: Config = serde_json::from_reader(&config_file)?;
+        for path in &config.paths {
+            path.exclude_patterns()?;
+        }
 
         log::info!("Successfully loaded config: [{:?}]", config);
         Ok(config)
     }
 
-    fn process_pdb_path(path: &Path) -> Option<(Uuid, PathBuf)> {
+    fn process_pdb_path(path: &Path) -> Option<(Uuid, PdbEntry)> {
         // Ignore non-PDBs
         match path.extension().and_then(|os_str| os_str.to_str()) {
-            Some("pdb") => (),
+            Some("pdb") | Some("pd_") => (),
             _ => return None,
         };
 
         log::info!("Checking PDB file: [{:?}]", path);
+        let modified = std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
 
         // Open PDB
-        let pdbfile = File::open(path).ok()?;
+        let expanded_path = match expand_if_compressed(path) {
+            Ok(expanded_path) => expanded_path,
+            Err(e) => {
+                log::warn!("Failed to expand [{:?}]: [{:?}]", path, e);
+                return None;
+            }
+        };
+
+        // Reject PDBs without fts_pdbsrc streams before paying for a full open
+        if !quick_scan::is_likely_fts_pdb(&expanded_path) {
+            log::trace!("Quick scan found no fts_pdbsrc streams");
+            return None;
+        }
+
+        let pdbfile = File::open(expanded_path).ok()?;
         log::trace!("Opened file");
         let mut pdb = pdb::PDB::open(pdbfile).ok()?;
         log::trace!("Opened file as PDB");
@@ -450,39 +1424,299 @@ mod fts_pdbsrc_service {
                 .and_then(|line| Uuid::parse_str(&line[key.len()..]).ok())?;
             log::trace!("Found UUID: {}", uuid);
 
+            // Symbol servers key PDBs by GUID+age
+            let info = pdb.pdb_information().ok()?;
+            let build_id = format!("{}{:x}", info.guid.to_simple(), info.age);
+
+            etw::write(
+                "PdbIndexed",
+                &[
+                    ("uuid", etw::Value::Str(&uuid.to_string())),
+                    ("path", etw::Value::Str(&path.to_string_lossy())),
+                ],
+            );
+
             // Return result
-            Some((uuid, path.to_owned()))
+            Some((
+                uuid,
+                PdbEntry {
+                    path: path.to_owned(),
+                    build_id,
+                    modified,
+                },
+            ))
         } else {
             log::trace!("Did not find VERCTRL=fts_pdbsrc");
             None
         }
     }
 
-    fn process_walkdir_entry(entry: walkdir::DirEntry) -> Option<(Uuid, PathBuf)> {
-        if entry.file_type().is_file() {
-            process_pdb_path(entry.path())
-        } else {
-            None
+    // Symbol stores often hold CAB compressed .pd_ files. These are expanded into a per-user cache
+    // keyed by path, size, and modification time. Other paths are returned unchanged.
+    // copy pasted from fts_pdbsrc/src/main.rs for simplicity
+    pub fn expand_if_compressed(pdb_path: &Path) -> anyhow::Result<PathBuf> {
+        use sha2::Digest;
+
+        let is_compressed = pdb_path
+            .extension()
+            .map(|ext| ext.eq_ignore_ascii_case("pd_"))
+            .unwrap_or(false);
+        if !is_compressed {
+            return Ok(pdb_path.to_owned());
+        }
+
+        // Shared temp dirs would let other users plant a PDB under the predictable cache name
+        let metadata = std::fs::metadata(pdb_path)?;
+        let key = format!("{:?}|{}|{:?}", pdb_path, metadata.len(), metadata.modified()?);
+        let key = hex::encode(sha2::Sha256::digest(key.as_bytes()));
+        let cache_dir = data_dir()
+            .map(|dir| dir.join("expanded"))
+            .ok_or_else(|| anyhow!("Failed to find local data dir"))?;
+        std::fs::create_dir_all(&cache_dir)?;
+        let stem = pdb_path.file_stem().unwrap_or_default().to_string_lossy();
+        let expanded_path = cache_dir.join(format!("{}-{}.pdb", stem, &key[..16]));
+        let sha256_path = expanded_path.with_extension("sha256");
+
+        // Cache entries are only trusted if they still match the hash recorded when they were expanded
+        let is_cached = match (
+            std::fs::read_to_string(&sha256_path),
+            std::fs::read(&expanded_path),
+        ) {
+            (Ok(expected), Ok(expanded)) => hex::encode(sha2::Sha256::digest(&expanded)) == expected.trim(),
+            _ => false,
+        };
+        if !is_cached {
+            // Unique temp file so concurrent or interrupted expands never clobber a valid cache entry
+            let partial_path = tempfile::Builder::new()
+                .prefix(&format!("{}-", stem))
+                .suffix(".partial")
+                .tempfile_in(&cache_dir)?
+                .into_temp_path();
+
+            // expand ships with Windows. Elsewhere cabextract is the common package.
+            #[cfg(windows)]
+            let status = subprocess::Exec::cmd("expand")
+                .arg(pdb_path)
+                .arg(&partial_path)
+                .stdout(subprocess::Redirection::Pipe)
+                .join()?;
+            #[cfg(not(windows))]
+            let status = subprocess::Exec::cmd("cabextract")
+                .arg("--pipe")
+                .arg(pdb_path)
+                .stdout(subprocess::Redirection::File(File::create(&partial_path)?))
+                .join()?;
+            if !status.success() {
+                bail!("Failed to expand [{:?}]. Status: [{:?}]", pdb_path, status);
+            }
+            let sha256 = hex::encode(sha2::Sha256::digest(&std::fs::read(&partial_path)?));
+            partial_path.persist(&expanded_path)?;
+            std::fs::write(&sha256_path, sha256)?;
         }
+
+        Ok(expanded_path)
     }
 
-    fn find_pdbs(paths: &[ConfigPath]) -> HashMap<Uuid, PathBuf> {
+    // Settings of the configured path a ScanItem was found under
+    struct ScanRoot {
+        follow_symlinks: bool,
+        exclude: ExcludePatterns,
+    }
+
+    // Unit of work for find_pdbs
+    enum ScanItem<'a> {
+        Directory(PathBuf, &'a ScanRoot),
+        File(PathBuf),
+    }
+
+    // Re-walks roots, or only path if given, and replaces their indexed PDBs. Returns number found.
+    fn rescan(roots: &Roots, pdbs: &PdbDb, path: Option<&Path>) -> anyhow::Result<usize> {
+        let scan_paths: Vec<ConfigPath> = match path {
+            Some(path) => {
+                let root = roots
+                    .paths
+                    .iter()
+                    .find(|root| path.starts_with(&root.path))
+                    .ok_or_else(|| anyhow!("[{:?}] is not under a configured path", path))?;
+                vec![ConfigPath {
+                    path: path.to_owned(),
+                    follow_symlinks: root.follow_symlinks,
+                    exclude: root.exclude.clone(),
+                }]
+            }
+            None => roots.paths.clone(),
+        };
+
+        let found = find_pdbs(&Roots {
+            paths: scan_paths,
+            duplicate_policy: roots.duplicate_policy,
+        });
+        let num_found = found.len();
+        let mut pdbs = pdbs.lock().unwrap();
+        match path {
+            Some(path) => {
+                pdbs.retain(|_, entry| !entry.path.starts_with(path));
+                for (uuid, entry) in found {
+                    insert_pdb(&mut pdbs, uuid, entry, roots);
+                }
+            }
+            None => *pdbs = found,
+        }
+
+        Ok(num_found)
+    }
+
+    // Adds PDB to index. If another copy is already indexed, keeps whichever roots.duplicate_policy prefers.
+    fn insert_pdb(pdbs: &mut HashMap<Uuid, PdbEntry>, uuid: Uuid, entry: PdbEntry, roots: &Roots) {
+        let existing = match pdbs.get(&uuid) {
+            Some(existing) if existing.path != entry.path => existing,
+            _ => {
+                pdbs.insert(uuid, entry);
+                return;
+            }
+        };
+
+        let root_idx = |path: &Path| roots.paths.iter().position(|root| path.starts_with(&root.path));
+        let replace = match roots.duplicate_policy {
+            DuplicatePolicy::Newest => entry.modified > existing.modified,
+            DuplicatePolicy::FirstRoot => match (root_idx(&entry.path), root_idx(&existing.path)) {
+                (Some(new_idx), Some(existing_idx)) => new_idx < existing_idx,
+                (new_idx, existing_idx) => new_idx.is_some() && existing_idx.is_none(),
+            },
+            DuplicatePolicy::Warn => false,
+        };
+
+        log::warn!(
+            "PDB [{}] found at both [{:?}] and [{:?}]. Keeping [{:?}] per [{:?}] policy.",
+            uuid,
+            existing.path,
+            entry.path,
+            if replace { &entry.path } else { &existing.path },
+            roots.duplicate_policy
+        );
+
+        if replace {
+            pdbs.insert(uuid, entry);
+        }
+    }
+
+    fn find_pdbs(roots: &Roots) -> HashMap<Uuid, PdbEntry> {
         log::info!("Searching for PDBs:");
         let start = std::time::Instant::now();
+        etw::write(
+            "ScanStarted",
+            &[("roots", etw::Value::U64(roots.paths.len() as u64))],
+        );
+
+        // Symbol shares commonly contain access denied directories and broken junctions.
+        // Unreadable entries are skipped rather than aborting the scan.
+        let num_unreadable = AtomicUsize::new(0);
+        let skip_unreadable = |path: &Path, e: std::io::Error| {
+            log::warn!("Skipping unreadable entry [{:?}]: [{}]", path, e);
+            num_unreadable.fetch_add(1, Ordering::Relaxed);
+        };
 
-        let pdbs = paths
+        // Symlinked directories are entered at most once so cycles terminate
+        let visited_links: Mutex<HashSet<PathBuf>> = Default::default();
+
+        // Directory enumeration and PDB header checks both run across every core. Shares are slow
+        // to list and large PDB counts are slow to open, so neither should be serial.
+        let scan_roots: Vec<ScanRoot> = roots
+            .paths
+            .iter()
+            .map(|path_entry| ScanRoot {
+                follow_symlinks: path_entry.follow_symlinks,
+                exclude: path_entry.exclude_patterns().unwrap_or_else(|e| {
+                    log::warn!("Ignoring excludes for [{:?}]: [{:?}]", path_entry.path, e);
+                    Default::default()
+                }),
+            })
+            .collect();
+        let items: Vec<ScanItem> = roots
+            .paths
             .iter()
-            .flat_map(|path_entry| {
+            .zip(&scan_roots)
+            .map(|(path_entry, scan_root)| {
                 log::info!("Searching root entry: [{:?}]", &path_entry.path);
-                walkdir::WalkDir::new(&path_entry.path)
-                    .follow_links(path_entry.follow_symlinks)
-                    .into_iter()
-                    .filter_map(|dir_entry| process_walkdir_entry(dir_entry.unwrap()))
+                ScanItem::Directory(path_entry.path.clone(), scan_root)
             })
-            .collect::<HashMap<Uuid, PathBuf>>();
+            .collect();
+        let found = job_system::run_recursive_job(items, |item, new_items, found| match item {
+            ScanItem::File(path) => found.extend(process_pdb_path(&path)),
+            ScanItem::Directory(dir, root) => {
+                let entries = match std::fs::read_dir(&dir) {
+                    Ok(entries) => entries,
+                    Err(e) => return skip_unreadable(&dir, e),
+                };
+                for entry in entries {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(e) => {
+                            skip_unreadable(&dir, e);
+                            continue;
+                        }
+                    };
+                    let path = entry.path();
+                    let file_type = match entry.file_type() {
+                        Ok(file_type) => file_type,
+                        Err(e) => {
+                            skip_unreadable(&path, e);
+                            continue;
+                        }
+                    };
+
+                    // Resolve symlinks to what they point at
+                    let (is_dir, is_file) = if file_type.is_symlink() {
+                        if !root.follow_symlinks {
+                            continue;
+                        }
+                        let target = std::fs::metadata(&path)
+                            .and_then(|metadata| Ok((metadata, std::fs::canonicalize(&path)?)));
+                        match target {
+                            Ok((metadata, target)) => {
+                                if metadata.is_dir() && !visited_links.lock().unwrap().insert(target) {
+                                    continue;
+                                }
+                                (metadata.is_dir(), metadata.is_file())
+                            }
+                            Err(e) => {
+                                skip_unreadable(&path, e);
+                                continue;
+                            }
+                        }
+                    } else {
+                        (file_type.is_dir(), file_type.is_file())
+                    };
+
+                    if is_dir && !root.exclude.excludes_directory(&path) {
+                        new_items.push(ScanItem::Directory(path, root));
+                    } else if is_file && !root.exclude.excludes_file(&path) {
+                        new_items.push(ScanItem::File(path));
+                    }
+                }
+            }
+        });
+        let mut pdbs: HashMap<Uuid, PdbEntry> = Default::default();
+        for (uuid, entry) in found {
+            insert_pdb(&mut pdbs, uuid, entry, roots);
+        }
+        let num_unreadable = num_unreadable.into_inner();
+        etw::write(
+            "ScanFinished",
+            &[
+                ("pdbs", etw::Value::U64(pdbs.len() as u64)),
+                ("unreadable", etw::Value::U64(num_unreadable as u64)),
+                ("duration_ms", etw::Value::U64(start.elapsed().as_millis() as u64)),
+            ],
+        );
 
         log::info!("Search time [{:?}]", std::time::Instant::now() - start);
         log::info!("Found PDBs: [{:?}]", pdbs);
+        log::info!(
+            "Scan summary: [{}] PDBs found. [{}] unreadable entries skipped.",
+            pdbs.len(),
+            num_unreadable
+        );
 
         pdbs
     }
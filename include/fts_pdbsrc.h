@@ -0,0 +1,33 @@
+// C API for fts_pdbsrc_ffi.dll
+//
+// Lets native debugger extensions and crash tools extract embedded sources without
+// spawning fts_pdbsrc.exe. Strings are NUL terminated UTF-8.
+#pragma once
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Reads fts_pdbsrc_config.json from config_path. Otherwise config is found as by fts_pdbsrc.exe:
+// FTS_PDBSRC_CONFIG, then %APPDATA%\fts\fts_pdbsrc_config.json if it exists, then beside the
+// host exe. Returns 0 on success, otherwise 1.
+int32_t fts_pdbsrc_set_config(const char* config_path);
+
+// Extracts file, as named in the PDB's srcsrv stream, from the PDB with FTS_PDBSTR_UUID uuid.
+// Returns 0 on success, otherwise the exit code `fts_pdbsrc extract_one` would return:
+//   1 general error, 2 no service, 3 uuid unknown, 4 PDB unreadable, 5 stream missing,
+//   6 decrypt failed, 7 decompress failed, 8 write failed, 9 tamper detected, 10 hash mismatch,
+//   11 invalid arguments
+int32_t fts_pdbsrc_extract(const char* uuid, const char* file, const char* out_path);
+
+// Asks fts_pdbsrc_service for the path of the PDB with FTS_PDBSTR_UUID uuid. Path is written to
+// path_out, NUL terminated, if it fits in path_out_len bytes. Returns bytes required including
+// NUL, or 0 if the PDB was not found. Pass NULL and 0 to query the required size.
+size_t fts_pdbsrc_find_pdb(const char* uuid, char* path_out, size_t path_out_len);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,181 @@
+// ----------------------------------------------------------------------------
+// Access control for extracted source
+//
+// Extracted files may be proprietary. Directories created during extraction
+// get a protected DACL granting access only to the current user, SYSTEM, and
+// any explicitly listed principals. Nothing is inherited from the parent, so a
+// permissive parent directory does not leak source to other users.
+// ----------------------------------------------------------------------------
+#[cfg(windows)]
+use anyhow::*;
+use std::path::Path;
+
+// Replaces DACL of path. extra_principals are SIDs or SDDL aliases such as "BU" or "S-1-5-32-545".
+#[cfg(windows)]
+pub fn restrict_access(path: &Path, extra_principals: &[String]) -> anyhow::Result<()> {
+    use std::ptr::null_mut;
+    use win32::*;
+
+    let mut sddl = format!("D:P(A;OICI;FA;;;{})(A;OICI;FA;;;SY)", current_user_sid()?);
+    for principal in extra_principals {
+        sddl += &format!("(A;OICI;FA;;;{})", principal);
+    }
+    let wide_sddl: Vec<u16> = sddl.encode_utf16().chain(Some(0)).collect();
+    let mut wide_path: Vec<u16> = super::long_path::to_extended(path)
+        .to_string_lossy()
+        .encode_utf16()
+        .chain(Some(0))
+        .collect();
+
+    unsafe {
+        let mut descriptor: *mut std::ffi::c_void = null_mut();
+        if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            wide_sddl.as_ptr(),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            null_mut(),
+        ) == 0
+        {
+            bail!(
+                "Invalid security descriptor [{}]: [{}]",
+                sddl,
+                std::io::Error::last_os_error()
+            );
+        }
+
+        let mut dacl_present: i32 = 0;
+        let mut dacl_defaulted: i32 = 0;
+        let mut dacl: *mut std::ffi::c_void = null_mut();
+        GetSecurityDescriptorDacl(descriptor, &mut dacl_present, &mut dacl, &mut dacl_defaulted);
+
+        let result = SetNamedSecurityInfoW(
+            wide_path.as_mut_ptr(),
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            null_mut(),
+            null_mut(),
+            dacl,
+            null_mut(),
+        );
+        LocalFree(descriptor);
+
+        if result != ERROR_SUCCESS {
+            bail!(
+                "Failed to set permissions on [{:?}]: [{}]",
+                path,
+                std::io::Error::from_raw_os_error(result as i32)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn restrict_access(_path: &Path, _extra_principals: &[String]) -> anyhow::Result<()> {
+    Ok(())
+}
+
+// Returns SID of user running this process, e.g. S-1-5-21-...
+#[cfg(windows)]
+fn current_user_sid() -> anyhow::Result<String> {
+    use std::ptr::null_mut;
+    use win32::*;
+
+    unsafe {
+        let mut token: *mut std::ffi::c_void = null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            bail!(
+                "Failed to open process token: [{}]",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        // First call gets size, second call gets TOKEN_USER
+        let mut needed: u32 = 0;
+        GetTokenInformation(token, TOKEN_USER_CLASS, null_mut(), 0, &mut needed);
+        let mut buffer: Vec<u8> = vec![0; needed as usize];
+        let ok = GetTokenInformation(
+            token,
+            TOKEN_USER_CLASS,
+            buffer.as_mut_ptr() as *mut std::ffi::c_void,
+            needed,
+            &mut needed,
+        );
+        CloseHandle(token);
+        if ok == 0 {
+            bail!(
+                "Failed to query token user: [{}]",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        // TOKEN_USER starts with a pointer to the user's SID
+        let sid = std::ptr::read_unaligned(buffer.as_ptr() as *const *mut std::ffi::c_void);
+        let mut wide_sid: *mut u16 = null_mut();
+        if ConvertSidToStringSidW(sid, &mut wide_sid) == 0 {
+            bail!("Failed to format SID: [{}]", std::io::Error::last_os_error());
+        }
+        let len = (0..).take_while(|idx| *wide_sid.add(*idx) != 0).count();
+        let sid_string = String::from_utf16_lossy(std::slice::from_raw_parts(wide_sid, len));
+        LocalFree(wide_sid as *mut std::ffi::c_void);
+
+        Ok(sid_string)
+    }
+}
+
+// Minimal security bindings. See sddl.h, aclapi.h, and securitybaseapi.h
+#[cfg(windows)]
+#[allow(non_snake_case)]
+mod win32 {
+    use std::ffi::c_void;
+
+    pub const ERROR_SUCCESS: u32 = 0;
+    pub const SDDL_REVISION_1: u32 = 1;
+    pub const SE_FILE_OBJECT: u32 = 1;
+    pub const DACL_SECURITY_INFORMATION: u32 = 0x0000_0004;
+    pub const PROTECTED_DACL_SECURITY_INFORMATION: u32 = 0x8000_0000;
+    pub const TOKEN_QUERY: u32 = 0x0008;
+    pub const TOKEN_USER_CLASS: u32 = 1;
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        pub fn ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            string_security_descriptor: *const u16,
+            string_sd_revision: u32,
+            security_descriptor: *mut *mut c_void,
+            security_descriptor_size: *mut u32,
+        ) -> i32;
+        pub fn GetSecurityDescriptorDacl(
+            security_descriptor: *mut c_void,
+            dacl_present: *mut i32,
+            dacl: *mut *mut c_void,
+            dacl_defaulted: *mut i32,
+        ) -> i32;
+        pub fn SetNamedSecurityInfoW(
+            object_name: *mut u16,
+            object_type: u32,
+            security_info: u32,
+            owner: *mut c_void,
+            group: *mut c_void,
+            dacl: *mut c_void,
+            sacl: *mut c_void,
+        ) -> u32;
+        pub fn OpenProcessToken(process: *mut c_void, desired_access: u32, token: *mut *mut c_void) -> i32;
+        pub fn GetTokenInformation(
+            token: *mut c_void,
+            token_information_class: u32,
+            token_information: *mut c_void,
+            token_information_length: u32,
+            return_length: *mut u32,
+        ) -> i32;
+        pub fn ConvertSidToStringSidW(sid: *mut c_void, string_sid: *mut *mut u16) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetCurrentProcess() -> *mut c_void;
+        pub fn CloseHandle(handle: *mut c_void) -> i32;
+        pub fn LocalFree(mem: *mut c_void) -> *mut c_void;
+    }
+}
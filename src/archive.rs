@@ -0,0 +1,178 @@
+// ----------------------------------------------------------------------------
+// Archive readers and writers for exported sources
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// Source bundles are a zip prefixed by a small header
+const SOURCE_BUNDLE_MAGIC: &[u8; 4] = b"SYSB";
+const SOURCE_BUNDLE_VERSION: u32 = 2;
+
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+
+// Describes contents of an exported archive. Files are stored at their relative path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub uuid: Option<String>,
+    pub pdb: String,
+    pub files: Vec<ArchiveManifestFile>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArchiveManifestFile {
+    pub original_path: String,
+    pub relative_path: String,
+    pub size: u64,
+}
+
+pub struct BundleFile<'a> {
+    pub original_path: &'a str,
+    pub contents: &'a [u8],
+}
+
+#[derive(Serialize)]
+struct SourceBundleManifest {
+    files: BTreeMap<String, SourceBundleFileInfo>,
+    debug_id: String,
+    object_name: String,
+}
+
+#[derive(Serialize)]
+struct SourceBundleFileInfo {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    path: String,
+}
+
+// Writes a source bundle in the format consumed by Sentry and Symbolicator
+pub fn write_source_bundle(
+    out_path: &Path,
+    debug_id: &str,
+    object_name: &str,
+    files: &[BundleFile],
+) -> anyhow::Result<()> {
+    let mut file = File::create(out_path)?;
+    file.write_all(SOURCE_BUNDLE_MAGIC)?;
+    file.write_all(&SOURCE_BUNDLE_VERSION.to_le_bytes())?;
+
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    let mut manifest = SourceBundleManifest {
+        files: Default::default(),
+        debug_id: debug_id.to_owned(),
+        object_name: object_name.to_owned(),
+    };
+
+    for file in files {
+        // Zip entries can't contain drive letters or backslashes
+        let bundle_path = format!(
+            "files/{}",
+            file.original_path
+                .replace('\\', "/")
+                .replace(':', "")
+                .trim_start_matches('/')
+        );
+
+        zip.start_file(bundle_path.as_str(), options)?;
+        zip.write_all(file.contents)?;
+
+        manifest.files.insert(
+            bundle_path,
+            SourceBundleFileInfo {
+                kind: "source",
+                path: file.original_path.to_owned(),
+            },
+        );
+    }
+
+    zip.start_file(MANIFEST_FILENAME, options)?;
+    serde_json::to_writer(&mut zip, &manifest)?;
+    zip.finish()?;
+
+    Ok(())
+}
+
+// Writes manifest plus contents of each manifest file as a zip
+pub fn write_zip(out_path: &Path, manifest: &ArchiveManifest, contents: &[&[u8]]) -> anyhow::Result<()> {
+    let mut zip = zip::ZipWriter::new(File::create(out_path)?);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for (file, bytes) in manifest.files.iter().zip(contents) {
+        zip.start_file(file.relative_path.as_str(), options)?;
+        zip.write_all(bytes)?;
+    }
+
+    zip.start_file(MANIFEST_FILENAME, options)?;
+    serde_json::to_writer_pretty(&mut zip, manifest)?;
+    zip.finish()?;
+
+    Ok(())
+}
+
+// Writes manifest plus contents of each manifest file as a tar
+pub fn write_tar(out_path: &Path, manifest: &ArchiveManifest, contents: &[&[u8]]) -> anyhow::Result<()> {
+    let mut tar = tar::Builder::new(File::create(out_path)?);
+
+    let mut append = |path: &str, bytes: &[u8]| -> anyhow::Result<()> {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, path, bytes)?;
+        Ok(())
+    };
+
+    for (file, bytes) in manifest.files.iter().zip(contents) {
+        append(&file.relative_path, bytes)?;
+    }
+    append(MANIFEST_FILENAME, &serde_json::to_vec_pretty(manifest)?)?;
+
+    tar.finish()?;
+    Ok(())
+}
+
+// Reads every file in a zip or tar archive. Keys are relative paths with forward slashes.
+pub fn read_archive(archive_path: &Path) -> anyhow::Result<HashMap<String, Vec<u8>>> {
+    let mut files: HashMap<String, Vec<u8>> = Default::default();
+
+    let is_tar = archive_path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("tar"))
+        .unwrap_or(false);
+
+    if is_tar {
+        let mut tar = tar::Archive::new(File::open(archive_path)?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let name = entry.path()?.to_string_lossy().replace('\\', "/");
+            let mut bytes: Vec<u8> = Default::default();
+            entry.read_to_end(&mut bytes)?;
+            files.insert(name, bytes);
+        }
+    } else {
+        let mut zip = zip::ZipArchive::new(File::open(archive_path)?)
+            .with_context(|| format!("Failed to open [{:?}] as zip", archive_path))?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            if entry.is_dir() {
+                continue;
+            }
+
+            let name = entry.name().replace('\\', "/");
+            let mut bytes: Vec<u8> = Default::default();
+            entry.read_to_end(&mut bytes)?;
+            files.insert(name, bytes);
+        }
+    }
+
+    Ok(files)
+}
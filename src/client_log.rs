@@ -0,0 +1,88 @@
+// ----------------------------------------------------------------------------
+// Console verbosity and client log file
+//
+// Visual Studio's source server runs extract_one invisibly, so whatever it
+// prints is lost. Every run appends to a log, by default
+// fts_pdbsrc_client.log in the temp dir next to the extraction failure record.
+// Once the log passes MAX_LOG_SIZE it rolls over to <log>.1, so at most two
+// logs are kept.
+//
+// -q hides progress messages and -v shows verbose ones. Both are always
+// logged. Command output, warnings, and errors are printed regardless.
+// ----------------------------------------------------------------------------
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
+
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const DEFAULT_LOG_FILENAME: &str = "fts_pdbsrc_client.log";
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    Quiet = 0,
+    Normal = 1,
+    Verbose = 2,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static LOG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// Prints unless quiet
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        $crate::client_log::log(&line);
+        if $crate::client_log::verbosity() >= $crate::client_log::Verbosity::Normal {
+            println!("{}", line);
+        }
+    }};
+}
+
+// Prints to stderr only if verbose so extract_one --stdout stays clean
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        $crate::client_log::log(&line);
+        if $crate::client_log::verbosity() >= $crate::client_log::Verbosity::Verbose {
+            eprintln!("{}", line);
+        }
+    }};
+}
+
+pub fn init(verbosity: Verbosity, log_path: Option<PathBuf>) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+    let log_path = log_path.unwrap_or_else(|| std::env::temp_dir().join(DEFAULT_LOG_FILENAME));
+    let _ = LOG_PATH.set(log_path);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+// Appends timestamped line. Logging never fails a command, so errors are ignored.
+pub fn log(line: &str) {
+    let log_path = match LOG_PATH.get() {
+        Some(log_path) => log_path,
+        None => return,
+    };
+
+    if fs::metadata(log_path).is_ok_and(|metadata| metadata.len() > MAX_LOG_SIZE) {
+        let mut rolled = OsString::from(log_path.as_os_str());
+        rolled.push(".1");
+        let _ = fs::rename(log_path, rolled);
+    }
+
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(log_path) {
+        let timestamp = super::sbom::utc_timestamp(std::time::SystemTime::now());
+        let _ = writeln!(file, "{} [{}] {}", timestamp, std::process::id(), line);
+    }
+}
@@ -0,0 +1,122 @@
+// ----------------------------------------------------------------------------
+// Client config
+//
+// Read by fts_pdbsrc.exe and fts_pdbsrc_ffi.dll alike. Project config used by
+// postbuild lives with the CLI.
+// ----------------------------------------------------------------------------
+use super::lock;
+use super::service_client::{DEFAULT_SERVICE_PORT, SERVICE_PORT};
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Config {
+    pub decode_keys: Vec<String>,
+
+    // Hex X25519 private keys for PDBs embedded with EncryptForRecipients
+    #[serde(default)]
+    pub private_keys: Vec<String>,
+
+    #[serde(default)]
+    pub lock_retry: lock::LockRetry,
+
+    #[serde(default)]
+    pub extract: ExtractConfig,
+
+    // Hex keys used to check srcsrv MACs. Extraction fails if a PDB's MAC matches none of them.
+    #[serde(default)]
+    pub srcsrv_mac_keys: Vec<String>,
+
+    // Also fail for PDBs without a srcsrv MAC
+    #[serde(default)]
+    pub require_srcsrv_mac: bool,
+
+    // Must match service port. Defaults to DEFAULT_SERVICE_PORT.
+    #[serde(default)]
+    pub service_port: Option<u16>,
+}
+
+// Files are always extracted to the per-user SRCSRVTRG directory, readable only by that user.
+// A shared cache lets users on the same machine skip decryption once anyone has extracted a file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractConfig {
+    pub shared_cache_dir: Option<PathBuf>,
+
+    // SIDs or SDDL aliases granted access to shared cache, e.g. "S-1-5-21-...-1105" for a group
+    pub shared_cache_users: Vec<String>,
+
+    // Search symbol caches for PDBs the service doesn't know. Downstream stores in _NT_SYMBOL_PATH
+    // are searched along with symbol_cache_dirs.
+    pub search_symbol_caches: bool,
+    pub symbol_cache_dirs: Vec<PathBuf>,
+
+    // extract_one deletes extracted files unused for this many days. Defaults to
+    // extract_cache::DEFAULT_MAX_AGE_DAYS. 0 keeps them forever.
+    pub cache_max_age_days: Option<u64>,
+}
+
+// Environment variable naming client config. --config takes precedence.
+const CONFIG_VARIABLE: &str = "FTS_PDBSRC_CONFIG";
+const CONFIG_FILENAME: &str = "fts_pdbsrc_config.json";
+
+// Missing or invalid config falls back to defaults, unless it was named by --config or FTS_PDBSRC_CONFIG
+pub fn read_config(config_arg: Option<&Path>) -> anyhow::Result<Config> {
+    let config_path = config_path(config_arg)?;
+    match read_config_from(&config_path) {
+        Ok(config) => Ok(config),
+        Err(e) if explicit_config_path(config_arg).is_some() => {
+            Err(e).with_context(|| format!("Failed to read config [{:?}]", config_path))
+        }
+        Err(_) => Ok(Default::default()),
+    }
+}
+
+// First of --config, FTS_PDBSRC_CONFIG, per-user config if it exists, and config beside the exe. The
+// exe may live in read-only Program Files, so per-user is used when neither config exists yet.
+pub fn config_path(config_arg: Option<&Path>) -> anyhow::Result<PathBuf> {
+    if let Some(config_path) = explicit_config_path(config_arg) {
+        return Ok(config_path);
+    }
+
+    let user_config_path = user_config_path();
+    if let Some(user_config_path) = user_config_path.as_ref().filter(|path| path.exists()) {
+        return Ok(user_config_path.clone());
+    }
+
+    let exe_config_path = std::env::current_exe()?.with_file_name(CONFIG_FILENAME);
+    match user_config_path {
+        Some(user_config_path) if !exe_config_path.exists() => Ok(user_config_path),
+        _ => Ok(exe_config_path),
+    }
+}
+
+fn explicit_config_path(config_arg: Option<&Path>) -> Option<PathBuf> {
+    config_arg.map(Path::to_owned).or_else(|| {
+        std::env::var_os(CONFIG_VARIABLE)
+            .filter(|var| !var.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+// %APPDATA%\fts\fts_pdbsrc_config.json. None if APPDATA is unset.
+fn user_config_path() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("fts").join(CONFIG_FILENAME))
+}
+
+pub fn read_config_from(config_path: &Path) -> anyhow::Result<Config> {
+    let config_file = std::fs::File::open(config_path)?;
+    let config: Config = serde_json::from_reader(&config_file)?;
+    Ok(config)
+}
+
+// Settings read through globals rather than passed around
+pub fn apply_config(config: &Config) {
+    lock::configure(&config.lock_retry);
+    SERVICE_PORT.store(
+        config.service_port.unwrap_or(DEFAULT_SERVICE_PORT),
+        Ordering::Relaxed,
+    );
+}
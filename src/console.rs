@@ -0,0 +1,44 @@
+// ----------------------------------------------------------------------------
+// Console control handling for `fts_pdbsrc serve`
+//
+// Ctrl+C is delivered to every process attached to the console. The service
+// running in the foreground saves its index before exiting, so this process
+// must survive Ctrl+C and wait for it rather than return to the shell early.
+// ----------------------------------------------------------------------------
+
+// Ignores Ctrl+C, Ctrl+Break, and console close for the rest of this process. Not inherited by children.
+#[cfg(windows)]
+pub fn ignore_ctrl_c() -> anyhow::Result<()> {
+    use win32::*;
+
+    unsafe extern "system" fn handler(_ctrl_type: u32) -> i32 {
+        TRUE
+    }
+
+    if unsafe { SetConsoleCtrlHandler(Some(handler), TRUE) } == 0 {
+        anyhow::bail!(
+            "Failed to set console handler: [{}]",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn ignore_ctrl_c() -> anyhow::Result<()> {
+    Ok(())
+}
+
+// Minimal bindings. See consoleapi.h
+#[cfg(windows)]
+#[allow(non_snake_case)]
+mod win32 {
+    pub const TRUE: i32 = 1;
+
+    pub type HandlerRoutine = unsafe extern "system" fn(ctrl_type: u32) -> i32;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn SetConsoleCtrlHandler(handler: Option<HandlerRoutine>, add: i32) -> i32;
+    }
+}
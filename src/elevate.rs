@@ -0,0 +1,192 @@
+// ----------------------------------------------------------------------------
+// Elevation for commands that need administrator rights
+//
+// Installing a service from a normal terminal fails deep inside the service
+// control manager. Instead the command is relaunched through ShellExecute with
+// the "runas" verb, which shows the UAC prompt, and this process waits for the
+// elevated copy to finish. The elevated copy gets its own console window so
+// its output isn't visible here. Only its exit code is reported.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+
+// Appended to relaunched arguments so a copy that is somehow still not elevated fails instead of looping
+#[cfg(windows)]
+const NO_ELEVATE_FLAG: &str = "--no-elevate";
+
+// True if this process has an elevated token
+#[cfg(windows)]
+pub fn is_elevated() -> bool {
+    use std::ptr::null_mut;
+    use win32::*;
+
+    unsafe {
+        let mut token: *mut std::ffi::c_void = null_mut();
+        if OpenProcessToken(GetCurrentProcess(), TOKEN_QUERY, &mut token) == 0 {
+            return false;
+        }
+
+        let mut elevation: u32 = 0;
+        let mut needed: u32 = 0;
+        let ok = GetTokenInformation(
+            token,
+            TOKEN_ELEVATION_CLASS,
+            &mut elevation as *mut u32 as *mut std::ffi::c_void,
+            std::mem::size_of::<u32>() as u32,
+            &mut needed,
+        );
+        CloseHandle(token);
+        ok != 0 && elevation != 0
+    }
+}
+
+#[cfg(not(windows))]
+pub fn is_elevated() -> bool {
+    true
+}
+
+// Runs this exe again, elevated, with the same arguments and waits for it. Errors if the UAC prompt
+// is declined or the elevated copy fails.
+#[cfg(windows)]
+pub fn relaunch_elevated() -> anyhow::Result<()> {
+    use win32::*;
+
+    let exe = std::env::current_exe()?;
+    let parameters: Vec<String> = std::env::args()
+        .skip(1)
+        .chain(Some(NO_ELEVATE_FLAG.to_owned()))
+        .map(|arg| quote_arg(&arg))
+        .collect();
+    let wide = |s: &str| -> Vec<u16> { s.encode_utf16().chain(Some(0)).collect() };
+    let verb = wide("runas");
+    let file = wide(&exe.to_string_lossy());
+    let parameters = wide(&parameters.join(" "));
+
+    status!("Not running as administrator. Relaunching elevated.");
+    unsafe {
+        let mut info: SHELLEXECUTEINFOW = std::mem::zeroed();
+        info.size = std::mem::size_of::<SHELLEXECUTEINFOW>() as u32;
+        info.mask = SEE_MASK_NOCLOSEPROCESS;
+        info.verb = verb.as_ptr();
+        info.file = file.as_ptr();
+        info.parameters = parameters.as_ptr();
+        info.show = SW_SHOWNORMAL;
+        if ShellExecuteExW(&mut info) == 0 {
+            bail!(
+                "Failed to relaunch elevated: [{}]",
+                std::io::Error::last_os_error()
+            );
+        }
+        if info.process.is_null() {
+            bail!("Failed to relaunch elevated: no process was started");
+        }
+
+        WaitForSingleObject(info.process, INFINITE);
+        let mut exit_code: u32 = 0;
+        let ok = GetExitCodeProcess(info.process, &mut exit_code);
+        CloseHandle(info.process);
+        if ok == 0 {
+            bail!(
+                "Failed to get elevated exit code: [{}]",
+                std::io::Error::last_os_error()
+            );
+        }
+        if exit_code != 0 {
+            bail!("Elevated command failed with exit code [{}]", exit_code);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn relaunch_elevated() -> anyhow::Result<()> {
+    bail!("Elevation is only supported on Windows")
+}
+
+// Quotes arg so CommandLineToArgvW splits it back out unchanged
+#[cfg(windows)]
+fn quote_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(&[' ', '\t', '"'][..]) {
+        return arg.to_owned();
+    }
+
+    // Backslashes are only special before a quote
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                backslashes = 0;
+            }
+        }
+        if c != '\\' {
+            quoted.push(c);
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+// Minimal bindings. See shellapi.h, securitybaseapi.h, and processthreadsapi.h
+#[cfg(windows)]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+mod win32 {
+    use std::ffi::c_void;
+
+    pub const TOKEN_QUERY: u32 = 0x0008;
+    pub const TOKEN_ELEVATION_CLASS: u32 = 20;
+    pub const SEE_MASK_NOCLOSEPROCESS: u32 = 0x0000_0040;
+    pub const SW_SHOWNORMAL: i32 = 1;
+    pub const INFINITE: u32 = 0xFFFF_FFFF;
+
+    #[repr(C)]
+    pub struct SHELLEXECUTEINFOW {
+        pub size: u32,
+        pub mask: u32,
+        pub hwnd: *mut c_void,
+        pub verb: *const u16,
+        pub file: *const u16,
+        pub parameters: *const u16,
+        pub directory: *const u16,
+        pub show: i32,
+        pub inst_app: *mut c_void,
+        pub id_list: *mut c_void,
+        pub class: *const u16,
+        pub hkey_class: *mut c_void,
+        pub hot_key: u32,
+        pub icon_or_monitor: *mut c_void,
+        pub process: *mut c_void,
+    }
+
+    #[link(name = "shell32")]
+    extern "system" {
+        pub fn ShellExecuteExW(info: *mut SHELLEXECUTEINFOW) -> i32;
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        pub fn OpenProcessToken(process: *mut c_void, desired_access: u32, token: *mut *mut c_void) -> i32;
+        pub fn GetTokenInformation(
+            token: *mut c_void,
+            token_information_class: u32,
+            token_information: *mut c_void,
+            token_information_length: u32,
+            return_length: *mut u32,
+        ) -> i32;
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        pub fn GetCurrentProcess() -> *mut c_void;
+        pub fn CloseHandle(handle: *mut c_void) -> i32;
+        pub fn WaitForSingleObject(handle: *mut c_void, milliseconds: u32) -> u32;
+        pub fn GetExitCodeProcess(process: *mut c_void, exit_code: *mut u32) -> i32;
+    }
+}
@@ -0,0 +1,112 @@
+// ----------------------------------------------------------------------------
+// Files to embed that the PDB doesn't reference
+//
+// Line tables only list files that produced code. Headers holding only
+// declarations, .inl files, and generated assets never appear, yet they're
+// often wanted while debugging. --extra globs find them so they're embedded
+// and extracted by relative path like any other file.
+//
+// Relative globs are searched under every root. * and ? match within a path
+// component and ** matches any number of components. Case is ignored unless
+// exact root matching is requested.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use regex::{Regex, RegexBuilder};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// Files matching any glob, in glob then directory order. May contain duplicates.
+pub fn find(globs: &[String], roots: &[PathBuf], ignore_case: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for glob in globs {
+        let (base, pattern) = split_glob(glob);
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .with_context(|| format!("Invalid glob [{}]", glob))?;
+
+        let bases: Vec<PathBuf> = if base.is_absolute() {
+            vec![base]
+        } else {
+            roots.iter().map(|root| root.join(&base)).collect()
+        };
+
+        let num_files = files.len();
+        for base in bases {
+            walk(&base, &base, &pattern, &mut files)?;
+        }
+        if files.len() == num_files {
+            println!("Extra glob [{}] matched no files", glob);
+        }
+    }
+    Ok(files)
+}
+
+// Splits glob into directory before first wildcard and regex matching paths below it
+fn split_glob(glob: &str) -> (PathBuf, String) {
+    let mut base = PathBuf::new();
+    let mut wild: Vec<String> = Vec::new();
+    for component in Path::new(glob).components() {
+        let part = component.as_os_str().to_string_lossy();
+        let is_wild = part.contains(['*', '?']);
+        if wild.is_empty() && (!is_wild || !matches!(component, Component::Normal(_))) {
+            base.push(component);
+        } else {
+            wild.push(part.into_owned());
+        }
+    }
+
+    // Glob without wildcards names a single file
+    if wild.is_empty() {
+        let name = base.file_name().map(|name| name.to_string_lossy().into_owned());
+        if let Some(name) = name {
+            base.pop();
+            wild.push(name);
+        }
+    }
+
+    let mut pattern = String::from("^");
+    for (idx, part) in wild.iter().enumerate() {
+        let is_last = idx + 1 == wild.len();
+        if part == "**" {
+            pattern.push_str(if is_last { ".+" } else { "(?:[^/]+/)*" });
+            continue;
+        }
+        for c in part.chars() {
+            match c {
+                '*' => pattern.push_str("[^/]*"),
+                '?' => pattern.push_str("[^/]"),
+                _ => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        if !is_last {
+            pattern.push('/');
+        }
+    }
+    pattern.push('$');
+    (base, pattern)
+}
+
+// Collects files under dir whose path relative to base, with / separators, matches pattern.
+// Missing directories match nothing. Symlinked directories aren't followed.
+fn walk(base: &Path, dir: &Path, pattern: &Regex, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to list [{:?}]", dir)),
+    };
+
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            walk(base, &path, pattern, files)?;
+        } else if path.is_file() {
+            let relpath = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+            if pattern.is_match(&relpath) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,635 @@
+// ----------------------------------------------------------------------------
+// extract_one
+//
+// Finds a PDB, reads one embedded file, and writes it where the debugger
+// expects it. Shared by fts_pdbsrc.exe and fts_pdbsrc_ffi.dll so both fail the
+// same way. Opening and decrypting PDBs lives here too since every other
+// command reads PDBs the same way.
+// ----------------------------------------------------------------------------
+use super::config::{Config, ExtractConfig};
+use super::failure::{ExtractFailure, Failure};
+use super::service_client::{connect_to_service, find_pdb, ServiceStreams, FETCH_FILE_PROTOCOL_VERSION};
+use super::{acl, lock, manifest, portable_pdb, recipients, srcsrv, symbol_cache};
+use aes_gcm::aead::{Aead, NewAead, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::*;
+use pdb::PDB;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use structopt::StructOpt;
+use subprocess::*;
+use uuid::Uuid;
+
+#[derive(Debug, StructOpt)]
+pub struct ExtractOneOp {
+    #[structopt(short, long, help = "Uuid of PDB to extract from")]
+    pub pdb_uuid: Uuid,
+
+    // Paths are Vecs since srcsrv commands written by older versions don't quote arguments.
+    // Paths with spaces arrive split across several values.
+    #[structopt(short, long, min_values = 1, help = "File to extract")]
+    pub file: Vec<String>,
+
+    #[structopt(short, long, help = "Nonce used to decode")]
+    pub nonce: Option<String>,
+
+    #[structopt(
+        short,
+        long,
+        min_values = 1,
+        required_unless = "stdout",
+        help = "Output path, including filename, to create"
+    )]
+    pub out: Vec<String>,
+
+    #[structopt(
+        long,
+        conflicts_with = "out",
+        help = "Write file contents to stdout instead of --out"
+    )]
+    pub stdout: bool,
+
+    #[structopt(long, help = "Service port. Overrides service_port in config")]
+    pub port: Option<u16>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Read this PDB instead of asking the service. Works without the service installed."
+    )]
+    pub pdb_path: Option<PathBuf>,
+}
+
+impl ExtractOneOp {
+    pub fn file(&self) -> String {
+        ExtractOneOp::rejoin(&self.file)
+    }
+
+    pub fn out(&self) -> PathBuf {
+        PathBuf::from(ExtractOneOp::rejoin(&self.out))
+    }
+
+    // Quotes are normally stripped by the shell. Strays are left when a quoted path ends with a backslash.
+    fn rejoin(parts: &[String]) -> String {
+        parts.join(" ").trim_matches('"').to_owned()
+    }
+}
+
+// Most recent extract_one failure. Debuggers hide extraction output so this is read by `doctor`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExtractFailureRecord {
+    pub failure: ExtractFailure,
+    pub message: String,
+    pub pdb_uuid: Uuid,
+    pub file: String,
+
+    // Seconds since unix epoch
+    pub time: u64,
+}
+
+impl ExtractFailureRecord {
+    pub fn path() -> PathBuf {
+        std::env::temp_dir().join("fts_pdbsrc_extract_failure.json")
+    }
+
+    pub fn write(&self) -> anyhow::Result<()> {
+        let file = File::create(ExtractFailureRecord::path())?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn read() -> anyhow::Result<Option<ExtractFailureRecord>> {
+        match File::open(ExtractFailureRecord::path()) {
+            Ok(file) => Ok(Some(serde_json::from_reader(file)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+pub fn record_extract_failure(op: &ExtractOneOp, failure: ExtractFailure, error: &anyhow::Error) {
+    let record = ExtractFailureRecord {
+        failure,
+        message: format!("{:?}", error),
+        pdb_uuid: op.pdb_uuid,
+        file: op.file(),
+        time: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+    if let Err(e) = record.write() {
+        eprintln!("Failed to write failure record: [{:?}]", e);
+    }
+}
+
+pub fn try_extract_one(
+    op: &ExtractOneOp,
+    config: &Config,
+) -> std::result::Result<(), (ExtractFailure, anyhow::Error)> {
+    let fail = |failure: ExtractFailure| move |e: anyhow::Error| (failure, e);
+    let out = op.out();
+
+    // Ask service for PDB path unless given one
+    let (pdb_path, stream) = match &op.pdb_path {
+        Some(pdb_path) => (pdb_path.clone(), None),
+        None => {
+            let mut stream = connect_to_service()
+                .context("Failed to connect to fts_pdbsrc_service")
+                .map_err(fail(ExtractFailure::NoService))?;
+            let pdb_path = match find_pdb(&mut stream, op.pdb_uuid) {
+                Ok(pdb_path) => pdb_path,
+                Err(e) if config.extract.search_symbol_caches => {
+                    search_symbol_caches(op.pdb_uuid, &out, &config.extract)
+                        .ok_or_else(|| e.context("PDB was not found in symbol caches either"))
+                        .map_err(fail(ExtractFailure::UuidUnknown))?
+                }
+                Err(e) => return Err((ExtractFailure::UuidUnknown, e)),
+            };
+            (pdb_path, Some(stream))
+        }
+    };
+    verbose!("Extracting [{}] from [{:?}]", op.file(), pdb_path);
+
+    let (mut pdb, relpath, manifest, embedded_uuid) = || -> anyhow::Result<_> {
+        // Load PDB. Service may be able to read PDBs the debugging user can't.
+        let mut pdb: Box<dyn manifest::NamedStreams> = match (open_streams(&pdb_path), stream) {
+            (Ok(pdb), _) => pdb,
+            (Err(e), Some(stream)) if stream.version >= FETCH_FILE_PROTOCOL_VERSION => {
+                eprintln!(
+                    "Failed to open [{:?}]. Fetching through service instead: [{}]",
+                    pdb_path, e
+                );
+                Box::new(ServiceStreams {
+                    stream,
+                    pdb_uuid: op.pdb_uuid,
+                })
+            }
+            (Err(e), _) => return Err(e),
+        };
+
+        // File arrives as %var2% which may be escaped
+        let srcsrv = read_fts_srcsrv(pdb.as_mut(), &pdb_path)?;
+        let relpath = match srcsrv.variable(srcsrv::ESCAPE_VARIABLE) {
+            Some("1") => srcsrv::unescape_field(&op.file()),
+            _ => op.file(),
+        };
+
+        let manifest = manifest::Manifest::read(pdb.as_mut())?;
+        let embedded_uuid = manifest
+            .as_ref()
+            .and_then(|manifest| manifest.pdb_uuid)
+            .or_else(|| {
+                srcsrv
+                    .variable("FTS_PDBSTR_UUID")
+                    .and_then(|uuid| Uuid::parse_str(uuid).ok())
+            });
+        Ok((pdb, relpath, manifest, embedded_uuid))
+    }()
+    .map_err(fail(ExtractFailure::PdbUnreadable))?;
+
+    // Explicit path may point at a different build than the debugger is asking about
+    if op.pdb_path.is_some() && embedded_uuid != Some(op.pdb_uuid) {
+        let error = anyhow!(
+            "PDB [{:?}] has uuid [{:?}] not [{}]",
+            pdb_path,
+            embedded_uuid,
+            op.pdb_uuid
+        );
+        return Err((ExtractFailure::UuidUnknown, error));
+    }
+
+    check_srcsrv_mac(
+        pdb.as_mut(),
+        manifest.as_ref(),
+        &config.srcsrv_mac_keys,
+        config.require_srcsrv_mac,
+    )
+    .map_err(fail(ExtractFailure::TamperDetected))?;
+
+    // Shared cache skips decryption. Key is srcsrv's raw %var2% which is identical for every user.
+    // Other users can write to it, so a hit is only served if it matches the hash recorded at embed.
+    // %var2% comes from the PDB, so one that isn't a plain relative path skips the shared cache.
+    let shared_path = config.extract.shared_cache_dir.as_ref().and_then(|dir| {
+        let relpath = untrusted_relpath(&op.file()).ok()?;
+        Some(dir.join(op.pdb_uuid.to_string()).join(relpath))
+    });
+    let modified = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.file(&relpath)?.modified());
+    let has_sha256 = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.file(&relpath)?.sha256.as_ref())
+        .is_some();
+    let shared_hit = shared_path
+        .as_ref()
+        .filter(|_| has_sha256)
+        .and_then(|path| Some((path, fs::read(path).ok()?)));
+    if let Some((shared_path, contents)) = shared_hit {
+        match manifest::check_sha256(manifest.as_ref(), &relpath, &contents) {
+            Ok(()) => {
+                verbose!("Found [{}] in shared cache [{:?}]", op.file(), shared_path);
+                let written = write_extract_output(op, &contents, modified);
+                return written.map_err(fail(ExtractFailure::WriteFailed));
+            }
+            Err(e) => eprintln!("Ignoring shared cache [{:?}]: [{:?}]", shared_path, e),
+        }
+    }
+
+    // Get file stream
+    let maybe_encrypted_text = manifest::read_file(pdb.as_mut(), manifest.as_ref(), &relpath)
+        .map_err(fail(ExtractFailure::StreamMissing))?;
+
+    // Get plaintext for maybe_encrypted_text
+    let encryption = manifest::read_encryption(&maybe_encrypted_text, manifest.as_ref(), op.nonce.as_deref())
+        .map_err(fail(ExtractFailure::DecryptFailed))?;
+    let stored = match encryption {
+        Some(encryption) => {
+            let key_id = manifest
+                .as_ref()
+                .and_then(|manifest| manifest.file(&relpath)?.key_id.as_deref());
+            decryption_keys(pdb.as_mut(), config)
+                .and_then(|keys| decrypt(&keys, key_id, &encryption, &maybe_encrypted_text))
+                .map_err(fail(ExtractFailure::DecryptFailed))?
+        }
+        None => maybe_encrypted_text,
+    };
+    let plaintext = manifest::read_dictionary(pdb.as_mut(), manifest.as_ref())
+        .and_then(|dictionary| manifest::decompress(stored, manifest.as_ref(), dictionary.as_deref()))
+        .map_err(fail(ExtractFailure::DecompressFailed))?;
+    manifest::check_sha256(manifest.as_ref(), &relpath, &plaintext)
+        .map_err(fail(ExtractFailure::HashMismatch))?;
+
+    // Write to output file with the source file's original modified time
+    write_extract_output(op, &plaintext, modified).map_err(fail(ExtractFailure::WriteFailed))?;
+
+    // Shared cache is an optimization so failure isn't fatal
+    if let Some(shared_path) = shared_path {
+        let shared_cache_users = &config.extract.shared_cache_users;
+        if let Err(e) = write_extracted(
+            &shared_path,
+            op.pdb_uuid,
+            &plaintext,
+            modified,
+            shared_cache_users,
+        ) {
+            eprintln!("Failed to write shared cache [{:?}]: [{:?}]", shared_path, e);
+        }
+    }
+
+    Ok(())
+}
+
+// Slow so only used when service has no match. Returns first PDB embedded with pdb_uuid.
+fn search_symbol_caches(pdb_uuid: Uuid, out: &Path, config: &ExtractConfig) -> Option<PathBuf> {
+    let mut dirs = config.symbol_cache_dirs.clone();
+    dirs.extend(symbol_cache::symbol_path_dirs());
+
+    // SRCSRVTRG is ...\fts_pdbsrc\<pdb name>\<uuid>\<relpath> which narrows the search
+    let uuid_str = pdb_uuid.to_string();
+    let components: Vec<_> = out
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    let pdb_name = components
+        .windows(2)
+        .find(|pair| pair[1].eq_ignore_ascii_case(&uuid_str))
+        .map(|pair| pair[0].as_ref());
+
+    let found = symbol_cache::find_pdbs(&dirs, pdb_name)
+        .into_iter()
+        .find(|path| {
+            let srcsrv = open_streams(path).and_then(|mut pdb| read_fts_srcsrv(&mut pdb, path));
+            let embedded_uuid = srcsrv.ok().and_then(|srcsrv| {
+                srcsrv
+                    .variable("FTS_PDBSTR_UUID")
+                    .and_then(|uuid| Uuid::parse_str(uuid).ok())
+            });
+            embedded_uuid == Some(pdb_uuid)
+        })?;
+    eprintln!(
+        "Service has no PDB with uuid [{}]. Found [{:?}] in symbol cache.",
+        pdb_uuid, found
+    );
+    Some(found)
+}
+
+// Writes to --out, or stdout for scripts and editors that pipe the file instead
+fn write_extract_output(
+    op: &ExtractOneOp,
+    contents: &[u8],
+    modified: Option<std::time::SystemTime>,
+) -> anyhow::Result<()> {
+    if op.stdout {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(contents)?;
+        stdout.flush()?;
+        return Ok(());
+    }
+
+    write_extracted(&op.out(), op.pdb_uuid, contents, modified, &[])?;
+    verbose!("Wrote [{}] bytes to [{:?}]", contents.len(), op.out());
+    Ok(())
+}
+
+// Writes extracted file. The directory named for the PDB uuid is restricted on every write, so a
+// directory someone else created first can't keep a permissive DACL. Targets outside a uuid
+// directory restrict the file's own directory instead.
+pub fn write_extracted(
+    path: &Path,
+    pdb_uuid: Uuid,
+    contents: &[u8],
+    modified: Option<std::time::SystemTime>,
+    extra_principals: &[String],
+) -> anyhow::Result<()> {
+    let out_dir = path
+        .parent()
+        .ok_or_else(|| anyhow!("Failed to get directory for path [{:?}]", path))?;
+    let uuid_str = pdb_uuid.to_string();
+    let restricted_dir = path
+        .ancestors()
+        .find(|dir| {
+            dir.file_name()
+                .is_some_and(|name| name.to_string_lossy().eq_ignore_ascii_case(&uuid_str))
+        })
+        .unwrap_or(out_dir);
+
+    fs::create_dir_all(out_dir)?;
+    acl::restrict_access(restricted_dir, extra_principals)?;
+
+    // Racing extraction already wrote it, and the debugger may have it open
+    if is_extracted(path, contents) {
+        return Ok(());
+    }
+
+    // Write to temp file then rename, so a killed or racing extraction never leaves a truncated file.
+    // Counter keeps temp names unique across threads of the extraction DLL.
+    static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("Failed to get file name for path [{:?}]", path))?;
+    let temp_path = out_dir.join(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = || -> anyhow::Result<()> {
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(contents)?;
+        if let Some(modified) = modified {
+            file.set_modified(modified)?;
+        }
+        drop(file);
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+
+        // Rename fails if another extraction won the race and the debugger opened its file
+        if !is_extracted(path, contents) {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+fn is_extracted(path: &Path, contents: &[u8]) -> bool {
+    let size_matches = fs::metadata(path).is_ok_and(|metadata| metadata.len() == contents.len() as u64);
+    size_matches && fs::read(path).is_ok_and(|existing| existing == contents)
+}
+
+// Relative paths from archive manifests and srcsrv are untrusted. They end up in extraction targets, so
+// one that is empty, rooted, has a drive, or contains .. could write outside the extract cache.
+pub fn untrusted_relpath(relative_path: &str) -> anyhow::Result<PathBuf> {
+    let parts: Vec<&str> = relative_path.split(['/', '\\']).collect();
+    let is_relative = parts
+        .iter()
+        .all(|part| !part.is_empty() && *part != "." && *part != ".." && !part.contains(':'));
+    if !is_relative {
+        bail!(
+            "[{}] must be a relative path without '.', '..', or a drive",
+            relative_path
+        );
+    }
+    Ok(parts.into_iter().collect())
+}
+
+// Checks srcsrv stream against MAC recorded in manifest. Unverifiable PDBs pass unless required.
+pub fn check_srcsrv_mac<S: manifest::NamedStreams + ?Sized>(
+    pdb: &mut S,
+    manifest: Option<&manifest::Manifest>,
+    keys: &[String],
+    required: bool,
+) -> anyhow::Result<()> {
+    let expected = match manifest.and_then(|manifest| manifest.srcsrv_mac.as_ref()) {
+        Some(expected) => expected,
+        None if required => bail!("PDB has no srcsrv MAC"),
+        None => return Ok(()),
+    };
+    if keys.is_empty() {
+        if required {
+            bail!("No keys configured to check srcsrv MAC");
+        }
+        return Ok(());
+    }
+
+    let srcsrv_stream = pdb
+        .read_stream("srcsrv")?
+        .ok_or_else(|| anyhow!("PDB has no srcsrv stream"))?;
+    for key in keys {
+        if manifest::verify_srcsrv_mac(key, &srcsrv_stream, expected)? {
+            return Ok(());
+        }
+    }
+
+    bail!("srcsrv stream does not match its MAC. Extraction commands may have been modified")
+}
+
+// Reads srcsrv stream and checks that it was written by fts_pdbsrc
+pub fn read_fts_srcsrv<S: manifest::NamedStreams + ?Sized>(
+    pdb: &mut S,
+    pdb_path: &Path,
+) -> anyhow::Result<srcsrv::SrcSrv> {
+    let srcsrv_stream = pdb
+        .read_stream("srcsrv")?
+        .ok_or_else(|| anyhow!("PDB [{:?}] has no srcsrv stream", pdb_path))?;
+    let srcsrv = srcsrv::SrcSrv::parse(std::str::from_utf8(&srcsrv_stream)?);
+    if srcsrv.variable("VERCTRL") != Some("fts_pdbsrc") {
+        bail!("PDB [{:?}] srcsrv stream was not written by fts_pdbsrc", pdb_path);
+    }
+
+    Ok(srcsrv)
+}
+
+// Missing PDBs get their own exit code so scripts can tell them apart from unreadable ones
+pub fn ensure_pdb_exists(pdb_path: &Path) -> anyhow::Result<()> {
+    if !pdb_path.exists() {
+        return Err(Failure::PdbNotFound).with_context(|| format!("PDB [{:?}] doesn't exist", pdb_path));
+    }
+    Ok(())
+}
+
+// Opens PDB for reading. Compressed .pd_ files are expanded first.
+pub fn open_pdb(pdb_path: &Path) -> anyhow::Result<PDB<'static, File>> {
+    ensure_pdb_exists(pdb_path)?;
+    let pdbfile = lock::open_read(&expand_if_compressed(pdb_path)?)?;
+    Ok(pdb::PDB::open(pdbfile)?)
+}
+
+// Opens MSF or portable PDB for reading named streams only
+pub fn open_streams(pdb_path: &Path) -> anyhow::Result<Box<dyn manifest::NamedStreams>> {
+    ensure_pdb_exists(pdb_path)?;
+    let pdb_path = expand_if_compressed(pdb_path)?;
+    if portable_pdb::is_portable_pdb(&pdb_path) {
+        Ok(Box::new(portable_pdb::PortablePdb::read(&pdb_path)?))
+    } else {
+        Ok(Box::new(open_pdb(&pdb_path)?))
+    }
+}
+
+// Symbol stores often hold CAB compressed .pd_ files. These are expanded into a per-user cache
+// keyed by path, size, and modification time. Other paths are returned unchanged.
+pub fn expand_if_compressed(pdb_path: &Path) -> anyhow::Result<PathBuf> {
+    let is_compressed = pdb_path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("pd_"))
+        .unwrap_or(false);
+    if !is_compressed {
+        return Ok(pdb_path.to_owned());
+    }
+
+    // Shared temp dirs would let other users plant a PDB under the predictable cache name
+    let metadata = fs::metadata(pdb_path)?;
+    let key = format!("{:?}|{}|{:?}", pdb_path, metadata.len(), metadata.modified()?);
+    let key = manifest::sha256(key.as_bytes());
+    let cache_dir = std::env::var_os("LOCALAPPDATA")
+        .map(|dir| PathBuf::from(dir).join("fts").join("expanded"))
+        .ok_or_else(|| anyhow!("LOCALAPPDATA is not set"))?;
+    fs::create_dir_all(&cache_dir)?;
+    let stem = pdb_path.file_stem().unwrap_or_default().to_string_lossy();
+    let expanded_path = cache_dir.join(format!("{}-{}.pdb", stem, &key[..16]));
+    let sha256_path = expanded_path.with_extension("sha256");
+
+    // Cache entries are only trusted if they still match the hash recorded when they were expanded
+    let is_cached = match (fs::read_to_string(&sha256_path), fs::read(&expanded_path)) {
+        (Ok(expected), Ok(expanded)) => manifest::sha256(&expanded) == expected.trim(),
+        _ => false,
+    };
+    if !is_cached {
+        // Unique temp file so concurrent or interrupted expands never clobber a valid cache entry
+        let partial_path = tempfile::Builder::new()
+            .prefix(&format!("{}-", stem))
+            .suffix(".partial")
+            .tempfile_in(&cache_dir)?
+            .into_temp_path();
+        let cmd = &[
+            "expand",
+            &pdb_path.to_string_lossy(),
+            &partial_path.to_string_lossy(),
+        ];
+        run_command(cmd).with_context(|| format!("Failed to expand [{:?}]", pdb_path))?;
+        let sha256 = manifest::sha256(&fs::read(&partial_path)?);
+        lock::persist(partial_path, &expanded_path)?;
+        fs::write(&sha256_path, sha256)?;
+    }
+
+    Ok(expanded_path)
+}
+
+// Returns configured decode keys plus the session key if PDB was encrypted for a configured private key
+pub fn decryption_keys<S: manifest::NamedStreams + ?Sized>(
+    pdb: &mut S,
+    config: &Config,
+) -> anyhow::Result<Vec<String>> {
+    let mut keys = config.decode_keys.clone();
+    if config.private_keys.is_empty() {
+        return Ok(keys);
+    }
+
+    // Not being a recipient isn't an error since a decode key may still match
+    if let Some(stream) = pdb.read_stream(recipients::STREAM_NAME)? {
+        if let Some(session_key) = recipients::unwrap(&stream, &config.private_keys)? {
+            keys.push(hex::encode(session_key));
+        }
+    }
+
+    Ok(keys)
+}
+
+// Key id, if recorded, selects the matching key rather than trying every one
+pub fn decrypt(
+    keys: &[String],
+    key_id: Option<&str>,
+    encryption: &manifest::Encryption,
+    stored: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let nonce = Nonce::from_slice(&encryption.nonce);
+    let (header, encrypted_text) = stored.split_at(encryption.header_len);
+
+    let keys: Vec<&String> = match key_id {
+        Some(key_id) => {
+            let matching: Vec<&String> = keys
+                .iter()
+                .filter(|key| hex::decode(key).is_ok_and(|key| manifest::key_id(&key) == key_id))
+                .collect();
+            if matching.is_empty() {
+                return Err(Failure::DecryptFailed).with_context(|| {
+                    format!(
+                        "No decode key has key id [{}]. Ask the PDB owner for that key.",
+                        key_id
+                    )
+                });
+            }
+            matching
+        }
+        None => keys.iter().collect(),
+    };
+
+    // Try to decrypt with each key
+    for hexkey in keys {
+        let try_key = |key_hex: &str, nonce| -> anyhow::Result<Vec<u8>> {
+            let key_bytes = hex::decode(key_hex)?;
+            let key = Key::from_slice(&key_bytes);
+            let cipher = Aes256Gcm::new(key);
+
+            match cipher.decrypt(
+                nonce,
+                Payload {
+                    msg: encrypted_text,
+                    aad: header,
+                },
+            ) {
+                Ok(plaintext) => Ok(plaintext),
+                Err(_) => bail!("Failed to decrypt with key"),
+            }
+        };
+
+        if let Ok(plaintext) = try_key(hexkey, nonce) {
+            return Ok(plaintext);
+        }
+    }
+
+    Err(Failure::DecryptFailed).context("Failed to decrypt with all keys")
+}
+
+fn run_command(cmd: &[&str]) -> anyhow::Result<()> {
+    let mut p = Popen::create(
+        cmd,
+        PopenConfig {
+            stdout: Redirection::Pipe,
+            ..Default::default()
+        },
+    )?;
+
+    let status = p.wait()?;
+    match status {
+        ExitStatus::Exited(0) => Ok(()),
+        _ => bail!("Encountered status [{:?}] on cmd [{:?}]", status, cmd),
+    }
+}
@@ -0,0 +1,168 @@
+// ----------------------------------------------------------------------------
+// Per-user cache of extracted source files
+//
+// srcsrv extracts to %LOCALAPPDATA%\fts\fts_pdbsrc\<pdb>\<uuid>\<file>. Debuggers
+// check that path before running extract_one, so once a file is extracted it's
+// never asked for again and the cache grows forever. extract_one prunes files
+// unused for extract.cache_max_age_days, at most once a day. `fts_pdbsrc cache`
+// lists and clears it by hand. Files a debugger has open are never deleted.
+// ----------------------------------------------------------------------------
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+pub const DEFAULT_MAX_AGE_DAYS: u64 = 30;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Modified time records the last prune
+const LAST_PRUNE_FILENAME: &str = ".last_prune";
+
+pub struct CachedFile {
+    pub path: PathBuf,
+
+    // None for files not under a <pdb>\<uuid> directory
+    pub pdb_uuid: Option<Uuid>,
+    pub size: u64,
+    pub last_used: SystemTime,
+}
+
+#[derive(Default)]
+pub struct Removed {
+    pub files: usize,
+    pub bytes: u64,
+
+    // Open in another process, e.g. a debugger, or otherwise failed to delete
+    pub skipped: usize,
+}
+
+// Where srcsrv commands written by embed extract to. None if LOCALAPPDATA is unset.
+pub fn root() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("fts").join("fts_pdbsrc"))
+}
+
+// Every cached file, in directory order. Empty if nothing has been extracted yet.
+pub fn files(root: &Path) -> anyhow::Result<Vec<CachedFile>> {
+    let mut files = Vec::new();
+    walk(root, root, &mut |file| {
+        files.push(file);
+        false
+    })?;
+    Ok(files)
+}
+
+// Prunes unless the last prune was less than a day ago
+pub fn prune_if_due(root: &Path, max_age: Duration) -> anyhow::Result<Removed> {
+    let last_prune_path = root.join(LAST_PRUNE_FILENAME);
+    let is_due = match fs::metadata(&last_prune_path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified
+            .elapsed()
+            .map_or(true, |elapsed| elapsed >= PRUNE_INTERVAL),
+        Err(_) => true,
+    };
+    if !is_due {
+        return Ok(Removed::default());
+    }
+
+    // Record first so concurrent extractions don't all prune
+    fs::write(&last_prune_path, b"")?;
+    prune(root, max_age)
+}
+
+// Removes files neither modified nor accessed within max_age
+pub fn prune(root: &Path, max_age: Duration) -> anyhow::Result<Removed> {
+    let cutoff = SystemTime::now()
+        .checked_sub(max_age)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    remove(root, |file| file.last_used < cutoff)
+}
+
+// Removes every file, or only those extracted from pdb_uuid
+pub fn clear(root: &Path, pdb_uuid: Option<Uuid>) -> anyhow::Result<Removed> {
+    remove(root, |file| pdb_uuid.is_none() || file.pdb_uuid == pdb_uuid)
+}
+
+// Removes matching files that aren't open, then any directories left empty
+fn remove(root: &Path, should_remove: impl Fn(&CachedFile) -> bool) -> anyhow::Result<Removed> {
+    let mut removed = Removed::default();
+    walk(root, root, &mut |file| {
+        if !should_remove(&file) {
+            return false;
+        }
+        if is_open(&file.path) || fs::remove_file(&file.path).is_err() {
+            removed.skipped += 1;
+            return false;
+        }
+        removed.files += 1;
+        removed.bytes += file.size;
+        true
+    })?;
+    Ok(removed)
+}
+
+// Calls visit for each file. visit returns true if it deleted the file. Directories left empty are
+// removed, except root. Returns true if dir is now empty. Symlinks are never followed or removed.
+fn walk(root: &Path, dir: &Path, visit: &mut dyn FnMut(CachedFile) -> bool) -> anyhow::Result<bool> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && dir == root => return Ok(true),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut is_empty = true;
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if walk(root, &path, visit)? && fs::remove_dir(&path).is_ok() {
+                continue;
+            }
+        } else if file_type.is_file() && entry.file_name() != LAST_PRUNE_FILENAME {
+            let metadata = entry.metadata()?;
+            let file = CachedFile {
+                pdb_uuid: pdb_uuid(root, &path),
+                size: metadata.len(),
+                last_used: last_used(&metadata),
+                path,
+            };
+            if visit(file) {
+                continue;
+            }
+        }
+        is_empty = false;
+    }
+    Ok(is_empty)
+}
+
+// Second component below root is the PDB uuid
+fn pdb_uuid(root: &Path, path: &Path) -> Option<Uuid> {
+    let uuid = path.strip_prefix(root).ok()?.components().nth(1)?;
+    Uuid::parse_str(&uuid.as_os_str().to_string_lossy()).ok()
+}
+
+// Access time may be disabled, so modified time counts as use too
+fn last_used(metadata: &fs::Metadata) -> SystemTime {
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let accessed = metadata.accessed().unwrap_or(SystemTime::UNIX_EPOCH);
+    modified.max(accessed)
+}
+
+// Debuggers may open files with FILE_SHARE_DELETE, which lets them be deleted out from under the
+// debugger. Opening without sharing fails if any other handle is open.
+#[cfg(windows)]
+fn is_open(path: &Path) -> bool {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    match fs::OpenOptions::new().read(true).share_mode(0).open(path) {
+        Ok(_) => false,
+        Err(e) => e.raw_os_error() == Some(ERROR_SHARING_VIOLATION),
+    }
+}
+
+// Deleting an open file elsewhere only unlinks it. Debuggers keep reading their open copy.
+#[cfg(not(windows))]
+fn is_open(_path: &Path) -> bool {
+    false
+}
@@ -0,0 +1,78 @@
+// ----------------------------------------------------------------------------
+// Failure categories and their exit codes
+//
+// Debuggers and CI scripts only see exit codes, so failures they can react to
+// get their own. fts_pdbsrc_ffi.dll returns the same codes.
+// ----------------------------------------------------------------------------
+use serde::{Deserialize, Serialize};
+
+// Why a command failed. Attached to errors as context so main can pick an exit code that CI scripts
+// and the debugger wrapper can react to. Other errors exit with 1. Codes match ExtractFailure where
+// categories overlap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Failure {
+    BadArguments,
+    PdbNotFound,
+    NoService,
+    DecryptFailed,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::NoService => ExtractFailure::NoService.exit_code(),
+            Failure::PdbNotFound => ExtractFailure::UuidUnknown.exit_code(),
+            Failure::DecryptFailed => ExtractFailure::DecryptFailed.exit_code(),
+            Failure::BadArguments => 11,
+        }
+    }
+
+    // Exit code for error, from outermost Failure it carries
+    pub fn exit_code_for(err: &anyhow::Error) -> i32 {
+        err.downcast_ref::<Failure>()
+            .map_or(1, |failure| failure.exit_code())
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::BadArguments => write!(f, "Invalid arguments"),
+            Failure::PdbNotFound => write!(f, "PDB not found"),
+            Failure::NoService => write!(f, "fts_pdbsrc_service is unreachable"),
+            Failure::DecryptFailed => write!(f, "No matching decode key"),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+// Why extract_one failed. Each category has its own exit code.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ExtractFailure {
+    NoService,
+    UuidUnknown,
+    PdbUnreadable,
+    StreamMissing,
+    DecryptFailed,
+    DecompressFailed,
+    WriteFailed,
+    TamperDetected,
+    HashMismatch,
+}
+
+impl ExtractFailure {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ExtractFailure::NoService => 2,
+            ExtractFailure::UuidUnknown => 3,
+            ExtractFailure::PdbUnreadable => 4,
+            ExtractFailure::StreamMissing => 5,
+            ExtractFailure::DecryptFailed => 6,
+            ExtractFailure::DecompressFailed => 7,
+            ExtractFailure::WriteFailed => 8,
+            ExtractFailure::TamperDetected => 9,
+            ExtractFailure::HashMismatch => 10,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// ----------------------------------------------------------------------------
+// C API exported by fts_pdbsrc_ffi.dll. Declared in include/fts_pdbsrc.h.
+//
+// Native debugger extensions and crash tools call these rather than spawning
+// fts_pdbsrc.exe. Strings are NUL terminated UTF-8. Panics are caught since
+// unwinding into C is undefined behavior.
+//
+// Config is found as by fts_pdbsrc.exe unless fts_pdbsrc_set_config is called
+// first: FTS_PDBSRC_CONFIG, then %APPDATA%\fts, then beside the host exe.
+// ----------------------------------------------------------------------------
+use super::config::{apply_config, read_config, read_config_from, Config};
+use super::extract::{record_extract_failure, try_extract_one, ExtractOneOp};
+use super::failure::Failure;
+use super::service_client::{connect_to_service, find_pdb};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// Returned for panics and config that can't be read. Same as fts_pdbsrc.exe exit code for general
+// errors.
+const FTS_PDBSRC_ERROR: i32 = 1;
+
+static CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+// Returns 0 on success
+#[no_mangle]
+pub unsafe extern "C" fn fts_pdbsrc_set_config(config_path: *const c_char) -> i32 {
+    guard(|| {
+        let config = read_config_from(Path::new(to_str(config_path)?)).ok()?;
+        *CONFIG.lock().unwrap() = Some(config);
+        Some(0)
+    })
+    .unwrap_or(FTS_PDBSRC_ERROR)
+}
+
+// Extracts file, as named in srcsrv, from PDB with uuid. Returns 0 on success, otherwise the code
+// extract_one would exit with.
+#[no_mangle]
+pub unsafe extern "C" fn fts_pdbsrc_extract(
+    uuid: *const c_char,
+    file: *const c_char,
+    out_path: *const c_char,
+) -> i32 {
+    guard(|| {
+        // Null, non UTF-8, or malformed uuid is what extract_one would reject as invalid arguments
+        let args = || {
+            Some((
+                Uuid::parse_str(to_str(uuid)?).ok()?,
+                to_str(file)?,
+                to_str(out_path)?,
+            ))
+        };
+        let (pdb_uuid, file, out_path) = match args() {
+            Some(args) => args,
+            None => return Some(Failure::BadArguments.exit_code()),
+        };
+        let op = ExtractOneOp {
+            pdb_uuid,
+            file: vec![file.to_owned()],
+            nonce: None,
+            out: vec![out_path.to_owned()],
+            stdout: false,
+            port: None,
+            pdb_path: None,
+        };
+        let config = config();
+        match try_extract_one(&op, &config) {
+            Ok(()) => Some(0),
+            Err((failure, error)) => {
+                record_extract_failure(&op, failure, &error);
+                Some(failure.exit_code())
+            }
+        }
+    })
+    .unwrap_or(FTS_PDBSRC_ERROR)
+}
+
+// Asks service for path of PDB with uuid. Path is written to path_out, NUL terminated, if it fits in
+// path_out_len bytes. Returns bytes required including NUL, or 0 if PDB was not found.
+#[no_mangle]
+pub unsafe extern "C" fn fts_pdbsrc_find_pdb(
+    uuid: *const c_char,
+    path_out: *mut c_char,
+    path_out_len: usize,
+) -> usize {
+    guard(|| {
+        let uuid = Uuid::parse_str(to_str(uuid)?).ok()?;
+        config();
+        let mut stream = connect_to_service().ok()?;
+        let path = find_pdb(&mut stream, uuid).ok()?;
+        let path = path.to_str()?;
+
+        let required = path.len() + 1;
+        if !path_out.is_null() && required <= path_out_len {
+            std::ptr::copy_nonoverlapping(path.as_ptr() as *const c_char, path_out, path.len());
+            *path_out.add(path.len()) = 0;
+        }
+        Some(required)
+    })
+    .unwrap_or(0)
+}
+
+// Config set by fts_pdbsrc_set_config, else config found as by fts_pdbsrc.exe with the host exe in
+// its place. Unreadable config means defaults. Also applies config globals.
+fn config() -> Config {
+    let config = CONFIG
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| read_config(None).unwrap_or_default());
+    apply_config(&config);
+    config
+}
+
+// None if null or not UTF-8
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+// Treats a panic in f as failure
+fn guard<R>(f: impl FnOnce() -> Option<R>) -> Option<R> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).ok().flatten()
+}
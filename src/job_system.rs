@@ -0,0 +1,49 @@
+// ----------------------------------------------------------------------------
+// Minimal job system for embarrassingly parallel work
+//
+// Workers pull the next item index from a shared counter until every item is
+// processed. Each worker owns state created by init, e.g. a compressor, so
+// jobs never contend on locks. Results are returned in input order so output
+// is deterministic regardless of thread count.
+// ----------------------------------------------------------------------------
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Runs job on every item across one worker per logical core
+pub fn run<T, S, R>(items: &[T], init: impl Fn() -> S + Sync, job: impl Fn(&mut S, &T) -> R + Sync) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let num_workers = num_cpus::get().max(1).min(items.len());
+    if num_workers <= 1 {
+        let mut state = init();
+        return items.iter().map(|item| job(&mut state, item)).collect();
+    }
+
+    let next_item = AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut state = init();
+                    let mut results: Vec<(usize, R)> = Default::default();
+                    loop {
+                        let idx = next_item.fetch_add(1, Ordering::Relaxed);
+                        match items.get(idx) {
+                            Some(item) => results.push((idx, job(&mut state, item))),
+                            None => break results,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+
+    results.sort_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,25 @@
+// ----------------------------------------------------------------------------
+// fts_pdbsrc_ffi.dll and the library fts_pdbsrc.exe is built on
+//
+// Holds the extraction path, from config and service protocol to reading and
+// decrypting PDBs, so native tools get exactly what fts_pdbsrc.exe uses.
+// Exported functions live in src/ffi.rs. The rest of the CLI stays in main.rs.
+// ----------------------------------------------------------------------------
+pub mod acl;
+#[macro_use]
+pub mod client_log;
+pub mod config;
+pub mod extract;
+pub mod extract_cache;
+pub mod failure;
+mod ffi;
+pub mod lock;
+pub mod long_path;
+pub mod manifest;
+pub mod msf;
+pub mod portable_pdb;
+pub mod recipients;
+pub mod sbom;
+pub mod service_client;
+pub mod srcsrv;
+pub mod symbol_cache;
@@ -0,0 +1,282 @@
+// ----------------------------------------------------------------------------
+// Retry for PDBs locked by another process
+//
+// PDBs are frequently held by the linker or mspdbsrv right after a build, or
+// are still being copied when extraction runs. Symbol upload may be reading a
+// PDB while embed replaces it. Sharing violations are retried with exponential
+// backoff. When retries are exhausted the processes holding the file are
+// reported via the Windows Restart Manager.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+const ERROR_ACCESS_DENIED: i32 = 5;
+const ERROR_SHARING_VIOLATION: i32 = 32;
+const ERROR_LOCK_VIOLATION: i32 = 33;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LockRetry {
+    pub attempts: u32,
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+}
+
+impl Default for LockRetry {
+    fn default() -> LockRetry {
+        LockRetry {
+            attempts: 10,
+            initial_delay_ms: 250,
+            max_delay_ms: 4000,
+        }
+    }
+}
+
+// Process wide settings. Set once from config at startup.
+static ATTEMPTS: AtomicU32 = AtomicU32::new(10);
+static INITIAL_DELAY_MS: AtomicU64 = AtomicU64::new(250);
+static MAX_DELAY_MS: AtomicU64 = AtomicU64::new(4000);
+
+pub fn configure(retry: &LockRetry) {
+    ATTEMPTS.store(retry.attempts, Ordering::Relaxed);
+    INITIAL_DELAY_MS.store(retry.initial_delay_ms, Ordering::Relaxed);
+    MAX_DELAY_MS.store(retry.max_delay_ms, Ordering::Relaxed);
+}
+
+// Opens file for reading, retrying while another process holds it
+pub fn open_read(path: &Path) -> anyhow::Result<File> {
+    retry(path, is_locked, || File::open(path))
+}
+
+// Reads whole file, retrying while another process holds it
+pub fn read(path: &Path) -> anyhow::Result<Vec<u8>> {
+    retry(path, is_locked, || std::fs::read(path))
+}
+
+// Waits until file can be opened for writing. Used before replacing PDB.
+pub fn wait_for_write_access(path: &Path) -> anyhow::Result<()> {
+    retry(path, is_locked, || {
+        OpenOptions::new().read(true).write(true).open(path)
+    })
+    .map(drop)
+}
+
+// Renames temp file over path, retrying while another process has path open. Replacing a file
+// that is open without delete sharing fails with access denied rather than a sharing violation.
+// Temp file is deleted if retries are exhausted.
+pub fn persist(temp_path: tempfile::TempPath, path: &Path) -> anyhow::Result<()> {
+    let mut temp_path = Some(temp_path);
+    retry(path, is_locked_or_denied, || {
+        match temp_path
+            .take()
+            .expect("temp path is restored after each failure")
+            .persist(path)
+        {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                temp_path = Some(e.path);
+                Err(e.error)
+            }
+        }
+    })
+}
+
+fn retry<T>(
+    path: &Path,
+    is_locked: fn(&std::io::Error) -> bool,
+    mut f: impl FnMut() -> std::io::Result<T>,
+) -> anyhow::Result<T> {
+    let attempts = ATTEMPTS.load(Ordering::Relaxed);
+    let max_delay_ms = MAX_DELAY_MS.load(Ordering::Relaxed);
+    let mut delay_ms = INITIAL_DELAY_MS.load(Ordering::Relaxed);
+
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if is_locked(&e) && attempt < attempts => {
+                attempt += 1;
+                // Not stdout, which carries file contents for extract_one --stdout
+                verbose!(
+                    "[{:?}] is locked by another process. Retrying in [{}]ms ([{}] of [{}])",
+                    path,
+                    delay_ms,
+                    attempt,
+                    attempts
+                );
+                std::thread::sleep(Duration::from_millis(delay_ms));
+                delay_ms = (delay_ms * 2).min(max_delay_ms);
+            }
+            Err(e) if is_locked(&e) => {
+                let holders = lock_holders(path);
+                if holders.is_empty() {
+                    bail!(
+                        "[{:?}] is locked by another process after [{}] retries: [{}]",
+                        path,
+                        attempts,
+                        e
+                    );
+                } else {
+                    bail!(
+                        "[{:?}] is locked after [{}] retries. Held by: [{}]",
+                        path,
+                        attempts,
+                        holders.join(", ")
+                    );
+                }
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to access [{:?}]", path)),
+        }
+    }
+}
+
+fn is_locked(e: &std::io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(ERROR_SHARING_VIOLATION) | Some(ERROR_LOCK_VIOLATION)
+    )
+}
+
+// Only used after write access was confirmed, so access denied means the file is open
+fn is_locked_or_denied(e: &std::io::Error) -> bool {
+    is_locked(e) || e.raw_os_error() == Some(ERROR_ACCESS_DENIED)
+}
+
+// Returns "name (pid N)" for each process holding path. Empty if unknown.
+#[cfg(windows)]
+fn lock_holders(path: &Path) -> Vec<String> {
+    use restart_manager::*;
+
+    let mut wide_path: Vec<u16> = super::long_path::to_extended(path)
+        .to_string_lossy()
+        .encode_utf16()
+        .collect();
+    wide_path.push(0);
+
+    unsafe {
+        let mut session: u32 = 0;
+        let mut session_key = [0u16; CCH_RM_SESSION_KEY + 1];
+        if RmStartSession(&mut session, 0, session_key.as_mut_ptr()) != ERROR_SUCCESS {
+            return Default::default();
+        }
+
+        let mut holders: Vec<String> = Default::default();
+        let filenames = [wide_path.as_ptr()];
+        if RmRegisterResources(
+            session,
+            1,
+            filenames.as_ptr(),
+            0,
+            std::ptr::null(),
+            0,
+            std::ptr::null(),
+        ) == ERROR_SUCCESS
+        {
+            // First call gets count, second call gets list. Processes may come and go in between.
+            let mut needed: u32 = 0;
+            let mut count: u32 = 0;
+            let mut reasons: u32 = 0;
+            let result = RmGetList(
+                session,
+                &mut needed,
+                &mut count,
+                std::ptr::null_mut(),
+                &mut reasons,
+            );
+            if result == ERROR_MORE_DATA && needed > 0 {
+                let mut infos: Vec<RM_PROCESS_INFO> = vec![std::mem::zeroed(); needed as usize];
+                count = needed;
+                if RmGetList(session, &mut needed, &mut count, infos.as_mut_ptr(), &mut reasons)
+                    == ERROR_SUCCESS
+                {
+                    holders = infos[..count as usize]
+                        .iter()
+                        .map(|info| {
+                            let name_len = info
+                                .app_name
+                                .iter()
+                                .position(|c| *c == 0)
+                                .unwrap_or(info.app_name.len());
+                            format!(
+                                "{} (pid {})",
+                                String::from_utf16_lossy(&info.app_name[..name_len]),
+                                info.process.process_id
+                            )
+                        })
+                        .collect();
+                }
+            }
+        }
+
+        RmEndSession(session);
+        holders
+    }
+}
+
+#[cfg(not(windows))]
+fn lock_holders(_path: &Path) -> Vec<String> {
+    Default::default()
+}
+
+// Minimal Restart Manager bindings. See RestartManager.h
+#[cfg(windows)]
+#[allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+mod restart_manager {
+    pub const ERROR_SUCCESS: u32 = 0;
+    pub const ERROR_MORE_DATA: u32 = 234;
+    pub const CCH_RM_SESSION_KEY: usize = 32;
+    const CCH_RM_MAX_APP_NAME: usize = 255;
+    const CCH_RM_MAX_SVC_NAME: usize = 63;
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct FILETIME {
+        pub low_date_time: u32,
+        pub high_date_time: u32,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct RM_UNIQUE_PROCESS {
+        pub process_id: u32,
+        pub process_start_time: FILETIME,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct RM_PROCESS_INFO {
+        pub process: RM_UNIQUE_PROCESS,
+        pub app_name: [u16; CCH_RM_MAX_APP_NAME + 1],
+        pub service_short_name: [u16; CCH_RM_MAX_SVC_NAME + 1],
+        pub application_type: i32,
+        pub app_status: u32,
+        pub ts_session_id: u32,
+        pub restartable: i32,
+    }
+
+    #[link(name = "rstrtmgr")]
+    extern "system" {
+        pub fn RmStartSession(session_handle: *mut u32, session_flags: u32, session_key: *mut u16) -> u32;
+        pub fn RmRegisterResources(
+            session_handle: u32,
+            num_files: u32,
+            filenames: *const *const u16,
+            num_applications: u32,
+            applications: *const RM_UNIQUE_PROCESS,
+            num_services: u32,
+            service_names: *const *const u16,
+        ) -> u32;
+        pub fn RmGetList(
+            session_handle: u32,
+            proc_info_needed: *mut u32,
+            proc_info: *mut u32,
+            affected_apps: *mut RM_PROCESS_INFO,
+            reboot_reasons: *mut u32,
+        ) -> u32;
+        pub fn RmEndSession(session_handle: u32) -> u32;
+    }
+}
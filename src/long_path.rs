@@ -0,0 +1,61 @@
+// ----------------------------------------------------------------------------
+// Paths longer than MAX_PATH
+//
+// Build trees often exceed MAX_PATH. std::fs adds the \\?\ extended-length
+// prefix itself when needed, but raw Win32 calls such as ACLs and the Restart
+// Manager do not, so paths passed to them go through to_extended first.
+//
+// Debuggers open SRCSRVTRG without the prefix and fail on long paths, so embed
+// shortens extraction targets that would exceed MAX_PATH. See srcsrv_target.
+// ----------------------------------------------------------------------------
+use std::path::Path;
+#[cfg(windows)]
+use std::path::PathBuf;
+
+// Includes the NUL terminator
+const MAX_PATH: usize = 260;
+
+// %LOCALAPPDATA% isn't known at embed. Allows for long user names and redirected profiles.
+const LOCALAPPDATA_BUDGET: usize = 64;
+
+// Mirrors the SRCSRVTRG written by embed: %LOCALAPPDATA%\fts\fts_pdbsrc\<pdb>\<uuid>\<target>
+const TARGET_PREFIX_LEN: usize = LOCALAPPDATA_BUDGET + "\\fts\\fts_pdbsrc\\".len();
+const UUID_LEN: usize = 36;
+
+// Returns absolute \\?\ path. Extended-length paths skip normalization, so the path is made
+// absolute with backslashes first. Paths already prefixed are returned unchanged.
+#[cfg(windows)]
+pub fn to_extended(path: &Path) -> PathBuf {
+    let path_str = path.to_string_lossy();
+    if path_str.starts_with(r"\\?\") || path_str.starts_with(r"\\.\") {
+        return path.to_owned();
+    }
+
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    let absolute = absolute.to_string_lossy();
+    match absolute.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{}", unc)),
+        None => PathBuf::from(format!(r"\\?\{}", absolute)),
+    }
+}
+
+// Path below the PDB's uuid directory to extract relpath to. Normally relpath itself. Targets
+// that would exceed MAX_PATH become a hash of relpath plus filename, or plus extension if the
+// filename alone is too long. Debuggers show the original path, so only the cache path changes.
+pub fn srcsrv_target(pdb_name: &str, relpath: &str, filename: &str) -> String {
+    let budget = (MAX_PATH - 1).saturating_sub(TARGET_PREFIX_LEN + pdb_name.len() + 1 + UUID_LEN + 1);
+    if relpath.len() <= budget {
+        return relpath.to_owned();
+    }
+
+    let hash = &super::manifest::sha256(relpath.as_bytes())[..16];
+    let target = format!("{}\\{}", hash, filename);
+    if target.len() <= budget {
+        return target;
+    }
+
+    match Path::new(filename).extension() {
+        Some(extension) => format!("{}.{}", hash, extension.to_string_lossy()),
+        None => hash.to_owned(),
+    }
+}
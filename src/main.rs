@@ -1,15 +1,34 @@
-use aes_gcm::aead::{Aead, NewAead};
+#[macro_use]
+extern crate fts_pdbsrc_ffi;
+
+mod archive;
+mod console;
+mod elevate;
+mod extra_files;
+mod job_system;
+mod path_map;
+mod pe;
+mod roots;
+
+use aes_gcm::aead::{Aead, NewAead, Payload};
 use aes_gcm::{Aes256Gcm, Key, Nonce};
 use anyhow::*;
+use fts_pdbsrc_ffi::config::{apply_config, config_path, read_config, Config, ExtractConfig};
+use fts_pdbsrc_ffi::extract::*;
+use fts_pdbsrc_ffi::failure::Failure;
+use fts_pdbsrc_ffi::service_client::*;
+use fts_pdbsrc_ffi::{
+    client_log, extract_cache, lock, long_path, manifest, msf, portable_pdb, recipients, sbom, srcsrv,
+};
 use pdb::*;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::Digest;
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{Read, Write};
-use std::net::{TcpStream};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::sync::atomic::Ordering;
 use structopt::StructOpt;
 use subprocess::*;
 use uuid::Uuid;
@@ -24,6 +43,36 @@ use uuid::Uuid;
     about = "Embeds and extracts source files into PDBs"
 )]
 struct Opts {
+    #[structopt(short, long, global = true, help = "Print verbose messages to stderr")]
+    verbose: bool,
+
+    #[structopt(
+        short,
+        long,
+        global = true,
+        conflicts_with = "verbose",
+        help = "Only print command output, warnings, and errors"
+    )]
+    quiet: bool,
+
+    #[structopt(
+        long,
+        global = true,
+        parse(from_os_str),
+        help = "Append log to this file. Defaults to fts_pdbsrc_client.log in the temp dir."
+    )]
+    log_file: Option<PathBuf>,
+
+    // Not global since serve and install_service take --config for the service config
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Client config. Must precede the command. Defaults to FTS_PDBSRC_CONFIG, then \
+                %APPDATA%\\fts\\fts_pdbsrc_config.json if it exists, then fts_pdbsrc_config.json \
+                beside the exe"
+    )]
+    config: Option<PathBuf>,
+
     #[structopt(subcommand)]
     op: Op,
 }
@@ -39,6 +88,109 @@ enum Op {
     #[structopt(name = "info", about = "Dump files and streams in PDB")]
     Info(InfoOp),
 
+    #[structopt(name = "list", about = "List source files embedded in PDB")]
+    List(ListOp),
+
+    #[structopt(
+        name = "verify",
+        about = "Compare embedded source files against files on disk"
+    )]
+    Verify(VerifyOp),
+
+    #[structopt(
+        name = "lint",
+        about = "Check embedded PDB is internally consistent. Fails if it isn't."
+    )]
+    Lint(LintOp),
+
+    #[structopt(name = "strip", about = "Remove embedded source files and srcsrv from PDB")]
+    Strip(StripOp),
+
+    #[structopt(name = "locate", about = "Ask service for paths of PDBs by Uuid")]
+    Locate(LocateOp),
+
+    #[structopt(
+        name = "list_pdbs",
+        alias = "list-pdbs",
+        about = "List every PDB indexed by service"
+    )]
+    ListPdbs(ListPdbsOp),
+
+    #[structopt(
+        name = "rescan",
+        about = "Ask service to search its configured paths for PDBs again"
+    )]
+    Rescan(RescanOp),
+
+    #[structopt(name = "keygen", about = "Generate key pair for EncryptForRecipients")]
+    Keygen(KeygenOp),
+
+    #[structopt(
+        name = "keys",
+        about = "Add, remove, or list decode keys in fts_pdbsrc_config.json"
+    )]
+    Keys(KeysOp),
+
+    #[structopt(
+        name = "doctor",
+        about = "Check service and report most recent extraction failure"
+    )]
+    Doctor(DoctorOp),
+
+    #[structopt(
+        name = "status",
+        about = "Check service is reachable and print its version and uptime"
+    )]
+    Status(StatusOp),
+
+    #[structopt(name = "service", about = "Query running fts_pdbsrc_service")]
+    Service(ServiceOp),
+
+    #[structopt(
+        name = "cache",
+        about = "Inspect or clear source files extracted for debuggers"
+    )]
+    Cache(CacheOp),
+
+    #[structopt(name = "rekey", about = "Re-encrypt embedded source files with a new key")]
+    Rekey(RekeyOp),
+
+    #[structopt(name = "export", about = "Export embedded source files to an archive")]
+    Export(ExportOp),
+
+    #[structopt(name = "import", about = "Embed source files from an archive into PDB")]
+    Import(ImportOp),
+
+    #[structopt(
+        name = "index-git",
+        about = "Source index PDB against a git commit instead of embedding sources"
+    )]
+    IndexGit(IndexGitOp),
+
+    #[structopt(
+        name = "index-p4",
+        about = "Source index PDB against Perforce revisions instead of embedding sources"
+    )]
+    IndexP4(IndexP4Op),
+
+    #[structopt(
+        name = "adopt",
+        about = "Fetch sources for a source-indexed PDB (git, p4, etc) and embed them"
+    )]
+    Adopt(AdoptOp),
+
+    #[structopt(
+        name = "postbuild",
+        about = "Embed and verify sources for a freshly built exe, dll, or PDB"
+    )]
+    PostBuild(PostBuildOp),
+
+    #[structopt(
+        name = "serve",
+        about = "Run fts_pdbsrc_service.exe in this console instead of as a Windows service"
+    )]
+    Serve(ServeOp),
+
     #[structopt(
         name = "install_service",
         about = "Install fts_pdbsrc_service.exe as Windows service"
@@ -57,6 +209,9 @@ enum EncryptMode {
     Plaintext,
     EncryptWithRngKey,
     EncryptWithKey(String),
+
+    // Hex X25519 public keys. See recipients.rs
+    EncryptForRecipients(Vec<String>),
 }
 
 impl std::str::FromStr for EncryptMode {
@@ -65,6 +220,15 @@ impl std::str::FromStr for EncryptMode {
         match arg {
             "plaintext" | "Plaintext" => Ok(EncryptMode::Plaintext),
             "EncryptWithRngKey" => Ok(EncryptMode::EncryptWithRngKey),
+            arg if arg.starts_with("EncryptForRecipients(") => {
+                let re_str = r"^EncryptForRecipients\(([a-fA-F0-9]{64}(?:,[a-fA-F0-9]{64})*)\)$";
+                let re = regex::Regex::new(re_str)?;
+                let caps = re
+                    .captures(arg)
+                    .ok_or_else(|| anyhow!("Failed to regex [{}] against arg [{}]", re_str, arg))?;
+                let public_keys = caps[1].split(',').map(|key| key.to_owned()).collect();
+                Ok(EncryptMode::EncryptForRecipients(public_keys))
+            }
             arg => {
                 let re_str = r"EncryptWithKey\(([a-fA-f0-9]{64})\)";
                 let re = regex::Regex::new(re_str)?;
@@ -81,503 +245,3872 @@ impl std::str::FromStr for EncryptMode {
     }
 }
 
+impl EncryptMode {
+    // Mode without key material, as recorded in manifest
+    fn name(&self) -> &'static str {
+        match self {
+            EncryptMode::Plaintext => "Plaintext",
+            EncryptMode::EncryptWithRngKey => "EncryptWithRngKey",
+            EncryptMode::EncryptWithKey(_) => "EncryptWithKey",
+            EncryptMode::EncryptForRecipients(_) => "EncryptForRecipients",
+        }
+    }
+}
 
-#[derive(Debug, StructOpt)]
-struct EmbedOp {
-    #[structopt(short, long, help = "Target PDB for specified operation")]
-    pdb: String,
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+enum AnnotationFormat {
+    GitHub,
+    TeamCity,
+    Azure,
+}
 
-    #[structopt(short, long, parse(from_os_str), help = "Root for files to embed")]
-    roots: Vec<PathBuf>,
+impl std::str::FromStr for AnnotationFormat {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg {
+            "github" | "GitHub" => Ok(AnnotationFormat::GitHub),
+            "teamcity" | "TeamCity" => Ok(AnnotationFormat::TeamCity),
+            "azure" | "Azure" => Ok(AnnotationFormat::Azure),
+            _ => bail!(
+                "Unknown annotation format [{}]. Expected GitHub, TeamCity, or Azure",
+                arg
+            ),
+        }
+    }
+}
 
+// Re-signing after embed. Embedding modifies the PDB which invalidates prior signatures.
+#[derive(Clone, Debug, StructOpt, Serialize, Deserialize)]
+#[serde(default)]
+struct SignOpts {
     #[structopt(
         long,
-        parse(try_from_str),
-        help = "Specify encryption mode. Plaintext, EncryptFromRngKey, EncryptWithKey(HexString)"
+        parse(from_os_str),
+        help = "Artifact to sign after embedding. May be repeated."
     )]
-    encrypt_mode: EncryptMode,
-}
-
-#[derive(Debug, StructOpt)]
-struct ExtractOneOp {
-    #[structopt(short, long, help = "Uuid of PDB to extract from")]
-    pdb_uuid: Uuid,
-
-    #[structopt(short, long, help = "File to extract")]
-    file: String,
+    sign: Vec<PathBuf>,
 
-    #[structopt(short, long, help = "Nonce used to decode")]
-    nonce: Option<String>,
+    #[structopt(
+        long,
+        default_value = r#"signtool sign /a /fd SHA256 "{file}""#,
+        help = "Command used to sign each artifact. {file} is replaced with the artifact path."
+    )]
+    sign_command: String,
 
-    #[structopt(short, long, help = "Output path, including filename, to create")]
-    out: PathBuf,
+    #[structopt(long, help = "Warn instead of failing when signing fails")]
+    sign_allow_failure: bool,
 }
 
-#[derive(Debug, StructOpt)]
-struct InfoOp {
-    #[structopt(short, long, help = "Target PDB for specified operation")]
-    pdb: String,
+impl Default for SignOpts {
+    fn default() -> SignOpts {
+        SignOpts {
+            sign: Default::default(),
+            sign_command: r#"signtool sign /a /fd SHA256 "{file}""#.to_owned(),
+            sign_allow_failure: false,
+        }
+    }
 }
 
-#[derive(Debug, StructOpt)]
-struct InstallServiceOp {}
+// What embed does when files under different roots have the same relative path
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum RootCollision {
+    // Prefix each colliding file's relative path with its root's directory name
+    PrefixRoot,
 
-#[derive(Debug, StructOpt)]
-struct UninstallServiceOp {}
+    // Keep first file found
+    Skip,
+    #[default]
+    Error,
+}
 
-#[derive(Serialize, Deserialize, Debug)]
-enum Message {
-    FindPdb(Uuid),
-    FoundPdb((Uuid, Option<PathBuf>)),
+impl std::str::FromStr for RootCollision {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg {
+            "prefix-root" | "PrefixRoot" => Ok(RootCollision::PrefixRoot),
+            "skip" | "Skip" => Ok(RootCollision::Skip),
+            "error" | "Error" => Ok(RootCollision::Error),
+            _ => bail!(
+                "Unknown collision handling [{}]. Expected prefix-root, skip, or error",
+                arg
+            ),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
-struct Config {
-    pub decode_keys: Vec<String>,
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum LicenseCheck {
+    Off,
+    Warn,
+    Fail,
 }
 
-// ----------------------------------------------------------------------------
-// Functions
-// ----------------------------------------------------------------------------
-fn main() -> anyhow::Result<()> {
-    // Parse args
-    let opts: Opts = Opts::from_args();
+impl std::str::FromStr for LicenseCheck {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg {
+            "off" | "Off" => Ok(LicenseCheck::Off),
+            "warn" | "Warn" => Ok(LicenseCheck::Warn),
+            "fail" | "Fail" => Ok(LicenseCheck::Fail),
+            _ => bail!("Unknown license check [{}]. Expected Off, Warn, or Fail", arg),
+        }
+    }
+}
 
-    // Read config
-    let config = read_config();
+// License gate run before embedding. Guards against shipping third-party source in external symbols.
+#[derive(Clone, Debug, StructOpt, Serialize, Deserialize)]
+#[serde(default)]
+struct LicenseOpts {
+    #[structopt(
+        long,
+        default_value = "Off",
+        parse(try_from_str),
+        help = "Scan files for license headers before embedding. Off, Warn, Fail"
+    )]
+    license_check: LicenseCheck,
 
-    // Run program
-    let exit_code = match run(opts, config) {
-        Ok(_) => 0,
-        Err(err) => {
-            eprint!("Error: {:?}", err);
-            1
-        }
-    };
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Third-party directory whose files are flagged by --license-check. May be repeated."
+    )]
+    external_dirs: Vec<PathBuf>,
 
-    // Result result
-    std::process::exit(exit_code);
+    #[structopt(
+        long,
+        default_value = "GPL",
+        use_delimiter = true,
+        help = "License identifiers flagged by --license-check. Matches substrings, so GPL also flags LGPL and AGPL."
+    )]
+    flagged_licenses: Vec<String>,
 }
 
-fn read_config() -> Config {
-    || -> anyhow::Result<Config> {
-        let mut config_path = std::env::current_exe()?;
-        config_path.set_file_name("fts_pdbsrc_config.json");
-        let config_file = std::fs::File::open(&config_path)?;
-        let config: Config = serde_json::from_reader(&config_file)?;
-        Ok(config)
-    }()
-    .unwrap_or_default()
+impl Default for LicenseOpts {
+    fn default() -> LicenseOpts {
+        LicenseOpts {
+            license_check: LicenseCheck::Off,
+            external_dirs: Default::default(),
+            flagged_licenses: vec!["GPL".to_owned()],
+        }
+    }
 }
 
-fn run(opts: Opts, config: Config) -> anyhow::Result<()> {
-    match opts.op {
-        Op::Embed(op) => embed(op)?,
-        Op::ExtractOne(op) => extract_one(op, config)?,
-        Op::Info(op) => info(op)?,
-        Op::InstallService(op) => install_service(op)?,
-        Op::UninstallService(op) => uninstall_service(op)?,
-    }
+// Options shared by every command that writes sources into a PDB
+#[derive(Debug, StructOpt)]
+struct EmbedOpts {
+    #[structopt(
+        long,
+        parse(try_from_str),
+        help = "Specify encryption mode. Plaintext, EncryptFromRngKey, EncryptWithKey(HexString), \
+                EncryptForRecipients(HexPublicKey,HexPublicKey)"
+    )]
+    encrypt_mode: EncryptMode,
 
-    Ok(())
-}
+    #[structopt(
+        long,
+        parse(try_from_str),
+        help = "Emit warnings as CI annotations. GitHub, TeamCity, Azure"
+    )]
+    annotations: Option<AnnotationFormat>,
 
-fn embed(op: EmbedOp) -> anyhow::Result<(), anyhow::Error> {
-    let canonical_roots: Vec<PathBuf> = op
-        .roots
-        .iter()
-        .filter_map(|root| fs::canonicalize(root).ok())
-        .collect();
+    #[structopt(
+        long,
+        default_value = "16777216",
+        help = "Warn about source files larger than this many bytes"
+    )]
+    max_file_size: u64,
 
-    // Load PDB
-    let pdbfile = File::open(&op.pdb)?;
-    let mut pdb = pdb::PDB::open(pdbfile)?;
-    let string_table = pdb.string_table()?;
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Write SPDX manifest of embedded files to this path"
+    )]
+    sbom: Option<PathBuf>,
 
-    // Iterate files
-    let mut filepaths: Vec<(RawString, PathBuf, String)> = Default::default();
+    #[structopt(long, help = "Verify every embedded stream before replacing the PDB")]
+    verify: bool,
 
-    let di = pdb.debug_information()?;
-    let mut modules = di.modules()?;
-    while let Some(module) = modules.next()? {
-        if let Some(module_info) = pdb.module_info(&module)? {
-            let line_program = module_info.line_program()?;
+    #[structopt(
+        long,
+        help = "Fail instead of warn when a file doesn't match the checksum recorded by the compiler"
+    )]
+    strict: bool,
 
-            let mut file_iter = line_program.files();
-            while let Some(file) = file_iter.next()? {
-                let raw_filepath = string_table.get(file.name)?;
+    #[structopt(
+        long,
+        help = "Pack small files into shared streams. Far fewer streams but requires a manifest aware reader"
+    )]
+    pack_streams: bool,
 
-                let filename_utf8 = std::str::from_utf8(raw_filepath.as_bytes())?;
-                let filepath = Path::new(filename_utf8);
-
-                if let Ok(canonical_filepath) = fs::canonicalize(&filepath) {
-                    
-                    // Find subpath relative to a specified root
-                    let maybe_subpath = canonical_roots
-                        .iter()
-                        .filter_map(|root| {
-                            canonical_filepath.starts_with(root).then(|| {
-                                canonical_filepath
-                                    .iter()
-                                    .skip(root.iter().count())
-                                    .collect::<PathBuf>()
-                            })
-                        })
-                        .next();
-                    
-                    if let Some(subpath) = maybe_subpath {
-                        filepaths.push((
-                            raw_filepath,
-                            subpath.clone(),
-                            subpath
-                                .file_name()
-                                .unwrap()
-                                .to_string_lossy()
-                                .to_owned()
-                                .to_string(),
-                        ))
-                    }
-                }
-            }
-        }
-    }
+    #[structopt(
+        long,
+        default_value = "none",
+        help = "Compress files before encryption: zstd, lz4, or none. Requires a manifest aware reader"
+    )]
+    compress: manifest::Compression,
 
-    // Make sure we found at least some files
-    if filepaths.is_empty() {
-        bail!("Failed to find any files");
-    }
+    #[structopt(
+        long,
+        help = "Compress files with a zstd dictionary trained across all embedded files"
+    )]
+    zstd_dictionary: bool,
 
-    // Print files that were found and will be embedded
-    println!("Found following files:");
-    filepaths.iter().for_each(|(_, filepath, _)| {
-        println!("  {}", filepath.to_string_lossy());
-    });
+    #[structopt(
+        long,
+        help = "Hex key for HMAC over srcsrv stream. Lets extract and verify detect modified commands"
+    )]
+    srcsrv_mac_key: Option<String>,
 
-    // Close PDB so we can write to it
-    drop(pdb);
+    #[structopt(
+        long,
+        help = "Service port extract_one should use when debugging this PDB. Requires a client with --port"
+    )]
+    service_port: Option<u16>,
 
-    // RNG for key / nonce generation (if needed)
-    let mut rng = rand::thread_rng();
+    #[structopt(
+        long,
+        help = "fts_pdbsrc_service HTTP address debuggers download sources from, e.g. http://symbols:8002. \
+                No client install is needed."
+    )]
+    http_url: Option<String>,
 
-    // Create cipher for encryption if specified by mode
-    let (cipher, rng_key): (Option<Aes256Gcm>, Option<[u8; 32]>) = match &op.encrypt_mode {
-        EncryptMode::Plaintext => (None, None),
-        EncryptMode::EncryptWithRngKey => {
-            // Create cipher with randomly generated key
-            let mut rng = rand::thread_rng();
-            let key_rng_bytes = rng.gen::<[u8; 32]>();
-            let cipher = Aes256Gcm::new(Key::from_slice(&key_rng_bytes));
-            (Some(cipher), Some(key_rng_bytes))
-        }
-        EncryptMode::EncryptWithKey(key_hex) => {
-            // Create cipher from provided key
-            let key = hex::decode(key_hex)?;
-            let cipher = Aes256Gcm::new(Key::from_slice(&key));
-            (Some(cipher), None)
-        }
-    };
-
-    // Store per-file nonce
-    let mut nonces: HashMap<RawString, String> = Default::default();
-
-    // Write source files into PDB
-    for (raw_filepath, relpath, _) in &filepaths {
-        // Read file
-        let mut file = File::open(&*raw_filepath.to_string())?;
-        let mut plaintext : Vec<u8> = Default::default();
-        file.read_to_end(&mut plaintext).with_context(|| format!("Error reading file: [{:?}]", raw_filepath))?;
-
-        // Optionally encrypt file contents
-        let (stream_filepath, delete_stream_file): (PathBuf, bool) = match &cipher {
-            None => (PathBuf::from_str(&*raw_filepath.to_string())?, false),
-            Some(cipher) => {
-                // Create per-file nonce; 96-bits, unique per message
-                let nonce_bytes = rng.gen::<[u8; 12]>();
-                let nonce = Nonce::from_slice(&nonce_bytes); // 96-bits; unique per message
-
-                // Encrypt text
-                let encrypted_text = cipher
-                    .encrypt(nonce, plaintext.as_slice())
-                    .unwrap_or_else(|_| panic!("Failed to encrypt file: [{:?}]", raw_filepath));
-
-                // Write encrypted data to temp file
-                let mut encrypted_file = tempfile::NamedTempFile::new()?;
-                encrypted_file.write_all(&encrypted_text)?;
-                let (_, encrypted_filepath) = encrypted_file.keep()?;
-
-                // Retain nonce
-                nonces.insert(*raw_filepath, hex::encode(nonce_bytes));
-
-                // Return path to tempfile with encrypted content
-                (encrypted_filepath, true)
-            }
-        };
+    #[structopt(
+        long,
+        help = "Derive FTS_PDBSTR_UUID from the PDB's GUID and age instead of a random uuid. \
+                Re-embedding the same build keeps the same uuid."
+    )]
+    pdb_identity: bool,
 
-        // Invoke pdbstr to write encrypted file into pdbstr
-        let cmd = &[
-            "pdbstr",                                                 // exe to run
-            "-w",                                                     // write
-            &format!("-p:{}", &op.pdb),                               // path to pdb
-            &format!("-s:/fts_pdbsrc/{}", relpath.to_string_lossy()), // stream to write
-            &format!("-i:{}", stream_filepath.to_string_lossy()),     // file to write into stream
-        ];
-        run_command(cmd).with_context(|| format!("Cmd: {:?}", cmd))?;
+    #[structopt(
+        long,
+        help = "Portable PDBs only. Write standard EmbeddedSource records that Visual Studio and Rider read \
+                natively instead of fts_pdbsrc streams and srcsrv. Requires --encrypt-mode Plaintext."
+    )]
+    dotnet_embedded_source: bool,
 
-        // Remove encrypted tempfile if one was created
-        if delete_stream_file {
-            std::fs::remove_file(stream_filepath)?;
-        }
-    }
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "SourceLink JSON to store with --dotnet-embedded-source for files that aren't embedded"
+    )]
+    source_link: Option<PathBuf>,
 
-    // Create tempfile representing srcsrv.ini
-    let uuid = uuid::Uuid::new_v4();
+    #[structopt(flatten)]
+    license: LicenseOpts,
 
-    let mut srcsrv = tempfile::NamedTempFile::new()?;
-    writeln!(
-        srcsrv,
-        "SRCSRV: ini ------------------------------------------------"
-    )?;
-    writeln!(srcsrv, "VERSION=1")?;
-    writeln!(srcsrv, "VERCTRL=fts_pdbsrc")?;
-    // TODO: add datetime
-    writeln!(
-        srcsrv,
-        "SRCSRV: variables ------------------------------------------"
-    )?;
-    writeln!(srcsrv, "FTS_PDBSTR_UUID={}", uuid)?;
-    writeln!(
-        srcsrv,
-        "SRCSRVTRG=%LOCALAPPDATA%\\fts\\fts_pdbsrc\\{}\\%FTS_PDBSTR_UUID%\\%var2%",
-        Path::new(&op.pdb).file_stem().unwrap().to_str().unwrap()
-    )?;
-    if nonces.is_empty() {
-        writeln!(
-            srcsrv,
-            "SRCSRVCMD=fts_pdbsrc extract_one --pdb-uuid %FTS_PDBSTR_UUID% --file %var2% --out %SRCSRVTRG%",
-        )?;
-    } else {
-        writeln!(
-            srcsrv,
-            "SRCSRVCMD=fts_pdbsrc extract_one --pdb-uuid %FTS_PDBSTR_UUID% --file %var2% --out %SRCSRVTRG% --nonce %var4%",
-        )?;
-    }
-    writeln!(
-        srcsrv,
-        "SRCSRV: source files ------------------------------------------"
-    )?;
+    #[structopt(flatten)]
+    sign: SignOpts,
+}
 
-    for (raw_filepath, relpath, filename) in &filepaths {
-        if nonces.is_empty() {
+#[derive(Debug, StructOpt)]
+struct EmbedOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: String,
+
+    #[structopt(short, long, parse(from_os_str), help = "Root for files to embed")]
+    roots: Vec<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Match files to roots case sensitively. By default case and separators are ignored."
+    )]
+    exact_root_match: bool,
+
+    #[structopt(
+        long = "map",
+        help = "Map recorded paths to relative paths. s#regex#replacement# or prefix=replacement"
+    )]
+    path_mappings: Vec<String>,
+
+    #[structopt(
+        long = "map-file",
+        parse(from_os_str),
+        help = "File of path mappings, one per line"
+    )]
+    path_mapping_files: Vec<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Also embed files matching this glob, e.g. include/**/*.inl. Relative globs search each root."
+    )]
+    extra: Vec<String>,
+
+    #[structopt(
+        long,
+        default_value = "error",
+        parse(try_from_str),
+        help = "When files under different roots have the same relative path. prefix-root, skip, error"
+    )]
+    on_collision: RootCollision,
+
+    #[structopt(flatten)]
+    opts: EmbedOpts,
+}
+
+#[derive(Debug, StructOpt)]
+struct KeygenOp {}
+
+#[derive(Debug, StructOpt)]
+enum KeysOp {
+    #[structopt(name = "add", about = "Add decode key. Keys already present are left alone.")]
+    Add(KeysAddOp),
+
+    #[structopt(name = "remove", about = "Remove decode key")]
+    Remove(KeysRemoveOp),
+
+    #[structopt(name = "list", about = "List key ids of decode keys")]
+    List(KeysListOp),
+}
+
+#[derive(Debug, StructOpt)]
+struct KeysAddOp {
+    #[structopt(help = "64 character hex key, as passed to EncryptWithKey")]
+    key: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct KeysRemoveOp {
+    #[structopt(help = "Hex key or its key id")]
+    key: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct KeysListOp {}
+
+#[derive(Debug, StructOpt)]
+struct DoctorOp {}
+
+#[derive(Debug, StructOpt)]
+struct StatusOp {}
+
+#[derive(Debug, StructOpt)]
+enum ServiceOp {
+    #[structopt(
+        name = "stats",
+        about = "Print request count, bytes served, and last access per PDB"
+    )]
+    Stats(ServiceStatsOp),
+}
+
+#[derive(Debug, StructOpt)]
+struct ServiceStatsOp {
+    #[structopt(
+        long,
+        help = "Only print PDBs not requested in this many days. Useful for pruning."
+    )]
+    unused_days: Option<u64>,
+}
+
+#[derive(Debug, StructOpt)]
+enum CacheOp {
+    #[structopt(name = "stats", about = "Print file count, size, and last use per cached PDB")]
+    Stats(CacheStatsOp),
+
+    #[structopt(name = "list", about = "List cached source files")]
+    List(CacheListOp),
+
+    #[structopt(
+        name = "clear",
+        about = "Delete cached source files. Files open in a debugger are skipped."
+    )]
+    Clear(CacheClearOp),
+}
+
+#[derive(Debug, StructOpt)]
+struct CacheStatsOp {
+    #[structopt(short, long, help = "Only PDB with this uuid")]
+    uuid: Option<Uuid>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CacheListOp {
+    #[structopt(short, long, help = "Only files extracted from PDB with this uuid")]
+    uuid: Option<Uuid>,
+}
+
+#[derive(Debug, StructOpt)]
+struct CacheClearOp {
+    #[structopt(short, long, help = "Only files extracted from PDB with this uuid")]
+    uuid: Option<Uuid>,
+}
+
+#[derive(Debug, StructOpt)]
+struct InfoOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: String,
+
+    #[structopt(
+        long,
+        default_value = "text",
+        parse(try_from_str),
+        help = "Output format. text, json"
+    )]
+    format: InfoFormat,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum InfoFormat {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for InfoFormat {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg {
+            "text" | "Text" => Ok(InfoFormat::Text),
+            "json" | "Json" | "JSON" => Ok(InfoFormat::Json),
+            _ => bail!("Unknown info format [{}]. Expected text or json", arg),
+        }
+    }
+}
+
+// info --format json. Field names are stable for build scripts.
+#[derive(Serialize)]
+struct InfoReport {
+    modules: Vec<InfoModule>,
+    files: Vec<InfoFile>,
+    streams: Vec<String>,
+
+    // None if PDB has no srcsrv stream
+    srcsrv: Option<InfoSrcSrv>,
+
+    // None if PDB has no embedded sources or was embedded before manifests
+    manifest: Option<manifest::Manifest>,
+
+    // Streams written by fts_pdbsrc, including srcsrv if fts_pdbsrc wrote it
+    embedded_streams: Vec<InfoStream>,
+    embedded_bytes: u64,
+    pdb_size: u64,
+
+    // Bytes embedding adds to a compactly written PDB. Same as what strip removes.
+    embedded_size_delta: u64,
+}
+
+#[derive(Serialize)]
+struct InfoStream {
+    name: String,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct InfoSrcSrv {
+    ini: Vec<(String, String)>,
+    variables: Vec<(String, String)>,
+
+    // Fields of each source line. fts_pdbsrc escaping is removed.
+    sources: Vec<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct InfoModule {
+    name: String,
+    object_file: String,
+
+    // Source files from module's line program
+    files: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct InfoFile {
+    path: String,
+    exists: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct ListOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct LintOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+struct VerifyOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Roots to find files by relative path. Original paths are used if none are given."
+    )]
+    roots: Vec<PathBuf>,
+
+    #[structopt(long, help = "Don't fail for embedded files that don't exist on disk")]
+    ignore_missing: bool,
+}
+
+#[derive(Debug, StructOpt)]
+struct StripOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+
+    #[structopt(short, long, help = "Write stripped PDB here instead of replacing original")]
+    out: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct RekeyOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+
+    #[structopt(
+        long,
+        help = "Hex key files are currently encrypted with. Defaults to decode_keys in config."
+    )]
+    old_key: Option<String>,
+
+    #[structopt(
+        long,
+        help = "Hex key to encrypt files with. Plaintext PDBs become encrypted."
+    )]
+    new_key: String,
+
+    #[structopt(long, help = "Hex key to re-sign srcsrv with if PDB has a srcsrv MAC")]
+    srcsrv_mac_key: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct LocateOp {
+    #[structopt(short, long = "pdb-uuid", help = "Uuid of PDB to find. May be repeated.")]
+    pdb_uuids: Vec<Uuid>,
+
+    #[structopt(
+        short,
+        long,
+        help = "File name of PDBs to find, e.g. game.pdb. Lists every indexed match."
+    )]
+    name: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ListPdbsOp {}
+
+#[derive(Debug, StructOpt)]
+struct RescanOp {
+    #[structopt(
+        short,
+        long,
+        help = "Only rescan this path. Must be under a path configured in service."
+    )]
+    path: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ExportFor {
+    Sentry,
+    Symbolicator,
+}
+
+impl std::str::FromStr for ExportFor {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg {
+            "sentry" | "Sentry" => Ok(ExportFor::Sentry),
+            "symbolicator" | "Symbolicator" => Ok(ExportFor::Symbolicator),
+            _ => bail!("Unknown export target [{}]. Expected sentry or symbolicator", arg),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum ArchiveFormat {
+    Zip,
+    Tar,
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg {
+            "zip" | "Zip" => Ok(ArchiveFormat::Zip),
+            "tar" | "Tar" => Ok(ArchiveFormat::Tar),
+            _ => bail!("Unknown archive format [{}]. Expected zip or tar", arg),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct ExportOp {
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        required_unless = "pdb-uuid",
+        help = "PDB to export sources from"
+    )]
+    pdb: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        conflicts_with = "pdb",
+        help = "Uuid of PDB to export from. Found via service."
+    )]
+    pdb_uuid: Option<Uuid>,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Output file. For symbolicator this is the root of a symbol server directory"
+    )]
+    out: PathBuf,
+
+    #[structopt(
+        long,
+        default_value = "zip",
+        parse(try_from_str),
+        help = "Archive format when not exporting for a service. zip, tar"
+    )]
+    format: ArchiveFormat,
+
+    #[structopt(
+        long = "for",
+        parse(try_from_str),
+        help = "Produce a source bundle for a symbolication service. sentry, symbolicator"
+    )]
+    export_for: Option<ExportFor>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ImportOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: String,
+
+    #[structopt(short, long, parse(from_os_str), help = "Zip or tar archive of source files")]
+    archive: PathBuf,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Manifest mapping original paths to archive paths. Defaults to manifest.json in archive"
+    )]
+    mapping: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    opts: EmbedOpts,
+}
+
+#[derive(Debug, StructOpt)]
+struct IndexP4Op {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+
+    #[structopt(
+        long,
+        help = "P4PORT debuggers fetch files from. Defaults to server address from p4 info"
+    )]
+    port: Option<String>,
+
+    #[structopt(
+        long,
+        parse(try_from_str),
+        help = "Emit warnings as CI annotations. GitHub, TeamCity, Azure"
+    )]
+    annotations: Option<AnnotationFormat>,
+}
+
+#[derive(Debug, StructOpt)]
+struct IndexGitOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: PathBuf,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Git repository sources were built from"
+    )]
+    repo: PathBuf,
+
+    #[structopt(
+        long,
+        help = "Fetch files over HTTPS instead of with git cat-file. {commit} and {path} are replaced, \
+                e.g. https://raw.githubusercontent.com/org/repo/{commit}/{path}"
+    )]
+    raw_url: Option<String>,
+
+    #[structopt(
+        long,
+        parse(try_from_str),
+        help = "Emit warnings as CI annotations. GitHub, TeamCity, Azure"
+    )]
+    annotations: Option<AnnotationFormat>,
+}
+
+#[derive(Debug, StructOpt)]
+struct AdoptOp {
+    #[structopt(short, long, help = "Target PDB for specified operation")]
+    pdb: String,
+
+    #[structopt(
+        short,
+        long,
+        parse(from_os_str),
+        help = "Original source roots. Relative paths are computed from these if given."
+    )]
+    roots: Vec<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Run the PDB's SRCSRVCMD commands to fetch sources. Only pass this for PDBs you trust."
+    )]
+    trust_srcsrv_commands: bool,
+
+    #[structopt(flatten)]
+    opts: EmbedOpts,
+}
+
+#[derive(Debug, StructOpt)]
+struct PostBuildOp {
+    #[structopt(short, long, parse(from_os_str), help = "Built exe, dll, or pdb")]
+    target: PathBuf,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Project config to use. Defaults to nearest fts_pdbsrc_project.json above target or cwd"
+    )]
+    project_config: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+struct InstallServiceOp {
+    #[structopt(
+        long,
+        help = "Fail instead of relaunching elevated when not running as administrator"
+    )]
+    no_elevate: bool,
+
+    #[structopt(
+        long,
+        help = "Account to run as instead of LocalSystem, e.g. DOMAIN\\svc_symbols, DOMAIN\\gmsa_symbols$, \
+                or NT SERVICE\\fts_pdbsrc_service"
+    )]
+    account: Option<String>,
+
+    #[structopt(long, help = "Password for --account. Not used by gMSA or built in accounts")]
+    password: Option<String>,
+
+    #[structopt(
+        long,
+        default_value = DEFAULT_SERVICE_NAME,
+        help = "Service name. Install more than one to run independent instances side by side"
+    )]
+    name: String,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Service config. Defaults to fts_pdbsrc_service_config.json beside fts_pdbsrc_service.exe"
+    )]
+    config: Option<PathBuf>,
+
+    #[structopt(long, help = "Port for msgpack protocol. Overrides port in service config")]
+    port: Option<u16>,
+}
+
+#[derive(Debug, StructOpt)]
+struct ServeOp {
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Service config. Defaults to fts_pdbsrc_service_config.json beside fts_pdbsrc_service.exe"
+    )]
+    config: Option<PathBuf>,
+
+    #[structopt(long, help = "Port for msgpack protocol. Overrides port in service config")]
+    port: Option<u16>,
+
+    #[structopt(long, default_value = DEFAULT_SERVICE_NAME, help = "Instance name. Selects index and stats")]
+    name: String,
+}
+
+#[derive(Debug, StructOpt)]
+struct UninstallServiceOp {
+    #[structopt(
+        long,
+        help = "Fail instead of relaunching elevated when not running as administrator"
+    )]
+    no_elevate: bool,
+
+    #[structopt(long, default_value = DEFAULT_SERVICE_NAME, help = "Name service was installed with")]
+    name: String,
+}
+
+// Project-local settings used by postbuild. Roots and other paths are relative to the config file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct ProjectConfig {
+    pub roots: Vec<PathBuf>,
+
+    #[serde(default)]
+    pub exact_root_match: bool,
+
+    #[serde(default)]
+    pub on_collision: RootCollision,
+
+    // Rules and rules files for path_map. Files are relative to the config file.
+    #[serde(default)]
+    pub path_mappings: Vec<String>,
+
+    #[serde(default)]
+    pub path_mapping_files: Vec<PathBuf>,
+
+    // Globs for files the PDB doesn't reference. See extra_files.
+    #[serde(default)]
+    pub extra: Vec<String>,
+
+    #[serde(default = "ProjectConfig::default_encrypt_mode")]
+    pub encrypt_mode: EncryptMode,
+
+    #[serde(default)]
+    pub annotations: Option<AnnotationFormat>,
+
+    #[serde(default = "ProjectConfig::default_max_file_size")]
+    pub max_file_size: u64,
+
+    #[serde(default)]
+    pub sbom: Option<PathBuf>,
+
+    #[serde(default)]
+    pub pack_streams: bool,
+
+    #[serde(default)]
+    pub compress: manifest::Compression,
+
+    #[serde(default)]
+    pub zstd_dictionary: bool,
+
+    #[serde(default)]
+    pub srcsrv_mac_key: Option<String>,
+
+    #[serde(default)]
+    pub service_port: Option<u16>,
+
+    #[serde(default)]
+    pub http_url: Option<String>,
+
+    #[serde(default)]
+    pub pdb_identity: bool,
+
+    #[serde(default)]
+    pub strict: bool,
+
+    #[serde(default)]
+    pub dotnet_embedded_source: bool,
+
+    #[serde(default)]
+    pub source_link: Option<PathBuf>,
+
+    #[serde(default)]
+    pub license: LicenseOpts,
+
+    #[serde(default)]
+    pub sign: SignOpts,
+}
+
+impl ProjectConfig {
+    const FILENAME: &'static str = "fts_pdbsrc_project.json";
+
+    fn default_encrypt_mode() -> EncryptMode {
+        EncryptMode::Plaintext
+    }
+
+    fn default_max_file_size() -> u64 {
+        16 * 1024 * 1024
+    }
+}
+
+// Source file referenced by a PDB that will be embedded
+struct SourceFile {
+    raw_filepath: String,
+    relpath: PathBuf,
+    filename: String,
+    checksum: Option<SourceChecksum>,
+
+    // Contents provided up front, e.g. from an archive. Otherwise read from raw_filepath.
+    contents: Option<Vec<u8>>,
+}
+
+// Owned copy of the checksum the compiler recorded for a source file
+#[derive(Clone, Debug)]
+enum SourceChecksum {
+    Md5(Vec<u8>),
+    Sha1(Vec<u8>),
+    Sha256(Vec<u8>),
+}
+
+impl SourceChecksum {
+    fn from_pdb(checksum: &FileChecksum) -> Option<SourceChecksum> {
+        match checksum {
+            FileChecksum::None => None,
+            FileChecksum::Md5(bytes) => Some(SourceChecksum::Md5(bytes.to_vec())),
+            FileChecksum::Sha1(bytes) => Some(SourceChecksum::Sha1(bytes.to_vec())),
+            FileChecksum::Sha256(bytes) => Some(SourceChecksum::Sha256(bytes.to_vec())),
+        }
+    }
+
+    fn from_document(document: &portable_pdb::Document) -> Option<SourceChecksum> {
+        match document.hash_algorithm {
+            portable_pdb::SHA1_HASH => Some(SourceChecksum::Sha1(document.hash.clone())),
+            portable_pdb::SHA256_HASH => Some(SourceChecksum::Sha256(document.hash.clone())),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, contents: &[u8]) -> bool {
+        match self {
+            SourceChecksum::Md5(expected) => md5::Md5::digest(contents).as_slice() == expected.as_slice(),
+            SourceChecksum::Sha1(expected) => sha1::Sha1::digest(contents).as_slice() == expected.as_slice(),
+            SourceChecksum::Sha256(expected) => {
+                sha2::Sha256::digest(contents).as_slice() == expected.as_slice()
+            }
+        }
+    }
+}
+
+// Source file stored in a PDB by embed
+struct EmbeddedSource {
+    original_path: String,
+    relpath: String,
+    contents: Vec<u8>,
+}
+
+// Non-fatal problems found while embedding
+#[derive(Debug)]
+enum EmbedWarning {
+    MissingFile(String),
+    ChecksumMismatch(String),
+    OversizedFile(String, u64),
+    FlaggedLicense(String, String),
+    ExternalFile(String, Option<String>),
+    UncommittedFile(String),
+
+    // Skipped file, file under an earlier root with the same relative path
+    RelpathCollision(String, String),
+}
+
+impl EmbedWarning {
+    fn path(&self) -> &str {
+        match self {
+            EmbedWarning::MissingFile(path)
+            | EmbedWarning::ChecksumMismatch(path)
+            | EmbedWarning::OversizedFile(path, _)
+            | EmbedWarning::FlaggedLicense(path, _)
+            | EmbedWarning::ExternalFile(path, _)
+            | EmbedWarning::UncommittedFile(path)
+            | EmbedWarning::RelpathCollision(path, _) => path,
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            EmbedWarning::MissingFile(_) => "Missing source file",
+            EmbedWarning::ChecksumMismatch(_) => "Source checksum mismatch",
+            EmbedWarning::OversizedFile(_, _) => "Oversized source file",
+            EmbedWarning::FlaggedLicense(_, _) => "Flagged source license",
+            EmbedWarning::ExternalFile(_, _) => "Third-party source file",
+            EmbedWarning::UncommittedFile(_) => "Uncommitted source file",
+            EmbedWarning::RelpathCollision(_, _) => "Colliding source file",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            EmbedWarning::MissingFile(path) => {
+                format!(
+                    "File [{}] is referenced by the PDB but does not exist on disk",
+                    path
+                )
+            }
+            EmbedWarning::ChecksumMismatch(path) => format!(
+                "File [{}] does not match the checksum recorded by the compiler",
+                path
+            ),
+            EmbedWarning::OversizedFile(path, size) => {
+                format!(
+                    "File [{}] is [{}] bytes which exceeds --max-file-size",
+                    path, size
+                )
+            }
+            EmbedWarning::FlaggedLicense(path, license) => {
+                format!("File [{}] has flagged license [{}]", path, license)
+            }
+            EmbedWarning::ExternalFile(path, license) => format!(
+                "File [{}] is in an external directory. License: [{}]",
+                path,
+                license.as_deref().unwrap_or("unknown")
+            ),
+            EmbedWarning::UncommittedFile(path) => format!(
+                "File [{}] differs from or is missing in the indexed commit. Local edits won't be shown.",
+                path
+            ),
+            EmbedWarning::RelpathCollision(path, kept) => format!(
+                "File [{}] has the same relative path as [{}] under another root and was skipped",
+                path, kept
+            ),
+        }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Functions
+// ----------------------------------------------------------------------------
+fn main() -> anyhow::Result<()> {
+    // Parse args. Help and version exit with 0 as usual.
+    let opts: Opts = match Opts::from_args_safe() {
+        Ok(opts) => opts,
+        Err(e) if !e.use_stderr() => e.exit(),
+        Err(e) => {
+            eprintln!("{}", e.message);
+            std::process::exit(Failure::BadArguments.exit_code());
+        }
+    };
+    let verbosity = match (opts.quiet, opts.verbose) {
+        (true, _) => client_log::Verbosity::Quiet,
+        (false, true) => client_log::Verbosity::Verbose,
+        (false, false) => client_log::Verbosity::Normal,
+    };
+    client_log::init(verbosity, opts.log_file.clone());
+    client_log::log(&format!(
+        "Running [{}]",
+        std::env::args().collect::<Vec<_>>().join(" ")
+    ));
+
+    // Read config and run program. keys may be creating the config so it's edited without reading first.
+    let config = match &opts.op {
+        Op::Keys(_) => Ok(Config::default()),
+        _ => read_config(opts.config.as_deref()),
+    };
+    let result = config.and_then(|config| {
+        apply_config(&config);
+        run(opts, config)
+    });
+    let exit_code = match result {
+        Ok(_) => 0,
+        Err(err) => {
+            client_log::log(&format!("Error: {:?}", err));
+            eprint!("Error: {:?}", err);
+            Failure::exit_code_for(&err)
+        }
+    };
+    client_log::log(&format!("Exited with [{}]", exit_code));
+
+    // Result result
+    std::process::exit(exit_code);
+}
+
+fn run(opts: Opts, config: Config) -> anyhow::Result<()> {
+    match opts.op {
+        Op::Embed(op) => embed(op)?,
+        Op::ExtractOne(op) => extract_one(op, config)?,
+        Op::Info(op) => info(op)?,
+        Op::List(op) => list(op)?,
+        Op::Verify(op) => verify(op, config)?,
+        Op::Lint(op) => lint(op)?,
+        Op::Strip(op) => strip(op)?,
+        Op::Locate(op) => locate(op)?,
+        Op::ListPdbs(op) => list_pdbs(op)?,
+        Op::Rescan(op) => rescan(op)?,
+        Op::Keygen(op) => keygen(op)?,
+        Op::Keys(KeysOp::Add(op)) => keys_add(op, opts.config.as_deref())?,
+        Op::Keys(KeysOp::Remove(op)) => keys_remove(op, opts.config.as_deref())?,
+        Op::Keys(KeysOp::List(op)) => keys_list(op, opts.config.as_deref())?,
+        Op::Doctor(op) => doctor(op, opts.config.as_deref())?,
+        Op::Status(op) => status(op)?,
+        Op::Service(ServiceOp::Stats(op)) => service_stats(op)?,
+        Op::Cache(CacheOp::Stats(op)) => cache_stats(op)?,
+        Op::Cache(CacheOp::List(op)) => cache_list(op)?,
+        Op::Cache(CacheOp::Clear(op)) => cache_clear(op)?,
+        Op::Rekey(op) => rekey(op, config)?,
+        Op::Export(op) => export(op, config)?,
+        Op::Import(op) => import(op)?,
+        Op::IndexGit(op) => index_git(op)?,
+        Op::IndexP4(op) => index_p4(op)?,
+        Op::Adopt(op) => adopt(op)?,
+        Op::PostBuild(op) => postbuild(op)?,
+        Op::Serve(op) => serve(op)?,
+        Op::InstallService(op) => install_service(op)?,
+        Op::UninstallService(op) => uninstall_service(op)?,
+    }
+
+    Ok(())
+}
+
+fn embed(op: EmbedOp) -> anyhow::Result<(), anyhow::Error> {
+    let mut root_matcher = roots::RootMatcher::new(&op.roots, !op.exact_root_match);
+    let path_mappings = path_map::load(&op.path_mappings, &op.path_mapping_files, !op.exact_root_match)?;
+
+    // Headers appear in the line program of every module that includes them. Each file is embedded once.
+    let mut seen_filepaths: std::collections::HashSet<PathBuf> = Default::default();
+    let mut num_duplicates = 0;
+
+    // Iterate files. Index of root each file was found under is kept for resolving collisions.
+    // None for files placed by a path mapping.
+    let mut filepaths: Vec<SourceFile> = Default::default();
+    let mut file_roots: Vec<Option<usize>> = Default::default();
+    let mut source_files = referenced_files(&op.pdb)?;
+    let extras = extra_files::find(&op.extra, &op.roots, !op.exact_root_match)?;
+    source_files.extend(
+        extras
+            .iter()
+            .map(|extra| (extra.to_string_lossy().to_string(), None)),
+    );
+    for (raw_filepath, checksum) in source_files {
+        let filepath = Path::new(&raw_filepath);
+        let mapped_relpath = path_map::map(&path_mappings, &raw_filepath)?;
+        let is_mapped = mapped_relpath.is_some();
+
+        if let Some(canonical_filepath) = root_matcher.canonicalize(filepath) {
+            if !seen_filepaths.insert(canonical_filepath.clone()) {
+                num_duplicates += 1;
+                continue;
+            }
+
+            // Path mappings take priority. Otherwise find subpath relative to a specified root.
+            let maybe_subpath = match mapped_relpath {
+                Some(relpath) => Some((None, relpath)),
+                None => root_matcher
+                    .relative_path(&canonical_filepath)
+                    .map(|(root_idx, subpath)| (Some(root_idx), subpath)),
+            };
+
+            if let Some((root_idx, subpath)) = maybe_subpath {
+                filepaths.push(SourceFile {
+                    relpath: subpath.clone(),
+                    filename: subpath.file_name().unwrap().to_string_lossy().to_string(),
+                    raw_filepath,
+                    checksum,
+                    contents: None,
+                });
+                file_roots.push(root_idx);
+            }
+        } else if is_mapped || root_matcher.is_under_root(filepath) {
+            // File is mapped or lives under a root but could not be found
+            if seen_filepaths.insert(filepath.to_owned()) {
+                report_warning(&EmbedWarning::MissingFile(raw_filepath), op.opts.annotations);
+            }
+        } else {
+            verbose!("Ignoring [{}]. Not under any root.", raw_filepath);
+        }
+    }
+    if num_duplicates > 0 {
+        status!(
+            "Skipped [{}] duplicate references to already found files",
+            num_duplicates
+        );
+    }
+    resolve_collisions(
+        &mut filepaths,
+        &file_roots,
+        &op.roots,
+        op.on_collision,
+        op.opts.annotations,
+    )?;
+
+    // Make sure we found at least some files
+    if filepaths.is_empty() {
+        bail!("Failed to find any files");
+    }
+
+    // Print files that were found and will be embedded
+    status!("Found following files:");
+    filepaths.iter().for_each(|source| {
+        status!("  {}", source.relpath.to_string_lossy());
+    });
+
+    check_licenses(&filepaths, &op.opts.license, op.opts.annotations)?;
+    write_embedded(&op.pdb, &filepaths, &op.opts)?;
+    sign_artifacts(&op.opts.sign)
+}
+
+// Returns path and compiler checksum of every source file referenced by PDB
+fn referenced_files(pdb_path: &str) -> anyhow::Result<Vec<(String, Option<SourceChecksum>)>> {
+    ensure_pdb_exists(Path::new(pdb_path))?;
+
+    // Portable PDBs list every file in their document table
+    if portable_pdb::is_portable_pdb(Path::new(pdb_path)) {
+        let documents = portable_pdb::PortablePdb::read(Path::new(pdb_path))?.documents()?;
+        return Ok(documents
+            .into_iter()
+            .map(|document| {
+                let checksum = SourceChecksum::from_document(&document);
+                (document.name, checksum)
+            })
+            .collect());
+    }
+
+    // Load PDB
+    let pdbfile = lock::open_read(Path::new(pdb_path))?;
+    let mut pdb = pdb::PDB::open(pdbfile)?;
+    referenced_files_in(&mut pdb)
+}
+
+// Files may be listed more than once. Sources are:
+//   - File checksum table of each module. Line and inlinee line records index into this table,
+//     so it covers every file with code.
+//   - UDT source line records. Headers that only declare types have no line records at all.
+fn referenced_files_in(pdb: &mut PDB<File>) -> anyhow::Result<Vec<(String, Option<SourceChecksum>)>> {
+    let string_table = pdb.string_table()?;
+
+    // Iterate files
+    let mut files: Vec<(String, Option<SourceChecksum>)> = Default::default();
+
+    let di = pdb.debug_information()?;
+    let mut modules = di.modules()?;
+    while let Some(module) = modules.next()? {
+        if let Some(module_info) = pdb.module_info(&module)? {
+            let line_program = module_info.line_program()?;
+
+            let mut file_iter = line_program.files();
+            while let Some(file) = file_iter.next()? {
+                let raw_filepath = string_table.get(file.name)?;
+                let filename_utf8 = std::str::from_utf8(raw_filepath.as_bytes())?;
+                files.push((filename_utf8.to_owned(), SourceChecksum::from_pdb(&file.checksum)));
+            }
+        }
+    }
+
+    // Iterate type definitions. Ids referenced by a record always precede it.
+    let id_information = pdb.id_information()?;
+    let mut id_finder = id_information.finder();
+    let mut ids = id_information.iter();
+    while let Some(id) = ids.next()? {
+        id_finder.update(&ids);
+
+        let udt_source = match id.parse() {
+            Ok(IdData::UserDefinedTypeSource(udt_source)) => udt_source,
+            _ => continue,
+        };
+        let raw_filepath = match udt_source.source_file {
+            UserDefinedTypeSourceFileRef::Local(id_index) => match id_finder.find(id_index)?.parse()? {
+                IdData::String(string) => string.name,
+                _ => continue,
+            },
+            UserDefinedTypeSourceFileRef::Remote(_, string_ref) => string_table.get(string_ref)?,
+        };
+        files.push((std::str::from_utf8(raw_filepath.as_bytes())?.to_owned(), None));
+    }
+
+    Ok(files)
+}
+
+// Name based uuid, RFC 4122 version 5, of the PDB's symbol server signature: GUID then age. The same
+// build always gets the same uuid, and it can be derived from the binary's debug directory. Portable
+// PDBs have no age; symbol servers use FFFFFFFF.
+fn pdb_identity_uuid(pdb_path: &Path) -> anyhow::Result<Uuid> {
+    const NAMESPACE: Uuid = Uuid::from_u128(0x6f1c_2b7e_4d0a_4c5e_9a63_d1e8_f02b_7c45);
+
+    let signature = if portable_pdb::is_portable_pdb(pdb_path) {
+        let (guid, _) = portable_pdb::PortablePdb::read(pdb_path)?.id()?;
+        format!("{:X}FFFFFFFF", guid.to_simple())
+    } else {
+        let info = open_pdb(pdb_path)?.pdb_information()?;
+        format!("{:X}{:X}", info.guid.to_simple(), info.age)
+    };
+    let mut hasher = sha1::Sha1::new();
+    hasher.update(NAMESPACE.as_bytes());
+    hasher.update(signature.as_bytes());
+
+    let mut bytes: uuid::Bytes = Default::default();
+    bytes.copy_from_slice(&hasher.finalize()[..16]);
+    Ok(uuid::Builder::from_bytes(bytes)
+        .set_variant(uuid::Variant::RFC4122)
+        .set_version(uuid::Version::Sha1)
+        .build())
+}
+
+// Writes source files and srcsrv stream into PDB
+fn write_embedded(pdb_path: &str, filepaths: &[SourceFile], opts: &EmbedOpts) -> anyhow::Result<()> {
+    let annotations = opts.annotations;
+    let max_file_size = opts.max_file_size;
+    let sbom_path = opts.sbom.as_deref();
+
+    // URL is expanded by srcsrv so must not contain '%'
+    let http_url = match &opts.http_url {
+        Some(_) if opts.service_port.is_some() => bail!("--http-url can not be combined with --service-port"),
+        Some(url) if !(url.starts_with("http://") || url.starts_with("https://")) || url.contains('%') => {
+            bail!(
+                "--http-url [{}] must be an http:// or https:// URL without '%'",
+                url
+            )
+        }
+        Some(url) => Some(url.trim_end_matches('/').to_owned()),
+        None => None,
+    };
+
+    // Standard records replace srcsrv and every fts_pdbsrc stream
+    if opts.dotnet_embedded_source {
+        return write_dotnet_embedded(pdb_path, filepaths, opts);
+    }
+    if opts.source_link.is_some() {
+        bail!("--source-link requires --dotnet-embedded-source");
+    }
+
+    // Linker or mspdbsrv may still hold PDB right after a build
+    lock::wait_for_write_access(Path::new(pdb_path))?;
+
+    // All writes go to a new file in the same directory. The original is atomically replaced only after
+    // every stream is written and optionally verified. New file is deleted on failure.
+    let pdb_dir = Path::new(pdb_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let work_pdb_path = tempfile::Builder::new()
+        .prefix(".fts_pdbsrc_")
+        .suffix(".pdb")
+        .tempfile_in(pdb_dir)?
+        .into_temp_path();
+
+    // Streams are added in memory and written out once
+    let mut msf = read_pdb_streams(Path::new(pdb_path))?;
+
+    // Keep srcsrv written by another indexer so strip can restore it
+    if let Some(previous_srcsrv) = msf.named_stream("srcsrv")?.filter(|bytes| !is_fts_srcsrv(bytes)) {
+        let previous_srcsrv = previous_srcsrv.to_owned();
+        msf.set_named_stream(manifest::PREVIOUS_SRCSRV_STREAM_NAME, previous_srcsrv)?;
+    }
+
+    // Create cipher for encryption if specified by mode
+    let (cipher, rng_key, key_id): (Option<Aes256Gcm>, Option<[u8; 32]>, Option<String>) =
+        match &opts.encrypt_mode {
+            EncryptMode::Plaintext => (None, None, None),
+            EncryptMode::EncryptWithRngKey => {
+                // Create cipher with randomly generated key
+                let mut rng = rand::thread_rng();
+                let key_rng_bytes = rng.gen::<[u8; 32]>();
+                let cipher = Aes256Gcm::new(Key::from_slice(&key_rng_bytes));
+                (
+                    Some(cipher),
+                    Some(key_rng_bytes),
+                    Some(manifest::key_id(&key_rng_bytes)),
+                )
+            }
+            EncryptMode::EncryptWithKey(key_hex) => {
+                // Create cipher from provided key
+                let key = hex::decode(key_hex)?;
+                let cipher = Aes256Gcm::new(Key::from_slice(&key));
+                (Some(cipher), None, Some(manifest::key_id(&key)))
+            }
+            EncryptMode::EncryptForRecipients(public_keys) => {
+                // Random key that only recipients can unwrap. Never printed.
+                let session_key = rand::thread_rng().gen::<[u8; 32]>();
+                msf.set_named_stream(
+                    recipients::STREAM_NAME,
+                    recipients::wrap(&session_key, public_keys)?,
+                )?;
+                let cipher = Aes256Gcm::new(Key::from_slice(&session_key));
+                (Some(cipher), None, Some(manifest::key_id(&session_key)))
+            }
+        };
+
+    // Bill of materials entries, if requested
+    let mut sbom_files: Vec<sbom::SbomFile> = Default::default();
+
+    // Records how each file is stored
+    let mut manifest = manifest::Manifest {
+        version: manifest::MANIFEST_VERSION,
+        encryption_header: cipher.is_some(),
+        tool_version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+        embedded_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok(),
+        encrypt_mode: Some(opts.encrypt_mode.name().to_owned()),
+        ..Default::default()
+    };
+
+    // Optionally compress with a dictionary trained on every file
+    let mut dictionary: Option<Vec<u8>> = None;
+    if opts.zstd_dictionary {
+        if opts.compress == manifest::Compression::Lz4 {
+            bail!("--zstd-dictionary can not be combined with --compress lz4");
+        }
+        dictionary = train_dictionary(filepaths)?;
+        if let Some(dictionary) = &dictionary {
+            msf.set_named_stream(manifest::DICTIONARY_STREAM_NAME, dictionary.clone())?;
+            manifest.zstd_dictionary = true;
+        }
+    }
+
+    // Read, compress, and encrypt files in parallel. Each worker owns its compressors.
+    // Files embedded with only a dictionary keep the headerless format older readers expect.
+    let compression_header = opts.compress != manifest::Compression::None;
+    manifest.compression_header = compression_header;
+    let staged = job_system::run(
+        filepaths,
+        || match (compression_header, &dictionary) {
+            (true, _) => (
+                Some(manifest::Compressor::new(opts.compress, dictionary.clone())),
+                None,
+            ),
+            (false, Some(dictionary)) => (None, Some(zstd::block::Compressor::with_dict(dictionary.clone()))),
+            (false, None) => (None, None),
+        },
+        |(compressor, legacy_compressor), source| {
+            let sbom = sbom_path.is_some();
+            stage_file(
+                source,
+                compressor.as_mut(),
+                legacy_compressor.as_mut(),
+                cipher.as_ref(),
+                sbom,
+            )
+        },
+    );
+
+    // Write source files into PDB. Warnings are reported in order.
+    let mut payload_writer = PayloadWriter::new(opts.pack_streams);
+    let mut num_checksum_mismatches = 0;
+    for (
+        SourceFile {
+            raw_filepath,
+            relpath,
+            ..
+        },
+        staged,
+    ) in filepaths.iter().zip(staged)
+    {
+        let StagedFile {
+            checksum_mismatch,
+            plaintext_len,
+            sha256,
+            modified,
+            sbom_file,
+            payload,
+            nonce,
+        } = staged?;
+
+        // Warn about files that don't match what was compiled or that are suspiciously large
+        if checksum_mismatch {
+            report_warning(&EmbedWarning::ChecksumMismatch(raw_filepath.clone()), annotations);
+            num_checksum_mismatches += 1;
+        }
+        if plaintext_len > max_file_size {
+            report_warning(
+                &EmbedWarning::OversizedFile(raw_filepath.clone(), plaintext_len),
+                annotations,
+            );
+        }
+
+        sbom_files.extend(sbom_file);
+        let mut file = manifest::ManifestFile {
+            relpath: relpath.to_string_lossy().to_string(),
+            key_id: if nonce.is_some() { key_id.clone() } else { None },
+            sha256: Some(sha256),
+            source_size: Some(plaintext_len),
+            original_path: Some(raw_filepath.clone()),
+            nonce,
+            ..Default::default()
+        };
+        if let Some(modified) = modified {
+            file.set_modified(modified);
+        }
+        payload_writer.write(msf.as_mut(), &mut manifest, file, payload)?;
+    }
+    payload_writer.finish(msf.as_mut())?;
+
+    // Nothing has been written to disk yet so failing leaves the PDB untouched
+    if opts.strict && num_checksum_mismatches > 0 {
+        bail!(
+            "[{}] files don't match the checksums recorded by the compiler. Rebuild or drop --strict.",
+            num_checksum_mismatches
+        );
+    }
+
+    // Create srcsrv.ini
+    let uuid = match opts.pdb_identity {
+        true => pdb_identity_uuid(Path::new(pdb_path))?,
+        false => uuid::Uuid::new_v4(),
+    };
+    manifest.pdb_uuid = Some(uuid);
+
+    let pdb_name = Path::new(pdb_path).file_stem().unwrap().to_string_lossy();
+    let mut srcsrv: Vec<u8> = Default::default();
+    writeln!(
+        srcsrv,
+        "SRCSRV: ini ------------------------------------------------"
+    )?;
+    writeln!(srcsrv, "VERSION=1")?;
+    writeln!(srcsrv, "VERCTRL=fts_pdbsrc")?;
+    // TODO: add datetime
+    writeln!(
+        srcsrv,
+        "SRCSRV: variables ------------------------------------------"
+    )?;
+    writeln!(srcsrv, "FTS_PDBSTR_UUID={}", uuid)?;
+    writeln!(srcsrv, "{}=1", srcsrv::ESCAPE_VARIABLE)?;
+    if let Some(http_url) = &http_url {
+        // Debugger downloads from the service's debuginfod endpoint itself. No command runs.
+        writeln!(srcsrv, "{}={}", HTTP_URL_VARIABLE, http_url)?;
+        writeln!(
+            srcsrv,
+            "SRCSRVTRG=%{}%/buildid/%FTS_PDBSTR_UUID%/source/%var2%",
+            HTTP_URL_VARIABLE
+        )?;
+    } else {
+        // Target is var3 rather than var2 since relative paths too deep for debuggers are shortened
+        writeln!(
+            srcsrv,
+            "SRCSRVTRG=%LOCALAPPDATA%\\fts\\fts_pdbsrc\\{}\\%FTS_PDBSTR_UUID%\\%var3%",
+            srcsrv::escape_field(&pdb_name)
+        )?;
+        if let Some(port) = opts.service_port {
+            writeln!(srcsrv, "{}={}", SERVICE_PORT_VARIABLE, port)?;
+        }
+        let mut command = String::from(
+            "fts_pdbsrc extract_one --pdb-uuid %FTS_PDBSTR_UUID% --file \"%var2%\" --out \"%SRCSRVTRG%\"",
+        );
+        if opts.service_port.is_some() {
+            command += &format!(" --port %{}%", SERVICE_PORT_VARIABLE);
+        }
+        writeln!(srcsrv, "SRCSRVCMD={}", command)?;
+    }
+    writeln!(
+        srcsrv,
+        "SRCSRV: source files ------------------------------------------"
+    )?;
+
+    for SourceFile {
+        raw_filepath,
+        relpath,
+        filename,
+        ..
+    } in filepaths
+    {
+        // Original path must stay verbatim for the debugger to match it. Relative path and
+        // extraction target are only used in expansions so they're escaped.
+        if raw_filepath.contains('*') {
+            bail!(
+                "Path [{}] contains '*' which can't be stored in srcsrv",
+                raw_filepath
+            );
+        }
+        let relpath = relpath.to_string_lossy();
+        let target = long_path::srcsrv_target(&pdb_name, &relpath, filename);
+
+        writeln!(
+            srcsrv,
+            "{}*{}*{}",
+            raw_filepath,
+            srcsrv::escape_field(&relpath),
+            srcsrv::escape_field(&target)
+        )?;
+    }
+    writeln!(
+        srcsrv,
+        "SRCSRV: end ------------------------------------------------"
+    )?;
+
+    if let Some(key) = &opts.srcsrv_mac_key {
+        manifest.srcsrv_mac = Some(manifest::srcsrv_mac(key, &srcsrv)?);
+    }
+
+    // Write srcsrv and manifest
+    msf.set_named_stream("srcsrv", srcsrv)?;
+    msf.set_named_stream(manifest::STREAM_NAME, serde_json::to_vec(&manifest)?)?;
+    msf.write(&work_pdb_path)
+        .with_context(|| format!("Failed to write [{:?}]", work_pdb_path))?;
+
+    if opts.verify {
+        let num_files = verify_embedded(&work_pdb_path, opts.srcsrv_mac_key.as_slice())?;
+        status!("Verified [{}] embedded files", num_files);
+    }
+
+    // Swap in modified PDB
+    lock::persist(work_pdb_path, Path::new(pdb_path))
+        .with_context(|| format!("Failed to replace [{}]", pdb_path))?;
+
+    // Write bill of materials
+    if let Some(sbom_path) = sbom_path {
+        sbom::write_spdx(sbom_path, pdb_path, &uuid.to_string(), &sbom_files)
+            .with_context(|| format!("Failed to write SBOM [{:?}]", sbom_path))?;
+        status!("Wrote SBOM [{:?}]", sbom_path);
+    }
+
+    // Write key to console IFF it was randomly generated
+    if let Some(rng_key) = rng_key {
+        println!("Files encrypted. The following key MUST be saved to decrypt. DO NOT LOSE THIS KEY.");
+        println!("BEGIN KEY------------------------------------------------");
+        let key_hex = hex::encode(rng_key);
+        println!("{}", key_hex);
+        println!("END KEY------------------------------------------------");
+    }
+
+    Ok(())
+}
+
+// Writes source files as standard EmbeddedSource records, plus optional SourceLink, into a portable PDB.
+// Debuggers read these directly so no srcsrv, service, or extract_one is involved.
+fn write_dotnet_embedded(pdb_path: &str, filepaths: &[SourceFile], opts: &EmbedOpts) -> anyhow::Result<()> {
+    if !portable_pdb::is_portable_pdb(Path::new(pdb_path)) {
+        bail!(
+            "--dotnet-embedded-source requires a portable PDB. [{}] is not one.",
+            pdb_path
+        );
+    }
+    if !matches!(opts.encrypt_mode, EncryptMode::Plaintext) {
+        bail!("--dotnet-embedded-source requires --encrypt-mode Plaintext. Debuggers can't decrypt it.");
+    }
+    let fts_options = opts.pack_streams
+        || opts.compress != manifest::Compression::None
+        || opts.zstd_dictionary
+        || opts.srcsrv_mac_key.is_some()
+        || opts.service_port.is_some()
+        || opts.http_url.is_some()
+        || opts.pdb_identity;
+    if fts_options {
+        bail!("--dotnet-embedded-source can not be combined with options for fts_pdbsrc streams or srcsrv");
+    }
+
+    lock::wait_for_write_access(Path::new(pdb_path))?;
+    let mut pdb = portable_pdb::PortablePdb::read(Path::new(pdb_path))
+        .with_context(|| format!("Failed to read [{}]", pdb_path))?;
+    let document_rows: HashMap<String, u32> = pdb
+        .documents()?
+        .into_iter()
+        .map(|document| (document.name, document.row))
+        .collect();
+
+    let source_link = match &opts.source_link {
+        Some(source_link_path) => {
+            let json = fs::read(source_link_path)
+                .with_context(|| format!("Failed to read SourceLink [{:?}]", source_link_path))?;
+            serde_json::from_slice::<serde_json::Value>(&json)
+                .with_context(|| format!("SourceLink [{:?}] is not valid JSON", source_link_path))?;
+            Some(json)
+        }
+        None => None,
+    };
+
+    // Read files in parallel. Records are deflated while they're added.
+    let sbom = opts.sbom.is_some();
+    let staged = job_system::run(
+        filepaths,
+        || (),
+        |_, source| stage_file(source, None, None, None, sbom),
+    );
+
+    let mut sbom_files: Vec<sbom::SbomFile> = Default::default();
+    let mut embedded: Vec<(u32, String, Vec<u8>)> = Default::default();
+    let mut num_checksum_mismatches = 0;
+    for (SourceFile { raw_filepath, .. }, staged) in filepaths.iter().zip(staged) {
+        let StagedFile {
+            checksum_mismatch,
+            plaintext_len,
+            sha256,
+            sbom_file,
+            payload,
+            ..
+        } = staged?;
+
+        if checksum_mismatch {
+            report_warning(
+                &EmbedWarning::ChecksumMismatch(raw_filepath.clone()),
+                opts.annotations,
+            );
+            num_checksum_mismatches += 1;
+        }
+        if plaintext_len > opts.max_file_size {
+            let warning = EmbedWarning::OversizedFile(raw_filepath.clone(), plaintext_len);
+            report_warning(&warning, opts.annotations);
+        }
+
+        let document_row = *document_rows
+            .get(raw_filepath)
+            .ok_or_else(|| anyhow!("PDB [{}] has no document [{}]", pdb_path, raw_filepath))?;
+        sbom_files.extend(sbom_file);
+        embedded.push((document_row, sha256, payload));
+    }
+
+    // Re-embedding would otherwise leave each old record behind the new ones
+    for (document_row, _, _) in &embedded {
+        pdb.remove_embedded_source(*document_row)?;
+    }
+    if let Some(source_link) = &source_link {
+        pdb.set_source_link(source_link)?;
+    }
+    for (document_row, _, contents) in &embedded {
+        pdb.set_embedded_source(*document_row, contents)?;
+    }
+
+    // Nothing has been written to disk yet so failing leaves the PDB untouched
+    if opts.strict && num_checksum_mismatches > 0 {
+        bail!(
+            "[{}] files don't match the checksums recorded by the compiler. Rebuild or drop --strict.",
+            num_checksum_mismatches
+        );
+    }
+
+    // Write next to original then swap, same as fts_pdbsrc streams
+    let pdb_dir = Path::new(pdb_path)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let work_pdb_path = tempfile::Builder::new()
+        .prefix(".fts_pdbsrc_")
+        .suffix(".pdb")
+        .tempfile_in(pdb_dir)?
+        .into_temp_path();
+    pdb.write(&work_pdb_path)
+        .with_context(|| format!("Failed to write [{:?}]", work_pdb_path))?;
+
+    if opts.verify {
+        let written = portable_pdb::PortablePdb::read(&work_pdb_path)?;
+        for (document_row, sha256, _) in &embedded {
+            let contents = written
+                .embedded_source(*document_row)?
+                .ok_or_else(|| anyhow!("Document [{}] has no EmbeddedSource record", document_row))?;
+            if manifest::sha256(&contents) != *sha256 {
+                bail!(
+                    "EmbeddedSource of document [{}] does not match source file",
+                    document_row
+                );
+            }
+        }
+        status!("Verified [{}] embedded files", embedded.len());
+    }
+
+    lock::persist(work_pdb_path, Path::new(pdb_path))
+        .with_context(|| format!("Failed to replace [{}]", pdb_path))?;
+
+    // Symbol servers key portable PDBs by guid
+    if let Some(sbom_path) = &opts.sbom {
+        let (guid, _) = pdb.id()?;
+        sbom::write_spdx(sbom_path, pdb_path, &guid.to_string(), &sbom_files)
+            .with_context(|| format!("Failed to write SBOM [{:?}]", sbom_path))?;
+        status!("Wrote SBOM [{:?}]", sbom_path);
+    }
+
+    status!(
+        "Wrote [{}] EmbeddedSource records into [{}]",
+        embedded.len(),
+        pdb_path
+    );
+    Ok(())
+}
+
+fn is_fts_srcsrv(srcsrv_stream: &[u8]) -> bool {
+    let srcsrv = srcsrv::SrcSrv::parse(&String::from_utf8_lossy(srcsrv_stream));
+    srcsrv.variable("FTS_PDBSTR_UUID").is_some()
+}
+
+// Writes file payloads into PDB streams and records where each went in the manifest
+struct PayloadWriter {
+    pack_streams: bool,
+
+    // Small files accumulate here when packing. Flushed to a new pack stream when full.
+    pack: Vec<u8>,
+    pack_idx: u32,
+}
+
+impl PayloadWriter {
+    fn new(pack_streams: bool) -> PayloadWriter {
+        PayloadWriter {
+            pack_streams,
+            pack: Default::default(),
+            pack_idx: 0,
+        }
+    }
+
+    // Large payloads are split across multiple streams. Fills in where file is stored.
+    fn write(
+        &mut self,
+        msf: &mut dyn manifest::StreamContainer,
+        manifest: &mut manifest::Manifest,
+        file: manifest::ManifestFile,
+        payload: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let stream_name = manifest::stream_name(&file.relpath);
+        let mut chunks: u32 = 0;
+        if self.pack_streams && payload.len() <= manifest::MAX_CHUNK_SIZE {
+            if self.pack.len() + payload.len() > manifest::MAX_CHUNK_SIZE {
+                let pack = std::mem::take(&mut self.pack);
+                msf.set_named_stream(&manifest::pack_stream_name(self.pack_idx), pack)?;
+                self.pack_idx += 1;
+            }
+
+            manifest.files.push(manifest::ManifestFile {
+                stream: Some(manifest::pack_stream_name(self.pack_idx)),
+                chunks: 0,
+                offset: Some(self.pack.len() as u64),
+                size: Some(payload.len() as u64),
+                ..file
+            });
+            self.pack.extend_from_slice(&payload);
+            return Ok(());
+        } else if payload.len() > manifest::MAX_CHUNK_SIZE {
+            for chunk in payload.chunks(manifest::MAX_CHUNK_SIZE) {
+                msf.set_named_stream(&manifest::chunk_stream_name(&stream_name, chunks), chunk.to_vec())?;
+                chunks += 1;
+            }
+        } else {
+            msf.set_named_stream(&stream_name, payload)?;
+        }
+
+        manifest.files.push(manifest::ManifestFile {
+            stream: Some(stream_name),
+            chunks,
+            offset: None,
+            size: None,
+            ..file
+        });
+        Ok(())
+    }
+
+    fn finish(self, msf: &mut dyn manifest::StreamContainer) -> anyhow::Result<()> {
+        if !self.pack.is_empty() {
+            msf.set_named_stream(&manifest::pack_stream_name(self.pack_idx), self.pack)?;
+        }
+        Ok(())
+    }
+}
+
+// Source file read, compressed, and encrypted, ready to be written into PDB
+struct StagedFile {
+    checksum_mismatch: bool,
+    plaintext_len: u64,
+    sha256: String,
+    modified: Option<std::time::SystemTime>,
+    sbom_file: Option<sbom::SbomFile>,
+    payload: Vec<u8>,
+
+    // Hex nonce if encrypted
+    nonce: Option<String>,
+}
+
+// Runs on job_system workers. Must not touch shared state.
+fn stage_file(
+    source: &SourceFile,
+    compressor: Option<&mut manifest::Compressor>,
+    legacy_compressor: Option<&mut zstd::block::Compressor>,
+    cipher: Option<&Aes256Gcm>,
+    sbom: bool,
+) -> anyhow::Result<StagedFile> {
+    let plaintext = read_source_file(&source.raw_filepath, &source.contents)?;
+
+    // Provided contents have no meaningful time
+    let modified = match &source.contents {
+        Some(_) => None,
+        None => fs::metadata(&source.raw_filepath)
+            .and_then(|metadata| metadata.modified())
+            .ok(),
+    };
+    let checksum_mismatch = match &source.checksum {
+        Some(checksum) => !checksum.matches(&plaintext),
+        None => false,
+    };
+    let sbom_file = if sbom {
+        Some(sbom::SbomFile::new(
+            &source.raw_filepath,
+            &source.relpath.to_string_lossy(),
+            &plaintext,
+        ))
+    } else {
+        None
+    };
+    let plaintext_len = plaintext.len() as u64;
+    let sha256 = manifest::sha256(&plaintext);
+
+    // Optionally compress then encrypt file contents
+    let stored = match (compressor, legacy_compressor) {
+        (Some(compressor), _) => compressor.compress(&plaintext)?,
+        (None, Some(compressor)) => compressor.compress(&plaintext, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+        (None, None) => plaintext,
+    };
+    let (payload, nonce) = match cipher {
+        None => (stored, None),
+        Some(cipher) => {
+            let (payload, nonce) = encrypt(cipher, &stored)
+                .with_context(|| format!("Failed to encrypt file: [{:?}]", source.raw_filepath))?;
+            (payload, Some(nonce))
+        }
+    };
+
+    Ok(StagedFile {
+        checksum_mismatch,
+        plaintext_len,
+        sha256,
+        modified,
+        sbom_file,
+        payload,
+        nonce,
+    })
+}
+
+// Reads file unless contents were provided up front
+fn read_source_file(raw_filepath: &str, contents: &Option<Vec<u8>>) -> anyhow::Result<Vec<u8>> {
+    match contents {
+        Some(contents) => Ok(contents.clone()),
+        None => {
+            let mut file = File::open(raw_filepath)?;
+            let mut plaintext: Vec<u8> = Default::default();
+            file.read_to_end(&mut plaintext)
+                .with_context(|| format!("Error reading file: [{:?}]", raw_filepath))?;
+            Ok(plaintext)
+        }
+    }
+}
+
+// Trains zstd dictionary on contents of every file. None if there are too few files to train on.
+fn train_dictionary(filepaths: &[SourceFile]) -> anyhow::Result<Option<Vec<u8>>> {
+    let samples = filepaths
+        .iter()
+        .map(|source| read_source_file(&source.raw_filepath, &source.contents))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    match zstd::dict::from_samples(&samples, manifest::DICTIONARY_SIZE) {
+        Ok(dictionary) => Ok(Some(dictionary)),
+        Err(e) => {
+            status!(
+                "Failed to train zstd dictionary. Files will not be compressed: [{}]",
+                e
+            );
+            Ok(None)
+        }
+    }
+}
+
+// Files under different roots may share a relative path. Streams and extraction targets are keyed
+// by relative path, and extraction ignores case, so one file would silently replace the other.
+fn resolve_collisions(
+    filepaths: &mut Vec<SourceFile>,
+    file_roots: &[Option<usize>],
+    roots: &[PathBuf],
+    on_collision: RootCollision,
+    annotations: Option<AnnotationFormat>,
+) -> anyhow::Result<()> {
+    let find_collisions = |filepaths: &[SourceFile]| -> Vec<Vec<usize>> {
+        let mut groups: HashMap<String, Vec<usize>> = Default::default();
+        for (idx, source) in filepaths.iter().enumerate() {
+            let key = source.relpath.to_string_lossy().replace('\\', "/").to_lowercase();
+            groups.entry(key).or_default().push(idx);
+        }
+        let mut collisions: Vec<Vec<usize>> = groups.into_values().filter(|group| group.len() > 1).collect();
+        collisions.sort();
+        collisions
+    };
+    let print_collisions = |filepaths: &[SourceFile], collisions: &[Vec<usize>]| {
+        for group in collisions {
+            println!(
+                "Relative path [{}] is shared by:",
+                filepaths[group[0]].relpath.to_string_lossy()
+            );
+            for idx in group {
+                println!("  {}", filepaths[*idx].raw_filepath);
+            }
+        }
+    };
+
+    let collisions = find_collisions(filepaths);
+    if collisions.is_empty() {
+        return Ok(());
+    }
+
+    match on_collision {
+        RootCollision::Error => {
+            print_collisions(filepaths, &collisions);
+            bail!(
+                "[{}] relative paths are shared by files under different roots. \
+                 Pass --on-collision prefix-root or skip.",
+                collisions.len()
+            );
+        }
+        RootCollision::Skip => {
+            let skipped: std::collections::HashSet<usize> = collisions
+                .iter()
+                .flat_map(|group| group[1..].iter().copied())
+                .collect();
+            for group in &collisions {
+                for idx in &group[1..] {
+                    let warning = EmbedWarning::RelpathCollision(
+                        filepaths[*idx].raw_filepath.clone(),
+                        filepaths[group[0]].raw_filepath.clone(),
+                    );
+                    report_warning(&warning, annotations);
+                }
+            }
+            let mut idx = 0;
+            filepaths.retain(|_| {
+                idx += 1;
+                !skipped.contains(&(idx - 1))
+            });
+        }
+        RootCollision::PrefixRoot => {
+            for idx in collisions.iter().flatten() {
+                // Mapped files already have the path rules chose
+                let root_idx = match file_roots[*idx] {
+                    Some(root_idx) => root_idx,
+                    None => continue,
+                };
+                let root_name = fs::canonicalize(&roots[root_idx])
+                    .ok()
+                    .and_then(|root| root.file_name().map(|name| name.to_string_lossy().to_string()))
+                    .unwrap_or_else(|| format!("root{}", root_idx));
+                let source = &mut filepaths[*idx];
+                source.relpath = Path::new(&root_name).join(&source.relpath);
+            }
+
+            // Roots with the same directory name, or mapped files, still collide
+            let collisions = find_collisions(filepaths);
+            if !collisions.is_empty() {
+                print_collisions(filepaths, &collisions);
+                bail!("Relative paths still collide after prefixing root names. Pass --on-collision skip.");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Scans files for license headers before anything is written into the PDB
+fn check_licenses(
+    filepaths: &[SourceFile],
+    opts: &LicenseOpts,
+    annotations: Option<AnnotationFormat>,
+) -> anyhow::Result<()> {
+    if opts.license_check == LicenseCheck::Off {
+        return Ok(());
+    }
+
+    // Compare paths ignoring case and separators
+    let normalize = |path: &Path| path.to_string_lossy().replace('\\', "/").to_lowercase();
+    let external_dirs: Vec<String> = opts
+        .external_dirs
+        .iter()
+        .map(|dir| {
+            let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.clone());
+            normalize(&dir).trim_end_matches('/').to_owned() + "/"
+        })
+        .collect();
+
+    let mut num_flagged = 0;
+    for SourceFile {
+        raw_filepath,
+        contents,
+        ..
+    } in filepaths
+    {
+        let plaintext = read_source_file(raw_filepath, contents)?;
+        let header = String::from_utf8_lossy(&plaintext[..plaintext.len().min(sbom::LICENSE_SCAN_BYTES)]);
+        let license = sbom::detect_license(&header);
+
+        let filepath = fs::canonicalize(raw_filepath).unwrap_or_else(|_| PathBuf::from(raw_filepath));
+        let is_external = external_dirs
+            .iter()
+            .any(|dir| normalize(&filepath).starts_with(dir));
+        let flagged_license = license.as_ref().filter(|license| {
+            let license = license.to_uppercase();
+            opts.flagged_licenses
+                .iter()
+                .any(|flagged| license.contains(&flagged.to_uppercase()))
+        });
+
+        let warning = match (flagged_license, is_external) {
+            (Some(license), _) => EmbedWarning::FlaggedLicense(raw_filepath.clone(), license.clone()),
+            (None, true) => EmbedWarning::ExternalFile(raw_filepath.clone(), license.clone()),
+            (None, false) => continue,
+        };
+        report_warning(&warning, annotations);
+        num_flagged += 1;
+    }
+
+    if num_flagged > 0 && opts.license_check == LicenseCheck::Fail {
+        bail!(
+            "License check failed. [{}] files are third-party or have flagged licenses",
+            num_flagged
+        );
+    }
+
+    Ok(())
+}
+
+// Runs signing command for each artifact
+fn sign_artifacts(opts: &SignOpts) -> anyhow::Result<()> {
+    for artifact in &opts.sign {
+        let command = opts.sign_command.replace("{file}", &artifact.to_string_lossy());
+        status!("Signing [{:?}]", artifact);
+
+        let failure = match Exec::shell(&command).join() {
+            Ok(status) if status.success() => None,
+            Ok(status) => Some(format!("status [{:?}]", status)),
+            Err(e) => Some(format!("error [{}]", e)),
+        };
+
+        if let Some(failure) = failure {
+            if opts.sign_allow_failure {
+                // Warnings print even when quiet
+                let warning = format!(
+                    "Warning: Signing [{:?}] failed with {}. Cmd: [{}]",
+                    artifact, failure, command
+                );
+                client_log::log(&warning);
+                println!("{}", warning);
+            } else {
+                bail!(
+                    "Signing [{:?}] failed with {}. Cmd: [{}]",
+                    artifact,
+                    failure,
+                    command
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn report_warning(warning: &EmbedWarning, format: Option<AnnotationFormat>) {
+    // Workflow commands are read from stdout by each CI system
+    match format {
+        None => println!("Warning: {}", warning.message()),
+        Some(AnnotationFormat::GitHub) => {
+            let escape = |s: &str| s.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A");
+            println!(
+                "::warning file={},title={}::{}",
+                escape(warning.path()).replace(',', "%2C").replace(':', "%3A"),
+                escape(warning.title()),
+                escape(&warning.message())
+            );
+        }
+        Some(AnnotationFormat::TeamCity) => {
+            let escape = |s: &str| {
+                s.replace('|', "||")
+                    .replace('\'', "|'")
+                    .replace('[', "|[")
+                    .replace(']', "|]")
+                    .replace('\r', "|r")
+                    .replace('\n', "|n")
+            };
+            println!(
+                "##teamcity[message text='{}: {}' status='WARNING']",
+                escape(warning.title()),
+                escape(&warning.message())
+            );
+        }
+        Some(AnnotationFormat::Azure) => {
+            let escape = |s: &str| {
+                s.replace('%', "%AZP25")
+                    .replace(';', "%3B")
+                    .replace(']', "%5D")
+                    .replace('\r', "%0D")
+                    .replace('\n', "%0A")
+            };
+            println!(
+                "##vso[task.logissue type=warning;sourcepath={}]{}",
+                escape(warning.path()),
+                escape(&warning.message())
+            );
+        }
+    }
+}
+
+fn extract_one(op: ExtractOneOp, config: Config) -> anyhow::Result<()> {
+    if let Some(port) = op.port {
+        SERVICE_PORT.store(port, Ordering::Relaxed);
+    }
+
+    // Debuggers only check exit code, so failures must exit non-zero. Category is recorded for `doctor`.
+    if let Err((failure, error)) = try_extract_one(&op, &config) {
+        record_extract_failure(&op, failure, &error);
+        client_log::log(&format!("Error: [{:?}] {:?}", failure, error));
+        eprintln!("Error: [{:?}] {:?}", failure, error);
+        client_log::log(&format!("Exited with [{}]", failure.exit_code()));
+        std::process::exit(failure.exit_code());
+    }
+
+    // Extraction succeeded. Pruning problems must not fail the debugger's command.
+    prune_extract_cache(&op.out(), &config.extract);
+    Ok(())
+}
+
+// Only prunes when extracting into the default cache, so custom SRCSRVTRG directories are left alone
+fn prune_extract_cache(out: &Path, config: &ExtractConfig) {
+    let max_age_days = config
+        .cache_max_age_days
+        .unwrap_or(extract_cache::DEFAULT_MAX_AGE_DAYS);
+    let root = match extract_cache::root() {
+        Some(root) if max_age_days > 0 && out.starts_with(&root) => root,
+        _ => return,
+    };
+    let max_age = std::time::Duration::from_secs(max_age_days.saturating_mul(24 * 60 * 60));
+    match extract_cache::prune_if_due(&root, max_age) {
+        Ok(removed) if removed.files == 0 => (),
+        Ok(removed) => {
+            verbose!(
+                "Removed [{}] extracted files unused for [{}] days",
+                removed.files,
+                max_age_days
+            )
+        }
+        Err(e) => eprintln!("Failed to prune extraction cache [{:?}]: [{:?}]", root, e),
+    }
+}
+
+fn info(op: InfoOp) -> anyhow::Result<()> {
+    // Load PDB. Portable PDBs have no modules and list files in their document table.
+    let mut pdb = match portable_pdb::is_portable_pdb(Path::new(&op.pdb)) {
+        true => None,
+        false => Some(open_pdb(Path::new(&op.pdb))?),
+    };
+    let referenced_files = match &mut pdb {
+        Some(pdb) => referenced_files_in(pdb)?,
+        None => referenced_files(&op.pdb)?,
+    };
+
+    // Iterate files
+    let mut seen_filepaths: std::collections::HashSet<String> = Default::default();
+    let mut files: Vec<InfoFile> = Default::default();
+    for (raw_filepath, _) in referenced_files {
+        if !seen_filepaths.insert(raw_filepath.to_lowercase()) {
+            continue;
+        }
+
+        let exists = std::fs::metadata(Path::new(&raw_filepath)).is_ok();
+        files.push(InfoFile {
+            path: raw_filepath,
+            exists,
+        });
+    }
+
+    // Iterate streams
+    let streams: Vec<String> = match &mut pdb {
+        Some(pdb) => pdb
+            .pdb_information()?
+            .stream_names()?
+            .iter()
+            .map(|stream_name| stream_name.name.to_string().into_owned())
+            .collect(),
+        None => portable_pdb::PortablePdb::read(Path::new(&op.pdb))?.named_streams()?,
+    };
+
+    // Measure what embedding added
+    let sizes = info_sizes(Path::new(&op.pdb))?;
+
+    // Decode srcsrv written by fts_pdbsrc or any other indexer
+    let mut streams_reader = open_streams(Path::new(&op.pdb))?;
+    let srcsrv = streams_reader
+        .read_stream("srcsrv")?
+        .map(|stream| srcsrv::SrcSrv::parse(&String::from_utf8_lossy(&stream)));
+    let manifest = manifest::Manifest::read(&mut streams_reader)?;
+
+    match op.format {
+        InfoFormat::Text => {
+            for file in &files {
+                let filepath = Path::new(&file.path);
+                if file.exists {
+                    println!("File exists: [{:?}]", filepath);
+                } else {
+                    println!("File not found: [{:?}]", filepath);
+                }
+            }
+            streams
+                .iter()
+                .for_each(|stream_name| println!("Stream: [{}]", stream_name));
+            if let Some(srcsrv) = &srcsrv {
+                print_srcsrv(srcsrv);
+            }
+            if let Some(manifest) = &manifest {
+                print_manifest(manifest);
+            }
+
+            if !sizes.embedded_streams.is_empty() {
+                println!();
+                for stream in &sizes.embedded_streams {
+                    println!("Embedded stream: [{}] [{}] bytes", stream.name, stream.size);
+                }
+                println!(
+                    "Embedded [{}] bytes in [{}] streams. PDB is [{}] bytes. Embedding adds [{}] bytes.",
+                    sizes.embedded_bytes,
+                    sizes.embedded_streams.len(),
+                    sizes.pdb_size,
+                    sizes.embedded_size_delta
+                );
+            }
+        }
+        InfoFormat::Json => {
+            let report = InfoReport {
+                modules: match &mut pdb {
+                    Some(pdb) => info_modules(pdb)?,
+                    None => Default::default(),
+                },
+                files,
+                streams,
+                srcsrv: srcsrv.map(|srcsrv| InfoSrcSrv {
+                    sources: srcsrv.unescaped_sources(),
+                    ini: srcsrv.ini,
+                    variables: srcsrv.variables,
+                }),
+                manifest,
+                embedded_streams: sizes.embedded_streams,
+                embedded_bytes: sizes.embedded_bytes,
+                pdb_size: sizes.pdb_size,
+                embedded_size_delta: sizes.embedded_size_delta,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+    }
+
+    Ok(())
+}
+
+struct InfoSizes {
+    embedded_streams: Vec<InfoStream>,
+    embedded_bytes: u64,
+    pdb_size: u64,
+    embedded_size_delta: u64,
+}
+
+fn info_sizes(pdb_path: &Path) -> anyhow::Result<InfoSizes> {
+    let pdb_path = expand_if_compressed(pdb_path)?;
+    let mut msf = read_pdb_streams(&pdb_path)?;
+
+    let is_fts_srcsrv = msf.named_stream("srcsrv")?.is_some_and(is_fts_srcsrv);
+    let mut embedded_streams: Vec<InfoStream> = Default::default();
+    for name in msf.stream_names()? {
+        if manifest::is_fts_stream(&name) || (is_fts_srcsrv && name == "srcsrv") {
+            let size = msf.named_stream(&name)?.map_or(0, |bytes| bytes.len() as u64);
+            embedded_streams.push(InfoStream { name, size });
+        }
+    }
+    embedded_streams.sort_by(|a, b| a.name.cmp(&b.name));
+    let embedded_bytes = embedded_streams.iter().map(|stream| stream.size).sum();
+
+    // Compare like for like. Original PDB may not be compactly laid out.
+    let embedded_size = msf.written_size()?;
+    strip_fts_streams(msf.as_mut())?;
+    let stripped_size = msf.written_size()?;
+
+    Ok(InfoSizes {
+        embedded_streams,
+        embedded_bytes,
+        pdb_size: fs::metadata(&pdb_path)?.len(),
+        embedded_size_delta: embedded_size.saturating_sub(stripped_size),
+    })
+}
+
+fn print_srcsrv(srcsrv: &srcsrv::SrcSrv) {
+    println!();
+    println!("srcsrv:");
+
+    // Most useful variables first
+    const KNOWN_VARIABLES: [&str; 5] = ["VERCTRL", "VERSION", "FTS_PDBSTR_UUID", "SRCSRVTRG", "SRCSRVCMD"];
+    for name in &KNOWN_VARIABLES {
+        if let Some(value) = srcsrv.variable(name) {
+            println!("  {:<16} {}", name, value);
+        }
+    }
+
+    // Remaining variables, e.g. from other indexers
+    for (name, value) in srcsrv.ini.iter().chain(srcsrv.variables.iter()) {
+        if !KNOWN_VARIABLES
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(name))
+        {
+            println!("  {:<16} {}", name, value);
+        }
+    }
+
+    // Source lines as a table with one column per %varN%
+    let sources = srcsrv.unescaped_sources();
+    let num_columns = sources.iter().map(|source| source.len()).max().unwrap_or(0);
+    let headers: Vec<String> = (1..=num_columns).map(|idx| format!("var{}", idx)).collect();
+    let widths: Vec<usize> = (0..num_columns)
+        .map(|column| {
+            sources
+                .iter()
+                .filter_map(|source| source.get(column))
+                .map(|field| field.chars().count())
+                .chain(Some(headers[column].len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let print_row = |fields: &[String]| {
+        let row: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| format!("{:<width$}", fields.get(column).map_or("", |s| s), width = width))
+            .collect();
+        println!("  {}", row.join("  ").trim_end());
+    };
+
+    println!();
+    println!("srcsrv sources: [{}]", sources.len());
+    if !sources.is_empty() {
+        print_row(&headers);
+        sources.iter().for_each(|source| print_row(source));
+    }
+}
+
+fn print_manifest(manifest: &manifest::Manifest) {
+    println!();
+    println!("manifest:");
+    let unknown = || "unknown".to_owned();
+    println!("  {:<16} {}", "version", manifest.version);
+    println!(
+        "  {:<16} {}",
+        "tool_version",
+        manifest.tool_version.clone().unwrap_or_else(unknown)
+    );
+    println!(
+        "  {:<16} {}",
+        "embedded_at",
+        manifest.embedded_at.map_or_else(unknown, |secs| secs.to_string())
+    );
+    println!(
+        "  {:<16} {}",
+        "encrypt_mode",
+        manifest.encrypt_mode.clone().unwrap_or_else(unknown)
+    );
+    println!(
+        "  {:<16} {}",
+        "pdb_uuid",
+        manifest.pdb_uuid.map_or_else(unknown, |uuid| uuid.to_string())
+    );
+
+    println!();
+    println!("manifest files: [{}]", manifest.files.len());
+    for file in &manifest.files {
+        let size = file.source_size.map_or_else(unknown, |size| size.to_string());
+        let sha256 = file.sha256.clone().unwrap_or_else(unknown);
+        println!("  {} [{}] bytes sha256 [{}]", file.relpath, size, sha256);
+    }
+}
+
+fn info_modules(pdb: &mut PDB<File>) -> anyhow::Result<Vec<InfoModule>> {
+    let string_table = pdb.string_table()?;
+    let mut result: Vec<InfoModule> = Default::default();
+
+    let di = pdb.debug_information()?;
+    let mut modules = di.modules()?;
+    while let Some(module) = modules.next()? {
+        let mut files: Vec<String> = Default::default();
+        if let Some(module_info) = pdb.module_info(&module)? {
+            let line_program = module_info.line_program()?;
+            let mut file_iter = line_program.files();
+            while let Some(file) = file_iter.next()? {
+                files.push(string_table.get(file.name)?.to_string().into_owned());
+            }
+        }
+
+        result.push(InfoModule {
+            name: module.module_name().into_owned(),
+            object_file: module.object_file_name().into_owned(),
+            files,
+        });
+    }
+
+    Ok(result)
+}
+
+// Prints embedded files without reading their contents. Keys are not needed.
+fn list(op: ListOp) -> anyhow::Result<()> {
+    let mut pdb = open_streams(&op.pdb)?;
+    let manifest = manifest::Manifest::read(&mut pdb)?;
+    let files = embedded_files(&mut pdb, &op.pdb, manifest.as_ref())?;
+    let encryption_header = manifest.is_some_and(|manifest| manifest.encryption_header);
+
+    for file in &files {
+        let mode = if encryption_header || file.srcsrv_nonce.is_some() {
+            "Encrypted"
+        } else {
+            "Plaintext"
+        };
+        println!("{} {} {}", mode, file.relpath, file.original_path);
+    }
+    println!("[{}] embedded files", files.len());
+
+    Ok(())
+}
+
+// Checks srcsrv, manifest, and streams agree with each other. Nothing is decrypted, so no keys are
+// needed. Fails if any check fails so CI can gate on it.
+fn lint(op: LintOp) -> anyhow::Result<()> {
+    let pdb_path = expand_if_compressed(&op.pdb)?;
+    let mut pdb = read_pdb_streams(&pdb_path)?;
+    let srcsrv = read_fts_srcsrv(pdb.as_mut(), &op.pdb)?;
+    let manifest = manifest::Manifest::read(pdb.as_mut())?;
+    let sources = srcsrv.unescaped_sources();
+    let mut problems: Vec<String> = Default::default();
+    let is_nonce = |nonce: &str| hex::decode(nonce).is_ok_and(|bytes| bytes.len() == manifest::NONCE_SIZE);
+
+    // Uuid must parse and agree with manifest
+    match srcsrv
+        .variable("FTS_PDBSTR_UUID")
+        .map(|uuid| (uuid, Uuid::parse_str(uuid)))
+    {
+        Some((_, Ok(uuid))) => {
+            let manifest_uuid = manifest.as_ref().and_then(|manifest| manifest.pdb_uuid);
+            if let Some(manifest_uuid) = manifest_uuid.filter(|manifest_uuid| *manifest_uuid != uuid) {
+                problems.push(format!(
+                    "FTS_PDBSTR_UUID [{}] but manifest has [{}]",
+                    uuid, manifest_uuid
+                ));
+            }
+        }
+        Some((uuid, Err(_))) => problems.push(format!("FTS_PDBSTR_UUID [{}] is not a uuid", uuid)),
+        None => problems.push("srcsrv has no FTS_PDBSTR_UUID".to_owned()),
+    }
+
+    // Nonces are passed to extract_one only by PDBs embedded before encryption headers
+    let encryption_header = manifest
+        .as_ref()
+        .is_some_and(|manifest| manifest.encryption_header);
+    let has_nonce_column = sources.iter().any(|source| source.get(3).is_some());
+    match srcsrv.variable("SRCSRVCMD") {
+        None if srcsrv.variable(HTTP_URL_VARIABLE).is_some() => (),
+        None => problems.push("srcsrv has no SRCSRVCMD".to_owned()),
+        Some(command) => {
+            let runs_extract = ["extract_one", "%FTS_PDBSTR_UUID%", "%var2%"]
+                .iter()
+                .all(|part| command.contains(part));
+            if !runs_extract {
+                problems.push(format!(
+                    "SRCSRVCMD [{}] doesn't run extract_one for %var2%",
+                    command
+                ));
+            }
+            match (command.contains("--nonce %var4%"), has_nonce_column) {
+                (false, true) => problems.push("Source lines have nonces SRCSRVCMD doesn't pass".to_owned()),
+                (true, false) => problems.push("SRCSRVCMD passes nonces source lines don't have".to_owned()),
+                _ => (),
+            }
+        }
+    }
+    if encryption_header && has_nonce_column {
+        problems.push("Files have encryption headers but source lines also have nonces".to_owned());
+    }
+    if let Some(encrypt_mode) = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.encrypt_mode.as_ref())
+    {
+        if (encrypt_mode != "Plaintext") != encryption_header {
+            problems.push(format!(
+                "Manifest encrypt mode is [{}] but encryption headers are [{}]",
+                encrypt_mode,
+                if encryption_header { "on" } else { "off" }
+            ));
+        }
+    }
+
+    // Every source line has a readable stream
+    let lists_files = manifest.as_ref().is_some_and(|manifest| manifest.lists_files());
+    let mut listed: std::collections::HashSet<String> = Default::default();
+    for source in &sources {
+        let relpath = match source.as_slice() {
+            [_, relpath, _, ..] => relpath,
+            _ => {
+                problems.push(format!("Malformed srcsrv source line [{}]", source.join("*")));
+                continue;
+            }
+        };
+        listed.insert(relpath.clone());
+        if let Some(nonce) = source.get(3).filter(|nonce| !is_nonce(nonce)) {
+            problems.push(format!("[{}] has invalid srcsrv nonce [{}]", relpath, nonce));
+        }
+
+        let manifest_file = manifest.as_ref().and_then(|manifest| manifest.file(relpath));
+        if lists_files && manifest_file.is_none() {
+            problems.push(format!("[{}] is in srcsrv but not manifest", relpath));
+        }
+        let stream = match manifest::read_file(pdb.as_mut(), manifest.as_ref(), relpath) {
+            Ok(stream) => stream,
+            Err(e) => {
+                problems.push(format!("[{}] has no readable stream: [{}]", relpath, e));
+                continue;
+            }
+        };
+        if encryption_header {
+            match manifest::read_encryption(&stream, manifest.as_ref(), None) {
+                Ok(Some(encryption)) => {
+                    let recorded = manifest_file.and_then(|file| file.nonce.as_deref());
+                    if recorded.is_some_and(|nonce| nonce != hex::encode(encryption.nonce)) {
+                        problems.push(format!("[{}] nonce doesn't match its encryption header", relpath));
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => problems.push(format!("[{}] has invalid encryption header: [{}]", relpath, e)),
+            }
+        }
+    }
+
+    if let Some(manifest) = &manifest {
+        for file in &manifest.files {
+            if !listed.contains(&file.relpath) {
+                problems.push(format!("[{}] is in manifest but not srcsrv", file.relpath));
+            }
+            if let Some(nonce) = file.nonce.as_deref().filter(|nonce| !is_nonce(nonce)) {
+                problems.push(format!(
+                    "[{}] has invalid manifest nonce [{}]",
+                    file.relpath, nonce
+                ));
+            }
+        }
+        if let Err(e) = manifest::read_dictionary(pdb.as_mut(), Some(manifest)) {
+            problems.push(format!("Failed to read zstd dictionary: [{}]", e));
+        }
+    }
+
+    // Names pdbstr or MSF may mangle would be unreadable
+    for name in pdb.stream_names()? {
+        if manifest::is_file_stream(&name) && !manifest::is_valid_stream_name(&name) {
+            problems.push(format!("Stream name [{}] may be mangled by pdbstr or MSF", name));
+        }
+    }
+
+    for problem in &problems {
+        println!("Problem: {}", problem);
+    }
+    if !problems.is_empty() {
+        bail!("[{}] problems found in [{:?}]", problems.len(), op.pdb);
+    }
+    println!(
+        "No problems found in [{:?}]. Checked [{}] files.",
+        op.pdb,
+        sources.len()
+    );
+    Ok(())
+}
+
+// Release gate for PDBs indexed against stale sources. Fails if any file differs.
+fn verify(op: VerifyOp, config: Config) -> anyhow::Result<()> {
+    let sources = read_embedded_sources(&op.pdb, &config)?;
+
+    let mut num_mismatched = 0;
+    let mut num_missing = 0;
+    for source in &sources {
+        let local_path = if op.roots.is_empty() {
+            Some(PathBuf::from(&source.original_path))
+        } else {
+            op.roots
+                .iter()
+                .map(|root| root.join(&source.relpath))
+                .find(|path| path.is_file())
+        };
+
+        match local_path.map(|path| (fs::read(&path), path)) {
+            Some((Ok(contents), _)) if contents == source.contents => (),
+            Some((Ok(_), path)) => {
+                println!("Mismatch: [{}] differs from [{:?}]", source.relpath, path);
+                num_mismatched += 1;
+            }
+            Some((Err(_), _)) | None => {
+                println!("Missing: [{}] not found on disk", source.relpath);
+                num_missing += 1;
+            }
+        }
+    }
+
+    println!(
+        "Compared [{}] embedded files. [{}] mismatched. [{}] missing.",
+        sources.len(),
+        num_mismatched,
+        num_missing
+    );
+    if num_mismatched > 0 || (num_missing > 0 && !op.ignore_missing) {
+        bail!("Embedded sources in [{:?}] don't match files on disk", op.pdb);
+    }
+
+    Ok(())
+}
+
+// Removes every fts_pdbsrc stream. srcsrv is restored to what it was before embedding, or removed
+// if there was none. srcsrv streams from other indexers are left alone.
+fn strip(op: StripOp) -> anyhow::Result<()> {
+    let out_path = op.out.clone().unwrap_or_else(|| op.pdb.clone());
+    if op.out.is_none() {
+        lock::wait_for_write_access(&op.pdb)?;
+    }
+
+    let mut msf = read_pdb_streams(&op.pdb)?;
+    let (num_removed, srcsrv_change) = strip_fts_streams(msf.as_mut())?;
+    match srcsrv_change {
+        SrcSrvChange::Untouched if num_removed == 0 => bail!("[{:?}] has no fts_pdbsrc streams", op.pdb),
+        SrcSrvChange::Untouched => (),
+        SrcSrvChange::Restored => status!("Restored srcsrv stream from before embedding"),
+        SrcSrvChange::Removed => status!("Removed srcsrv stream"),
+    }
+
+    // Write next to destination then swap, same as embed
+    let out_dir = out_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let work_pdb_path = tempfile::Builder::new()
+        .prefix(".fts_pdbsrc_")
+        .suffix(".pdb")
+        .tempfile_in(out_dir)?
+        .into_temp_path();
+    msf.write(&work_pdb_path)?;
+    if portable_pdb::is_portable_pdb(&work_pdb_path) {
+        portable_pdb::PortablePdb::read(&work_pdb_path).context("Stripped PDB failed to parse")?;
+    } else {
+        pdb::PDB::open(File::open(&work_pdb_path)?)?
+            .pdb_information()
+            .context("Stripped PDB failed to parse")?;
+    }
+    lock::persist(work_pdb_path, &out_path).with_context(|| format!("Failed to replace [{:?}]", out_path))?;
+
+    status!(
+        "Removed [{}] fts_pdbsrc streams. Wrote [{:?}]",
+        num_removed,
+        out_path
+    );
+    Ok(())
+}
+
+// What strip_fts_streams did to srcsrv
+enum SrcSrvChange {
+    Untouched,
+    Restored,
+    Removed,
+}
+
+// Removes every fts_pdbsrc stream. Returns number of streams removed.
+fn strip_fts_streams(msf: &mut dyn manifest::StreamContainer) -> anyhow::Result<(usize, SrcSrvChange)> {
+    let named_streams = msf.stream_names()?;
+    let is_fts_srcsrv = msf.named_stream("srcsrv")?.is_some_and(is_fts_srcsrv);
+    let previous_srcsrv = msf
+        .named_stream(manifest::PREVIOUS_SRCSRV_STREAM_NAME)?
+        .map(|bytes| bytes.to_owned());
+
+    let mut num_removed = 0;
+    for name in &named_streams {
+        if manifest::is_fts_stream(name) {
+            msf.remove_named_stream(name)?;
+            num_removed += 1;
+        }
+    }
+
+    let srcsrv_change = match (is_fts_srcsrv, previous_srcsrv) {
+        (false, _) => SrcSrvChange::Untouched,
+        (true, Some(previous_srcsrv)) => {
+            msf.set_named_stream("srcsrv", previous_srcsrv)?;
+            SrcSrvChange::Restored
+        }
+        (true, None) => {
+            msf.remove_named_stream("srcsrv")?;
+            SrcSrvChange::Removed
+        }
+    };
+
+    Ok((num_removed, srcsrv_change))
+}
+
+// Re-encrypts every embedded file in place. Compression, packing, and srcsrv uuid are kept so
+// extraction caches stay valid.
+fn rekey(op: RekeyOp, config: Config) -> anyhow::Result<()> {
+    let new_key = hex::decode(op.new_key.trim()).context("--new-key is not hex")?;
+    if new_key.len() != 32 {
+        bail!("--new-key must be 32 bytes but is [{}] bytes", new_key.len());
+    }
+    let cipher = Aes256Gcm::new(Key::from_slice(&new_key));
+    let key_id = manifest::key_id(&new_key);
+
+    ensure_pdb_exists(&op.pdb)?;
+    lock::wait_for_write_access(&op.pdb)?;
+    let mut msf = msf::Msf::read(&op.pdb).with_context(|| format!("Failed to read [{:?}]", op.pdb))?;
+    let srcsrv_stream = msf
+        .named_stream("srcsrv")?
+        .filter(|bytes| is_fts_srcsrv(bytes))
+        .ok_or_else(|| anyhow!("PDB [{:?}] has no sources embedded by fts_pdbsrc", op.pdb))?;
+    let srcsrv_text = String::from_utf8(srcsrv_stream.to_owned())?;
+    let old_manifest = manifest::Manifest::read(&mut msf)?;
+    let embedded = embedded_files(&mut msf, &op.pdb, old_manifest.as_ref())?;
+    let old_keys = match &op.old_key {
+        Some(old_key) => vec![old_key.trim().to_owned()],
+        None => decryption_keys(&mut msf, &config)?,
+    };
+
+    // Decrypt everything before touching any stream. Compressed bytes are re-encrypted as is.
+    let mut files: Vec<(manifest::ManifestFile, Vec<u8>)> = Default::default();
+    for EmbeddedFile {
+        original_path,
+        relpath,
+        srcsrv_nonce,
+    } in &embedded
+    {
+        let stream = manifest::read_file(&mut msf, old_manifest.as_ref(), relpath)
+            .with_context(|| format!("Failed to read [{}] from PDB [{:?}]", relpath, op.pdb))?;
+        let encryption = manifest::read_encryption(&stream, old_manifest.as_ref(), srcsrv_nonce.as_deref())?;
+        let stored = match encryption {
+            Some(encryption) => {
+                let old_key_id = old_manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.file(relpath)?.key_id.as_deref());
+                decrypt(&old_keys, old_key_id, &encryption, &stream)
+                    .with_context(|| format!("Failed to decrypt [{}] with old key", relpath))?
+            }
+            None => stream,
+        };
+
+        // Keep what was recorded at embed. Storage is filled in when rewritten.
+        let old_file = old_manifest
+            .as_ref()
+            .and_then(|manifest| manifest.file(relpath).cloned())
+            .unwrap_or_default();
+        let file = manifest::ManifestFile {
+            relpath: relpath.clone(),
+            key_id: Some(key_id.clone()),
+            sha256: old_file.sha256,
+            modified_ns: old_file.modified_ns,
+            source_size: old_file.source_size,
+            original_path: Some(original_path.clone()),
+            ..Default::default()
+        };
+        files.push((file, stored));
+    }
+
+    // Replace every file stream. Recipients can no longer unwrap the new key.
+    for (name, _) in msf.named_streams()? {
+        if manifest::is_file_stream(&name) || name == recipients::STREAM_NAME {
+            msf.remove_named_stream(&name)?;
+        }
+    }
+    let old_manifest = old_manifest.unwrap_or_default();
+    let pack_streams = old_manifest.files.iter().any(|file| file.offset.is_some());
+    let mut manifest = manifest::Manifest {
+        version: manifest::MANIFEST_VERSION,
+        files: Default::default(),
+        encryption_header: true,
+        encrypt_mode: Some("EncryptWithKey".to_owned()),
+        ..old_manifest
+    };
+    let mut payload_writer = PayloadWriter::new(pack_streams);
+    for (file, stored) in files {
+        let (payload, nonce) =
+            encrypt(&cipher, &stored).with_context(|| format!("Failed to encrypt [{}]", file.relpath))?;
+        let file = manifest::ManifestFile {
+            nonce: Some(nonce),
+            ..file
+        };
+        payload_writer.write(&mut msf, &mut manifest, file, payload)?;
+    }
+    payload_writer.finish(&mut msf)?;
+
+    // Nonces now live in encryption headers. Changing srcsrv invalidates its MAC.
+    let new_srcsrv = strip_srcsrv_nonces(&srcsrv_text);
+    if new_srcsrv != srcsrv_text && manifest.srcsrv_mac.is_some() {
+        let mac_key = op.srcsrv_mac_key.as_ref().ok_or_else(|| {
+            anyhow!(
+                "PDB [{:?}] has a srcsrv MAC. Pass --srcsrv-mac-key to re-sign.",
+                op.pdb
+            )
+        })?;
+        manifest.srcsrv_mac = Some(manifest::srcsrv_mac(mac_key, new_srcsrv.as_bytes())?);
+    }
+    msf.set_named_stream("srcsrv", new_srcsrv.into_bytes())?;
+    msf.set_named_stream(manifest::STREAM_NAME, serde_json::to_vec(&manifest)?)?;
+
+    // Write next to original then swap, same as embed
+    let pdb_dir = op
+        .pdb
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let work_pdb_path = tempfile::Builder::new()
+        .prefix(".fts_pdbsrc_")
+        .suffix(".pdb")
+        .tempfile_in(pdb_dir)?
+        .into_temp_path();
+    msf.write(&work_pdb_path)
+        .with_context(|| format!("Failed to write [{:?}]", work_pdb_path))?;
+    verify_embedded(&work_pdb_path, &[])?;
+    lock::persist(work_pdb_path, &op.pdb).with_context(|| format!("Failed to replace [{:?}]", op.pdb))?;
+
+    status!(
+        "Re-encrypted [{}] files in [{:?}] with key id [{}]",
+        manifest.files.len(),
+        op.pdb,
+        key_id
+    );
+    Ok(())
+}
+
+// Drops nonce column from source lines and --nonce from command. Written before encryption headers.
+fn strip_srcsrv_nonces(srcsrv: &str) -> String {
+    let mut result = String::with_capacity(srcsrv.len());
+    let mut in_sources = false;
+    for line in srcsrv.lines() {
+        if line.starts_with("SRCSRV:") {
+            in_sources = line.starts_with("SRCSRV: source files");
+            result += line;
+        } else if in_sources {
+            result += &line.splitn(4, '*').take(3).collect::<Vec<_>>().join("*");
+        } else if line.starts_with("SRCSRVCMD=") {
+            result += &line.replace(" --nonce %var4%", "");
+        } else {
+            result += line;
+        }
+        result.push('\n');
+    }
+    result
+}
+
+fn export(op: ExportOp, config: Config) -> anyhow::Result<()> {
+    // Find PDB
+    let pdb_path = match (&op.pdb, op.pdb_uuid) {
+        (Some(pdb_path), _) => pdb_path.clone(),
+        (None, Some(pdb_uuid)) => find_pdb(&mut connect_to_service()?, pdb_uuid)?,
+        (None, None) => bail!("Either --pdb or --pdb-uuid must be specified"),
+    };
+
+    // Read and decrypt every embedded file
+    let sources = read_embedded_sources(&pdb_path, &config)?;
+
+    // Read identifiers
+    let mut pdb = open_pdb(&pdb_path)?;
+    let srcsrv = read_fts_srcsrv(&mut pdb, &pdb_path)?;
+    let pdb_info = pdb.pdb_information()?;
+    let object_name = pdb_path
+        .file_name()
+        .ok_or_else(|| anyhow!("Failed to get filename for path [{:?}]", pdb_path))?
+        .to_string_lossy()
+        .to_string();
+
+    let out_path = match op.export_for {
+        Some(ExportFor::Symbolicator) => {
+            let unified_id = format!("{}{:x}", pdb_info.guid.to_simple(), pdb_info.age);
+            op.out
+                .join(&unified_id[..2])
+                .join(&unified_id[2..])
+                .join("sourcebundle")
+        }
+        _ => op.out.clone(),
+    };
+    if let Some(out_dir) = out_path.parent() {
+        fs::create_dir_all(out_dir)?;
+    }
+
+    match op.export_for {
+        // Symbolication services identify PDBs by GUID and age
+        Some(_) => {
+            let bundle_files: Vec<archive::BundleFile> = sources
+                .iter()
+                .map(|source| archive::BundleFile {
+                    original_path: &source.original_path,
+                    contents: &source.contents,
+                })
+                .collect();
+
+            let debug_id = format!("{}-{:x}", pdb_info.guid.to_hyphenated(), pdb_info.age);
+            archive::write_source_bundle(&out_path, &debug_id, &object_name, &bundle_files)?;
+        }
+
+        // Plain archive of relative paths plus manifest
+        None => {
+            let manifest = archive::ArchiveManifest {
+                uuid: srcsrv.variable("FTS_PDBSTR_UUID").map(|uuid| uuid.to_owned()),
+                pdb: object_name,
+                files: sources
+                    .iter()
+                    .map(|source| archive::ArchiveManifestFile {
+                        original_path: source.original_path.clone(),
+                        relative_path: source.relpath.replace('\\', "/"),
+                        size: source.contents.len() as u64,
+                    })
+                    .collect(),
+            };
+            let contents: Vec<&[u8]> = sources.iter().map(|source| source.contents.as_slice()).collect();
+
+            match op.format {
+                ArchiveFormat::Zip => archive::write_zip(&out_path, &manifest, &contents)?,
+                ArchiveFormat::Tar => archive::write_tar(&out_path, &manifest, &contents)?,
+            }
+        }
+    }
+    status!("Exported [{}] files to [{:?}]", sources.len(), out_path);
+
+    Ok(())
+}
+
+fn import(op: ImportOp) -> anyhow::Result<()> {
+    // Read archive
+    let mut archive_files = archive::read_archive(&op.archive)?;
+
+    // Manifest maps paths recorded in the PDB to paths in the archive
+    let manifest: archive::ArchiveManifest = match &op.mapping {
+        Some(mapping_path) => {
+            let mapping_file = File::open(mapping_path)
+                .with_context(|| format!("Failed to open mapping [{:?}]", mapping_path))?;
+            serde_json::from_reader(mapping_file)
+                .with_context(|| format!("Failed to parse mapping [{:?}]", mapping_path))?
+        }
+        None => {
+            let manifest_bytes = archive_files.remove(archive::MANIFEST_FILENAME).ok_or_else(|| {
+                anyhow!(
+                    "Archive [{:?}] has no {} and no --mapping was given",
+                    op.archive,
+                    archive::MANIFEST_FILENAME
+                )
+            })?;
+            serde_json::from_slice(&manifest_bytes)?
+        }
+    };
+
+    // Paths are matched case insensitively since they originate on Windows
+    let referenced: HashMap<String, (String, Option<SourceChecksum>)> = referenced_files(&op.pdb)?
+        .into_iter()
+        .map(|(path, checksum)| (path.to_lowercase(), (path, checksum)))
+        .collect();
+
+    let mut filepaths: Vec<SourceFile> = Default::default();
+    for file in manifest.files {
+        let (raw_filepath, checksum) = match referenced.get(&file.original_path.to_lowercase()) {
+            Some(found) => found.clone(),
+            None => {
+                status!(
+                    "Skipping [{}]. File is not referenced by PDB.",
+                    file.original_path
+                );
+                continue;
+            }
+        };
+
+        let relpath = untrusted_relpath(&file.relative_path)
+            .with_context(|| format!("Archive [{:?}] has invalid relative path", op.archive))?;
+        let contents = archive_files
+            .remove(&file.relative_path)
+            .ok_or_else(|| anyhow!("Archive [{:?}] is missing [{}]", op.archive, file.relative_path))?;
+        let filename = relpath
+            .file_name()
+            .map(|filename| filename.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Relative path [{}] has no filename", file.relative_path))?;
+
+        filepaths.push(SourceFile {
+            filename,
+            relpath,
+            raw_filepath,
+            checksum,
+            contents: Some(contents),
+        });
+    }
+
+    // Make sure we found at least some files
+    if filepaths.is_empty() {
+        bail!("Failed to find any files in archive referenced by PDB");
+    }
+
+    // Print files that were found and will be embedded
+    status!("Found following files:");
+    filepaths.iter().for_each(|source| {
+        status!("  {}", source.relpath.to_string_lossy());
+    });
+
+    check_licenses(&filepaths, &op.opts.license, op.opts.annotations)?;
+    write_embedded(&op.pdb, &filepaths, &op.opts)?;
+    sign_artifacts(&op.opts.sign)
+}
+
+// Writes a srcsrv stream mapping each referenced file to its blob at HEAD. Nothing is embedded so the
+// PDB stays small, but debugging requires the repo or a raw file server.
+fn index_git(op: IndexGitOp) -> anyhow::Result<()> {
+    // Every expansion is written into srcsrv so '%' would start a variable
+    if let Some(raw_url) = &op.raw_url {
+        if raw_url.contains('%') || !raw_url.contains("{path}") {
+            bail!("--raw-url [{}] must contain {{path}} and no '%'", raw_url);
+        }
+    }
+
+    let git = |args: &[&str]| -> anyhow::Result<String> {
+        let capture = Exec::cmd("git")
+            .arg("-C")
+            .arg(&op.repo)
+            .args(args)
+            .stdout(Redirection::Pipe)
+            .stderr(Redirection::Pipe)
+            .capture()
+            .context("Failed to run git")?;
+        if !capture.success() {
+            bail!("git {} failed: [{}]", args.join(" "), capture.stderr_str().trim());
+        }
+        Ok(capture.stdout_str())
+    };
+    let toplevel = git(&["rev-parse", "--show-toplevel"])?.trim().to_owned();
+    let commit = git(&["rev-parse", "HEAD"])?.trim().to_owned();
+
+    // Blob of every tracked file, keyed by lowercase path since PDBs often record lowercase paths.
+    // Entries are "<mode> <type> <blob>\t<path>".
+    let blobs: HashMap<String, (String, String)> = git(&["ls-tree", "-r", "-z", "--full-tree", "HEAD"])?
+        .split('\0')
+        .filter_map(|entry| {
+            let (info, path) = entry.split_once('\t')?;
+            let blob = info.split(' ').nth(2)?;
+            Some((path.to_lowercase(), (path.to_owned(), blob.to_owned())))
+        })
+        .collect();
+
+    // Map referenced files under repo to their blobs
+    let normalize = |path: &str| path.replace('\\', "/").to_lowercase();
+    let repo_prefix = format!("{}/", normalize(&toplevel).trim_end_matches('/'));
+    let mut sources: Vec<(String, String, String)> = Default::default();
+    let mut num_outside = 0;
+    for (raw_filepath, _) in referenced_files(&op.pdb.to_string_lossy())? {
+        let repo_path = match normalize(&raw_filepath).strip_prefix(&repo_prefix) {
+            Some(repo_path) => repo_path.to_owned(),
+            None => {
+                num_outside += 1;
+                continue;
+            }
+        };
+        let (repo_path, blob) = match blobs.get(&repo_path) {
+            Some(found) => found.clone(),
+            None => {
+                report_warning(&EmbedWarning::UncommittedFile(raw_filepath), op.annotations);
+                continue;
+            }
+        };
+        if raw_filepath.contains('*') || repo_path.contains('*') || repo_path.contains('%') {
+            status!(
+                "Skipping [{}]. Paths with '*' or '%' can't be stored in srcsrv.",
+                raw_filepath
+            );
+            continue;
+        }
+
+        // Working tree file was most likely what was compiled. Warn if it isn't what HEAD holds.
+        let is_committed = fs::read(&raw_filepath).map_or(true, |contents| {
+            let mut hasher = sha1::Sha1::new();
+            hasher.update(format!("blob {}\0", contents.len()));
+            hasher.update(&contents);
+            hex::encode(hasher.finalize()) == blob
+        });
+        if !is_committed {
+            report_warning(
+                &EmbedWarning::UncommittedFile(raw_filepath.clone()),
+                op.annotations,
+            );
+        }
+
+        sources.push((raw_filepath, repo_path, blob));
+    }
+    if sources.is_empty() {
+        bail!("PDB [{:?}] references no files tracked in [{}]", op.pdb, toplevel);
+    }
+
+    // Source lines are: original path * repo path * blob
+    let mut srcsrv: Vec<u8> = Default::default();
+    writeln!(
+        srcsrv,
+        "SRCSRV: ini ------------------------------------------------"
+    )?;
+    writeln!(srcsrv, "VERSION=1")?;
+    writeln!(srcsrv, "VERCTRL=git")?;
+    writeln!(
+        srcsrv,
+        "SRCSRV: variables ------------------------------------------"
+    )?;
+    writeln!(srcsrv, "GIT_COMMIT={}", commit)?;
+    match &op.raw_url {
+        Some(raw_url) => {
+            let url = raw_url
+                .replace("{commit}", "%GIT_COMMIT%")
+                .replace("{path}", "%var2%");
+            writeln!(srcsrv, "SRCSRVTRG={}", url)?;
+        }
+        None => {
+            // Debugging machines need a clone at the same path as the build machine
+            writeln!(srcsrv, "GIT_REPO={}", toplevel)?;
             writeln!(
                 srcsrv,
-                "{}*{}*{}",
-                raw_filepath,
-                relpath.to_string_lossy(),
-                filename
+                "SRCSRVTRG=%LOCALAPPDATA%\\fts\\fts_pdbsrc_git\\%var3%\\%fnfile%(%var1%)"
             )?;
-        } else {
             writeln!(
                 srcsrv,
-                "{}*{}*{}*{}",
-                raw_filepath,
-                relpath.to_string_lossy(),
-                filename,
-                nonces.get(raw_filepath).unwrap()
+                "SRCSRVCMD=cmd /c git -C \"%GIT_REPO%\" cat-file blob %var3% > \"%SRCSRVTRG%\""
             )?;
         }
     }
+    writeln!(
+        srcsrv,
+        "SRCSRV: source files ------------------------------------------"
+    )?;
+    for (raw_filepath, repo_path, blob) in &sources {
+        writeln!(srcsrv, "{}*{}*{}", raw_filepath, repo_path, blob)?;
+    }
+    writeln!(
+        srcsrv,
+        "SRCSRV: end ------------------------------------------------"
+    )?;
+    write_srcsrv_index(&op.pdb, srcsrv)?;
+
+    status!(
+        "Indexed [{}] files at commit [{}]. Skipped [{}] files outside repo.",
+        sources.len(),
+        commit,
+        num_outside
+    );
+    Ok(())
+}
+
+// Resolves each referenced file to //depot/path#rev with `p4 have` and writes a srcsrv stream whose
+// command runs `p4 print`, as SSIndex does
+fn index_p4(op: IndexP4Op) -> anyhow::Result<()> {
+    // p4 exits with failure for per-file errors too, so p4 info is what checks the connection
+    let server_address = p4_ztag(&["info"], "")?
+        .into_iter()
+        .find_map(|mut record| record.remove("serverAddress"))
+        .ok_or_else(|| anyhow!("p4 info failed. Check P4PORT, P4CLIENT, and p4 login."))?;
+    let port = op.port.clone().unwrap_or(server_address);
+    if port.contains('%') {
+        bail!("P4PORT [{}] can't be stored in srcsrv", port);
+    }
+
+    // p4 reads file arguments from stdin with -x - so long file lists don't overflow the command line
+    let referenced: Vec<String> = referenced_files(&op.pdb.to_string_lossy())?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect();
+    let file_list = referenced.join("\n");
+    let normalize = |path: &str| path.replace('\\', "/").to_lowercase();
+
+    // Local path to depot path and revision
+    let have: HashMap<String, (String, String)> = p4_ztag(&["-x", "-", "have"], &file_list)?
+        .into_iter()
+        .filter_map(|mut record| {
+            let path = record.remove("path")?;
+            let depot_file = record.remove("depotFile")?;
+            let have_rev = record.remove("haveRev")?;
+            Some((normalize(&path), (depot_file, have_rev)))
+        })
+        .collect();
+
+    // Files opened for edit were likely compiled with changes debuggers won't see
+    let opened: std::collections::HashSet<String> = p4_ztag(&["-x", "-", "opened"], &file_list)?
+        .into_iter()
+        .filter_map(|mut record| record.remove("depotFile"))
+        .collect();
+
+    // Source lines are: original path * depot path without leading // * revision
+    let num_referenced = referenced.len();
+    let mut sources: Vec<(String, String, String)> = Default::default();
+    for raw_filepath in referenced {
+        let (depot_file, have_rev) = match have.get(&normalize(&raw_filepath)) {
+            Some(found) => found.clone(),
+            None => continue,
+        };
+        if raw_filepath.contains('*') || depot_file.contains('*') || depot_file.contains('%') {
+            status!(
+                "Skipping [{}]. Paths with '*' or '%' can't be stored in srcsrv.",
+                raw_filepath
+            );
+            continue;
+        }
+        if opened.contains(&depot_file) {
+            report_warning(
+                &EmbedWarning::UncommittedFile(raw_filepath.clone()),
+                op.annotations,
+            );
+        }
+        sources.push((
+            raw_filepath,
+            depot_file.trim_start_matches('/').to_owned(),
+            have_rev,
+        ));
+    }
+    if sources.is_empty() {
+        bail!("PDB [{:?}] references no files synced from Perforce", op.pdb);
+    }
+
+    let mut srcsrv: Vec<u8> = Default::default();
+    writeln!(
+        srcsrv,
+        "SRCSRV: ini ------------------------------------------------"
+    )?;
+    writeln!(srcsrv, "VERSION=1")?;
+    writeln!(srcsrv, "VERCTRL=Perforce")?;
+    writeln!(
+        srcsrv,
+        "SRCSRV: variables ------------------------------------------"
+    )?;
+    writeln!(srcsrv, "P4PORT={}", port)?;
+    writeln!(
+        srcsrv,
+        "SRCSRVTRG=%targ%\\p4\\%fnbksl%(%var2%)\\%var3%\\%fnfile%(%var1%)"
+    )?;
+    writeln!(
+        srcsrv,
+        "SRCSRVCMD=p4 -p %P4PORT% print -o \"%SRCSRVTRG%\" -q \"//%var2%#%var3%\""
+    )?;
+    writeln!(
+        srcsrv,
+        "SRCSRV: source files ------------------------------------------"
+    )?;
+    for (raw_filepath, depot_path, have_rev) in &sources {
+        writeln!(srcsrv, "{}*{}*{}", raw_filepath, depot_path, have_rev)?;
+    }
     writeln!(
         srcsrv,
         "SRCSRV: end ------------------------------------------------"
     )?;
+    write_srcsrv_index(&op.pdb, srcsrv)?;
 
-    // Close and keep tempfile
-    let (_, tempfile_path) = srcsrv.keep()?;
+    status!(
+        "Indexed [{}] files from [{}]. Skipped [{}] files not synced from Perforce.",
+        sources.len(),
+        port,
+        num_referenced - sources.len()
+    );
+    Ok(())
+}
 
-    // Write srcsrv
-    let cmd = &[
-        "pdbstr",                                           // exe to run
-        "-w",                                               // write
-        &format!("-p:{}", &op.pdb),                         // path to pdb
-        "-s:srcsrv",                                        // stream to write
-        &format!("-i:{}", tempfile_path.to_string_lossy()), // file to write into stream
-    ];
-    run_command(cmd)?;
+// Runs p4 with tagged output and returns one map per record. Errors, such as files not in the client,
+// are ignored.
+fn p4_ztag(args: &[&str], stdin: &str) -> anyhow::Result<Vec<HashMap<String, String>>> {
+    let capture = Exec::cmd("p4")
+        .arg("-ztag")
+        .args(args)
+        .stdin(stdin)
+        .stdout(Redirection::Pipe)
+        .stderr(Redirection::Pipe)
+        .capture()
+        .context("Failed to run p4")?;
 
-    // Delete tempfile
-    std::fs::remove_file(tempfile_path)?;
+    // Records are blocks of "... key value" lines separated by blank lines
+    let mut records: Vec<HashMap<String, String>> = Default::default();
+    let mut record: HashMap<String, String> = Default::default();
+    for line in capture.stdout_str().lines() {
+        match line.strip_prefix("... ") {
+            Some(field) => {
+                let (key, value) = field.split_once(' ').unwrap_or((field, ""));
+                record.insert(key.to_owned(), value.to_owned());
+            }
+            None if !record.is_empty() => records.push(std::mem::take(&mut record)),
+            None => (),
+        }
+    }
+    if !record.is_empty() {
+        records.push(record);
+    }
+    Ok(records)
+}
 
-    // Write key to console IFF it was randomly generated
-    if let Some(rng_key) = rng_key {
-        println!("Files encrypted. The following key MUST be saved to decrypt. DO NOT LOSE THIS KEY.");
-        println!("BEGIN KEY------------------------------------------------");
-        let key_hex = hex::encode(&rng_key);
-        println!("{}", key_hex);
-        println!("END KEY------------------------------------------------");
+// Replaces srcsrv with one written by another source indexer. Refuses PDBs with embedded sources since
+// they would be left without a srcsrv describing them.
+fn write_srcsrv_index(pdb_path: &Path, srcsrv: Vec<u8>) -> anyhow::Result<()> {
+    ensure_pdb_exists(pdb_path)?;
+    lock::wait_for_write_access(pdb_path)?;
+    let mut msf = msf::Msf::read(pdb_path).with_context(|| format!("Failed to read [{:?}]", pdb_path))?;
+    if msf.named_stream("srcsrv")?.is_some_and(is_fts_srcsrv) {
+        bail!("PDB [{:?}] has embedded sources. Run strip first.", pdb_path);
     }
+    msf.set_named_stream("srcsrv", srcsrv)?;
 
+    // Write next to original then swap, same as embed
+    let pdb_dir = pdb_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+    let work_pdb_path = tempfile::Builder::new()
+        .prefix(".fts_pdbsrc_")
+        .suffix(".pdb")
+        .tempfile_in(pdb_dir)?
+        .into_temp_path();
+    msf.write(&work_pdb_path)
+        .with_context(|| format!("Failed to write [{:?}]", work_pdb_path))?;
+    lock::persist(work_pdb_path, pdb_path).with_context(|| format!("Failed to replace [{:?}]", pdb_path))?;
     Ok(())
 }
 
-fn extract_one(op: ExtractOneOp, config: Config) -> anyhow::Result<()> {
-    // Query server
-    // FTS_TODO: make port configurable
-    match TcpStream::connect("localhost:23685") {
-        Ok(mut stream) => {
-            // Ask service for PDB path
-            send_message(&mut stream, Message::FindPdb(op.pdb_uuid))?;
-
-            // Wait for response
-            let response = read_message(&mut stream)?;
-
-            // Go ahead and close stream
-            drop(stream);
-
-            // Read response
-            let (_, pdb_path) = match response {
-                Message::FoundPdb((uuid, Some(path))) => {
-                    assert_eq!(
-                        uuid, op.pdb_uuid,
-                        "Mismatched Uuids. Requested: [{}] Found: [{}]",
-                        op.pdb_uuid, uuid
-                    );
-                    (uuid, path)
-                }
-                _ => {
-                    return Err(anyhow!(
-                    "extract_one queried service for PDB with uuid [{}], but failed with response: [{:?}]",
-                    op.pdb_uuid,
-                    response
-                ))
-                }
-            };
+fn adopt(op: AdoptOp) -> anyhow::Result<()> {
+    // Read existing srcsrv stream
+    let srcsrv = {
+        ensure_pdb_exists(Path::new(&op.pdb))?;
+        let pdbfile = lock::open_read(Path::new(&op.pdb))?;
+        let mut pdb = pdb::PDB::open(pdbfile)?;
+        let srcsrv_stream = pdb
+            .named_stream(b"srcsrv")
+            .with_context(|| format!("PDB [{}] has no srcsrv stream to adopt", op.pdb))?;
+        srcsrv::SrcSrv::parse(std::str::from_utf8(&srcsrv_stream)?)
+    };
+    match srcsrv.variable("VERCTRL") {
+        Some("fts_pdbsrc") => bail!("PDB [{}] already has embedded sources", op.pdb),
+        verctrl => status!("Adopting PDB indexed with [{}]", verctrl.unwrap_or("unknown")),
+    }
 
-            // Load PDB
-            let pdb_file = File::open(pdb_path)?;
-            let mut pdb = pdb::PDB::open(pdb_file)?;
-
-            // Get file stream
-            let stream_name = format!("/fts_pdbsrc/{}", op.file);
-            let file_stream = pdb
-                .named_stream(stream_name.as_bytes())
-                .unwrap_or_else(|_| panic!("Failed to find stream named [{}]", stream_name));
-            let maybe_encrypted_text = file_stream.as_slice();
-
-            // Decrypt file
-            let try_decrypt = |config: Config, nonce_str: &str| -> anyhow::Result<Vec<u8>> {
-                // Parse Nonce
-                let nonce_bytes = hex::decode(nonce_str)?;
-                let nonce = Nonce::from_slice(&nonce_bytes);
-
-                // Try to decrypt with each key
-                for hexkey in config.decode_keys {
-                    let try_key = |key_hex: &str, nonce| -> anyhow::Result<Vec<u8>> {
-                        let key_bytes = hex::decode(key_hex)?;
-                        let key = Key::from_slice(&key_bytes);
-                        let cipher = Aes256Gcm::new(key);
-
-                        match cipher.decrypt(nonce, maybe_encrypted_text) {
-                            Ok(plaintext) => Ok(plaintext),
-                            Err(_) => bail!("Failed to decrypt with key"),
-                        }
-                    };
-
-                    if let Ok(plaintext) = try_key(&hexkey, nonce) {
-                        return Ok(plaintext);
-                    }
-                }
+    // Compiler checksums, keyed case insensitively
+    let checksums: HashMap<String, Option<SourceChecksum>> = referenced_files(&op.pdb)?
+        .into_iter()
+        .map(|(path, checksum)| (path.to_lowercase(), checksum))
+        .collect();
 
-                bail!("Failed to decrypt with all keys")
-            };
+    // Fetch each file into a temp dir using the PDB's own commands
+    let targ_dir = tempfile::tempdir()?;
+    let targ = targ_dir.path().to_string_lossy().to_string();
+    let canonical_targ_dir = fs::canonicalize(targ_dir.path())?;
+    let mut filepaths: Vec<SourceFile> = Default::default();
+    for source in &srcsrv.sources {
+        let raw_filepath = match source.first() {
+            Some(path) if !path.is_empty() => path.clone(),
+            _ => continue,
+        };
 
-            // Get plaintext for maybe_encrypted_text
-            let plaintext = match op.nonce {
-                Some(ref nonce) => try_decrypt(config, nonce)?,
-                None => maybe_encrypted_text.to_owned(),
-            };
+        let target = srcsrv.expand("%srcsrvtrg%", source, &targ);
+        if target.starts_with("http://") || target.starts_with("https://") {
+            report_warning(&EmbedWarning::MissingFile(raw_filepath), op.opts.annotations);
+            continue;
+        }
 
-            // Write to output file
-            let out_dir = op
-                .out
-                .parent()
-                .ok_or_else(|| anyhow!("Failed to get directory for path [{:?}]", op.out))?;
-            fs::create_dir_all(out_dir)?;
-            let mut file = std::fs::File::create(op.out)?;
-            file.write_all(&plaintext)?;
+        // Target comes from the PDB. Outside the temp dir it could name any local file, e.g. a private key.
+        let is_in_targ_dir = Path::new(&target).strip_prefix(targ_dir.path()).is_ok_and(|rel| {
+            rel.components()
+                .all(|part| matches!(part, std::path::Component::Normal(_)))
+        });
+        if !is_in_targ_dir {
+            bail!("Target [{}] for [{}] is outside [{}]", target, raw_filepath, targ);
         }
-        Err(e) => {
-            println!("Failed to connect: {}", e);
+
+        // Run fetch command if there is one. Commands come from the PDB, so only run them when trusted.
+        let command = srcsrv.expand("%srcsrvcmd%", source, &targ);
+        if !command.is_empty() && !command.eq_ignore_ascii_case("%srcsrvcmd%") {
+            if !op.trust_srcsrv_commands {
+                bail!(
+                    "PDB [{}] fetches sources with commands like [{}]. Pass --trust-srcsrv-commands to run them.",
+                    op.pdb,
+                    command
+                );
+            }
+            if let Some(target_dir) = Path::new(&target).parent() {
+                fs::create_dir_all(target_dir)?;
+            }
+            let status = Exec::shell(&command).stdout(Redirection::Pipe).join()?;
+            if !status.success() {
+                status!("Command [{}] failed with status [{:?}]", command, status);
+            }
         }
+
+        // Symlinks could still point outside the temp dir
+        let contents = match fs::canonicalize(&target) {
+            Ok(canonical) if !canonical.starts_with(&canonical_targ_dir) => {
+                bail!(
+                    "Target [{}] for [{}] resolves outside [{}]",
+                    target,
+                    raw_filepath,
+                    targ
+                )
+            }
+            Ok(canonical) => fs::read(canonical).ok(),
+            Err(_) => None,
+        };
+        let contents = match contents {
+            Some(contents) => contents,
+            None => {
+                report_warning(&EmbedWarning::MissingFile(raw_filepath), op.opts.annotations);
+                continue;
+            }
+        };
+
+        // Relative path is based on roots if given, otherwise the full path minus its drive
+        let normalized = raw_filepath.replace('/', "\\");
+        let relpath_str = op
+            .roots
+            .iter()
+            .map(|root| root.to_string_lossy().replace('/', "\\"))
+            .filter(|root| normalized.to_lowercase().starts_with(&root.to_lowercase()))
+            .map(|root| normalized[root.len()..].to_owned())
+            .next()
+            .unwrap_or_else(|| match normalized.find(':') {
+                Some(idx) => normalized[idx + 1..].to_owned(),
+                None => normalized.clone(),
+            });
+        let relpath: PathBuf = relpath_str
+            .split('\\')
+            .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+            .collect();
+        let filename = match relpath.file_name() {
+            Some(filename) => filename.to_string_lossy().to_string(),
+            None => continue,
+        };
+
+        filepaths.push(SourceFile {
+            checksum: checksums.get(&raw_filepath.to_lowercase()).cloned().flatten(),
+            raw_filepath,
+            relpath,
+            filename,
+            contents: Some(contents),
+        });
+    }
+
+    // Make sure we found at least some files
+    if filepaths.is_empty() {
+        bail!("Failed to fetch any files");
+    }
+
+    // Print files that were found and will be embedded
+    status!("Fetched following files:");
+    filepaths.iter().for_each(|source| {
+        status!("  {}", source.relpath.to_string_lossy());
+    });
+
+    check_licenses(&filepaths, &op.opts.license, op.opts.annotations)?;
+    write_embedded(&op.pdb, &filepaths, &op.opts)?;
+    sign_artifacts(&op.opts.sign)
+}
+
+fn read_embedded_sources(pdb_path: &Path, config: &Config) -> anyhow::Result<Vec<EmbeddedSource>> {
+    let mut pdb = open_streams(pdb_path)?;
+    let manifest = manifest::Manifest::read(&mut pdb)?;
+    let files = embedded_files(&mut pdb, pdb_path, manifest.as_ref())?;
+    let dictionary = manifest::read_dictionary(&mut pdb, manifest.as_ref())?;
+    let keys = decryption_keys(&mut pdb, config)?;
+
+    let mut sources: Vec<EmbeddedSource> = Default::default();
+    for EmbeddedFile {
+        original_path,
+        relpath,
+        srcsrv_nonce,
+    } in files
+    {
+        let relpath = &relpath;
+        let stream = manifest::read_file(&mut pdb, manifest.as_ref(), relpath)
+            .with_context(|| format!("Failed to read [{}] from PDB [{:?}]", relpath, pdb_path))?;
+        let encryption = manifest::read_encryption(&stream, manifest.as_ref(), srcsrv_nonce.as_deref())
+            .with_context(|| format!("Failed to decrypt [{}]", relpath))?;
+        let stored = match encryption {
+            Some(encryption) => {
+                let key_id = manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.file(relpath)?.key_id.as_deref());
+                decrypt(&keys, key_id, &encryption, &stream)
+                    .with_context(|| format!("Failed to decrypt [{}]", relpath))?
+            }
+            None => stream,
+        };
+        let contents = manifest::decompress(stored, manifest.as_ref(), dictionary.as_deref())
+            .with_context(|| format!("Failed to decompress [{}]", relpath))?;
+        manifest::check_sha256(manifest.as_ref(), relpath, &contents)?;
+
+        sources.push(EmbeddedSource {
+            original_path,
+            relpath: relpath.clone(),
+            contents,
+        });
+    }
+
+    Ok(sources)
+}
+
+// File listed by manifest or, for PDBs embedded before manifests recorded original paths, by srcsrv
+struct EmbeddedFile {
+    original_path: String,
+    relpath: String,
+
+    // Only PDBs embedded before encryption headers keep nonces in srcsrv
+    srcsrv_nonce: Option<String>,
+}
+
+fn embedded_files<S: manifest::NamedStreams + ?Sized>(
+    pdb: &mut S,
+    pdb_path: &Path,
+    manifest: Option<&manifest::Manifest>,
+) -> anyhow::Result<Vec<EmbeddedFile>> {
+    if let Some(manifest) = manifest.filter(|manifest| manifest.lists_files()) {
+        return Ok(manifest
+            .files
+            .iter()
+            .map(|file| EmbeddedFile {
+                original_path: file.original_path.clone().unwrap_or_default(),
+                relpath: file.relpath.clone(),
+                srcsrv_nonce: None,
+            })
+            .collect());
     }
 
+    // Source lines are: original path * relative path * target or filename [* nonce]
+    let srcsrv = read_fts_srcsrv(pdb, pdb_path)?;
+    srcsrv
+        .unescaped_sources()
+        .into_iter()
+        .map(|source| match source.as_slice() {
+            [original_path, relpath, rest @ ..] => Ok(EmbeddedFile {
+                original_path: original_path.clone(),
+                relpath: relpath.clone(),
+                srcsrv_nonce: rest.get(1).cloned(),
+            }),
+            _ => bail!("Malformed srcsrv source line [{}]", source.join("*")),
+        })
+        .collect()
+}
+
+// Reads whole PDB into memory to add, replace, or remove streams
+fn read_pdb_streams(pdb_path: &Path) -> anyhow::Result<Box<dyn manifest::StreamContainer>> {
+    ensure_pdb_exists(pdb_path)?;
+    let pdb: anyhow::Result<Box<dyn manifest::StreamContainer>> = if portable_pdb::is_portable_pdb(pdb_path) {
+        portable_pdb::PortablePdb::read(pdb_path).map(|pdb| Box::new(pdb) as _)
+    } else {
+        msf::Msf::read(pdb_path).map(|msf| Box::new(msf) as _)
+    };
+    pdb.with_context(|| format!("Failed to read [{:?}]", pdb_path))
+}
+
+// Returns encryption header followed by ciphertext, and hex nonce
+fn encrypt(cipher: &Aes256Gcm, stored: &[u8]) -> anyhow::Result<(Vec<u8>, String)> {
+    // Create per-file nonce; 96-bits, unique per message
+    let nonce_bytes = rand::thread_rng().gen::<[u8; manifest::NONCE_SIZE]>();
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let mut payload = manifest::encryption_header(&nonce_bytes);
+    let encrypted_text = cipher
+        .encrypt(
+            nonce,
+            Payload {
+                msg: stored,
+                aad: &payload,
+            },
+        )
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+    payload.extend_from_slice(&encrypted_text);
+    Ok((payload, hex::encode(nonce_bytes)))
+}
+
+fn postbuild(op: PostBuildOp) -> anyhow::Result<()> {
+    // Determine PDB for target
+    let pdb_path = find_target_pdb(&op.target)?;
+    status!("postbuild: using PDB [{:?}]", pdb_path);
+
+    // Find project config
+    let config_path = match op.project_config {
+        Some(path) => path,
+        None => {
+            let cwd = std::env::current_dir()?;
+            let target_dir = fs::canonicalize(&op.target)?
+                .parent()
+                .map(|dir| dir.to_owned())
+                .unwrap_or_else(|| cwd.clone());
+            target_dir
+                .ancestors()
+                .chain(cwd.ancestors())
+                .map(|dir| dir.join(ProjectConfig::FILENAME))
+                .find(|path| path.exists())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Failed to find [{}] above [{:?}] or [{:?}]",
+                        ProjectConfig::FILENAME,
+                        target_dir,
+                        cwd
+                    )
+                })?
+        }
+    };
+    let config_file = File::open(&config_path)
+        .with_context(|| format!("Failed to open project config [{:?}]", config_path))?;
+    let project_config: ProjectConfig = serde_json::from_reader(config_file)
+        .with_context(|| format!("Failed to parse project config [{:?}]", config_path))?;
+    status!("postbuild: using project config [{:?}]", config_path);
+
+    // Roots are relative to the config file
+    let config_dir = config_path
+        .parent()
+        .ok_or_else(|| anyhow!("Failed to get directory for path [{:?}]", config_path))?;
+    let roots = project_config
+        .roots
+        .iter()
+        .map(|root| config_dir.join(root))
+        .collect();
+    let path_mapping_files = project_config
+        .path_mapping_files
+        .iter()
+        .map(|file| config_dir.join(file))
+        .collect();
+    let sbom = project_config.sbom.map(|sbom| config_dir.join(sbom));
+    let source_link = project_config
+        .source_link
+        .map(|source_link| config_dir.join(source_link));
+    let mut license = project_config.license;
+    license.external_dirs = license
+        .external_dirs
+        .iter()
+        .map(|dir| config_dir.join(dir))
+        .collect();
+
+    // Embed
+    embed(EmbedOp {
+        pdb: pdb_path.to_string_lossy().to_string(),
+        roots,
+        exact_root_match: project_config.exact_root_match,
+        path_mappings: project_config.path_mappings,
+        path_mapping_files,
+        extra: project_config.extra,
+        on_collision: project_config.on_collision,
+        opts: EmbedOpts {
+            encrypt_mode: project_config.encrypt_mode,
+            annotations: project_config.annotations,
+            max_file_size: project_config.max_file_size,
+            sbom,
+            verify: true,
+            pack_streams: project_config.pack_streams,
+            compress: project_config.compress,
+            zstd_dictionary: project_config.zstd_dictionary,
+            srcsrv_mac_key: project_config.srcsrv_mac_key,
+            service_port: project_config.service_port,
+            http_url: project_config.http_url,
+            pdb_identity: project_config.pdb_identity,
+            strict: project_config.strict,
+            dotnet_embedded_source: project_config.dotnet_embedded_source,
+            source_link,
+            license,
+            sign: project_config.sign,
+        },
+    })?;
+    status!("postbuild: embedded and verified sources in [{:?}]", pdb_path);
+
     Ok(())
 }
 
-fn info(op: InfoOp) -> anyhow::Result<()> {
-    // Load PDB
-    let pdbfile = File::open(op.pdb)?;
-    let mut pdb = pdb::PDB::open(pdbfile)?;
-    let string_table = pdb.string_table()?;
+fn find_target_pdb(target: &Path) -> anyhow::Result<PathBuf> {
+    let is_pdb = target
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("pdb"))
+        .unwrap_or(false);
+    if is_pdb {
+        return Ok(target.to_owned());
+    }
 
-    // Iterate files
-    let di = pdb.debug_information()?;
-    let mut modules = di.modules()?;
-    while let Some(module) = modules.next()? {
-        if let Some(module_info) = pdb.module_info(&module)? {
-            let line_program = module_info.line_program()?;
+    // Prefer the path recorded by the linker, then a PDB next to the target
+    if let Some(linked_pdb) = pe::read_pdb_path(target)? {
+        if linked_pdb.exists() {
+            return Ok(linked_pdb);
+        }
+    }
 
-            let mut file_iter = line_program.files();
-            while let Some(file) = file_iter.next()? {
-                let filename = string_table.get(file.name)?;
+    let sibling_pdb = target.with_extension("pdb");
+    if sibling_pdb.exists() {
+        return Ok(sibling_pdb);
+    }
 
-                let filename_utf8 = std::str::from_utf8(filename.as_bytes())?;
-                let filepath = Path::new(filename_utf8);
+    bail!("Failed to find PDB for target [{:?}]", target)
+}
 
-                if std::fs::metadata(filepath).is_ok() {
-                    println!("File exists: [{:?}]", filepath);
-                } else {
-                    println!("File not found: [{:?}]", filepath);
-                }
-            }
+// Checks that every file listed in srcsrv has an embedded stream. Returns number of files.
+// srcsrv MAC must match one of mac_keys if any are given.
+fn verify_embedded(pdb_path: &Path, mac_keys: &[String]) -> anyhow::Result<usize> {
+    let mut pdb = open_streams(pdb_path)?;
+    let srcsrv = read_fts_srcsrv(&mut pdb, pdb_path)?;
+
+    if srcsrv.sources.is_empty() {
+        bail!("PDB [{:?}] srcsrv stream lists no source files", pdb_path);
+    }
+    let manifest = manifest::Manifest::read(&mut pdb)?;
+    manifest::read_dictionary(&mut pdb, manifest.as_ref())?;
+    check_srcsrv_mac(&mut pdb, manifest.as_ref(), mac_keys, !mac_keys.is_empty())?;
+
+    for source in &srcsrv.unescaped_sources() {
+        let relpath = source
+            .get(1)
+            .ok_or_else(|| anyhow!("Malformed srcsrv source line [{}]", source.join("*")))?;
+        manifest::read_file(&mut pdb, manifest.as_ref(), relpath)
+            .with_context(|| format!("Failed to read [{}] from PDB [{:?}]", relpath, pdb_path))?;
+    }
+
+    Ok(srcsrv.sources.len())
+}
+
+fn serve(op: ServeOp) -> anyhow::Result<()> {
+    let service_exe_path =
+        which::which("fts_pdbsrc_service.exe").context("Could not find fts_pdbsrc_service.exe on PATH")?;
+
+    let mut command = std::process::Command::new(service_exe_path);
+    command.arg("--console");
+    if op.name != DEFAULT_SERVICE_NAME {
+        command.arg("--name").arg(&op.name);
+    }
+    if let Some(config) = &op.config {
+        if !config.is_file() {
+            bail!("Service config [{:?}] does not exist", config);
         }
+        command.arg("--config").arg(config);
+    }
+    if let Some(port) = op.port {
+        command.arg("--port").arg(port.to_string());
     }
 
-    // Iterate streams
-    let info = pdb.pdb_information()?;
-    let stream_names = info.stream_names()?;
-    stream_names
-        .iter()
-        .for_each(|stream_name| println!("Stream: [{}]", stream_name.name));
+    // Shares this console, so Ctrl+C reaches the service too. Wait for it to save and exit.
+    console::ignore_ctrl_c()?;
+    let status = command.status().context("Failed to run fts_pdbsrc_service.exe")?;
+    if !status.success() {
+        bail!("fts_pdbsrc_service.exe exited with [{}]", status);
+    }
 
     Ok(())
 }
 
-fn install_service(_op: InstallServiceOp) -> anyhow::Result<()> {
+fn install_service(op: InstallServiceOp) -> anyhow::Result<()> {
     use std::ffi::OsString;
     use windows_service::{
         service::{ServiceAccess, ServiceErrorControl, ServiceInfo, ServiceStartType, ServiceType},
         service_manager::{ServiceManager, ServiceManagerAccess},
     };
 
+    // Group managed service accounts end in '$'. Windows manages their passwords, as it does for virtual
+    // and built in accounts.
+    if let Some(account) = &op.account {
+        let upper_account = account.to_uppercase();
+        let managed = account.ends_with('$')
+            || upper_account.starts_with("NT SERVICE\\")
+            || upper_account.starts_with("NT AUTHORITY\\");
+        match (managed, &op.password) {
+            (true, Some(_)) => bail!("Account [{}] is managed by Windows. Remove --password.", account),
+            (false, None) => bail!("Account [{}] requires --password", account),
+            _ => (),
+        }
+    } else if op.password.is_some() {
+        bail!("--password requires --account");
+    }
+
+    if !elevate::is_elevated() {
+        if op.no_elevate {
+            bail!("install_service must be run as administrator");
+        }
+        return elevate::relaunch_elevated();
+    }
+
     // Find where
     let service_exe_path =
-        which::which("fts_pdbsrc_service.exe").expect("Could not find fts_pdbsrc_service.exe on PATH.");
+        which::which("fts_pdbsrc_service.exe").context("Could not find fts_pdbsrc_service.exe on PATH")?;
+
+    // Services start in system32 so config path must be absolute
+    let mut launch_arguments: Vec<OsString> = Default::default();
+    if op.name != DEFAULT_SERVICE_NAME {
+        launch_arguments.extend(vec!["--name".into(), op.name.clone().into()]);
+    }
+    if let Some(config) = &op.config {
+        let config = std::env::current_dir()?.join(config);
+        if !config.is_file() {
+            bail!("Service config [{:?}] does not exist", config);
+        }
+        launch_arguments.extend(vec!["--config".into(), config.into()]);
+    }
+    if let Some(port) = op.port {
+        launch_arguments.extend(vec!["--port".into(), port.to_string().into()]);
+    }
+    let display_name = match op.name.as_str() {
+        DEFAULT_SERVICE_NAME => "FTS PDB Source Service".to_owned(),
+        name => format!("FTS PDB Source Service ({})", name),
+    };
 
     || -> anyhow::Result<()> {
         let manager_access = ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE;
         let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)?;
 
         let service_info = ServiceInfo {
-            name: OsString::from("fts_pdbsrc_service"),
-            display_name: OsString::from("FTS PDB Source Service"),
+            name: OsString::from(&op.name),
+            display_name: OsString::from(display_name),
             service_type: ServiceType::OWN_PROCESS,
             start_type: ServiceStartType::AutoStart,
             error_control: ServiceErrorControl::Normal,
             executable_path: service_exe_path,
-            launch_arguments: vec![],
+            launch_arguments,
             dependencies: vec![],
-            account_name: None, // run as System
-            account_password: None,
+            account_name: op.account.as_ref().map(OsString::from), // None runs as System
+            account_password: op.password.as_ref().map(OsString::from),
         };
         let service = service_manager
             .create_service(&service_info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)?;
@@ -587,23 +4120,30 @@ fn install_service(_op: InstallServiceOp) -> anyhow::Result<()> {
         service.start(&start_args)?;
         Ok(())
     }()
-    .expect("Failed to start service. Are you running as administrator?");
+    .context("Failed to install and start service")?;
 
     Ok(())
 }
 
-fn uninstall_service(_op: UninstallServiceOp) -> anyhow::Result<()> {
+fn uninstall_service(op: UninstallServiceOp) -> anyhow::Result<()> {
     use std::{thread, time::Duration};
     use windows_service::{
         service::{ServiceAccess, ServiceState},
         service_manager::{ServiceManager, ServiceManagerAccess},
     };
 
+    if !elevate::is_elevated() {
+        if op.no_elevate {
+            bail!("uninstall_service must be run as administrator");
+        }
+        return elevate::relaunch_elevated();
+    }
+
     let manager_access = ServiceManagerAccess::CONNECT;
     let service_manager = ServiceManager::local_computer(None::<&str>, manager_access)?;
 
     let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE;
-    let service = service_manager.open_service("fts_pdbsrc_service", service_access)?;
+    let service = service_manager.open_service(&op.name, service_access)?;
 
     let service_status = service.query_status()?;
     if service_status.current_state != ServiceState::Stopped {
@@ -617,48 +4157,471 @@ fn uninstall_service(_op: UninstallServiceOp) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn send_message(stream: &mut TcpStream, message: Message) -> anyhow::Result<()> {
-    // Serialize message
-    let buf = rmp_serde::to_vec(&message).unwrap();
+// Name of service installed without --name. Other names are additional instances.
+const DEFAULT_SERVICE_NAME: &str = "fts_pdbsrc_service";
 
-    // Write packet size
-    let packet_size = u16::to_ne_bytes(buf.len() as u16);
-    stream.write_all(&packet_size)?;
+// srcsrv variable holding service HTTP address. Only written when embed specifies --http-url.
+const HTTP_URL_VARIABLE: &str = "FTS_PDBSRC_HTTP_URL";
+
+// srcsrv variable holding port passed to extract_one. Only written when embed specifies a port.
+const SERVICE_PORT_VARIABLE: &str = "FTS_PDBSRC_PORT";
+
+fn locate(op: LocateOp) -> anyhow::Result<()> {
+    let mut stream = connect_to_service().context("Failed to connect to service")?;
+    if let Some(name) = &op.name {
+        send_message(&mut stream, Message::FindPdbByName(name.clone()))?;
+        match read_message(&mut stream)? {
+            Message::FoundPdbByName(found) => {
+                if found.is_empty() {
+                    println!("No indexed PDBs named [{}]", name);
+                }
+                for (uuid, path, modified) in found {
+                    let modified = modified
+                        .map(|secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+                        .map(sbom::utc_timestamp)
+                        .unwrap_or_else(|| "<unknown time>".to_owned());
+                    println!("{} {} {}", uuid, modified, path.to_string_lossy());
+                }
+            }
+            Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+            response => bail!(
+                "Queried service for PDBs named [{}], but failed with response: [{:?}]",
+                name,
+                response
+            ),
+        }
+    }
 
-    // Write message
-    stream.write_all(&buf)?;
+    for (uuid, path) in find_pdbs(&mut stream, &op.pdb_uuids)? {
+        match path {
+            Some(path) => println!("{} {}", uuid, path.to_string_lossy()),
+            None => println!("{} <not found>", uuid),
+        }
+    }
 
     Ok(())
 }
 
-fn read_message(stream: &mut TcpStream) -> anyhow::Result<Message> {
-    // Read packet size
-    let mut packet_size_buf: [u8; 2] = Default::default();
-    stream.read_exact(&mut packet_size_buf)?;
-    let packet_size = u16::from_ne_bytes(packet_size_buf);
+fn list_pdbs(_op: ListPdbsOp) -> anyhow::Result<()> {
+    let mut stream = connect_to_service().context("Failed to connect to service")?;
 
-    // Read packet
-    let mut packet_buf = vec![0; packet_size as usize]; // TODO: make thread_local
-    stream.read_exact(&mut packet_buf)?;
+    // Results are paged to fit packet size limit
+    let mut all_pdbs: Vec<(Uuid, PathBuf)> = Default::default();
+    loop {
+        send_message(&mut stream, Message::ListPdbs(all_pdbs.len() as u32))?;
+        match read_message(&mut stream)? {
+            Message::ListedPdbs((total, page)) => {
+                let done = page.is_empty() || all_pdbs.len() + page.len() >= total as usize;
+                all_pdbs.extend(page);
+                if done {
+                    break;
+                }
+            }
+            Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+            response => bail!(
+                "Queried service for indexed PDBs, but failed with response: [{:?}]",
+                response
+            ),
+        }
+    }
 
-    // Deserialize
-    let message: Message = rmp_serde::from_read_ref(&packet_buf)?;
+    for (uuid, path) in &all_pdbs {
+        println!("{} {}", uuid, path.to_string_lossy());
+    }
+    println!("[{}] indexed PDBs", all_pdbs.len());
 
-    Ok(message)
+    Ok(())
 }
 
-fn run_command(cmd: &[&str]) -> anyhow::Result<()> {
-    let mut p = Popen::create(
-        cmd,
-        PopenConfig {
-            stdout: Redirection::Pipe,
-            ..Default::default()
+fn rescan(op: RescanOp) -> anyhow::Result<()> {
+    let mut stream = connect_to_service().context("Failed to connect to service")?;
+
+    // Service has a different working directory. Not canonicalized since that adds a \\?\ prefix
+    // which would never match configured paths.
+    let path = match op.path {
+        Some(path) if path.is_relative() => Some(std::env::current_dir()?.join(path)),
+        path => path,
+    };
+
+    // Blocks until service finishes walking
+    send_message(&mut stream, Message::Rescan(path.clone()))?;
+    match read_message(&mut stream)? {
+        Message::Rescanned(num_found) => match path {
+            Some(path) => println!("Found [{}] PDBs under [{}]", num_found, path.to_string_lossy()),
+            None => println!("Found [{}] PDBs", num_found),
         },
-    )?;
+        Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+        response => bail!(
+            "Asked service to rescan, but failed with response: [{:?}]",
+            response
+        ),
+    }
+
+    Ok(())
+}
+
+fn service_stats(op: ServiceStatsOp) -> anyhow::Result<()> {
+    let mut stream = connect_to_service().context("Failed to connect to service")?;
+
+    // Results are paged to fit packet size limit
+    let mut all_stats: Vec<PdbStats> = Default::default();
+    loop {
+        send_message(&mut stream, Message::Stats(all_stats.len() as u32))?;
+        match read_message(&mut stream)? {
+            Message::FoundStats((total, page)) => {
+                let done = page.is_empty() || all_stats.len() + page.len() >= total as usize;
+                all_stats.extend(page);
+                if done {
+                    break;
+                }
+            }
+            Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+            response => bail!(
+                "Queried service for stats, but failed with response: [{:?}]",
+                response
+            ),
+        }
+    }
+
+    let cutoff = op.unused_days.map(|days| {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        now.saturating_sub(days * 24 * 60 * 60)
+    });
+
+    println!(
+        "{:<36} {:>8} {:>12} {:<20} Path",
+        "Uuid", "Requests", "Bytes", "Last Access"
+    );
+    for stats in all_stats {
+        if let Some(cutoff) = cutoff {
+            if stats.last_access.is_some_and(|last_access| last_access >= cutoff) {
+                continue;
+            }
+        }
+
+        let last_access = stats
+            .last_access
+            .map(|secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .map(sbom::utc_timestamp)
+            .unwrap_or_else(|| "never".to_owned());
+        let path = stats
+            .path
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_else(|| "<no longer indexed>".to_owned());
+        println!(
+            "{:<36} {:>8} {:>12} {:<20} {}",
+            stats.uuid, stats.requests, stats.bytes_served, last_access, path
+        );
+    }
+
+    Ok(())
+}
+
+fn extract_cache_root() -> anyhow::Result<PathBuf> {
+    extract_cache::root().ok_or_else(|| anyhow!("LOCALAPPDATA is not set"))
+}
+
+fn cache_stats(op: CacheStatsOp) -> anyhow::Result<()> {
+    let root = extract_cache_root()?;
+    let files = extract_cache::files(&root)?;
+
+    // Grouped by <pdb>\<uuid> directory, which is what clear --uuid removes
+    let mut pdbs: std::collections::BTreeMap<PathBuf, Vec<extract_cache::CachedFile>> = Default::default();
+    for file in files {
+        if op.uuid.is_none() || file.pdb_uuid == op.uuid {
+            let dir = file
+                .path
+                .strip_prefix(&root)
+                .unwrap_or(&file.path)
+                .iter()
+                .take(2)
+                .collect();
+            pdbs.entry(dir).or_default().push(file);
+        }
+    }
+
+    println!("Cache: {:?}", root);
+    println!(
+        "{:<36} {:>8} {:>12} {:<20} PDB",
+        "Uuid", "Files", "Bytes", "Last Used"
+    );
+    let (mut total_files, mut total_bytes) = (0, 0);
+    for (dir, files) in &pdbs {
+        let pdb_uuid = files[0]
+            .pdb_uuid
+            .map_or_else(|| "<unknown>".to_owned(), |uuid| uuid.to_string());
+        let bytes: u64 = files.iter().map(|file| file.size).sum();
+        let last_used = files
+            .iter()
+            .map(|file| file.last_used)
+            .max()
+            .unwrap_or(std::time::UNIX_EPOCH);
+        let pdb_name = dir.iter().next().unwrap_or_default().to_string_lossy();
+        println!(
+            "{:<36} {:>8} {:>12} {:<20} {}",
+            pdb_uuid,
+            files.len(),
+            bytes,
+            sbom::utc_timestamp(last_used),
+            pdb_name
+        );
+        total_files += files.len();
+        total_bytes += bytes;
+    }
+    println!(
+        "Total: [{}] PDBs, [{}] files, [{}] bytes",
+        pdbs.len(),
+        total_files,
+        total_bytes
+    );
+
+    Ok(())
+}
+
+fn cache_list(op: CacheListOp) -> anyhow::Result<()> {
+    let root = extract_cache_root()?;
+    for file in extract_cache::files(&root)? {
+        if op.uuid.is_none() || file.pdb_uuid == op.uuid {
+            println!(
+                "{:>12} {:<20} {}",
+                file.size,
+                sbom::utc_timestamp(file.last_used),
+                file.path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn cache_clear(op: CacheClearOp) -> anyhow::Result<()> {
+    let root = extract_cache_root()?;
+    let removed = extract_cache::clear(&root, op.uuid)?;
+    println!(
+        "Removed [{}] files, [{}] bytes, from [{:?}]",
+        removed.files, removed.bytes, root
+    );
+
+    // Debugger will keep showing these, so the user should know they survived
+    if removed.skipped > 0 {
+        println!(
+            "Skipped [{}] files that are open, e.g. in a debugger. Close them and run clear again.",
+            removed.skipped
+        );
+    }
+
+    Ok(())
+}
+
+fn keygen(_op: KeygenOp) -> anyhow::Result<()> {
+    let (private_key, public_key) = recipients::generate_keypair();
+    println!("Add the private key to \"private_keys\" in fts_pdbsrc_config.json. DO NOT SHARE IT.");
+    println!("Private key: {}", private_key);
+    println!("Give the public key to whoever embeds, e.g. CI, for EncryptForRecipients.");
+    println!("Public key:  {}", public_key);
+    Ok(())
+}
+
+fn keys_add(op: KeysAddOp, config_arg: Option<&Path>) -> anyhow::Result<()> {
+    let key = op.key.trim().to_ascii_lowercase();
+    let key_id = match hex::decode(&key) {
+        Ok(key_bytes) if key_bytes.len() == 32 => manifest::key_id(&key_bytes),
+        _ => return Err(Failure::BadArguments).context("Key must be 64 hex characters, i.e. 32 bytes"),
+    };
+
+    let config_path = config_path(config_arg)?;
+    let mut config = read_config_json(&config_path)?;
+    let decode_keys = decode_keys_mut(&mut config)?;
+    let is_present = decode_keys.iter().any(|existing| {
+        existing
+            .as_str()
+            .is_some_and(|existing| existing.eq_ignore_ascii_case(&key))
+    });
+    if is_present {
+        status!("Key [{}] is already in [{:?}]", key_id, config_path);
+        return Ok(());
+    }
+
+    decode_keys.push(serde_json::Value::String(key));
+    write_config_json(&config_path, &config)?;
+    status!("Added key [{}] to [{:?}]", key_id, config_path);
+    Ok(())
+}
+
+fn keys_remove(op: KeysRemoveOp, config_arg: Option<&Path>) -> anyhow::Result<()> {
+    let config_path = config_path(config_arg)?;
+    let mut config = read_config_json(&config_path)?;
+    let decode_keys = decode_keys_mut(&mut config)?;
+
+    // Matches full key or key id so keys can be removed by what `keys list` prints
+    let target = op.key.trim().to_ascii_lowercase();
+    let num_keys = decode_keys.len();
+    decode_keys.retain(|existing| {
+        let existing = existing.as_str().unwrap_or_default().to_ascii_lowercase();
+        let key_id = hex::decode(&existing)
+            .ok()
+            .map(|key_bytes| manifest::key_id(&key_bytes));
+        existing != target && key_id.as_ref() != Some(&target)
+    });
+    let num_removed = num_keys - decode_keys.len();
+    if num_removed == 0 {
+        bail!(
+            "No decode key or key id matches [{}] in [{:?}]",
+            target,
+            config_path
+        );
+    }
+
+    write_config_json(&config_path, &config)?;
+    status!("Removed [{}] keys from [{:?}]", num_removed, config_path);
+    Ok(())
+}
+
+// Prints key ids rather than keys so the output is safe to paste into a ticket
+fn keys_list(_op: KeysListOp, config_arg: Option<&Path>) -> anyhow::Result<()> {
+    let config_path = config_path(config_arg)?;
+    let mut config = read_config_json(&config_path)?;
+    let decode_keys = decode_keys_mut(&mut config)?;
+    if decode_keys.is_empty() {
+        println!("No decode keys in [{:?}]", config_path);
+        return Ok(());
+    }
+
+    println!("Decode keys in [{:?}]:", config_path);
+    for (idx, key) in decode_keys.iter().enumerate() {
+        let key_bytes = key.as_str().and_then(|key| hex::decode(key).ok());
+        match key_bytes {
+            Some(key_bytes) if key_bytes.len() == 32 => println!("  {}", manifest::key_id(&key_bytes)),
+            _ => println!("  Entry [{}] is not a 64 character hex key", idx),
+        }
+    }
+    Ok(())
+}
+
+// Config is edited as JSON rather than Config so fields this version doesn't know about survive.
+// Missing config is treated as empty.
+fn read_config_json(config_path: &Path) -> anyhow::Result<serde_json::Map<String, serde_json::Value>> {
+    let bytes = match fs::read(config_path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Default::default()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read [{:?}]", config_path)),
+    };
+    match serde_json::from_slice(&bytes).with_context(|| format!("Failed to parse [{:?}]", config_path))? {
+        serde_json::Value::Object(config) => Ok(config),
+        _ => bail!("[{:?}] is not a JSON object", config_path),
+    }
+}
+
+fn decode_keys_mut(
+    config: &mut serde_json::Map<String, serde_json::Value>,
+) -> anyhow::Result<&mut Vec<serde_json::Value>> {
+    let decode_keys = config
+        .entry("decode_keys")
+        .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    match decode_keys {
+        serde_json::Value::Array(decode_keys) => Ok(decode_keys),
+        _ => bail!("\"decode_keys\" is not an array"),
+    }
+}
+
+// Written to temp file then renamed so an interrupted write never leaves a truncated config
+fn write_config_json(
+    config_path: &Path,
+    config: &serde_json::Map<String, serde_json::Value>,
+) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(config)?;
+    serde_json::from_str::<Config>(&json).context("Edited config is no longer valid. Fix it by hand.")?;
+
+    let config_dir = config_path.parent().unwrap_or_else(|| Path::new("."));
+    fs::create_dir_all(config_dir)?;
+    let mut temp_file = tempfile::Builder::new()
+        .prefix(".fts_pdbsrc_config_")
+        .suffix(".json")
+        .tempfile_in(config_dir)
+        .with_context(|| format!("Failed to write config in [{:?}]", config_dir))?;
+    temp_file.write_all(json.as_bytes())?;
+    lock::persist(temp_file.into_temp_path(), config_path)
+}
+
+fn doctor(_op: DoctorOp, config_arg: Option<&Path>) -> anyhow::Result<()> {
+    let config_path = config_path(config_arg)?;
+    let config_state = if config_path.exists() {
+        ""
+    } else {
+        " (missing, using defaults)"
+    };
+    println!("Config: [{:?}]{}", config_path, config_state);
+
+    match connect_to_service() {
+        Ok(_) => println!("Service: running"),
+        Err(e) => println!(
+            "Service: not reachable [{}]. Is fts_pdbsrc_service installed and started?",
+            e
+        ),
+    }
+
+    match ExtractFailureRecord::read()? {
+        Some(record) => {
+            println!("Most recent extraction failure:");
+            println!(
+                "  Category: {:?} (exit code {})",
+                record.failure,
+                record.failure.exit_code()
+            );
+            println!("  PDB Uuid: {}", record.pdb_uuid);
+            println!("  File: {}", record.file);
+            println!("  Time: {} (unix seconds)", record.time);
+            println!("  Error: {}", record.message);
+        }
+        None => println!("No extraction failures recorded"),
+    }
+
+    Ok(())
+}
+
+fn status(_op: StatusOp) -> anyhow::Result<()> {
+    let port = SERVICE_PORT.load(Ordering::Relaxed);
+    let mut stream = match connect_to_service() {
+        Ok(stream) => stream,
+        Err(e) => bail!(
+            "Service not reachable on port [{}]: [{}]. Is fts_pdbsrc_service installed, started, \
+             and not paused?",
+            port,
+            e
+        ),
+    };
+    println!("Service: reachable on port {}", port);
+    println!("Protocol: {}", stream.version);
+
+    // Older services don't know Status and would reply with ProtocolError
+    if stream.version < STATUS_PROTOCOL_VERSION {
+        println!("Version: unknown. Service predates status requests.");
+        return Ok(());
+    }
 
-    let status = p.wait()?;
-    match status {
-        ExitStatus::Exited(0) => Ok(()),
-        _ => bail!("Encountered status [{:?}] on cmd [{:?}]", status, cmd),
+    send_message(&mut stream, Message::Status)?;
+    match read_message(&mut stream)? {
+        Message::FoundStatus(status) => {
+            let uptime = status.uptime_seconds;
+            println!("Version: {}", status.version);
+            println!(
+                "Uptime: {}d {}h {}m {}s",
+                uptime / 86400,
+                uptime / 3600 % 24,
+                uptime / 60 % 60,
+                uptime % 60
+            );
+            println!("Indexed PDBs: {}", status.pdbs);
+        }
+        Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+        response => bail!(
+            "Queried service for status, but failed with response: [{:?}]",
+            response
+        ),
     }
+
+    Ok(())
 }
@@ -0,0 +1,604 @@
+// ----------------------------------------------------------------------------
+// Manifest stream describing how embedded files are stored
+//
+// Files larger than MAX_CHUNK_SIZE are split across numbered streams since pdbstr and MSF
+// struggle with very large streams. Small files may instead be packed together
+// into shared streams to keep the stream count down. PDBs written before the
+// manifest existed have no manifest stream; every file is a single stream.
+//
+// Relative paths that pdbstr or MSF may mangle are stored under a hashed
+// stream name. The manifest maps the true path to its stream.
+//
+// Files may be zstd compressed, prior to encryption, with a dictionary trained
+// across all embedded files. The dictionary lives in its own stream.
+//
+// Files may also be compressed with zstd or lz4, prior to encryption. Each
+// compressed file starts with a small header naming the algorithm so readers
+// don't need to know how embed was invoked.
+//
+// Encrypted files start with a small header holding the per-file nonce.
+// Older PDBs instead kept the nonce in the srcsrv source line, which tools
+// that rewrite srcsrv could drop.
+//
+// Debuggers run the commands in the srcsrv stream. An optional keyed MAC over
+// the stream lets extract and verify detect commands modified after embedding.
+//
+// The manifest also records how and when the PDB was embedded and each file's
+// original path and nonce, so tools can list and read files without parsing
+// srcsrv. Older manifests lack these and srcsrv source lines are used instead.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use hmac::{Mac, NewMac};
+use pdb::PDB;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const STREAM_NAME: &str = "fts_pdbsrc_manifest";
+pub const DICTIONARY_STREAM_NAME: &str = "fts_pdbsrc_zstd_dictionary";
+
+// srcsrv written by another indexer (git, p4, etc) before embedding. Restored by strip.
+pub const PREVIOUS_SRCSRV_STREAM_NAME: &str = "fts_pdbsrc_previous_srcsrv";
+pub const MANIFEST_VERSION: u32 = 1;
+pub const MAX_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+// Matches zstd CLI default for --train
+pub const DICTIONARY_SIZE: usize = 112640;
+
+// Compression header is COMPRESSION_MAGIC followed by one Compression byte
+const COMPRESSION_MAGIC: &[u8; 4] = b"FTSC";
+
+// Encryption header is ENCRYPTION_MAGIC, version byte, nonce, then flags byte. No flags are defined.
+// Whole header is authenticated as associated data.
+const ENCRYPTION_MAGIC: &[u8; 4] = b"FTSE";
+const ENCRYPTION_HEADER_VERSION: u8 = 1;
+const ENCRYPTION_HEADER_LEN: usize = ENCRYPTION_MAGIC.len() + 1 + NONCE_SIZE + 1;
+pub const NONCE_SIZE: usize = 12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+}
+
+impl std::str::FromStr for Compression {
+    type Err = anyhow::Error;
+    fn from_str(arg: &str) -> anyhow::Result<Self, Self::Err> {
+        match arg.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            "lz4" => Ok(Compression::Lz4),
+            _ => bail!("Unknown compression [{}]. Expected zstd, lz4, or none", arg),
+        }
+    }
+}
+
+impl Compression {
+    fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Lz4 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> anyhow::Result<Compression> {
+        match id {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            2 => Ok(Compression::Lz4),
+            _ => bail!("Unknown compression id [{}]", id),
+        }
+    }
+}
+
+// Compresses files at embed time. Output starts with a compression header.
+pub enum Compressor {
+    None,
+    Zstd(zstd::block::Compressor),
+    Lz4,
+}
+
+impl Compressor {
+    // Dictionary is only used by zstd
+    pub fn new(compression: Compression, dictionary: Option<Vec<u8>>) -> Compressor {
+        match (compression, dictionary) {
+            (Compression::None, _) => Compressor::None,
+            (Compression::Zstd, Some(dictionary)) => {
+                Compressor::Zstd(zstd::block::Compressor::with_dict(dictionary))
+            }
+            (Compression::Zstd, None) => Compressor::Zstd(zstd::block::Compressor::new()),
+            (Compression::Lz4, _) => Compressor::Lz4,
+        }
+    }
+
+    pub fn compress(&mut self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut stored: Vec<u8> = COMPRESSION_MAGIC.to_vec();
+        match self {
+            Compressor::None => {
+                stored.push(Compression::None.id());
+                stored.extend_from_slice(plaintext);
+            }
+            Compressor::Zstd(compressor) => {
+                stored.push(Compression::Zstd.id());
+                stored.extend(compressor.compress(plaintext, zstd::DEFAULT_COMPRESSION_LEVEL)?);
+            }
+            Compressor::Lz4 => {
+                stored.push(Compression::Lz4.id());
+                stored.extend(lz4_flex::compress_prepend_size(plaintext));
+            }
+        }
+        Ok(stored)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub files: Vec<ManifestFile>,
+
+    // Files are compressed with dictionary stored in DICTIONARY_STREAM_NAME
+    #[serde(default)]
+    pub zstd_dictionary: bool,
+
+    // Every file starts with a compression header
+    #[serde(default)]
+    pub compression_header: bool,
+
+    // Every file is encrypted and starts with an encryption header. srcsrv holds no nonces.
+    #[serde(default)]
+    pub encryption_header: bool,
+
+    // Hex HMAC-SHA256 of srcsrv stream
+    #[serde(default)]
+    pub srcsrv_mac: Option<String>,
+
+    // Version of fts_pdbsrc that embedded. None if embedded before this was recorded.
+    #[serde(default)]
+    pub tool_version: Option<String>,
+
+    // Seconds since unix epoch
+    #[serde(default)]
+    pub embedded_at: Option<u64>,
+
+    // EncryptMode without key material, e.g. EncryptWithKey
+    #[serde(default)]
+    pub encrypt_mode: Option<String>,
+
+    // Same as FTS_PDBSTR_UUID in srcsrv
+    #[serde(default)]
+    pub pdb_uuid: Option<uuid::Uuid>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub relpath: String,
+
+    // Base stream name. Absent for files embedded before stream names were recorded.
+    #[serde(default)]
+    pub stream: Option<String>,
+
+    // Number of .partN streams. Zero if file is stored in a single stream.
+    #[serde(default)]
+    pub chunks: u32,
+
+    // Byte range within stream for files packed together with other files
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub size: Option<u64>,
+
+    // key_id of key file was encrypted with. None if plaintext or embedded before key ids.
+    #[serde(default)]
+    pub key_id: Option<String>,
+
+    // Hex SHA-256 of plaintext. None if embedded before hashes were recorded.
+    #[serde(default)]
+    pub sha256: Option<String>,
+
+    // Source file's modified time at embed, in nanoseconds since unix epoch. Restored on extraction.
+    // None if embedded before times were recorded, or contents came from an archive.
+    #[serde(default)]
+    pub modified_ns: Option<u64>,
+
+    // Plaintext size of source file. Unlike size, which is the stored size in a packed stream.
+    #[serde(default)]
+    pub source_size: Option<u64>,
+
+    // Path recorded by the compiler, as in srcsrv source lines
+    #[serde(default)]
+    pub original_path: Option<String>,
+
+    // Hex nonce, also in the encryption header. None if plaintext.
+    #[serde(default)]
+    pub nonce: Option<String>,
+}
+
+impl ManifestFile {
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified_ns.map(|ns| UNIX_EPOCH + Duration::from_nanos(ns))
+    }
+
+    // None for times before unix epoch or after 2554
+    pub fn set_modified(&mut self, time: SystemTime) {
+        use std::convert::TryFrom;
+        self.modified_ns = time
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|duration| u64::try_from(duration.as_nanos()).ok());
+    }
+}
+
+// Source of named streams. Usually a PDB, but extract_one may fetch streams through the service
+// when the debugging user can't read the PDB.
+pub trait NamedStreams {
+    // Returns None if stream does not exist
+    fn read_stream(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>>;
+}
+
+impl NamedStreams for PDB<'_, File> {
+    fn read_stream(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.named_stream(name.as_bytes()) {
+            Ok(stream) => Ok(Some(stream.as_slice().to_owned())),
+            Err(pdb::Error::StreamNameNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl NamedStreams for super::msf::Msf {
+    fn read_stream(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.named_stream(name)?.map(|bytes| bytes.to_owned()))
+    }
+}
+
+impl NamedStreams for super::portable_pdb::PortablePdb {
+    fn read_stream(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.named_stream(name)?.map(|bytes| bytes.to_owned()))
+    }
+}
+
+impl<S: NamedStreams + ?Sized> NamedStreams for Box<S> {
+    fn read_stream(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        (**self).read_stream(name)
+    }
+}
+
+// PDB read into memory to add, replace, or remove streams. MSF or portable PDB.
+pub trait StreamContainer: NamedStreams {
+    fn named_stream(&self, name: &str) -> anyhow::Result<Option<&[u8]>>;
+    fn stream_names(&self) -> anyhow::Result<Vec<String>>;
+    fn set_named_stream(&mut self, name: &str, bytes: Vec<u8>) -> anyhow::Result<()>;
+
+    // Returns false if no stream has that name
+    fn remove_named_stream(&mut self, name: &str) -> anyhow::Result<bool>;
+
+    // Size of file write would produce
+    fn written_size(&self) -> anyhow::Result<u64>;
+    fn write(&self, path: &Path) -> anyhow::Result<()>;
+}
+
+impl StreamContainer for super::msf::Msf {
+    fn named_stream(&self, name: &str) -> anyhow::Result<Option<&[u8]>> {
+        super::msf::Msf::named_stream(self, name)
+    }
+
+    fn stream_names(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.named_streams()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    fn set_named_stream(&mut self, name: &str, bytes: Vec<u8>) -> anyhow::Result<()> {
+        super::msf::Msf::set_named_stream(self, name, bytes)
+    }
+
+    fn remove_named_stream(&mut self, name: &str) -> anyhow::Result<bool> {
+        super::msf::Msf::remove_named_stream(self, name)
+    }
+
+    fn written_size(&self) -> anyhow::Result<u64> {
+        Ok(super::msf::Msf::written_size(self))
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        super::msf::Msf::write(self, path)
+    }
+}
+
+impl StreamContainer for super::portable_pdb::PortablePdb {
+    fn named_stream(&self, name: &str) -> anyhow::Result<Option<&[u8]>> {
+        super::portable_pdb::PortablePdb::named_stream(self, name)
+    }
+
+    fn stream_names(&self) -> anyhow::Result<Vec<String>> {
+        self.named_streams()
+    }
+
+    fn set_named_stream(&mut self, name: &str, bytes: Vec<u8>) -> anyhow::Result<()> {
+        super::portable_pdb::PortablePdb::set_named_stream(self, name, bytes)
+    }
+
+    fn remove_named_stream(&mut self, name: &str) -> anyhow::Result<bool> {
+        super::portable_pdb::PortablePdb::remove_named_stream(self, name)
+    }
+
+    fn written_size(&self) -> anyhow::Result<u64> {
+        super::portable_pdb::PortablePdb::written_size(self)
+    }
+
+    fn write(&self, path: &Path) -> anyhow::Result<()> {
+        super::portable_pdb::PortablePdb::write(self, path)
+    }
+}
+
+// Errors if stream does not exist
+fn require_stream<S: NamedStreams + ?Sized>(pdb: &mut S, name: &str) -> anyhow::Result<Vec<u8>> {
+    pdb.read_stream(name)?
+        .ok_or_else(|| anyhow!("Missing stream [{}]", name))
+}
+
+impl Manifest {
+    // Returns None for PDBs embedded before manifests were written
+    pub fn read<S: NamedStreams + ?Sized>(pdb: &mut S) -> anyhow::Result<Option<Manifest>> {
+        match pdb.read_stream(STREAM_NAME)? {
+            Some(stream) => Ok(Some(serde_json::from_slice(&stream)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn file(&self, relpath: &str) -> Option<&ManifestFile> {
+        self.files.iter().find(|file| file.relpath == relpath)
+    }
+
+    // True if every file's original path is recorded, so srcsrv source lines aren't needed to list files
+    pub fn lists_files(&self) -> bool {
+        !self.files.is_empty() && self.files.iter().all(|file| file.original_path.is_some())
+    }
+}
+
+// Returns stream name for relpath. Paths that pdbstr or MSF may mangle are replaced by a hash.
+// The true path is kept in the manifest.
+pub fn stream_name(relpath: &str) -> String {
+    if is_safe_stream_path(relpath) {
+        legacy_stream_name(relpath)
+    } else {
+        let hash = hex::encode(sha2::Sha256::digest(relpath.as_bytes()));
+        format!("/fts_pdbsrc/~{}", &hash[..32])
+    }
+}
+
+// Short fingerprint of an encryption key so the right decode key is found without trying every one.
+// Hex of first 8 bytes of SHA-256. Reveals nothing useful about the key.
+pub fn key_id(key: &[u8]) -> String {
+    hex::encode(&sha2::Sha256::digest(key)[..8])
+}
+
+// True for every stream fts_pdbsrc writes other than srcsrv
+pub fn is_fts_stream(stream_name: &str) -> bool {
+    stream_name.starts_with("/fts_pdbsrc/") || stream_name.starts_with("fts_pdbsrc_")
+}
+
+// True for streams holding file contents, as opposed to srcsrv, manifest, and other metadata
+pub fn is_file_stream(stream_name: &str) -> bool {
+    stream_name.starts_with("/fts_pdbsrc/")
+}
+
+// Stream holding many small files. '~' never appears in unhashed stream names.
+pub fn pack_stream_name(pack: u32) -> String {
+    format!("/fts_pdbsrc/~pack{}", pack)
+}
+
+pub fn chunk_stream_name(stream_name: &str, chunk: u32) -> String {
+    format!("{}.part{}", stream_name, chunk)
+}
+
+// True if pdbstr and MSF store file stream name intact. Hashed, pack, and chunk names always are.
+pub fn is_valid_stream_name(stream_name: &str) -> bool {
+    let path = match stream_name.strip_prefix("/fts_pdbsrc/") {
+        Some(path) => path,
+        None => return false,
+    };
+    let path = path
+        .rsplit_once(".part")
+        .filter(|(_, chunk)| chunk.parse::<u32>().is_ok())
+        .map_or(path, |(base, _)| base);
+    path.starts_with('~') || is_safe_stream_path(path)
+}
+
+// Name used for every file prior to stream name hashing
+fn legacy_stream_name(relpath: &str) -> String {
+    format!("/fts_pdbsrc/{}", relpath)
+}
+
+// Printable ASCII without characters reserved by cmd.exe, pdbstr arguments, or srcsrv
+fn is_safe_stream_path(relpath: &str) -> bool {
+    const MAX_COMPONENT_LEN: usize = 100;
+    const MAX_PATH_LEN: usize = 200;
+
+    relpath.len() <= MAX_PATH_LEN
+        && relpath
+            .chars()
+            .all(|c| c == ' ' || (c.is_ascii_graphic() && !"\"*?<>|:%^&~".contains(c)))
+        && relpath
+            .split(['/', '\\'])
+            .all(|component| !component.is_empty() && component.len() <= MAX_COMPONENT_LEN)
+}
+
+// Reads stored bytes for file, reassembling chunks if needed. Bytes may be encrypted.
+pub fn read_file<S: NamedStreams + ?Sized>(
+    pdb: &mut S,
+    manifest: Option<&Manifest>,
+    relpath: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let file = manifest.and_then(|manifest| manifest.file(relpath));
+    let chunks = file.map(|file| file.chunks).unwrap_or(0);
+    let base_stream_name = file
+        .and_then(|file| file.stream.clone())
+        .unwrap_or_else(|| legacy_stream_name(relpath));
+
+    if chunks == 0 {
+        let stream = require_stream(pdb, &base_stream_name)?;
+
+        // Packed files are a slice of a shared stream
+        let (offset, size) = match file.map(|file| (file.offset, file.size)) {
+            Some((Some(offset), Some(size))) => (offset as usize, size as usize),
+            _ => return Ok(stream),
+        };
+        let bytes = stream
+            .get(offset..offset + size)
+            .ok_or_else(|| anyhow!("[{}] exceeds pack stream [{}]", relpath, base_stream_name))?;
+        return Ok(bytes.to_owned());
+    }
+
+    let mut bytes: Vec<u8> = Default::default();
+    for chunk in 0..chunks {
+        let stream_name = chunk_stream_name(&base_stream_name, chunk);
+        bytes.extend(require_stream(pdb, &stream_name)?);
+    }
+    Ok(bytes)
+}
+
+// Returns zstd dictionary if files were compressed with one
+pub fn read_dictionary<S: NamedStreams + ?Sized>(
+    pdb: &mut S,
+    manifest: Option<&Manifest>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match manifest {
+        Some(manifest) if manifest.zstd_dictionary => Ok(Some(require_stream(pdb, DICTIONARY_STREAM_NAME)?)),
+        _ => Ok(None),
+    }
+}
+
+// Nonce and location of ciphertext for an encrypted file
+pub struct Encryption {
+    pub nonce: [u8; NONCE_SIZE],
+
+    // Bytes before ciphertext. Authenticated as associated data.
+    pub header_len: usize,
+}
+
+// Returns header written before the ciphertext of a file encrypted with nonce
+pub fn encryption_header(nonce: &[u8; NONCE_SIZE]) -> Vec<u8> {
+    let mut header: Vec<u8> = ENCRYPTION_MAGIC.to_vec();
+    header.push(ENCRYPTION_HEADER_VERSION);
+    header.extend_from_slice(nonce);
+    header.push(0);
+    header
+}
+
+// Returns None if file is plaintext. Files embedded before encryption headers take their nonce
+// from the srcsrv source line instead.
+pub fn read_encryption(
+    bytes: &[u8],
+    manifest: Option<&Manifest>,
+    srcsrv_nonce: Option<&str>,
+) -> anyhow::Result<Option<Encryption>> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    if !manifest.is_some_and(|manifest| manifest.encryption_header) {
+        return match srcsrv_nonce {
+            Some(nonce_hex) => {
+                hex::decode_to_slice(nonce_hex, &mut nonce)
+                    .with_context(|| format!("Invalid nonce [{}]", nonce_hex))?;
+                Ok(Some(Encryption { nonce, header_len: 0 }))
+            }
+            None => Ok(None),
+        };
+    }
+
+    if bytes.len() < ENCRYPTION_HEADER_LEN || &bytes[..ENCRYPTION_MAGIC.len()] != ENCRYPTION_MAGIC {
+        bail!("Missing encryption header");
+    }
+    let header = &bytes[ENCRYPTION_MAGIC.len()..ENCRYPTION_HEADER_LEN];
+    if header[0] != ENCRYPTION_HEADER_VERSION {
+        bail!("Unknown encryption header version [{}]", header[0]);
+    }
+    if header[1 + NONCE_SIZE] != 0 {
+        bail!("Unknown encryption header flags [{}]", header[1 + NONCE_SIZE]);
+    }
+    nonce.copy_from_slice(&header[1..1 + NONCE_SIZE]);
+    Ok(Some(Encryption {
+        nonce,
+        header_len: ENCRYPTION_HEADER_LEN,
+    }))
+}
+
+pub fn sha256(plaintext: &[u8]) -> String {
+    hex::encode(sha2::Sha256::digest(plaintext))
+}
+
+// Errors if plaintext doesn't match the hash recorded at embed time. Catches corrupt streams and
+// decryption that succeeds with the wrong result. Files without a recorded hash pass.
+pub fn check_sha256(manifest: Option<&Manifest>, relpath: &str, plaintext: &[u8]) -> anyhow::Result<()> {
+    let expected = match manifest.and_then(|manifest| manifest.file(relpath)?.sha256.as_deref()) {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let actual = sha256(plaintext);
+    if !actual.eq_ignore_ascii_case(expected) {
+        bail!(
+            "SHA-256 of [{}] is [{}] but [{}] was recorded at embed",
+            relpath,
+            actual,
+            expected
+        );
+    }
+    Ok(())
+}
+
+// Undoes compression applied at embed time. Bytes must already be decrypted.
+pub fn decompress(
+    bytes: Vec<u8>,
+    manifest: Option<&Manifest>,
+    dictionary: Option<&[u8]>,
+) -> anyhow::Result<Vec<u8>> {
+    // Files embedded with only a dictionary have no header
+    if !manifest.is_some_and(|manifest| manifest.compression_header) {
+        return match dictionary {
+            Some(dictionary) => decompress_zstd(&bytes, Some(dictionary)),
+            None => Ok(bytes),
+        };
+    }
+
+    let header_len = COMPRESSION_MAGIC.len() + 1;
+    if bytes.len() < header_len || &bytes[..COMPRESSION_MAGIC.len()] != COMPRESSION_MAGIC {
+        bail!("Missing compression header");
+    }
+    let compressed = &bytes[header_len..];
+    match Compression::from_id(bytes[COMPRESSION_MAGIC.len()])? {
+        Compression::None => Ok(compressed.to_owned()),
+        Compression::Zstd => decompress_zstd(compressed, dictionary),
+        Compression::Lz4 => lz4_flex::decompress_size_prepended(compressed)
+            .map_err(|e| anyhow!("Failed to decompress lz4: [{}]", e)),
+    }
+}
+
+fn decompress_zstd(bytes: &[u8], dictionary: Option<&[u8]>) -> anyhow::Result<Vec<u8>> {
+    let mut plaintext: Vec<u8> = Default::default();
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(bytes, dictionary.unwrap_or(&[]))?;
+    decoder.read_to_end(&mut plaintext)?;
+    Ok(plaintext)
+}
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+fn new_mac(key_hex: &str) -> anyhow::Result<HmacSha256> {
+    HmacSha256::new_from_slice(&hex::decode(key_hex)?).map_err(|_| anyhow!("Invalid srcsrv MAC key"))
+}
+
+// Returns hex MAC of srcsrv stream contents
+pub fn srcsrv_mac(key_hex: &str, srcsrv: &[u8]) -> anyhow::Result<String> {
+    let mut mac = new_mac(key_hex)?;
+    mac.update(srcsrv);
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+// Constant time comparison against MAC recorded in manifest
+pub fn verify_srcsrv_mac(key_hex: &str, srcsrv: &[u8], expected_hex: &str) -> anyhow::Result<bool> {
+    let mut mac = new_mac(key_hex)?;
+    mac.update(srcsrv);
+    Ok(mac.verify(&hex::decode(expected_hex)?).is_ok())
+}
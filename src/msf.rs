@@ -0,0 +1,586 @@
+// ----------------------------------------------------------------------------
+// Minimal MSF 7.00 reader and writer for named streams
+//
+// Embedding used to spawn pdbstr once per file which required the Debugging
+// Tools and was extremely slow for large projects. Instead every stream is
+// read into memory, named streams are added, replaced, or removed, and a fresh
+// compact file is written. Nothing from removed streams survives in free blocks.
+//
+// Removed named stream map entries are tombstoned rather than rehashed so
+// lookups for the remaining names probe exactly as before.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MSF_MAGIC: &[u8; 32] = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0";
+const SUPERBLOCK_SIZE: usize = 56;
+const PDB_INFO_STREAM: usize = 1;
+const NIL_STREAM_SIZE: u32 = 0xFFFF_FFFF;
+
+// Version, signature, age, and guid precede named stream map
+const PDB_INFO_HEADER_SIZE: usize = 28;
+
+pub struct Msf {
+    block_size: u32,
+
+    // None for nil streams
+    streams: Vec<Option<Vec<u8>>>,
+}
+
+// Hands out blocks in order. Blocks 1 and 2 of every interval hold free block maps.
+struct BlockAllocator {
+    block_size: u32,
+    next_block: u32,
+}
+
+impl BlockAllocator {
+    fn new(block_size: u32) -> BlockAllocator {
+        // Block 0 is the superblock
+        BlockAllocator {
+            block_size,
+            next_block: 3,
+        }
+    }
+
+    fn allocate(&mut self) -> u32 {
+        while self.next_block % self.block_size == 1 || self.next_block % self.block_size == 2 {
+            self.next_block += 1;
+        }
+        self.next_block += 1;
+        self.next_block - 1
+    }
+
+    // Total blocks in file. Last interval's free block maps must be inside the file.
+    fn num_blocks(&self) -> u32 {
+        if self.next_block % self.block_size == 1 {
+            self.next_block + 2
+        } else {
+            self.next_block
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let slice = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| anyhow!("Unexpected end of data at offset [{}]", offset))?;
+    Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+fn num_blocks(size: u32, block_size: u32) -> usize {
+    if size == NIL_STREAM_SIZE {
+        0
+    } else {
+        (size as u64).div_ceil(block_size as u64) as usize
+    }
+}
+
+fn read_blocks(file: &mut File, blocks: &[u32], block_size: u32, len: usize) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; blocks.len() * block_size as usize];
+    for (idx, block) in blocks.iter().enumerate() {
+        let start = idx * block_size as usize;
+        file.seek(SeekFrom::Start(*block as u64 * block_size as u64))?;
+        file.read_exact(&mut bytes[start..start + block_size as usize])?;
+    }
+    bytes.truncate(len);
+    Ok(bytes)
+}
+
+impl Msf {
+    pub fn read(path: &Path) -> anyhow::Result<Msf> {
+        let mut file = super::lock::open_read(path)?;
+
+        // Superblock
+        let mut superblock = [0u8; SUPERBLOCK_SIZE];
+        file.read_exact(&mut superblock)?;
+        if &superblock[..32] != MSF_MAGIC {
+            bail!("[{:?}] is not an MSF 7.00 PDB", path);
+        }
+        let block_size = read_u32(&superblock, 32)?;
+        let num_directory_bytes = read_u32(&superblock, 44)?;
+        let block_map_addr = read_u32(&superblock, 52)?;
+        if !block_size.is_power_of_two() || !(512..=32768).contains(&block_size) {
+            bail!("Unexpected block size [{}]", block_size);
+        }
+
+        // Block map lists the blocks holding the stream directory
+        let num_directory_blocks = num_blocks(num_directory_bytes, block_size);
+        if num_directory_blocks * 4 > block_size as usize {
+            bail!("Stream directory of [{}] bytes is too large", num_directory_bytes);
+        }
+        let block_map = read_blocks(&mut file, &[block_map_addr], block_size, num_directory_blocks * 4)?;
+        let directory_blocks: Vec<u32> = (0..num_directory_blocks)
+            .map(|idx| read_u32(&block_map, idx * 4))
+            .collect::<anyhow::Result<_>>()?;
+        let directory = read_blocks(
+            &mut file,
+            &directory_blocks,
+            block_size,
+            num_directory_bytes as usize,
+        )?;
+
+        // Directory: num_streams, stream sizes, then block list for each stream
+        let num_streams = read_u32(&directory, 0)? as usize;
+        let mut block_list_offset = 4 + num_streams * 4;
+        let mut streams: Vec<Option<Vec<u8>>> = Vec::with_capacity(num_streams);
+        for idx in 0..num_streams {
+            let size = read_u32(&directory, 4 + idx * 4)?;
+            let blocks: Vec<u32> = (0..num_blocks(size, block_size))
+                .map(|block| read_u32(&directory, block_list_offset + block * 4))
+                .collect::<anyhow::Result<_>>()?;
+            block_list_offset += blocks.len() * 4;
+
+            streams.push(match size {
+                NIL_STREAM_SIZE => None,
+                _ => Some(read_blocks(&mut file, &blocks, block_size, size as usize)?),
+            });
+        }
+
+        Ok(Msf { block_size, streams })
+    }
+
+    // Writes every stream contiguously. Block 0 is the superblock and the free block maps
+    // occupy blocks 1 and 2 of every block_size interval.
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let block_size = self.block_size;
+        let mut allocator = BlockAllocator::new(block_size);
+
+        let mut file = std::io::BufWriter::new(File::create(path)?);
+        let write_block = |file: &mut std::io::BufWriter<File>, block: u32, bytes: &[u8]| {
+            file.seek(SeekFrom::Start(block as u64 * block_size as u64))?;
+            file.write_all(bytes)
+        };
+
+        // Streams, building directory as they're written
+        let mut sizes: Vec<u8> = Default::default();
+        let mut block_lists: Vec<u8> = Default::default();
+        for stream in &self.streams {
+            match stream {
+                None => sizes.extend_from_slice(&NIL_STREAM_SIZE.to_le_bytes()),
+                Some(bytes) => {
+                    sizes.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                    for chunk in bytes.chunks(block_size as usize) {
+                        let block = allocator.allocate();
+                        write_block(&mut file, block, chunk)?;
+                        block_lists.extend_from_slice(&block.to_le_bytes());
+                    }
+                }
+            }
+        }
+        let mut directory: Vec<u8> = (self.streams.len() as u32).to_le_bytes().to_vec();
+        directory.extend(sizes);
+        directory.extend(block_lists);
+
+        // Directory and block map
+        let mut block_map: Vec<u8> = Default::default();
+        for chunk in directory.chunks(block_size as usize) {
+            let block = allocator.allocate();
+            write_block(&mut file, block, chunk)?;
+            block_map.extend_from_slice(&block.to_le_bytes());
+        }
+        if block_map.len() > block_size as usize {
+            bail!("Stream directory of [{}] bytes is too large", directory.len());
+        }
+        let block_map_addr = allocator.allocate();
+        write_block(&mut file, block_map_addr, &block_map)?;
+        let num_blocks = allocator.num_blocks();
+
+        // Free block map. Set bits are free. Every block up to num_blocks is in use.
+        let num_intervals = num_blocks.div_ceil(block_size);
+        let mut fpm = vec![0xFFu8; (num_intervals * block_size) as usize];
+        for block in 0..num_blocks as usize {
+            fpm[block / 8] &= !(1 << (block % 8));
+        }
+        for (interval, chunk) in fpm.chunks(block_size as usize).enumerate() {
+            let interval_start = interval as u32 * block_size;
+            write_block(&mut file, interval_start + 1, chunk)?;
+            write_block(&mut file, interval_start + 2, chunk)?;
+        }
+
+        // Superblock
+        let mut superblock: Vec<u8> = MSF_MAGIC.to_vec();
+        for value in &[
+            block_size,
+            1,
+            num_blocks,
+            directory.len() as u32,
+            0,
+            block_map_addr,
+        ] {
+            superblock.extend_from_slice(&value.to_le_bytes());
+        }
+        write_block(&mut file, 0, &superblock)?;
+
+        // File must span every block, including a trailing partial block
+        let file = file
+            .into_inner()
+            .map_err(|e| anyhow!("Failed to write [{:?}]: [{}]", path, e.error()))?;
+        file.set_len(num_blocks as u64 * block_size as u64)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    // Size of file write would produce
+    pub fn written_size(&self) -> u64 {
+        let block_size = self.block_size as usize;
+        let blocks_for = |len: usize| len.div_ceil(block_size);
+
+        let num_stream_blocks: usize = self
+            .streams
+            .iter()
+            .flatten()
+            .map(|bytes| blocks_for(bytes.len()))
+            .sum();
+        let directory_len = 4 + self.streams.len() * 4 + num_stream_blocks * 4;
+
+        // Streams, directory, and block map
+        let mut allocator = BlockAllocator::new(self.block_size);
+        for _ in 0..num_stream_blocks + blocks_for(directory_len) + 1 {
+            allocator.allocate();
+        }
+        allocator.num_blocks() as u64 * self.block_size as u64
+    }
+
+    pub fn stream(&self, idx: usize) -> Option<&[u8]> {
+        self.streams.get(idx).and_then(|stream| stream.as_deref())
+    }
+
+    // Returns name and stream index of every named stream
+    pub fn named_streams(&self) -> anyhow::Result<Vec<(String, u32)>> {
+        let map = NamedStreamMap::parse(self.info_stream()?)?;
+        Ok(map
+            .entries
+            .iter()
+            .map(|entry| (map.name(entry.name_offset), entry.stream))
+            .collect())
+    }
+
+    // Empties stream and unlinks its name. Returns false if no stream has that name.
+    pub fn remove_named_stream(&mut self, name: &str) -> anyhow::Result<bool> {
+        let mut map = NamedStreamMap::parse(self.info_stream()?)?;
+        let stream = match map.remove(name) {
+            Some(stream) => stream,
+            None => return Ok(false),
+        };
+        self.streams[PDB_INFO_STREAM] = Some(map.to_bytes());
+        if let Some(slot) = self.streams.get_mut(stream as usize) {
+            *slot = None;
+        }
+        Ok(true)
+    }
+
+    // Adds named stream or replaces contents of existing stream with that name
+    pub fn set_named_stream(&mut self, name: &str, bytes: Vec<u8>) -> anyhow::Result<()> {
+        let existing = self
+            .named_streams()?
+            .into_iter()
+            .find(|(stream_name, _)| stream_name == name);
+        match existing {
+            Some((_, stream)) => {
+                let slot = self
+                    .streams
+                    .get_mut(stream as usize)
+                    .ok_or_else(|| anyhow!("Stream [{}] has invalid index [{}]", name, stream))?;
+                *slot = Some(bytes);
+            }
+            None => {
+                let mut map = NamedStreamMap::parse(self.info_stream()?)?;
+                map.insert(name, self.streams.len() as u32);
+                self.streams.push(Some(bytes));
+                self.streams[PDB_INFO_STREAM] = Some(map.to_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn named_stream(&self, name: &str) -> anyhow::Result<Option<&[u8]>> {
+        Ok(self
+            .named_streams()?
+            .into_iter()
+            .find(|(stream_name, _)| stream_name == name)
+            .and_then(|(_, stream)| self.stream(stream as usize)))
+    }
+
+    fn info_stream(&self) -> anyhow::Result<&[u8]> {
+        self.stream(PDB_INFO_STREAM)
+            .ok_or_else(|| anyhow!("Missing PDB info stream"))
+    }
+}
+
+struct NamedStreamEntry {
+    bucket: u32,
+    name_offset: u32,
+    stream: u32,
+}
+
+// Serialized hash table from name offset to stream index. See NamedStreamMap.cpp in LLVM.
+struct NamedStreamMap {
+    header: Vec<u8>,
+    names: Vec<u8>,
+    capacity: u32,
+    present: Vec<u32>,
+    deleted: Vec<u32>,
+    entries: Vec<NamedStreamEntry>,
+
+    // Feature signatures following the table
+    trailer: Vec<u8>,
+}
+
+impl NamedStreamMap {
+    fn parse(info: &[u8]) -> anyhow::Result<NamedStreamMap> {
+        let header = info
+            .get(..PDB_INFO_HEADER_SIZE)
+            .ok_or_else(|| anyhow!("PDB info stream is truncated"))?
+            .to_owned();
+        let names_size = read_u32(info, PDB_INFO_HEADER_SIZE)? as usize;
+        let mut offset = PDB_INFO_HEADER_SIZE + 4;
+        let names = info
+            .get(offset..offset + names_size)
+            .ok_or_else(|| anyhow!("Named stream map exceeds PDB info stream"))?
+            .to_owned();
+        offset += names_size;
+
+        let size = read_u32(info, offset)?;
+        let capacity = read_u32(info, offset + 4)?;
+        offset += 8;
+
+        let read_bit_vector = |offset: &mut usize| -> anyhow::Result<Vec<u32>> {
+            let num_words = read_u32(info, *offset)? as usize;
+            let words = (0..num_words)
+                .map(|idx| read_u32(info, *offset + 4 + idx * 4))
+                .collect::<anyhow::Result<_>>()?;
+            *offset += 4 + num_words * 4;
+            Ok(words)
+        };
+        let present = read_bit_vector(&mut offset)?;
+        let deleted = read_bit_vector(&mut offset)?;
+
+        let mut entries: Vec<NamedStreamEntry> = Default::default();
+        for bucket in 0..capacity {
+            if is_set(&present, bucket) {
+                entries.push(NamedStreamEntry {
+                    bucket,
+                    name_offset: read_u32(info, offset)?,
+                    stream: read_u32(info, offset + 4)?,
+                });
+                offset += 8;
+            }
+        }
+        if entries.len() != size as usize {
+            bail!(
+                "Named stream map has [{}] entries but expected [{}]",
+                entries.len(),
+                size
+            );
+        }
+
+        Ok(NamedStreamMap {
+            header,
+            names,
+            capacity,
+            present,
+            deleted,
+            entries,
+            trailer: info[offset..].to_owned(),
+        })
+    }
+
+    fn name(&self, offset: u32) -> String {
+        let bytes = self.names.get(offset as usize..).unwrap_or_default();
+        let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..len]).to_string()
+    }
+
+    // Appends name to string buffer and inserts into first free bucket, growing if needed
+    fn insert(&mut self, name: &str, stream: u32) {
+        let name_offset = self.names.len() as u32;
+        self.names.extend_from_slice(name.as_bytes());
+        self.names.push(0);
+
+        if self.capacity == 0 {
+            self.grow();
+        }
+        self.insert_entry(name_offset, stream);
+        if self.entries.len() as u32 >= max_load(self.capacity) {
+            self.grow();
+        }
+    }
+
+    // Linear probe from hash. Deleted buckets are reused.
+    fn insert_entry(&mut self, name_offset: u32, stream: u32) {
+        let hash = hash_string_v1(self.name(name_offset).as_bytes()) as u16 as u32;
+        let mut bucket = hash % self.capacity;
+        while is_set(&self.present, bucket) {
+            bucket = (bucket + 1) % self.capacity;
+        }
+
+        let num_words = self.capacity.div_ceil(32) as usize;
+        self.present.resize(num_words.max(self.present.len()), 0);
+        self.present[bucket as usize / 32] |= 1 << (bucket % 32);
+        if let Some(word) = self.deleted.get_mut(bucket as usize / 32) {
+            *word &= !(1 << (bucket % 32));
+        }
+        self.entries.push(NamedStreamEntry {
+            bucket,
+            name_offset,
+            stream,
+        });
+    }
+
+    // Rehashes every entry into a larger table. Matches growth policy of LLVM's HashTable.
+    fn grow(&mut self) {
+        let mut old_entries = std::mem::take(&mut self.entries);
+        old_entries.sort_by_key(|entry| entry.bucket);
+
+        self.capacity = max_load(self.capacity) * 2;
+        self.present = vec![0; self.capacity.div_ceil(32) as usize];
+        self.deleted.clear();
+        for entry in old_entries {
+            self.insert_entry(entry.name_offset, entry.stream);
+        }
+    }
+
+    // Marks bucket deleted so probing continues past it. Name stays in string buffer.
+    fn remove(&mut self, name: &str) -> Option<u32> {
+        let idx = self
+            .entries
+            .iter()
+            .position(|entry| self.name(entry.name_offset) == name)?;
+        let entry = self.entries.remove(idx);
+
+        let num_words = self.capacity.div_ceil(32) as usize;
+        if self.deleted.len() < num_words {
+            self.deleted.resize(num_words, 0);
+        }
+        self.present[entry.bucket as usize / 32] &= !(1 << (entry.bucket % 32));
+        self.deleted[entry.bucket as usize / 32] |= 1 << (entry.bucket % 32);
+        Some(entry.stream)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.clone();
+        let push = |bytes: &mut Vec<u8>, value: u32| bytes.extend_from_slice(&value.to_le_bytes());
+
+        push(&mut bytes, self.names.len() as u32);
+        bytes.extend_from_slice(&self.names);
+        push(&mut bytes, self.entries.len() as u32);
+        push(&mut bytes, self.capacity);
+        for bit_vector in &[&self.present, &self.deleted] {
+            push(&mut bytes, bit_vector.len() as u32);
+            for word in bit_vector.iter() {
+                push(&mut bytes, *word);
+            }
+        }
+        let mut entries: Vec<&NamedStreamEntry> = self.entries.iter().collect();
+        entries.sort_by_key(|entry| entry.bucket);
+        for entry in entries {
+            push(&mut bytes, entry.name_offset);
+            push(&mut bytes, entry.stream);
+        }
+        bytes.extend_from_slice(&self.trailer);
+        bytes
+    }
+}
+
+fn max_load(capacity: u32) -> u32 {
+    capacity * 2 / 3 + 1
+}
+
+// hashStringV1 from LLVM's DebugInfo/PDB/Native/Hash.cpp
+fn hash_string_v1(bytes: &[u8]) -> u32 {
+    let mut result: u32 = 0;
+    let mut words = bytes.chunks_exact(4);
+    for word in &mut words {
+        result ^= u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    let mut remainder = words.remainder();
+    if remainder.len() >= 2 {
+        result ^= u16::from_le_bytes([remainder[0], remainder[1]]) as u32;
+        remainder = &remainder[2..];
+    }
+    if let Some(byte) = remainder.first() {
+        result ^= *byte as u32;
+    }
+
+    result |= 0x2020_2020;
+    result ^= result >> 11;
+    result ^ (result >> 16)
+}
+
+fn is_set(bit_vector: &[u32], bit: u32) -> bool {
+    bit_vector
+        .get(bit as usize / 32)
+        .is_some_and(|word| word & (1 << (bit % 32)) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Adds enough names to grow the named stream map, replaces one, and removes others. Written file
+    // must read back through the pdb crate with the same identity and every named stream intact.
+    #[test]
+    fn named_streams_round_trip() -> anyhow::Result<()> {
+        let pdb_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("example/CrashTest/CrashTest.pdb");
+        let mut msf = Msf::read(&pdb_path)?;
+        let capacity = NamedStreamMap::parse(msf.info_stream()?)?.capacity;
+        let mut expected: HashMap<String, Vec<u8>> = Default::default();
+        for (name, _) in msf.named_streams()? {
+            let bytes = msf.named_stream(&name)?.unwrap_or_default().to_owned();
+            expected.insert(name, bytes);
+        }
+
+        // Keep adding past the first grow so entries are probed in the larger table
+        let mut added = 0;
+        while added < 4 || NamedStreamMap::parse(msf.info_stream()?)?.capacity == capacity {
+            let name = format!("/fts_pdbsrc/test/{}", added);
+            let bytes = name.repeat(added + 1).into_bytes();
+            msf.set_named_stream(&name, bytes.clone())?;
+            expected.insert(name, bytes);
+            added += 1;
+        }
+
+        let replaced = b"replaced".to_vec();
+        msf.set_named_stream("/fts_pdbsrc/test/0", replaced.clone())?;
+        expected.insert("/fts_pdbsrc/test/0".to_owned(), replaced);
+        for name in &["/fts_pdbsrc/test/1", "/fts_pdbsrc/test/2"] {
+            assert!(msf.remove_named_stream(name)?);
+            assert!(!msf.remove_named_stream(name)?);
+            expected.remove(*name);
+        }
+
+        let temp_dir = tempfile::tempdir()?;
+        let out_path = temp_dir.path().join("CrashTest.pdb");
+        msf.write(&out_path)?;
+        assert_eq!(std::fs::metadata(&out_path)?.len(), msf.written_size());
+
+        // Identity and named streams as the pdb crate sees them
+        let identity = |path: &Path| -> anyhow::Result<_> {
+            let info = pdb::PDB::open(File::open(path)?)?.pdb_information()?;
+            Ok((info.guid, info.age, info.signature))
+        };
+        assert_eq!(identity(&out_path)?, identity(&pdb_path)?);
+
+        let mut pdb = pdb::PDB::open(File::open(&out_path)?)?;
+        let info = pdb.pdb_information()?;
+        let mut found: HashMap<String, Vec<u8>> = Default::default();
+        for name in info.stream_names()?.iter() {
+            let stream = pdb
+                .raw_stream(name.stream_id)?
+                .ok_or_else(|| anyhow!("Stream [{}] is missing", name.name))?;
+            found.insert(name.name.to_string().into_owned(), stream.as_slice().to_owned());
+        }
+        assert_eq!(found, expected);
+
+        // And as Msf sees them after a second read
+        let reread = Msf::read(&out_path)?;
+        for (name, bytes) in &expected {
+            assert_eq!(reread.named_stream(name)?, Some(bytes.as_slice()));
+        }
+        assert_eq!(reread.named_stream("/fts_pdbsrc/test/1")?, None);
+        Ok(())
+    }
+}
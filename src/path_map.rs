@@ -0,0 +1,121 @@
+// ----------------------------------------------------------------------------
+// Rules mapping recorded source paths to relative paths
+//
+// Build agents check out to per-agent directories such as
+// D:\build\agent\work\17\, so no single --roots value matches every agent.
+// Rules rewrite the path recorded in the PDB into the relative path files are
+// embedded and extracted under. First matching rule wins. Files no rule
+// matches fall back to roots.
+//
+//   s#^D:\\build\\agent\\work\\[0-9]+\\#src\\#   regex, any delimiter after s
+//   D:\build\agent\=src\                        prefix, either separator matches
+//
+// Regex replacements may use $1 or ${name}. Rules files hold one rule per line.
+// Blank lines and lines starting with // are ignored.
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use regex::{Regex, RegexBuilder};
+use std::path::{Component, PathBuf};
+
+pub struct PathMapping {
+    rule: String,
+    pattern: Regex,
+    replacement: String,
+}
+
+impl PathMapping {
+    pub fn parse(rule: &str, ignore_case: bool) -> anyhow::Result<PathMapping> {
+        let (pattern, replacement) = match parse_substitution(rule) {
+            Some((pattern, replacement)) => (pattern.to_owned(), replacement.to_owned()),
+            None => {
+                let (prefix, replacement) = rule.split_once('=').ok_or_else(|| {
+                    anyhow!(
+                        "Path mapping [{}] is neither s#regex#replacement# nor prefix=replacement",
+                        rule
+                    )
+                })?;
+                (prefix_pattern(prefix), replacement.replace('$', "$$"))
+            }
+        };
+
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .with_context(|| format!("Invalid regex in path mapping [{}]", rule))?;
+        Ok(PathMapping {
+            rule: rule.to_owned(),
+            pattern,
+            replacement,
+        })
+    }
+}
+
+// Rules from command line followed by rules from files
+pub fn load(rules: &[String], files: &[PathBuf], ignore_case: bool) -> anyhow::Result<Vec<PathMapping>> {
+    let mut mappings = Vec::new();
+    for rule in rules {
+        mappings.push(PathMapping::parse(rule, ignore_case)?);
+    }
+    for file in files {
+        let text = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read path mapping file [{:?}]", file))?;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            mappings.push(PathMapping::parse(line, ignore_case)?);
+        }
+    }
+    Ok(mappings)
+}
+
+// Relative path for recorded filepath from first matching rule. Errors if the rule
+// doesn't produce a relative path, e.g. a pattern that isn't anchored.
+pub fn map(mappings: &[PathMapping], filepath: &str) -> anyhow::Result<Option<PathBuf>> {
+    let mapping = match mappings.iter().find(|mapping| mapping.pattern.is_match(filepath)) {
+        Some(mapping) => mapping,
+        None => return Ok(None),
+    };
+
+    // Separators are normalized so rules written for Windows agents work elsewhere
+    let mapped = mapping.pattern.replace(filepath, mapping.replacement.as_str());
+    let relpath: PathBuf = mapped
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty())
+        .collect();
+    let is_relative = relpath
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if !is_relative || relpath.file_name().is_none() {
+        bail!(
+            "Path mapping [{}] mapped [{}] to [{}], which isn't a relative file path",
+            mapping.rule,
+            filepath,
+            mapped
+        );
+    }
+    Ok(Some(relpath))
+}
+
+// s<delim>pattern<delim>replacement<delim>
+fn parse_substitution(rule: &str) -> Option<(&str, &str)> {
+    let rest = rule.strip_prefix('s')?;
+    let delim = rest.chars().next().filter(|c| c.is_ascii_punctuation())?;
+    let mut parts = rest[delim.len_utf8()..].split(delim);
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(pattern), Some(replacement), Some(""), None) => Some((pattern, replacement)),
+        _ => None,
+    }
+}
+
+// Anchored pattern for prefix where / and \ match either separator
+fn prefix_pattern(prefix: &str) -> String {
+    let mut pattern = String::from("^");
+    for c in prefix.chars() {
+        match c {
+            '/' | '\\' => pattern.push_str(r"[/\\]"),
+            _ => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    pattern
+}
@@ -0,0 +1,108 @@
+// ----------------------------------------------------------------------------
+// Minimal PE reader used to find the PDB an exe or dll was linked against
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use std::path::{Path, PathBuf};
+
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+const DEBUG_DIRECTORY_INDEX: usize = 6;
+const DEBUG_DIRECTORY_ENTRY_SIZE: usize = 28;
+const SECTION_HEADER_SIZE: usize = 40;
+
+// Returns the PDB path stored in the CodeView (RSDS) debug record, if any
+pub fn read_pdb_path(image_path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let bytes = std::fs::read(image_path)?;
+
+    let u16_at = |offset: usize| -> anyhow::Result<u16> {
+        let buf = bytes
+            .get(offset..offset + 2)
+            .ok_or_else(|| anyhow!("Unexpected end of file in [{:?}]", image_path))?;
+        Ok(u16::from_le_bytes([buf[0], buf[1]]))
+    };
+    let u32_at = |offset: usize| -> anyhow::Result<u32> {
+        let buf = bytes
+            .get(offset..offset + 4)
+            .ok_or_else(|| anyhow!("Unexpected end of file in [{:?}]", image_path))?;
+        Ok(u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]))
+    };
+
+    // DOS header points at the PE header
+    if bytes.get(0..2) != Some(b"MZ") {
+        bail!("[{:?}] is not a PE image", image_path);
+    }
+    let pe_offset = u32_at(0x3c)? as usize;
+    if bytes.get(pe_offset..pe_offset + 4) != Some(b"PE\0\0") {
+        bail!("[{:?}] has an invalid PE signature", image_path);
+    }
+
+    // COFF header followed by optional header
+    let coff_offset = pe_offset + 4;
+    let num_sections = u16_at(coff_offset + 2)? as usize;
+    let optional_header_size = u16_at(coff_offset + 16)? as usize;
+    let optional_offset = coff_offset + 20;
+    let data_directories_offset = match u16_at(optional_offset)? {
+        0x10b => optional_offset + 96,  // PE32
+        0x20b => optional_offset + 112, // PE32+
+        magic => bail!(
+            "Unknown optional header magic [{:#x}] in [{:?}]",
+            magic,
+            image_path
+        ),
+    };
+
+    // Find debug directory
+    let debug_dir_rva = u32_at(data_directories_offset + DEBUG_DIRECTORY_INDEX * 8)?;
+    let debug_dir_size = u32_at(data_directories_offset + DEBUG_DIRECTORY_INDEX * 8 + 4)? as usize;
+    if debug_dir_rva == 0 || debug_dir_size == 0 {
+        return Ok(None);
+    }
+
+    // Map RVA to file offset using section table
+    let sections_offset = optional_offset + optional_header_size;
+    let rva_to_offset = |rva: u32| -> anyhow::Result<usize> {
+        for i in 0..num_sections {
+            let section = sections_offset + i * SECTION_HEADER_SIZE;
+            let virtual_size = u32_at(section + 8)?;
+            let virtual_address = u32_at(section + 12)?;
+            let raw_size = u32_at(section + 16)?;
+            let raw_pointer = u32_at(section + 20)?;
+
+            // Values come from the file, so a malformed image must not overflow
+            let section_end = virtual_address
+                .checked_add(virtual_size.max(raw_size))
+                .ok_or_else(|| anyhow!("Section [{}] overflows address space in [{:?}]", i, image_path))?;
+            if rva >= virtual_address && rva < section_end {
+                let offset = rva
+                    .checked_sub(virtual_address)
+                    .and_then(|delta| delta.checked_add(raw_pointer))
+                    .ok_or_else(|| anyhow!("Section [{}] overflows file offset in [{:?}]", i, image_path))?;
+                return Ok(offset as usize);
+            }
+        }
+        bail!("Failed to map rva [{:#x}] in [{:?}]", rva, image_path)
+    };
+    let debug_dir_offset = rva_to_offset(debug_dir_rva)?;
+
+    // Look for CodeView entry
+    for i in 0..(debug_dir_size / DEBUG_DIRECTORY_ENTRY_SIZE) {
+        let entry = debug_dir_offset + i * DEBUG_DIRECTORY_ENTRY_SIZE;
+        if u32_at(entry + 12)? != IMAGE_DEBUG_TYPE_CODEVIEW {
+            continue;
+        }
+
+        // RSDS signature, 16-byte guid, 4-byte age, null terminated path
+        let data_offset = u32_at(entry + 24)? as usize;
+        if bytes.get(data_offset..data_offset + 4) != Some(b"RSDS") {
+            continue;
+        }
+        let path_bytes = bytes
+            .get(data_offset + 24..)
+            .and_then(|rest| rest.split(|b| *b == 0).next())
+            .ok_or_else(|| anyhow!("Truncated CodeView record in [{:?}]", image_path))?;
+        return Ok(Some(PathBuf::from(
+            String::from_utf8_lossy(path_bytes).to_string(),
+        )));
+    }
+
+    Ok(None)
+}
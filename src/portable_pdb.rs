@@ -0,0 +1,726 @@
+// ----------------------------------------------------------------------------
+// Minimal portable PDB reader and writer
+//
+// Managed code built with /debug:portable gets an ECMA-335 metadata PDB rather
+// than an MSF one. It has no named streams, so fts_pdbsrc streams are stored as
+// CustomDebugInformation rows on the module. Each row has kind STREAM_KIND and
+// a value of stream name, NUL, then stream bytes. Anything that reads or
+// writes named streams handles both formats.
+//
+// Sources may instead be written as the standard EmbeddedSource and SourceLink
+// records Visual Studio and Rider read without fts_pdbsrc.
+//
+// Only tables a standalone portable PDB may contain are understood. Tables are
+// re-encoded on write because heap and row index sizes may grow. Blobs of
+// removed streams are zeroed, and dropped when they end the blob heap, which
+// they do when every stream fts_pdbsrc added is stripped.
+//
+// https://github.com/dotnet/runtime/blob/main/docs/design/specs/PortablePdb-Metadata.md
+// ----------------------------------------------------------------------------
+use anyhow::*;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+const METADATA_SIGNATURE: &[u8; 4] = b"BSJB";
+const NUM_TABLES: usize = 64;
+
+// Kind of CustomDebugInformation rows holding fts_pdbsrc streams
+const STREAM_KIND: Uuid = Uuid::from_u128(0x7c6b_3a52_9e1d_4f0a_b8c4_2d5e_6f70_8192);
+
+// Standard kinds. See CustomDebugInformation section of the spec.
+const EMBEDDED_SOURCE_KIND: Uuid = Uuid::from_u128(0x0e8a_571b_6926_466e_b4ad_8ab0_4611_f5fe);
+const SOURCE_LINK_KIND: Uuid = Uuid::from_u128(0xcc11_0556_a091_4d38_9fec_25ab_9a35_1a6a);
+
+// Compilers store smaller sources uncompressed
+const MIN_DEFLATED_SOURCE_SIZE: usize = 200;
+
+// Document hash algorithms
+pub const SHA1_HASH: Uuid = Uuid::from_u128(0xff18_16ec_aa5e_4d10_87f7_6f49_6383_3460);
+pub const SHA256_HASH: Uuid = Uuid::from_u128(0x8829_d00f_11b8_4213_878b_770e_8597_ac16);
+
+// Type system tables live in the assembly. Their row counts are in the #Pdb stream.
+const METHOD_DEF_TABLE: usize = 0x06;
+const DOCUMENT_TABLE: usize = 0x30;
+const METHOD_DEBUG_INFORMATION_TABLE: usize = 0x31;
+const LOCAL_SCOPE_TABLE: usize = 0x32;
+const LOCAL_VARIABLE_TABLE: usize = 0x33;
+const LOCAL_CONSTANT_TABLE: usize = 0x34;
+const IMPORT_SCOPE_TABLE: usize = 0x35;
+const STATE_MACHINE_METHOD_TABLE: usize = 0x36;
+const CUSTOM_DEBUG_INFORMATION_TABLE: usize = 0x37;
+
+// HasCustomDebugInformation coded index. Tag is the table's position in this list.
+const HAS_CUSTOM_DEBUG_INFORMATION: [usize; 27] = [
+    0x06, 0x04, 0x01, 0x02, 0x08, 0x09, 0x0a, 0x00, 0x0e, 0x17, 0x14, 0x11, 0x1a, 0x1b, 0x20, 0x23, 0x26,
+    0x27, 0x28, 0x2a, 0x2c, 0x2b, 0x30, 0x32, 0x33, 0x34, 0x35,
+];
+const TAG_BITS: u32 = 5;
+const MODULE_TAG: u32 = 7;
+const DOCUMENT_TAG: u32 = 22;
+
+// Module is always row 1
+const MODULE_PARENT: u32 = 1 << TAG_BITS | MODULE_TAG;
+
+#[derive(Clone, Copy)]
+enum Column {
+    U16,
+    U32,
+    String,
+    Guid,
+    Blob,
+    Table(usize),
+    HasCustomDebugInformation,
+}
+
+// Empty for tables a portable PDB can't contain
+fn columns(table: usize) -> &'static [Column] {
+    use Column::*;
+    match table {
+        DOCUMENT_TABLE => &[Blob, Guid, Blob, Guid],
+        METHOD_DEBUG_INFORMATION_TABLE => &[Table(DOCUMENT_TABLE), Blob],
+        LOCAL_SCOPE_TABLE => &[
+            Table(METHOD_DEF_TABLE),
+            Table(IMPORT_SCOPE_TABLE),
+            Table(LOCAL_VARIABLE_TABLE),
+            Table(LOCAL_CONSTANT_TABLE),
+            U32,
+            U32,
+        ],
+        LOCAL_VARIABLE_TABLE => &[U16, U16, String],
+        LOCAL_CONSTANT_TABLE => &[String, Blob],
+        IMPORT_SCOPE_TABLE => &[Table(IMPORT_SCOPE_TABLE), Blob],
+        STATE_MACHINE_METHOD_TABLE => &[Table(METHOD_DEF_TABLE), Table(METHOD_DEF_TABLE)],
+        CUSTOM_DEBUG_INFORMATION_TABLE => &[HasCustomDebugInformation, Guid, Blob],
+        _ => &[],
+    }
+}
+
+fn column_size(column: Column, heap_sizes: u8, row_counts: &[u32; NUM_TABLES]) -> usize {
+    let size = |large: bool| if large { 4 } else { 2 };
+    match column {
+        Column::U16 => 2,
+        Column::U32 => 4,
+        Column::String => size(heap_sizes & 0x01 != 0),
+        Column::Guid => size(heap_sizes & 0x02 != 0),
+        Column::Blob => size(heap_sizes & 0x04 != 0),
+        Column::Table(table) => size(row_counts[table] >= 0x1_0000),
+        Column::HasCustomDebugInformation => {
+            let max_rows = HAS_CUSTOM_DEBUG_INFORMATION
+                .iter()
+                .map(|table| row_counts[*table])
+                .max()
+                .unwrap_or(0);
+            size(max_rows >= 1 << (16 - TAG_BITS))
+        }
+    }
+}
+
+pub struct PortablePdb {
+    version: String,
+    pdb_stream: Vec<u8>,
+    strings: Vec<u8>,
+    user_strings: Option<Vec<u8>>,
+    guids: Vec<u8>,
+    blobs: Vec<u8>,
+    valid: u64,
+    sorted: u64,
+
+    // Row counts of type system tables, zero for debug tables
+    type_system_rows: [u32; NUM_TABLES],
+
+    // Column values of every row of every table
+    rows: Vec<Vec<Vec<u32>>>,
+
+    // Start and end of zeroed blobs
+    removed_blobs: Vec<(usize, usize)>,
+}
+
+pub struct Document {
+    // 1-based row in Document table
+    pub row: u32,
+    pub name: String,
+    pub hash_algorithm: Uuid,
+    pub hash: Vec<u8>,
+}
+
+// Cheap check used to pick a reader. Compressed PDBs must be expanded first.
+pub fn is_portable_pdb(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    super::lock::open_read(path).is_ok_and(|mut file| file.read_exact(&mut magic).is_ok())
+        && &magic == METADATA_SIGNATURE
+}
+
+impl PortablePdb {
+    pub fn read(path: &Path) -> anyhow::Result<PortablePdb> {
+        PortablePdb::parse(&super::lock::read(path)?)
+    }
+
+    fn parse(bytes: &[u8]) -> anyhow::Result<PortablePdb> {
+        if bytes.get(..4) != Some(&METADATA_SIGNATURE[..]) {
+            bail!("Not a portable PDB");
+        }
+
+        // Metadata root then stream headers
+        let version_len = read_u32(bytes, 12)? as usize;
+        let version = String::from_utf8_lossy(slice(bytes, 16, version_len)?)
+            .trim_end_matches('\0')
+            .to_owned();
+        let num_streams = read_u16(bytes, 16 + version_len + 2)?;
+        let mut offset = 16 + version_len + 4;
+        let mut streams: HashMap<String, &[u8]> = Default::default();
+        for _ in 0..num_streams {
+            let stream_offset = read_u32(bytes, offset)? as usize;
+            let stream_size = read_u32(bytes, offset + 4)? as usize;
+            let name = slice(bytes, offset + 8, bytes.len().saturating_sub(offset + 8))?;
+            let name_len = name
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or_else(|| anyhow!("Unterminated stream name at offset [{}]", offset + 8))?;
+            streams.insert(
+                String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                slice(bytes, stream_offset, stream_size)?,
+            );
+            offset = align4(offset + 8 + name_len + 1);
+        }
+        let stream = |name: &str| streams.get(name).map(|stream| stream.to_vec());
+        let pdb_stream = stream("#Pdb").ok_or_else(|| anyhow!("Missing #Pdb stream"))?;
+        let tables = stream("#~").ok_or_else(|| anyhow!("Missing #~ stream"))?;
+
+        // Row counts of referenced type system tables follow id and entry point
+        let mut type_system_rows = [0u32; NUM_TABLES];
+        let referenced_tables = read_u64(&pdb_stream, 24)?;
+        let mut offset = 32;
+        for (table, rows) in type_system_rows.iter_mut().enumerate() {
+            if referenced_tables & (1 << table) != 0 {
+                *rows = read_u32(&pdb_stream, offset)?;
+                offset += 4;
+            }
+        }
+
+        // Tables header, row counts, then rows
+        let heap_sizes = *tables.get(6).ok_or_else(|| anyhow!("Truncated #~ stream"))?;
+        let valid = read_u64(&tables, 8)?;
+        let sorted = read_u64(&tables, 16)?;
+        let mut row_counts = type_system_rows;
+        let mut offset = 24;
+        for (table, rows) in row_counts.iter_mut().enumerate() {
+            if valid & (1 << table) != 0 {
+                if columns(table).is_empty() {
+                    bail!("Unsupported metadata table [{:#x}] in portable PDB", table);
+                }
+                *rows = read_u32(&tables, offset)?;
+                offset += 4;
+            }
+        }
+        if heap_sizes & 0x40 != 0 {
+            // Extra data
+            offset += 4;
+        }
+        let mut rows: Vec<Vec<Vec<u32>>> = vec![Default::default(); NUM_TABLES];
+        for (table, table_rows) in rows.iter_mut().enumerate() {
+            if valid & (1 << table) == 0 {
+                continue;
+            }
+            for _ in 0..row_counts[table] {
+                let mut row = Vec::with_capacity(columns(table).len());
+                for column in columns(table) {
+                    let size = column_size(*column, heap_sizes, &row_counts);
+                    row.push(match size {
+                        2 => read_u16(&tables, offset)? as u32,
+                        _ => read_u32(&tables, offset)?,
+                    });
+                    offset += size;
+                }
+                table_rows.push(row);
+            }
+        }
+
+        // Index 0 of the string and blob heaps is always empty
+        Ok(PortablePdb {
+            version,
+            pdb_stream,
+            strings: stream("#Strings").unwrap_or_else(|| vec![0]),
+            user_strings: stream("#US"),
+            guids: stream("#GUID").unwrap_or_default(),
+            blobs: stream("#Blob").unwrap_or_else(|| vec![0]),
+            valid,
+            sorted,
+            type_system_rows,
+            rows,
+            removed_blobs: Default::default(),
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_bytes()?)?;
+        Ok(())
+    }
+
+    // Size of file write would produce
+    pub fn written_size(&self) -> anyhow::Result<u64> {
+        Ok(self.to_bytes()?.len() as u64)
+    }
+
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        // Drop STREAM_KIND once every stream is removed
+        let mut guids = self.guids.clone();
+        if let Some(kind) = self.find_guid(STREAM_KIND) {
+            if kind as usize == guids.len() / 16 && !self.references(Column::Guid, kind) {
+                guids.truncate(guids.len() - 16);
+            }
+        }
+
+        // Heap and row index sizes may differ from what was read
+        let mut heap_sizes = 0u8;
+        for (bit, heap) in [&self.strings, &guids, &self.blobs].iter().enumerate() {
+            if heap.len() >= 0x1_0000 {
+                heap_sizes |= 1 << bit;
+            }
+        }
+        let mut row_counts = self.type_system_rows;
+        let mut valid = self.valid;
+        for (table, rows) in self.rows.iter().enumerate() {
+            if !rows.is_empty() {
+                row_counts[table] = rows.len() as u32;
+                valid |= 1 << table;
+            }
+        }
+
+        let mut tables: Vec<u8> = Default::default();
+        tables.extend_from_slice(&0u32.to_le_bytes());
+        tables.extend_from_slice(&[2, 0, heap_sizes, 1]);
+        tables.extend_from_slice(&valid.to_le_bytes());
+        tables.extend_from_slice(&self.sorted.to_le_bytes());
+        for (table, rows) in self.rows.iter().enumerate() {
+            if valid & (1 << table) != 0 {
+                tables.extend_from_slice(&(rows.len() as u32).to_le_bytes());
+            }
+        }
+        for (table, rows) in self.rows.iter().enumerate() {
+            for row in rows {
+                for (column, value) in columns(table).iter().zip(row) {
+                    match column_size(*column, heap_sizes, &row_counts) {
+                        2 => tables.extend_from_slice(&(*value as u16).to_le_bytes()),
+                        _ => tables.extend_from_slice(&value.to_le_bytes()),
+                    }
+                }
+            }
+        }
+
+        let mut streams: Vec<(&str, Vec<u8>)> = vec![("#Pdb", self.pdb_stream.clone()), ("#~", tables)];
+        streams.push(("#Strings", self.strings.clone()));
+        if let Some(user_strings) = &self.user_strings {
+            streams.push(("#US", user_strings.clone()));
+        }
+        streams.push(("#GUID", guids));
+        streams.push(("#Blob", self.blobs.clone()));
+        for (_, stream) in &mut streams {
+            stream.resize(align4(stream.len()), 0);
+        }
+
+        // Metadata root
+        let mut version = self.version.clone().into_bytes();
+        version.resize(align4(version.len() + 1), 0);
+        let mut bytes: Vec<u8> = METADATA_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(version.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&version);
+        bytes.extend_from_slice(&0u16.to_le_bytes());
+        bytes.extend_from_slice(&(streams.len() as u16).to_le_bytes());
+
+        // Stream headers then stream data
+        let headers_len: usize = streams.iter().map(|(name, _)| 8 + align4(name.len() + 1)).sum();
+        let mut stream_offset = bytes.len() + headers_len;
+        for (name, stream) in &streams {
+            let stream_offset_u32: u32 = stream_offset.try_into()?;
+            bytes.extend_from_slice(&stream_offset_u32.to_le_bytes());
+            bytes.extend_from_slice(&(stream.len() as u32).to_le_bytes());
+            let mut name = name.as_bytes().to_vec();
+            name.resize(align4(name.len() + 1), 0);
+            bytes.extend_from_slice(&name);
+            stream_offset += stream.len();
+        }
+        for (_, stream) in &streams {
+            bytes.extend_from_slice(stream);
+        }
+        Ok(bytes)
+    }
+
+    // Guid and stamp. Symbol servers key portable PDBs by guid with age FFFFFFFF.
+    pub fn id(&self) -> anyhow::Result<(Uuid, u32)> {
+        Ok((
+            guid_from_bytes(slice(&self.pdb_stream, 0, 16)?)?,
+            read_u32(&self.pdb_stream, 16)?,
+        ))
+    }
+
+    pub fn documents(&self) -> anyhow::Result<Vec<Document>> {
+        self.rows[DOCUMENT_TABLE]
+            .iter()
+            .enumerate()
+            .map(|(idx, row)| {
+                Ok(Document {
+                    row: idx as u32 + 1,
+                    name: self.document_name(row[0])?,
+                    hash_algorithm: self.guid(row[1])?,
+                    hash: self.blob(row[2])?.to_owned(),
+                })
+            })
+            .collect()
+    }
+
+    // Separator character then blob index of each part
+    fn document_name(&self, index: u32) -> anyhow::Result<String> {
+        let blob = self.blob(index)?;
+        let separator_len = match blob.first() {
+            None | Some(0) => 1,
+            Some(b) if *b < 0x80 => 1,
+            Some(b) if *b < 0xe0 => 2,
+            Some(b) if *b < 0xf0 => 3,
+            Some(_) => 4,
+        };
+        let separator = match blob.first() {
+            None | Some(0) => "",
+            Some(_) => std::str::from_utf8(slice(blob, 0, separator_len)?)?,
+        };
+        let mut parts: Vec<String> = Default::default();
+        let mut offset = separator_len;
+        while offset < blob.len() {
+            let (part, header_len) = read_compressed(blob, offset)?;
+            parts.push(String::from_utf8_lossy(self.blob(part)?).into_owned());
+            offset += header_len;
+        }
+        Ok(parts.join(separator))
+    }
+
+    // Names of every stream stored by set_named_stream
+    pub fn named_streams(&self) -> anyhow::Result<Vec<String>> {
+        let mut names: Vec<String> = Default::default();
+        for row in self.stream_rows() {
+            let value = self.blob(row[2])?;
+            let (name, _) = split_stream_value(value)?;
+            names.push(name.to_owned());
+        }
+        Ok(names)
+    }
+
+    pub fn named_stream(&self, name: &str) -> anyhow::Result<Option<&[u8]>> {
+        for row in self.stream_rows() {
+            let (stream_name, bytes) = split_stream_value(self.blob(row[2])?)?;
+            if stream_name == name {
+                return Ok(Some(bytes));
+            }
+        }
+        Ok(None)
+    }
+
+    // Adds named stream or replaces contents of existing stream with that name
+    pub fn set_named_stream(&mut self, name: &str, bytes: Vec<u8>) -> anyhow::Result<()> {
+        self.remove_named_stream(name)?;
+        let mut value = name.as_bytes().to_vec();
+        value.push(0);
+        value.extend_from_slice(&bytes);
+        let kind = self.add_guid(STREAM_KIND);
+        let value = self.add_blob(&value)?;
+        self.add_custom_debug_information(MODULE_PARENT, kind, value);
+        Ok(())
+    }
+
+    // Returns false if no stream has that name
+    pub fn remove_named_stream(&mut self, name: &str) -> anyhow::Result<bool> {
+        let kind = match self.find_guid(STREAM_KIND) {
+            Some(kind) => kind,
+            None => return Ok(false),
+        };
+        let mut found: Option<usize> = None;
+        for (idx, row) in self.rows[CUSTOM_DEBUG_INFORMATION_TABLE].iter().enumerate() {
+            if row[0] == MODULE_PARENT && row[1] == kind && split_stream_value(self.blob(row[2])?)?.0 == name
+            {
+                found = Some(idx);
+                break;
+            }
+        }
+        match found {
+            Some(idx) => {
+                let row = self.rows[CUSTOM_DEBUG_INFORMATION_TABLE].remove(idx);
+                self.remove_blob(row[2])?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // Replaces any existing record. Format is uncompressed size then deflated bytes, or 0 then raw bytes.
+    pub fn set_embedded_source(&mut self, document_row: u32, contents: &[u8]) -> anyhow::Result<()> {
+        let mut value: Vec<u8> = Default::default();
+        if contents.len() >= MIN_DEFLATED_SOURCE_SIZE {
+            let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(contents)?;
+            let deflated = encoder.finish()?;
+            if deflated.len() < contents.len() {
+                let len: i32 = contents.len().try_into()?;
+                value.extend_from_slice(&len.to_le_bytes());
+                value.extend_from_slice(&deflated);
+            }
+        }
+        if value.is_empty() {
+            value.extend_from_slice(&0i32.to_le_bytes());
+            value.extend_from_slice(contents);
+        }
+        let parent = document_row << TAG_BITS | DOCUMENT_TAG;
+        self.set_custom_debug_information(parent, EMBEDDED_SOURCE_KIND, &value)
+    }
+
+    pub fn embedded_source(&self, document_row: u32) -> anyhow::Result<Option<Vec<u8>>> {
+        let parent = document_row << TAG_BITS | DOCUMENT_TAG;
+        let value = match self.custom_debug_information(parent, EMBEDDED_SOURCE_KIND)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let format = read_u32(value, 0)? as i32;
+        match format {
+            0 => Ok(Some(value[4..].to_owned())),
+            len if len > 0 => {
+                let mut contents: Vec<u8> = Vec::with_capacity(len as usize);
+                DeflateDecoder::new(&value[4..]).read_to_end(&mut contents)?;
+                if contents.len() != len as usize {
+                    bail!(
+                        "EmbeddedSource inflated to [{}] bytes not [{}]",
+                        contents.len(),
+                        len
+                    );
+                }
+                Ok(Some(contents))
+            }
+            _ => bail!("Unknown EmbeddedSource format [{}]", format),
+        }
+    }
+
+    // Removing every old record before adding new ones lets the blob heap shrink back
+    pub fn remove_embedded_source(&mut self, document_row: u32) -> anyhow::Result<bool> {
+        let parent = document_row << TAG_BITS | DOCUMENT_TAG;
+        self.remove_custom_debug_information(parent, EMBEDDED_SOURCE_KIND)
+    }
+
+    // Replaces any existing SourceLink JSON
+    pub fn set_source_link(&mut self, json: &[u8]) -> anyhow::Result<()> {
+        self.set_custom_debug_information(MODULE_PARENT, SOURCE_LINK_KIND, json)
+    }
+
+    fn custom_debug_information(&self, parent: u32, kind: Uuid) -> anyhow::Result<Option<&[u8]>> {
+        let kind = match self.find_guid(kind) {
+            Some(kind) => kind,
+            None => return Ok(None),
+        };
+        match self.rows[CUSTOM_DEBUG_INFORMATION_TABLE]
+            .iter()
+            .find(|row| row[0] == parent && row[1] == kind)
+        {
+            Some(row) => Ok(Some(self.blob(row[2])?)),
+            None => Ok(None),
+        }
+    }
+
+    // Standard kinds allow one record per parent
+    fn set_custom_debug_information(&mut self, parent: u32, kind: Uuid, value: &[u8]) -> anyhow::Result<()> {
+        self.remove_custom_debug_information(parent, kind)?;
+        let kind = self.add_guid(kind);
+        let value = self.add_blob(value)?;
+        self.add_custom_debug_information(parent, kind, value);
+        Ok(())
+    }
+
+    fn remove_custom_debug_information(&mut self, parent: u32, kind: Uuid) -> anyhow::Result<bool> {
+        let kind = match self.find_guid(kind) {
+            Some(kind) => kind,
+            None => return Ok(false),
+        };
+        let rows = &mut self.rows[CUSTOM_DEBUG_INFORMATION_TABLE];
+        match rows.iter().position(|row| row[0] == parent && row[1] == kind) {
+            Some(idx) => {
+                let row = rows.remove(idx);
+                self.remove_blob(row[2])?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn stream_rows(&self) -> impl Iterator<Item = &Vec<u32>> {
+        let kind = self.find_guid(STREAM_KIND);
+        self.rows[CUSTOM_DEBUG_INFORMATION_TABLE]
+            .iter()
+            .filter(move |row| row[0] == MODULE_PARENT && Some(row[1]) == kind)
+    }
+
+    // Table must stay sorted by parent. Rows with the same parent keep insertion order.
+    fn add_custom_debug_information(&mut self, parent: u32, kind: u32, value: u32) {
+        let rows = &mut self.rows[CUSTOM_DEBUG_INFORMATION_TABLE];
+        let idx = rows.iter().position(|row| row[0] > parent).unwrap_or(rows.len());
+        rows.insert(idx, vec![parent, kind, value]);
+    }
+
+    fn blob(&self, index: u32) -> anyhow::Result<&[u8]> {
+        let (len, header_len) = read_compressed(&self.blobs, index as usize)?;
+        slice(&self.blobs, index as usize + header_len, len as usize)
+    }
+
+    // Never shared with another row, unlike blobs written by compilers
+    fn add_blob(&mut self, bytes: &[u8]) -> anyhow::Result<u32> {
+        let index = self.blobs.len();
+        write_compressed(&mut self.blobs, bytes.len())?;
+        self.blobs.extend_from_slice(bytes);
+        Ok(index.try_into()?)
+    }
+
+    // Zeroes contents so nothing survives even if it isn't at the end of the heap. Blobs another row
+    // still references are left alone.
+    fn remove_blob(&mut self, index: u32) -> anyhow::Result<()> {
+        if self.references(Column::Blob, index) {
+            return Ok(());
+        }
+        let start = index as usize;
+        let (len, header_len) = read_compressed(&self.blobs, start)?;
+        let end = start + header_len + len as usize;
+        self.blobs[start + header_len..end]
+            .iter_mut()
+            .for_each(|b| *b = 0);
+        self.removed_blobs.push((start, end));
+
+        // Heap shrinks once removed blobs reach its end. Heap read from file may end in alignment padding.
+        self.removed_blobs.sort_unstable();
+        while let Some((start, end)) = self.removed_blobs.last().copied() {
+            let blobs = &self.blobs;
+            if end > blobs.len() || blobs.len() - end >= 4 || blobs[end..].iter().any(|b| *b != 0) {
+                break;
+            }
+            self.blobs.truncate(start);
+            self.removed_blobs.pop();
+        }
+        Ok(())
+    }
+
+    // 1-based. 0 is the nil guid.
+    fn guid(&self, index: u32) -> anyhow::Result<Uuid> {
+        match index {
+            0 => Ok(Uuid::nil()),
+            _ => guid_from_bytes(slice(&self.guids, (index as usize - 1) * 16, 16)?),
+        }
+    }
+
+    fn find_guid(&self, guid: Uuid) -> Option<u32> {
+        let bytes = guid_to_bytes(guid);
+        self.guids
+            .chunks_exact(16)
+            .position(|chunk| chunk == bytes)
+            .map(|idx| idx as u32 + 1)
+    }
+
+    // True if any row has a heap column of this kind with this index
+    fn references(&self, heap: Column, index: u32) -> bool {
+        self.rows.iter().enumerate().any(|(table, rows)| {
+            rows.iter().any(|row| {
+                columns(table).iter().zip(row).any(|(column, value)| {
+                    std::mem::discriminant(column) == std::mem::discriminant(&heap) && *value == index
+                })
+            })
+        })
+    }
+
+    fn add_guid(&mut self, guid: Uuid) -> u32 {
+        self.find_guid(guid).unwrap_or_else(|| {
+            self.guids.extend_from_slice(&guid_to_bytes(guid));
+            (self.guids.len() / 16) as u32
+        })
+    }
+}
+
+// Stream name then NUL then stream bytes
+fn split_stream_value(value: &[u8]) -> anyhow::Result<(&str, &[u8])> {
+    let nul = value
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| anyhow!("Malformed fts_pdbsrc stream record"))?;
+    Ok((std::str::from_utf8(&value[..nul])?, &value[nul + 1..]))
+}
+
+// ECMA-335 compressed unsigned integer. Returns value and encoded length.
+fn read_compressed(bytes: &[u8], offset: usize) -> anyhow::Result<(u32, usize)> {
+    let b = slice(bytes, offset, 1)?[0] as u32;
+    if b & 0x80 == 0 {
+        Ok((b, 1))
+    } else if b & 0xc0 == 0x80 {
+        let next = slice(bytes, offset + 1, 1)?;
+        Ok(((b & 0x3f) << 8 | next[0] as u32, 2))
+    } else if b & 0xe0 == 0xc0 {
+        let next = slice(bytes, offset + 1, 3)?;
+        Ok((
+            (b & 0x1f) << 24 | (next[0] as u32) << 16 | (next[1] as u32) << 8 | next[2] as u32,
+            4,
+        ))
+    } else {
+        bail!("Invalid compressed integer at offset [{}]", offset)
+    }
+}
+
+fn write_compressed(bytes: &mut Vec<u8>, value: usize) -> anyhow::Result<()> {
+    match value {
+        0..=0x7f => bytes.push(value as u8),
+        0x80..=0x3fff => bytes.extend_from_slice(&(value as u16 | 0x8000).to_be_bytes()),
+        0x4000..=0x1fff_ffff => bytes.extend_from_slice(&(value as u32 | 0xc000_0000).to_be_bytes()),
+        _ => bail!("Blob of [{}] bytes is too large for a portable PDB", value),
+    }
+    Ok(())
+}
+
+// Guid heap stores the first three fields little endian
+fn guid_from_bytes(bytes: &[u8]) -> anyhow::Result<Uuid> {
+    let d1 = read_u32(bytes, 0)?;
+    let d2 = read_u16(bytes, 4)?;
+    let d3 = read_u16(bytes, 6)?;
+    Ok(Uuid::from_fields(d1, d2, d3, slice(bytes, 8, 8)?)?)
+}
+
+fn guid_to_bytes(guid: Uuid) -> [u8; 16] {
+    let (d1, d2, d3, d4) = guid.as_fields();
+    let mut bytes = [0u8; 16];
+    bytes[..4].copy_from_slice(&d1.to_le_bytes());
+    bytes[4..6].copy_from_slice(&d2.to_le_bytes());
+    bytes[6..8].copy_from_slice(&d3.to_le_bytes());
+    bytes[8..].copy_from_slice(d4);
+    bytes
+}
+
+fn align4(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+fn slice(bytes: &[u8], offset: usize, len: usize) -> anyhow::Result<&[u8]> {
+    bytes
+        .get(offset..offset + len)
+        .ok_or_else(|| anyhow!("Unexpected end of data at offset [{}]", offset))
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> anyhow::Result<u16> {
+    let slice = slice(bytes, offset, 2)?;
+    Ok(u16::from_le_bytes([slice[0], slice[1]]))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> anyhow::Result<u32> {
+    let slice = slice(bytes, offset, 4)?;
+    Ok(u32::from_le_bytes([slice[0], slice[1], slice[2], slice[3]]))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> anyhow::Result<u64> {
+    let slice = slice(bytes, offset, 8)?;
+    let mut value = [0u8; 8];
+    value.copy_from_slice(slice);
+    Ok(u64::from_le_bytes(value))
+}
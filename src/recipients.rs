@@ -0,0 +1,141 @@
+// ----------------------------------------------------------------------------
+// Session key wrapping for EncryptMode::EncryptForRecipients
+//
+// Sources are encrypted with a random AES-256 session key as with
+// EncryptWithRngKey. Rather than printing that key, it is wrapped once per
+// recipient X25519 public key and stored in STREAM_NAME. Any recipient can
+// unwrap it with their private key so build machines only hold public keys.
+//
+// Each wrap derives a key encryption key with HKDF-SHA256 from an exchange
+// between a fresh ephemeral key and the recipient's key, then seals the
+// session key with AES-256-GCM.
+// ----------------------------------------------------------------------------
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use x25519_dalek::{PublicKey, SharedSecret, StaticSecret};
+
+pub const STREAM_NAME: &str = "fts_pdbsrc_recipients";
+
+const KDF_INFO: &[u8] = b"fts_pdbsrc recipient key v1";
+
+// Session key sealed for one recipient. Fields are hex.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct WrappedKey {
+    public_key: String,
+    ephemeral_public_key: String,
+    nonce: String,
+    wrapped_key: String,
+}
+
+// Returns hex private key and matching hex public key
+pub fn generate_keypair() -> (String, String) {
+    let secret = StaticSecret::from(rand::thread_rng().gen::<[u8; 32]>());
+    let public_key = PublicKey::from(&secret);
+    (hex::encode(secret.to_bytes()), hex::encode(public_key.as_bytes()))
+}
+
+// Returns contents of STREAM_NAME
+pub fn wrap(session_key: &[u8; 32], public_keys: &[String]) -> anyhow::Result<Vec<u8>> {
+    if public_keys.is_empty() {
+        bail!("EncryptForRecipients requires at least one public key");
+    }
+
+    let mut rng = rand::thread_rng();
+    let wrapped: Vec<WrappedKey> = public_keys
+        .iter()
+        .map(|public_key_hex| {
+            let public_key = parse_key(public_key_hex)
+                .with_context(|| format!("Invalid recipient public key [{}]", public_key_hex))?;
+            let public_key = PublicKey::from(public_key);
+            let ephemeral = StaticSecret::from(rng.gen::<[u8; 32]>());
+            let ephemeral_public_key = PublicKey::from(&ephemeral);
+            let shared_secret = ephemeral.diffie_hellman(&public_key);
+            let cipher = key_encryption_cipher(&shared_secret, &ephemeral_public_key, &public_key);
+
+            let nonce = rng.gen::<[u8; 12]>();
+            let wrapped_key = cipher
+                .encrypt(Nonce::from_slice(&nonce), session_key.as_ref())
+                .map_err(|_| anyhow!("Failed to wrap session key for [{}]", public_key_hex))?;
+
+            Ok(WrappedKey {
+                public_key: hex::encode(public_key.as_bytes()),
+                ephemeral_public_key: hex::encode(ephemeral_public_key.as_bytes()),
+                nonce: hex::encode(nonce),
+                wrapped_key: hex::encode(wrapped_key),
+            })
+        })
+        .collect::<anyhow::Result<_>>()?;
+
+    Ok(serde_json::to_vec_pretty(&wrapped)?)
+}
+
+// Returns session key if any private key belongs to a recipient
+pub fn unwrap(stream: &[u8], private_keys: &[String]) -> anyhow::Result<Option<[u8; 32]>> {
+    let wrapped: Vec<WrappedKey> = serde_json::from_slice(stream)?;
+    for (idx, private_key_hex) in private_keys.iter().enumerate() {
+        // Errors are logged, so identify the key by position rather than echoing it
+        let secret = parse_key(private_key_hex).with_context(|| format!("Invalid private_keys[{}]", idx))?;
+        let secret = StaticSecret::from(secret);
+        let public_key = PublicKey::from(&secret);
+        let public_key_hex = hex::encode(public_key.as_bytes());
+
+        let entry = wrapped
+            .iter()
+            .find(|entry| entry.public_key.eq_ignore_ascii_case(&public_key_hex));
+        if let Some(entry) = entry {
+            let ephemeral_public_key = parse_key(&entry.ephemeral_public_key)
+                .with_context(|| format!("Wrapped key for [{}] has invalid ephemeral key", public_key_hex))?;
+            let ephemeral_public_key = PublicKey::from(ephemeral_public_key);
+            let shared_secret = secret.diffie_hellman(&ephemeral_public_key);
+            let cipher = key_encryption_cipher(&shared_secret, &ephemeral_public_key, &public_key);
+
+            let nonce = hex::decode(&entry.nonce)?;
+            if nonce.len() != 12 {
+                bail!("Wrapped key for [{}] has invalid nonce", public_key_hex);
+            }
+            let session_key = cipher
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    hex::decode(&entry.wrapped_key)?.as_slice(),
+                )
+                .map_err(|_| anyhow!("Failed to unwrap session key for [{}]", public_key_hex))?;
+            return Ok(Some(to_key(&session_key)?));
+        }
+    }
+
+    Ok(None)
+}
+
+// Shared secret is ephemeral secret with recipient public key when wrapping, or recipient secret
+// with ephemeral public key when unwrapping. Both are equal.
+fn key_encryption_cipher(
+    shared_secret: &SharedSecret,
+    ephemeral_public_key: &PublicKey,
+    recipient_public_key: &PublicKey,
+) -> Aes256Gcm {
+    let mut salt: Vec<u8> = ephemeral_public_key.as_bytes().to_vec();
+    salt.extend_from_slice(recipient_public_key.as_bytes());
+    let kdf = hkdf::Hkdf::<sha2::Sha256>::new(Some(&salt), shared_secret.as_bytes());
+    let mut key = [0u8; 32];
+    kdf.expand(KDF_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 length");
+
+    Aes256Gcm::new(Key::from_slice(&key))
+}
+
+// Doesn't echo key_hex since it may be a private key. Callers say which key failed.
+fn parse_key(key_hex: &str) -> anyhow::Result<[u8; 32]> {
+    to_key(&hex::decode(key_hex.trim()).context("Key is not hex")?)
+}
+
+fn to_key(bytes: &[u8]) -> anyhow::Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    if bytes.len() != key.len() {
+        bail!("Expected 32 byte key but found [{}] bytes", bytes.len());
+    }
+    key.copy_from_slice(bytes);
+    Ok(key)
+}
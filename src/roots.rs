@@ -0,0 +1,168 @@
+// ----------------------------------------------------------------------------
+// Matching referenced files against embed roots
+//
+// PDBs may reference 100k+ files. Canonicalizing each file and comparing it
+// against every root is a hot spot. Instead each directory is canonicalized and
+// listed once, and roots are stored in a trie keyed by path component so a
+// lookup walks the file path exactly once.
+//
+// PDBs record paths as the compiler saw them: any case, either separator, and
+// maybe through a subst or mapped drive. Canonicalizing resolves drives, and
+// components are compared ignoring case unless exact matching is requested.
+// Files that don't exist can't be canonicalized, so they're compared against
+// roots as given and as canonicalized, with separators and \\?\ normalized.
+// ----------------------------------------------------------------------------
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Default)]
+struct TrieNode {
+    children: HashMap<OsString, TrieNode>,
+
+    // Index of root ending at this node. Lower indices take priority.
+    root: Option<usize>,
+}
+
+// Canonical directory and names of its entries
+struct DirListing {
+    canonical: PathBuf,
+    names: HashSet<OsString>,
+
+    // Lowercase name to true name. PDBs frequently record lowercase paths.
+    names_lowercase: HashMap<String, OsString>,
+}
+
+pub struct RootMatcher {
+    trie: TrieNode,
+    ignore_case: bool,
+
+    // Keyed by directory as written in PDB. None if directory doesn't exist.
+    dirs: HashMap<PathBuf, Option<DirListing>>,
+
+    // Each root as given and canonicalized. See normalize.
+    normalized_roots: Vec<String>,
+}
+
+impl RootMatcher {
+    // Roots that don't exist only match files that don't exist
+    pub fn new(roots: &[PathBuf], ignore_case: bool) -> RootMatcher {
+        let mut trie = TrieNode::default();
+        let mut normalized_roots: Vec<String> = Default::default();
+        for (idx, root) in roots.iter().enumerate() {
+            normalized_roots.push(normalize(root, ignore_case));
+            if let Ok(canonical_root) = fs::canonicalize(root) {
+                let mut node = &mut trie;
+                for component in canonical_root.components() {
+                    node = node.children.entry(key(component, ignore_case)).or_default();
+                }
+                node.root.get_or_insert(idx);
+                normalized_roots.push(normalize(&canonical_root, ignore_case));
+            }
+        }
+
+        RootMatcher {
+            trie,
+            ignore_case,
+            dirs: Default::default(),
+            normalized_roots,
+        }
+    }
+
+    // True if filepath, which may not exist, is under any root
+    pub fn is_under_root(&self, filepath: &Path) -> bool {
+        let filepath = normalize(filepath, self.ignore_case);
+        self.normalized_roots
+            .iter()
+            .any(|root| filepath.starts_with(root.as_str()))
+    }
+
+    // Returns canonical path of file, or None if it doesn't exist. Equivalent to fs::canonicalize
+    // except symlinked files are not resolved, and names differing only in case are matched unless
+    // exact matching was requested.
+    pub fn canonicalize(&mut self, filepath: &Path) -> Option<PathBuf> {
+        let dir = filepath.parent()?;
+        let name = filepath.file_name()?;
+        let ignore_case = self.ignore_case;
+        let listing = self
+            .dirs
+            .entry(dir.to_owned())
+            .or_insert_with(|| list_dir(dir))
+            .as_ref()?;
+
+        if listing.names.contains(name) {
+            Some(listing.canonical.join(name))
+        } else if ignore_case {
+            let true_name = listing
+                .names_lowercase
+                .get(&name.to_string_lossy().to_lowercase())?;
+            Some(listing.canonical.join(true_name))
+        } else {
+            None
+        }
+    }
+
+    // Returns index of first matching root and canonical_filepath relative to it
+    pub fn relative_path(&self, canonical_filepath: &Path) -> Option<(usize, PathBuf)> {
+        let components: Vec<Component> = canonical_filepath.components().collect();
+
+        // Walk trie remembering highest priority root seen. (root_idx, num_components)
+        let mut best: Option<(usize, usize)> = None;
+        let mut node = &self.trie;
+        for (idx, component) in components.iter().enumerate() {
+            node = match node.children.get(&key(*component, self.ignore_case)) {
+                Some(child) => child,
+                None => break,
+            };
+            if let Some(root) = node.root {
+                if best.is_none_or(|(best_root, _)| root < best_root) {
+                    best = Some((root, idx + 1));
+                }
+            }
+        }
+
+        best.filter(|(_, len)| *len < components.len())
+            .map(|(root, len)| (root, components[len..].iter().collect()))
+    }
+}
+
+fn key(component: Component, ignore_case: bool) -> OsString {
+    match ignore_case {
+        true => component.as_os_str().to_string_lossy().to_lowercase().into(),
+        false => component.as_os_str().to_owned(),
+    }
+}
+
+// Forward slashes, no \\?\ prefix, and a trailing slash so prefixes only match whole components
+fn normalize(path: &Path, ignore_case: bool) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = match path.strip_prefix("//?/UNC/") {
+        Some(unc) => format!("//{}", unc),
+        None => path.strip_prefix("//?/").unwrap_or(&path).to_owned(),
+    };
+    let path = format!("{}/", path.trim_end_matches('/'));
+    match ignore_case {
+        true => path.to_lowercase(),
+        false => path,
+    }
+}
+
+fn list_dir(dir: &Path) -> Option<DirListing> {
+    let canonical = fs::canonicalize(dir).ok()?;
+    let names: HashSet<OsString> = fs::read_dir(&canonical)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .collect();
+    let names_lowercase = names
+        .iter()
+        .map(|name| (name.to_string_lossy().to_lowercase(), name.clone()))
+        .collect();
+
+    Some(DirListing {
+        canonical,
+        names,
+        names_lowercase,
+    })
+}
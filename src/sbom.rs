@@ -0,0 +1,230 @@
+// ----------------------------------------------------------------------------
+// SPDX software bill of materials for embedded sources
+//
+// Written at embed time so compliance teams know exactly which files ship
+// inside distributed PDBs. Follows the SPDX 2.3 JSON schema.
+// ----------------------------------------------------------------------------
+use serde::Serialize;
+use sha2::Digest;
+use std::fs::File;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// License headers are expected near the top of a file
+pub const LICENSE_SCAN_BYTES: usize = 4096;
+
+// Well known license text and the SPDX identifier it maps to. More specific entries come first.
+const LICENSE_PATTERNS: &[(&str, &str)] = &[
+    ("GNU Affero General Public License", "AGPL-3.0-or-later"),
+    ("GNU Lesser General Public License", "LGPL-2.1-or-later"),
+    ("GNU Library General Public License", "LGPL-2.0-or-later"),
+    ("GNU General Public License", "GPL-2.0-or-later"),
+    ("Mozilla Public License", "MPL-2.0"),
+    ("Eclipse Public License", "EPL-2.0"),
+    ("Apache License", "Apache-2.0"),
+    ("Boost Software License", "BSL-1.0"),
+    ("Permission is hereby granted, free of charge", "MIT"),
+    (
+        "Redistribution and use in source and binary forms",
+        "BSD-3-Clause",
+    ),
+    (
+        "This is free and unencumbered software released into the public domain",
+        "Unlicense",
+    ),
+    (
+        "This software is provided 'as-is', without any express or implied",
+        "Zlib",
+    ),
+];
+
+// Embedded file as recorded in the bill of materials
+pub struct SbomFile {
+    pub original_path: String,
+    pub relative_path: String,
+    pub sha1: String,
+    pub sha256: String,
+    pub license: Option<String>,
+    pub copyright: Option<String>,
+}
+
+impl SbomFile {
+    pub fn new(original_path: &str, relative_path: &str, contents: &[u8]) -> SbomFile {
+        let header = String::from_utf8_lossy(&contents[..contents.len().min(LICENSE_SCAN_BYTES)]);
+        SbomFile {
+            original_path: original_path.to_owned(),
+            relative_path: relative_path.replace('\\', "/"),
+            sha1: hex::encode(sha1::Sha1::digest(contents)),
+            sha256: hex::encode(sha2::Sha256::digest(contents)),
+            license: detect_license(&header),
+            copyright: detect_copyright(&header),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxDocument {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    spdx_id: &'static str,
+    name: String,
+    document_namespace: String,
+    creation_info: SpdxCreationInfo,
+    files: Vec<SpdxFile>,
+    relationships: Vec<SpdxRelationship>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxCreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxFile {
+    file_name: String,
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    checksums: Vec<SpdxChecksum>,
+    license_concluded: &'static str,
+    license_info_in_files: Vec<String>,
+    copyright_text: String,
+    comment: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxChecksum {
+    algorithm: &'static str,
+    checksum_value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxRelationship {
+    spdx_element_id: &'static str,
+    relationship_type: &'static str,
+    related_spdx_element: String,
+}
+
+// Writes SPDX document describing every file embedded into PDB
+pub fn write_spdx(out_path: &Path, pdb_path: &str, pdb_uuid: &str, files: &[SbomFile]) -> anyhow::Result<()> {
+    let pdb_name = Path::new(pdb_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| pdb_path.to_owned());
+
+    let spdx_files: Vec<SpdxFile> = files
+        .iter()
+        .enumerate()
+        .map(|(idx, file)| SpdxFile {
+            file_name: format!("./{}", file.relative_path),
+            spdx_id: format!("SPDXRef-File-{}", idx),
+            checksums: vec![
+                SpdxChecksum {
+                    algorithm: "SHA1",
+                    checksum_value: file.sha1.clone(),
+                },
+                SpdxChecksum {
+                    algorithm: "SHA256",
+                    checksum_value: file.sha256.clone(),
+                },
+            ],
+            license_concluded: "NOASSERTION",
+            license_info_in_files: vec![file.license.clone().unwrap_or_else(|| "NOASSERTION".to_owned())],
+            copyright_text: file.copyright.clone().unwrap_or_else(|| "NOASSERTION".to_owned()),
+            comment: format!("Original path: {}", file.original_path),
+        })
+        .collect();
+
+    let relationships = spdx_files
+        .iter()
+        .map(|file| SpdxRelationship {
+            spdx_element_id: "SPDXRef-DOCUMENT",
+            relationship_type: "DESCRIBES",
+            related_spdx_element: file.spdx_id.clone(),
+        })
+        .collect();
+
+    let document = SpdxDocument {
+        spdx_version: "SPDX-2.3",
+        data_license: "CC0-1.0",
+        spdx_id: "SPDXRef-DOCUMENT",
+        name: format!("{} embedded sources", pdb_name),
+        document_namespace: format!("https://spdx.org/spdxdocs/fts_pdbsrc/{}/{}", pdb_name, pdb_uuid),
+        creation_info: SpdxCreationInfo {
+            created: utc_timestamp(SystemTime::now()),
+            creators: vec![format!("Tool: fts_pdbsrc-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        files: spdx_files,
+        relationships,
+    };
+
+    let file = File::create(out_path)?;
+    serde_json::to_writer_pretty(file, &document)?;
+    Ok(())
+}
+
+// Returns SPDX identifier for license header found in text, if any
+pub fn detect_license(text: &str) -> Option<String> {
+    // Explicit identifiers win
+    if let Some(idx) = text.find("SPDX-License-Identifier:") {
+        let rest = &text[idx + "SPDX-License-Identifier:".len()..];
+        let id = rest
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .trim_end_matches("*/")
+            .trim();
+        if !id.is_empty() {
+            return Some(id.to_owned());
+        }
+    }
+
+    // License text often wraps across comment lines so compare with whitespace collapsed
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    LICENSE_PATTERNS
+        .iter()
+        .find(|(pattern, _)| collapsed.contains(pattern))
+        .map(|(_, id)| id.to_string())
+}
+
+fn detect_copyright(text: &str) -> Option<String> {
+    text.lines()
+        .map(|line| line.trim_start_matches(|c: char| c.is_whitespace() || "/*#;-!".contains(c)))
+        .find(|line| line.starts_with("Copyright") || line.starts_with("(c)") || line.starts_with("©"))
+        .map(|line| line.trim().to_owned())
+}
+
+// Formats time as ISO 8601 UTC, e.g. 2021-10-28T17:04:12Z
+pub fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Convert days since epoch to civil date. See http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60
+    )
+}
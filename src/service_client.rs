@@ -0,0 +1,310 @@
+// ----------------------------------------------------------------------------
+// Client side of the fts_pdbsrc_service protocol
+//
+// Messages are msgpack. Message and PROTOCOL_VERSION must match
+// fts_pdbsrc_service.
+// ----------------------------------------------------------------------------
+use super::failure::Failure;
+use super::manifest;
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU16, Ordering};
+use uuid::Uuid;
+
+// Port chosen randomly. Service may override via config or registry policy.
+pub const DEFAULT_SERVICE_PORT: u16 = 23685;
+
+pub static SERVICE_PORT: AtomicU16 = AtomicU16::new(DEFAULT_SERVICE_PORT);
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Message {
+    FindPdb(Uuid),
+    FoundPdb((Uuid, Option<PathBuf>)),
+    FindPdbs(Vec<Uuid>),
+    FoundPdbs(Vec<(Uuid, Option<PathBuf>)>),
+
+    // Look up by file name, e.g. game.pdb. Response is uuid, path, and modified time in unix seconds.
+    FindPdbByName(String),
+    FoundPdbByName(Vec<(Uuid, PathBuf, Option<u64>)>),
+
+    // Sent by service in place of a response when a request could not be understood
+    ProtocolError(String),
+
+    // Usage of indexed PDBs, most recently used first. Request is index of first result.
+    // Response is total count and a page of results starting at that index.
+    Stats(u32),
+    FoundStats((u32, Vec<PdbStats>)),
+
+    // First message on every connection. Each side sends its PROTOCOL_VERSION. Services older than
+    // the handshake reject Hello, so clients reconnect and use only the original messages.
+    Hello(u32),
+    HelloAck(u32),
+
+    // Every indexed PDB, sorted by path. Request is index of first result.
+    // Response is total count and a page of results starting at that index.
+    ListPdbs(u32),
+    ListedPdbs((u32, Vec<(Uuid, PathBuf)>)),
+
+    // Raw, possibly encrypted, bytes of a named stream in an indexed PDB. Only srcsrv and fts_pdbsrc
+    // streams may be fetched. Response is None if PDB or stream was not found.
+    FetchFile((Uuid, String)),
+    FetchedFile(Option<Vec<u8>>),
+
+    // Re-walk every configured root, or only the given path which must be under one.
+    // Response is number of PDBs found by the walk.
+    Rescan(Option<PathBuf>),
+    Rescanned(u32),
+
+    // Service version, uptime, and number of indexed PDBs
+    Status,
+    FoundStatus(StatusInfo),
+}
+
+// Bump when Message changes. Variants are only ever appended so msgpack indices stay stable.
+//   2: Chunked framing after HelloAck
+//   3: ListPdbs
+//   4: FetchFile
+//   5: Rescan
+//   6: Status
+pub const PROTOCOL_VERSION: u32 = 6;
+
+// First protocol versions with messages that older services reply to with ProtocolError
+pub const FETCH_FILE_PROTOCOL_VERSION: u32 = 4;
+pub const STATUS_PROTOCOL_VERSION: u32 = 6;
+
+// Messages are u16 framed until handshake. Services speaking protocol 2 or later then switch to
+// chunks, each a u32 little endian header then up to MAX_CHUNK_SIZE bytes. Header is chunk size,
+// with MORE_CHUNKS_BIT set on all but the last chunk.
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+const MORE_CHUNKS_BIT: u32 = 0x8000_0000;
+
+// Connection to fts_pdbsrc_service
+pub struct ServiceStream {
+    pub stream: TcpStream,
+
+    // Service's PROTOCOL_VERSION. Zero if service predates handshake.
+    pub version: u32,
+}
+
+impl ServiceStream {
+    pub fn chunked(&self) -> bool {
+        self.version >= 2
+    }
+}
+
+// Streams of a PDB read through the service
+pub struct ServiceStreams {
+    pub stream: ServiceStream,
+    pub pdb_uuid: Uuid,
+}
+
+impl manifest::NamedStreams for ServiceStreams {
+    fn read_stream(&mut self, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        send_message(
+            &mut self.stream,
+            Message::FetchFile((self.pdb_uuid, name.to_owned())),
+        )?;
+        match read_message(&mut self.stream)? {
+            Message::FetchedFile(bytes) => Ok(bytes),
+            Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+            response => bail!(
+                "Fetched stream [{}], but failed with response: [{:?}]",
+                name,
+                response
+            ),
+        }
+    }
+}
+
+// Must match fts_pdbsrc_service
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PdbStats {
+    pub uuid: Uuid,
+
+    // None if PDB is no longer indexed
+    pub path: Option<PathBuf>,
+
+    pub requests: u64,
+
+    // Unix seconds. None if never requested.
+    pub last_access: Option<u64>,
+
+    pub bytes_served: u64,
+}
+
+// Must match fts_pdbsrc_service
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusInfo {
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub pdbs: u32,
+}
+
+// Uuids per FindPdbs message. Responses fit the u16 packet size limit, or MAX_MESSAGE_SIZE once
+// chunked, while paths average under 1000 bytes. Fewer batches means fewer round trips.
+const MAX_FIND_PDBS_BATCH: usize = 64;
+const MAX_FIND_PDBS_BATCH_CHUNKED: usize = 8 * 1024;
+
+pub fn connect_to_service() -> anyhow::Result<ServiceStream> {
+    let connect = || -> std::io::Result<ServiceStream> {
+        let stream = TcpStream::connect(("localhost", SERVICE_PORT.load(Ordering::Relaxed)))?;
+        Ok(ServiceStream { stream, version: 0 })
+    };
+
+    let mut stream = connect().context(Failure::NoService)?;
+    send_message(&mut stream, Message::Hello(PROTOCOL_VERSION))?;
+    match read_message(&mut stream) {
+        Ok(Message::HelloAck(version)) => {
+            if version < PROTOCOL_VERSION {
+                eprintln!(
+                    "Warning: fts_pdbsrc_service speaks protocol [{}] but fts_pdbsrc speaks [{}]. \
+                     Update fts_pdbsrc_service to match.",
+                    version, PROTOCOL_VERSION
+                );
+            }
+            stream.version = version;
+            Ok(stream)
+        }
+        Ok(Message::ProtocolError(_)) | Err(_) => {
+            // Service predates handshake. It has already closed this connection.
+            eprintln!(
+                "Warning: fts_pdbsrc_service is older than fts_pdbsrc. Commands other than extract may fail \
+                 until fts_pdbsrc_service is updated."
+            );
+            Ok(connect().context(Failure::NoService)?)
+        }
+        Ok(response) => bail!("Unexpected handshake response from service: [{:?}]", response),
+    }
+}
+
+// Asks service for path of PDB with given uuid
+pub fn find_pdb(stream: &mut ServiceStream, pdb_uuid: Uuid) -> anyhow::Result<PathBuf> {
+    send_message(stream, Message::FindPdb(pdb_uuid))?;
+
+    // Wait for response
+    let response = read_message(stream)?;
+    match response {
+        Message::FoundPdb((uuid, Some(path))) if uuid == pdb_uuid => Ok(path),
+        Message::FoundPdb((uuid, Some(_))) => Err(anyhow!(
+            "Mismatched Uuids. Requested: [{}] Found: [{}]",
+            pdb_uuid,
+            uuid
+        )),
+        Message::FoundPdb((_, None)) => Err(Failure::PdbNotFound)
+            .with_context(|| format!("Service found no PDB with uuid [{}]", pdb_uuid)),
+        Message::ProtocolError(reason) => Err(anyhow!("Service rejected request: [{}]", reason)),
+        _ => Err(anyhow!(
+            "Queried service for PDB with uuid [{}], but failed with response: [{:?}]",
+            pdb_uuid,
+            response
+        )),
+    }
+}
+
+// Asks service for paths of many PDBs. Uuids are sent in batches, one round trip per batch.
+pub fn find_pdbs(
+    stream: &mut ServiceStream,
+    pdb_uuids: &[Uuid],
+) -> anyhow::Result<Vec<(Uuid, Option<PathBuf>)>> {
+    let batch_size = if stream.chunked() {
+        MAX_FIND_PDBS_BATCH_CHUNKED
+    } else {
+        MAX_FIND_PDBS_BATCH
+    };
+    let mut results: Vec<(Uuid, Option<PathBuf>)> = Default::default();
+    for batch in pdb_uuids.chunks(batch_size) {
+        send_message(stream, Message::FindPdbs(batch.to_vec()))?;
+
+        // Wait for response
+        match read_message(stream)? {
+            Message::FoundPdbs(found) if found.len() == batch.len() => results.extend(found),
+            Message::ProtocolError(reason) => bail!("Service rejected request: [{}]", reason),
+            response => bail!(
+                "Queried service for [{}] PDBs, but failed with response: [{:?}]",
+                batch.len(),
+                response
+            ),
+        }
+    }
+
+    Ok(results)
+}
+
+pub fn send_message(stream: &mut ServiceStream, message: Message) -> anyhow::Result<()> {
+    // Serialize message
+    let buf = rmp_serde::to_vec(&message).unwrap();
+    let max_size = if stream.chunked() {
+        MAX_MESSAGE_SIZE
+    } else {
+        u16::MAX as usize
+    };
+    if buf.len() > max_size {
+        bail!("Message of [{}] bytes exceeds packet size limit", buf.len());
+    }
+
+    if !stream.chunked() {
+        // Write packet size then message
+        let packet_size = u16::to_ne_bytes(buf.len() as u16);
+        stream.stream.write_all(&packet_size)?;
+        stream.stream.write_all(&buf)?;
+        return Ok(());
+    }
+
+    // Write each chunk's header then chunk
+    let mut chunks = buf.chunks(MAX_CHUNK_SIZE).peekable();
+    while let Some(chunk) = chunks.next() {
+        let more = if chunks.peek().is_some() {
+            MORE_CHUNKS_BIT
+        } else {
+            0
+        };
+        stream
+            .stream
+            .write_all(&u32::to_le_bytes(chunk.len() as u32 | more))?;
+        stream.stream.write_all(chunk)?;
+    }
+
+    Ok(())
+}
+
+pub fn read_message(stream: &mut ServiceStream) -> anyhow::Result<Message> {
+    let mut packet_buf: Vec<u8> = Default::default(); // TODO: make thread_local
+    if !stream.chunked() {
+        // Read packet size
+        let mut packet_size_buf: [u8; 2] = Default::default();
+        stream.stream.read_exact(&mut packet_size_buf)?;
+        let packet_size = u16::from_ne_bytes(packet_size_buf);
+
+        // Read packet
+        packet_buf.resize(packet_size as usize, 0);
+        stream.stream.read_exact(&mut packet_buf)?;
+    } else {
+        loop {
+            // Read chunk header
+            let mut header_buf: [u8; 4] = Default::default();
+            stream.stream.read_exact(&mut header_buf)?;
+            let header = u32::from_le_bytes(header_buf);
+            let chunk_size = (header & !MORE_CHUNKS_BIT) as usize;
+            if chunk_size > MAX_CHUNK_SIZE || packet_buf.len() + chunk_size > MAX_MESSAGE_SIZE {
+                bail!("Chunk of [{}] bytes exceeds packet size limit", chunk_size);
+            }
+
+            // Read chunk
+            let start = packet_buf.len();
+            packet_buf.resize(start + chunk_size, 0);
+            stream.stream.read_exact(&mut packet_buf[start..])?;
+            if header & MORE_CHUNKS_BIT == 0 {
+                break;
+            }
+        }
+    }
+
+    // Deserialize
+    let message: Message = rmp_serde::from_read_ref(&packet_buf)?;
+
+    Ok(message)
+}
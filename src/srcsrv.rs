@@ -0,0 +1,232 @@
+// ----------------------------------------------------------------------------
+// Parsing for srcsrv streams
+// ----------------------------------------------------------------------------
+
+#[derive(Clone, Debug, Default)]
+pub struct SrcSrv {
+    pub ini: Vec<(String, String)>,
+    pub variables: Vec<(String, String)>,
+    pub sources: Vec<Vec<String>>,
+}
+
+impl SrcSrv {
+    pub fn parse(text: &str) -> SrcSrv {
+        enum Section {
+            None,
+            Ini,
+            Variables,
+            Sources,
+        }
+
+        let mut result = SrcSrv::default();
+        let mut section = Section::None;
+
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+
+            // Section headers look like "SRCSRV: ini -------"
+            if let Some(header) = line.strip_prefix("SRCSRV:") {
+                let header = header.trim_start().to_lowercase();
+                section = if header.starts_with("ini") {
+                    Section::Ini
+                } else if header.starts_with("variables") {
+                    Section::Variables
+                } else if header.starts_with("source files") {
+                    Section::Sources
+                } else {
+                    Section::None
+                };
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let split_variable = |line: &str| -> Option<(String, String)> {
+                let idx = line.find('=')?;
+                Some((line[..idx].trim().to_owned(), line[idx + 1..].to_owned()))
+            };
+
+            match section {
+                Section::Ini => result.ini.extend(split_variable(line)),
+                Section::Variables => result.variables.extend(split_variable(line)),
+                Section::Sources => result
+                    .sources
+                    .push(line.split('*').map(|s| s.to_owned()).collect()),
+                Section::None => (),
+            }
+        }
+
+        result
+    }
+
+    // Variable names are case insensitive
+    pub fn variable(&self, name: &str) -> Option<&str> {
+        self.ini
+            .iter()
+            .chain(self.variables.iter())
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Returns source lines with fts_pdbsrc escaping removed from relative path and filename
+    pub fn unescaped_sources(&self) -> Vec<Vec<String>> {
+        let is_escaped = self.variable(ESCAPE_VARIABLE) == Some("1");
+        self.sources
+            .iter()
+            .map(|source| {
+                source
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, field)| match idx {
+                        1 | 2 if is_escaped => unescape_field(field),
+                        _ => field.clone(),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// Set by fts_pdbsrc when relative path and filename fields are escaped
+pub const ESCAPE_VARIABLE: &str = "FTS_PDBSRC_ESCAPE";
+
+// Escapes characters that split source lines or start variable expansion.
+// srcsrv has no escape syntax of its own so '@' followed by two hex digits is used.
+pub fn escape_field(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '@' | '*' | '%' => result.push_str(&format!("@{:02X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+pub fn unescape_field(field: &str) -> String {
+    let mut result = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(idx) = rest.find('@') {
+        result.push_str(&rest[..idx]);
+        let escaped = rest
+            .get(idx + 1..idx + 3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                result.push(byte as char);
+                rest = &rest[idx + 3..];
+            }
+            None => {
+                result.push('@');
+                rest = &rest[idx + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+impl SrcSrv {
+    // Expands variables in text for one source line the way the debugger would.
+    // Supports %varN%, %targ%, named variables, environment variables, and the
+    // %fnvar%(), %fnbksl%(), and %fnfile%() functions.
+    pub fn expand(&self, text: &str, source: &[String], targ: &str) -> String {
+        self.expand_recursive(text, source, targ, 0)
+    }
+
+    fn expand_recursive(&self, text: &str, source: &[String], targ: &str, depth: usize) -> String {
+        // Guard against self referential variables
+        if depth > 16 {
+            return text.to_owned();
+        }
+
+        let mut result = String::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('%') {
+            result.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let end = match after.find('%') {
+                Some(end) => end,
+                None => {
+                    result.push_str(&rest[start..]);
+                    rest = "";
+                    break;
+                }
+            };
+            let name = after[..end].to_lowercase();
+            rest = &after[end + 1..];
+
+            // Functions take a parenthesized argument
+            let is_function = matches!(name.as_str(), "fnvar" | "fnbksl" | "fnfile");
+            if is_function && rest.starts_with('(') {
+                let close = find_matching_paren(rest).unwrap_or(rest.len() - 1);
+                let arg = self.expand_recursive(&rest[1..close], source, targ, depth + 1);
+                rest = rest.get(close + 1..).unwrap_or_default();
+
+                let value = match name.as_str() {
+                    "fnvar" => self
+                        .lookup(&arg, source, targ)
+                        .map(|value| self.expand_recursive(&value, source, targ, depth + 1))
+                        .unwrap_or_default(),
+                    "fnbksl" => arg.replace('/', "\\"),
+                    _ => arg.rsplit(['\\', '/']).next().unwrap_or_default().to_owned(),
+                };
+                result.push_str(&value);
+                continue;
+            }
+
+            match self.lookup(&name, source, targ) {
+                Some(value) => result.push_str(&self.expand_recursive(&value, source, targ, depth + 1)),
+                None => {
+                    // Unknown variables are left as-is
+                    result.push('%');
+                    result.push_str(&after[..end]);
+                    result.push('%');
+                }
+            }
+        }
+        result.push_str(rest);
+
+        result
+    }
+
+    fn lookup(&self, name: &str, source: &[String], targ: &str) -> Option<String> {
+        if name.eq_ignore_ascii_case("targ") {
+            return Some(targ.to_owned());
+        }
+
+        // %var1% is the first field of the source line
+        let lower_name = name.to_lowercase();
+        if let Some(index) = lower_name
+            .strip_prefix("var")
+            .and_then(|n| n.parse::<usize>().ok())
+        {
+            return source.get(index.checked_sub(1)?).cloned();
+        }
+
+        self.variable(name)
+            .map(|value| value.to_owned())
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+// Returns index of ')' matching the '(' at the start of text
+fn find_matching_paren(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
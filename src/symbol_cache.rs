@@ -0,0 +1,99 @@
+// ----------------------------------------------------------------------------
+// Local symbol cache search for extract_one
+//
+// Debuggers download PDBs into the downstream stores named in _NT_SYMBOL_PATH.
+// A PDB the service never indexed may still be sitting in one of them. Stores
+// are laid out as <store>/<name>.pdb/<signature>/<name>.pdb, so when the PDB
+// name is known only that one directory is listed. Otherwise every directory is
+// searched a few levels deep, which covers stores and plain directories alike.
+// ----------------------------------------------------------------------------
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Deep enough for <store>/<name>.pdb/<signature>/<name>.pdb
+const MAX_DEPTH: usize = 3;
+
+// Returns local directories from _NT_SYMBOL_PATH and _NT_ALT_SYMBOL_PATH. Upstream URLs are skipped.
+pub fn symbol_path_dirs() -> Vec<PathBuf> {
+    ["_NT_SYMBOL_PATH", "_NT_ALT_SYMBOL_PATH"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .flat_map(|symbol_path| parse_symbol_path(&symbol_path))
+        .collect()
+}
+
+// Elements are separated by ';'. Each is srv*<downstream>*<upstream>,
+// symsrv*<dll>*<downstream>*<upstream>, cache*<dir>, or a plain directory.
+fn parse_symbol_path(symbol_path: &str) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = Default::default();
+    for element in symbol_path.split(';') {
+        let parts: Vec<&str> = element.split('*').map(str::trim).collect();
+        let paths = match parts[0].to_ascii_lowercase().as_str() {
+            "srv" | "cache" => &parts[1..],
+            "symsrv" => parts.get(2..).unwrap_or_default(),
+            _ => &parts[..],
+        };
+        dirs.extend(
+            paths
+                .iter()
+                .filter(|path| !path.is_empty() && !path.contains("://"))
+                .map(PathBuf::from),
+        );
+    }
+    dirs
+}
+
+// Returns PDB files, including compressed .pd_ files, found in dirs. pdb_name excludes extension.
+pub fn find_pdbs(dirs: &[PathBuf], pdb_name: Option<&str>) -> Vec<PathBuf> {
+    let mut pdbs: Vec<PathBuf> = Default::default();
+    for dir in dirs {
+        match pdb_name {
+            Some(pdb_name) => {
+                let file_names = [format!("{}.pdb", pdb_name), format!("{}.pd_", pdb_name)];
+                let existing_files = |dir: &Path| -> Vec<PathBuf> {
+                    file_names
+                        .iter()
+                        .map(|file_name| dir.join(file_name))
+                        .filter(|path| path.is_file())
+                        .collect()
+                };
+
+                // Plain directory
+                pdbs.extend(existing_files(dir));
+
+                // Symbol store
+                let signature_dirs = match fs::read_dir(dir.join(&file_names[0])) {
+                    Ok(entries) => entries,
+                    Err(_) => continue,
+                };
+                for signature_dir in signature_dirs.flatten() {
+                    pdbs.extend(existing_files(&signature_dir.path()));
+                }
+            }
+            None => walk(dir, MAX_DEPTH, &mut pdbs),
+        }
+    }
+    pdbs
+}
+
+fn walk(dir: &Path, depth: usize, pdbs: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() && depth > 0 => walk(&path, depth - 1, pdbs),
+            Ok(file_type) if file_type.is_file() && is_pdb(&path) => pdbs.push(path),
+            _ => (),
+        }
+    }
+}
+
+fn is_pdb(path: &Path) -> bool {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => extension.eq_ignore_ascii_case("pdb") || extension.eq_ignore_ascii_case("pd_"),
+        None => false,
+    }
+}